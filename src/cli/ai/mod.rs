@@ -23,6 +23,11 @@
 //! - Tool results are command strings, not data
 //! - The CLI executes commands AFTER AI is done, results never sent back
 //!
+//! ## Exception: gateway risk classification
+//! `cli::gateway::classify` sends the body of an outgoing gateway message,
+//! and nothing else, to the provider for scoring. It only runs for API keys
+//! the user has opted in with `gateway keys classify <key> on`.
+//!
 //! ## Audit checklist:
 //! - [ ] No `Database` parameter in `ToolExecutor::new()` or `execute()`
 //! - [ ] No `Person`, `Contact`, `Message` imports in executor.rs
@@ -50,7 +55,7 @@ pub use executor::{ToolExecutor, ToolResult};
 pub use local::LocalProvider;
 #[cfg(feature = "local-ai")]
 pub use models::LocalModel;
-pub use provider::AiProvider;
+pub use provider::{create_provider, AiProvider};
 pub use remote::RemoteProvider;
 pub use session::{AiChatResult, AiChatSession, CommandFeedback, FeedbackAction};
 pub use tools::{get_all_tools, AiTool, ToolParameter};
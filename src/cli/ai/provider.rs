@@ -2,8 +2,8 @@
 //!
 //! Defines the interface that all AI providers must implement.
 
-use super::{AiResponse, AiTool, ChatMessage};
-use anyhow::Result;
+use super::{AiConfig, AiProviderType, AiResponse, AiTool, ChatMessage, RemoteProvider};
+use anyhow::{anyhow, Result};
 
 /// Trait for AI providers that can generate completions
 pub trait AiProvider: Send + Sync {
@@ -23,3 +23,20 @@ pub trait AiProvider: Send + Sync {
     /// Check if this provider is ready to use
     fn is_ready(&self) -> bool;
 }
+
+/// Create the provider selected in the configuration
+pub fn create_provider(config: &AiConfig) -> Result<Box<dyn AiProvider>> {
+    match config.provider_type {
+        AiProviderType::None => Err(anyhow!("AI not configured")),
+        AiProviderType::Remote => {
+            let provider = RemoteProvider::new(config)?;
+            Ok(Box::new(provider))
+        }
+        #[cfg(feature = "local-ai")]
+        AiProviderType::Local => {
+            use super::LocalProvider;
+            let provider = LocalProvider::new(config)?;
+            Ok(Box::new(provider))
+        }
+    }
+}
@@ -26,9 +26,9 @@
 
 use super::{
     executor::ToolExecutor,
-    provider::AiProvider,
+    provider::{create_provider, AiProvider},
     tools::get_all_tools,
-    AiConfig, AiProviderType, ChatMessage, RemoteProvider,
+    AiConfig, AiProviderType, ChatMessage,
 };
 use crate::db::Database;
 use anyhow::{anyhow, Result};
@@ -156,19 +156,7 @@ impl AiChatSession {
 
     /// Create the appropriate provider based on configuration
     fn create_provider(&self) -> Result<Box<dyn AiProvider>> {
-        match self.config.provider_type {
-            AiProviderType::None => Err(anyhow!("AI not configured")),
-            AiProviderType::Remote => {
-                let provider = RemoteProvider::new(&self.config)?;
-                Ok(Box::new(provider))
-            }
-            #[cfg(feature = "local-ai")]
-            AiProviderType::Local => {
-                use super::LocalProvider;
-                let provider = LocalProvider::new(&self.config)?;
-                Ok(Box::new(provider))
-            }
-        }
+        create_provider(&self.config)
     }
 
     /// Clear conversation history (keep system prompt)
@@ -1,11 +1,14 @@
 //! HTTP client for communicating with Moltbot.
 
 use anyhow::{anyhow, Result};
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, RequestBuilder};
 use std::time::Duration;
 
-use super::signing::{compute_signature, current_timestamp};
-use super::types::{BridgeApiResponse, HandshakeRequest, HandshakeResponse, HealthStatus, InboundMessage};
+use super::protocol::{self, Negotiated};
+use super::signing::{current_timestamp, generate_nonce, sign_request};
+use super::types::{
+    BridgeApiResponse, HandshakeRequest, HandshakeResponse, HealthStatus, InboundMessage, OutboundResult,
+};
 
 /// HTTP client for sending messages to Moltbot.
 pub struct BridgeClient {
@@ -37,25 +40,13 @@ impl BridgeClient {
 
     /// Perform initial handshake with Moltbot.
     ///
-    /// Returns the session token if accepted.
-    pub fn handshake(&mut self) -> Result<String> {
+    /// On success the session token replaces the configured token for
+    /// subsequent requests, and Moltbot's response is returned along with the
+    /// negotiated features. Fails if Moltbot's protocol version is incompatible.
+    pub fn handshake(&mut self) -> Result<(HandshakeResponse, Negotiated)> {
         let request = HandshakeRequest::default();
         let body = serde_json::to_vec(&request)?;
-        let timestamp = current_timestamp();
-        let signature = compute_signature(&self.secret, &timestamp, &body);
-
-        let mut req = self
-            .client
-            .post(format!("{}/bridge/handshake", self.base_url))
-            .header("Content-Type", "application/json")
-            .header("X-Bridge-Timestamp", &timestamp)
-            .header("X-Bridge-Signature", &signature);
-
-        if let Some(ref token) = self.token {
-            req = req.header("X-Bridge-Token", token);
-        }
-
-        let response = req.body(body).send()?;
+        let response = self.signed_post("/bridge/handshake", body).send()?;
 
         if !response.status().is_success() {
             return Err(anyhow!(
@@ -82,36 +73,50 @@ impl BridgeClient {
             ));
         }
 
+        let negotiated = protocol::negotiate(data.version.as_deref(), &data.capabilities)?;
+
         let session_token = data
             .session_token
+            .clone()
             .ok_or_else(|| anyhow!("No session token in handshake response"))?;
 
-        self.token = Some(session_token.clone());
-        Ok(session_token)
+        self.token = Some(session_token);
+        Ok((data, negotiated))
     }
 
     /// Send an inbound message to Moltbot.
     pub fn send_inbound(&self, message: &InboundMessage) -> Result<()> {
         let body = serde_json::to_vec(message)?;
-        let timestamp = current_timestamp();
-        let signature = compute_signature(&self.secret, &timestamp, &body);
+        let response = self.signed_post("/bridge/inbound", body).send()?;
 
-        let mut req = self
-            .client
-            .post(format!("{}/bridge/inbound", self.base_url))
-            .header("Content-Type", "application/json")
-            .header("X-Bridge-Timestamp", &timestamp)
-            .header("X-Bridge-Signature", &signature);
+        if !response.status().is_success() {
+            return Err(anyhow!(
+                "Failed to send inbound message: {}",
+                response.status()
+            ));
+        }
 
-        if let Some(ref token) = self.token {
-            req = req.header("X-Bridge-Token", token);
+        let resp: BridgeApiResponse<()> = response.json()?;
+
+        if !resp.success {
+            return Err(anyhow!(
+                "Inbound message rejected: {}",
+                resp.error.unwrap_or_else(|| "unknown error".to_string())
+            ));
         }
 
-        let response = req.body(body).send()?;
+        Ok(())
+    }
+
+    /// Report what happened to an outbound message.
+    pub fn report_result(&self, result: &OutboundResult) -> Result<()> {
+        let body = serde_json::to_vec(result)?;
+        let response = self.signed_post("/bridge/outbound/result", body).send()?;
 
         if !response.status().is_success() {
             return Err(anyhow!(
-                "Failed to send inbound message: {}",
+                "Failed to report result for {}: {}",
+                result.id,
                 response.status()
             ));
         }
@@ -120,7 +125,8 @@ impl BridgeClient {
 
         if !resp.success {
             return Err(anyhow!(
-                "Inbound message rejected: {}",
+                "Result for {} rejected: {}",
+                result.id,
                 resp.error.unwrap_or_else(|| "unknown error".to_string())
             ));
         }
@@ -153,6 +159,27 @@ impl BridgeClient {
             .ok_or_else(|| anyhow!("Missing health status data"))
     }
 
+    /// POST a body signed over its timestamp and a fresh nonce.
+    fn signed_post(&self, path: &str, body: Vec<u8>) -> RequestBuilder {
+        let timestamp = current_timestamp();
+        let nonce = generate_nonce();
+        let signature = sign_request(&self.secret, &timestamp, &nonce, &body);
+
+        let mut req = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .header("Content-Type", "application/json")
+            .header("X-Bridge-Timestamp", &timestamp)
+            .header("X-Bridge-Nonce", &nonce)
+            .header("X-Bridge-Signature", &signature);
+
+        if let Some(ref token) = self.token {
+            req = req.header("X-Bridge-Token", token);
+        }
+
+        req.body(body)
+    }
+
     /// Update the authentication token.
     pub fn set_token(&mut self, token: String) {
         self.token = Some(token);
@@ -0,0 +1,277 @@
+//! Connection to Moltbot: handshake, session refresh and reconnection.
+//!
+//! The bridge handshakes with Moltbot on startup and uses the session token
+//! it gets back for every request. The session is renewed before it expires,
+//! and a failed handshake, health check or callback drops the connection and
+//! retries with exponential backoff. The current state is saved to settings
+//! so `bridge status` can show it.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use super::client::BridgeClient;
+use super::protocol::SharedNegotiation;
+use crate::db::Database;
+
+/// Settings key for the saved connection state.
+pub const SETTING_BRIDGE_CONNECTION: &str = "bridge_connection";
+
+/// How often a connected Moltbot is health-checked.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Session lifetime assumed when Moltbot doesn't give one.
+const DEFAULT_SESSION_SECS: u64 = 60 * 60;
+
+/// Delay before the first reconnection attempt; doubles after each failure.
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between reconnection attempts.
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Whether the bridge currently has a session with Moltbot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    /// No handshake attempted yet
+    Connecting,
+    /// Handshake succeeded and Moltbot is answering
+    Connected,
+    /// Moltbot is unreachable or refused the handshake; retrying
+    Disconnected,
+}
+
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionState::Connecting => write!(f, "connecting"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
+
+/// Connection state as saved for `bridge status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    /// When the state last changed
+    pub since: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_handshake_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    /// Optional features both sides support
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl ConnectionStatus {
+    /// The saved state, if the bridge has run.
+    pub fn load(db: &Database) -> Result<Option<Self>> {
+        Ok(db
+            .get_setting(SETTING_BRIDGE_CONNECTION)?
+            .and_then(|json| serde_json::from_str(&json).ok()))
+    }
+
+    fn save(&self, db: &Database) -> Result<()> {
+        db.set_setting(SETTING_BRIDGE_CONNECTION, &serde_json::to_string(self)?)
+    }
+}
+
+/// Delay before reconnection attempt number `failures + 1`.
+pub fn reconnect_delay(failures: u32) -> Duration {
+    let exponent = failures.max(1).saturating_sub(1).min(20);
+    RECONNECT_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(RECONNECT_MAX_DELAY)
+}
+
+/// A session with Moltbot, re-established as needed.
+pub struct Connection {
+    client: Option<BridgeClient>,
+    status: ConnectionStatus,
+    failures: u32,
+    next_attempt: Instant,
+    session_expires: Option<Instant>,
+    last_health: Instant,
+    negotiated: SharedNegotiation,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Self {
+            client: None,
+            status: ConnectionStatus {
+                state: ConnectionState::Connecting,
+                since: Utc::now(),
+                last_handshake_at: None,
+                peer_version: None,
+                capabilities: Vec::new(),
+                features: Vec::new(),
+                session_expires_at: None,
+                last_error: None,
+                next_attempt_at: None,
+            },
+            failures: 0,
+            next_attempt: Instant::now(),
+            session_expires: None,
+            last_health: Instant::now(),
+            negotiated: SharedNegotiation::default(),
+        }
+    }
+}
+
+impl Connection {
+    /// Handshake, renew the session or health-check as due, and return the
+    /// client while connected.
+    ///
+    /// `connect` builds a client from the current configuration, so changes
+    /// made with `bridge config` are picked up on the next handshake.
+    pub fn tick(
+        &mut self,
+        db: &Database,
+        connect: impl FnOnce() -> Result<BridgeClient>,
+    ) -> Result<Option<&BridgeClient>> {
+        let now = Instant::now();
+        if self.client.is_some() {
+            if self.session_expires.is_some_and(|at| at <= now) {
+                println!("Renewing Moltbot session");
+                self.client = None;
+            } else if self.last_health.elapsed() >= HEALTH_INTERVAL {
+                self.last_health = now;
+                let healthy = self.client.as_ref().map(|c| c.health_check());
+                if let Some(Err(e)) = healthy {
+                    self.lost(db, &format!("health check failed: {}", e))?;
+                }
+            }
+        }
+
+        if self.client.is_none() && now >= self.next_attempt {
+            match connect().and_then(|mut client| client.handshake().map(|r| (client, r))) {
+                Ok((client, (response, negotiated))) => {
+                    let lifetime = Duration::from_secs(
+                        response.expires_in_secs.unwrap_or(DEFAULT_SESSION_SECS),
+                    );
+                    // Renew a little early so requests never carry an expired token
+                    let renew_after = lifetime.saturating_sub(lifetime / 10);
+                    self.session_expires = Some(now + renew_after);
+                    self.last_health = now;
+                    self.failures = 0;
+                    self.client = Some(client);
+
+                    let was = self.status.state;
+                    self.status.state = ConnectionState::Connected;
+                    if was != ConnectionState::Connected {
+                        self.status.since = Utc::now();
+                    }
+                    self.status.last_handshake_at = Some(Utc::now());
+                    self.status.peer_version = response.version;
+                    self.status.capabilities = response.capabilities;
+                    self.status.features =
+                        negotiated.features.iter().map(|f| f.to_string()).collect();
+                    if let Ok(mut shared) = self.negotiated.lock() {
+                        *shared = Some(negotiated);
+                    }
+                    self.status.session_expires_at = chrono::Duration::from_std(lifetime)
+                        .ok()
+                        .map(|d| Utc::now() + d);
+                    self.status.last_error = None;
+                    self.status.next_attempt_at = None;
+                    self.status.save(db)?;
+                    if was != ConnectionState::Connected {
+                        println!(
+                            "Connected to Moltbot (version {})",
+                            self.status.peer_version.as_deref().unwrap_or("unknown")
+                        );
+                    }
+                }
+                Err(e) => self.lost(db, &format!("handshake failed: {}", e))?,
+            }
+        }
+
+        Ok(self.client.as_ref())
+    }
+
+    /// Drop the session after a failed request and schedule a reconnection.
+    pub fn lost(&mut self, db: &Database, error: &str) -> Result<()> {
+        self.client = None;
+        self.session_expires = None;
+        if let Ok(mut shared) = self.negotiated.lock() {
+            *shared = None;
+        }
+        self.failures += 1;
+        let delay = reconnect_delay(self.failures);
+        self.next_attempt = Instant::now() + delay;
+
+        if self.status.state != ConnectionState::Disconnected {
+            eprintln!("Lost connection to Moltbot: {}", error);
+            self.status.state = ConnectionState::Disconnected;
+            self.status.since = Utc::now();
+        }
+        self.status.session_expires_at = None;
+        self.status.last_error = Some(error.to_string());
+        self.status.next_attempt_at = chrono::Duration::from_std(delay)
+            .ok()
+            .map(|d| Utc::now() + d);
+        self.status.save(db)
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.status.state
+    }
+
+    /// The session's negotiated features, updated on every handshake.
+    pub fn negotiation(&self) -> SharedNegotiation {
+        self.negotiated.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_delay_backoff() {
+        assert_eq!(reconnect_delay(1), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(2), Duration::from_secs(2));
+        assert_eq!(reconnect_delay(5), Duration::from_secs(16));
+        assert_eq!(reconnect_delay(40), RECONNECT_MAX_DELAY);
+    }
+
+    #[test]
+    fn test_unreachable_moltbot_backs_off() {
+        let db = Database::open_memory().unwrap();
+        let mut connection = Connection::default();
+        // Nothing listens on port 1
+        let client = connection
+            .tick(&db, || BridgeClient::new(1, "secret".to_string(), None))
+            .unwrap();
+        assert!(client.is_none());
+        assert_eq!(connection.state(), ConnectionState::Disconnected);
+
+        // The next attempt waits for the backoff delay
+        let mut attempted = false;
+        connection
+            .tick(&db, || {
+                attempted = true;
+                BridgeClient::new(1, "secret".to_string(), None)
+            })
+            .unwrap();
+        assert!(!attempted);
+
+        let saved = ConnectionStatus::load(&db).unwrap().unwrap();
+        assert_eq!(saved.state, ConnectionState::Disconnected);
+        assert!(saved.last_error.unwrap().contains("handshake failed"));
+        assert!(saved.next_attempt_at.is_some());
+    }
+}
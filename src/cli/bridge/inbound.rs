@@ -0,0 +1,203 @@
+//! Forwarding of received messages to Moltbot.
+//!
+//! New messages are read from a `MessageSource` and sent to Moltbot in order.
+//! The source's cursor is saved only once everything read from it has been
+//! forwarded, so a restart resumes where forwarding left off instead of
+//! resending. Messages from contacts who opted out of AI contact are dropped.
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use super::client::BridgeClient;
+use super::types::{BridgeChannel, InboundMessage};
+use crate::cli::messages::source::{
+    ChatDbSource, MessageSource, ReceivedMessage, ResumableSource, SpoolSource,
+};
+use crate::db::Database;
+
+/// Settings keys for the resume position of each source.
+const SETTING_BRIDGE_INBOUND_CHATDB_ROWID: &str = "bridge_inbound_chatdb_rowid";
+const SETTING_BRIDGE_INBOUND_SPOOL_CURSOR: &str = "bridge_inbound_spool_cursor";
+
+/// How often the source is polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Where messages to forward come from.
+#[derive(Debug, Clone)]
+pub enum InboundSource {
+    /// macOS Messages database
+    ChatDb,
+    /// Directory of JSONL spool files
+    Spool(PathBuf),
+}
+
+impl InboundSource {
+    fn cursor_setting(&self) -> &'static str {
+        match self {
+            InboundSource::ChatDb => SETTING_BRIDGE_INBOUND_CHATDB_ROWID,
+            InboundSource::Spool(_) => SETTING_BRIDGE_INBOUND_SPOOL_CURSOR,
+        }
+    }
+
+    /// Open the source, resuming from the saved cursor if any.
+    fn open(&self, db: &Database) -> Result<Box<dyn MessageSource>> {
+        let cursor = db.get_setting(self.cursor_setting())?;
+        match self {
+            InboundSource::ChatDb => Ok(Box::new(ChatDbSource::new(cursor.as_deref())?)),
+            InboundSource::Spool(dir) => {
+                Ok(Box::new(SpoolSource::new(dir.clone(), cursor.as_deref())?))
+            }
+        }
+    }
+}
+
+/// The message as sent to Moltbot, or `None` if it must not be forwarded.
+///
+/// The sender's name comes from our contacts. Contacts who opted out of AI
+/// contact are never forwarded, nor are channels the bridge doesn't carry.
+fn to_inbound(db: &Database, msg: &ReceivedMessage) -> Result<Option<InboundMessage>> {
+    let channel = match msg.channel.as_str() {
+        "imessage" => BridgeChannel::IMessage,
+        "sms" => BridgeChannel::Sms,
+        _ => return Ok(None),
+    };
+
+    let contact = if msg.sender.contains('@') {
+        db.get_person_by_email(&msg.sender)?
+    } else {
+        db.get_person_by_phone(&msg.sender)?
+    };
+    if contact
+        .as_ref()
+        .is_some_and(|person| !person.ai_contact_allowed)
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(InboundMessage {
+        id: msg.id.clone(),
+        sender: msg.sender.clone(),
+        content: msg.content.clone(),
+        channel,
+        timestamp: msg.received_at.timestamp(),
+        sender_name: contact
+            .and_then(|person| person.display_name)
+            .filter(|name| !name.is_empty()),
+    }))
+}
+
+/// Reads new messages from a source and forwards them to Moltbot.
+pub struct InboundPump {
+    messages: ResumableSource,
+    last_poll: Option<Instant>,
+}
+
+impl InboundPump {
+    pub fn open(db: &Database, config: InboundSource) -> Result<Self> {
+        let source = config.open(db)?;
+        Ok(Self {
+            messages: ResumableSource::new(source, Some(config.cursor_setting())),
+            last_poll: None,
+        })
+    }
+
+    /// Short name of the source for logging.
+    pub fn name(&self) -> &str {
+        self.messages.name()
+    }
+
+    /// Poll the source when due and forward what it returned, stopping at the
+    /// first failure so the rest are retried in order. Returns how many were
+    /// forwarded.
+    pub fn pump(&mut self, db: &Database, client: &BridgeClient) -> Result<usize> {
+        self.pump_with(db, &mut |msg| client.send_inbound(msg))
+    }
+
+    fn pump_with(
+        &mut self,
+        db: &Database,
+        send: &mut dyn FnMut(&InboundMessage) -> Result<()>,
+    ) -> Result<usize> {
+        if !self.messages.has_pending() {
+            if self.last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+                return Ok(0);
+            }
+            self.last_poll = Some(Instant::now());
+        }
+
+        self.messages.drain(db, &mut |msg| match to_inbound(db, msg)? {
+            Some(inbound) => send(&inbound).map(|()| 1),
+            None => Ok(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Email, Person, Phone};
+
+    #[test]
+    fn test_forwarding_resumes_without_resending() {
+        let db = Database::open_memory().unwrap();
+        let mut alice = Person::new();
+        alice.name_given = Some("Alice".to_string());
+        alice.compute_names();
+        db.insert_person(&alice).unwrap();
+        db.insert_phone(&Phone::new(alice.id, "+15551234567".to_string()))
+            .unwrap();
+        let mut bob = Person::new();
+        bob.ai_contact_allowed = false;
+        db.insert_person(&bob).unwrap();
+        db.insert_email(&Email::new(bob.id, "bob@example.com".to_string()))
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("001.jsonl"),
+            concat!(
+                r#"{"id": "a", "sender": "+1 (555) 123-4567", "content": "Hi", "channel": "sms"}"#,
+                "\n",
+                r#"{"id": "b", "sender": "bob@example.com", "content": "Private"}"#,
+                "\n",
+                r#"{"id": "c", "sender": "+15550000000", "content": "Who's this?"}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        let config = InboundSource::Spool(dir.path().to_path_buf());
+
+        // The first send fails: nothing is lost and the cursor isn't saved
+        let mut pump = InboundPump::open(&db, config.clone()).unwrap();
+        let err = pump
+            .pump_with(&db, &mut |_| Err(anyhow::anyhow!("Moltbot is down")))
+            .unwrap_err();
+        assert!(err.to_string().contains("down"));
+        assert!(db
+            .get_setting(SETTING_BRIDGE_INBOUND_SPOOL_CURSOR)
+            .unwrap()
+            .is_none());
+
+        let mut sent = Vec::new();
+        let forwarded = pump
+            .pump_with(&db, &mut |msg| {
+                sent.push(msg.clone());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(forwarded, 2);
+        assert_eq!(sent[0].id, "a");
+        assert_eq!(sent[0].channel, BridgeChannel::Sms);
+        assert_eq!(sent[0].sender_name.as_deref(), Some("Alice"));
+        assert_eq!(sent[1].id, "c");
+        assert!(sent[1].sender_name.is_none());
+
+        // After a restart nothing is sent again
+        let mut restarted = InboundPump::open(&db, config).unwrap();
+        let forwarded = restarted
+            .pump_with(&db, &mut |msg| panic!("resent {}", msg.id))
+            .unwrap();
+        assert_eq!(forwarded, 0);
+    }
+}
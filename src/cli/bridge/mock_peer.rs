@@ -0,0 +1,404 @@
+//! In-process Moltbot stand-in for end-to-end bridge tests.
+//!
+//! `MockMoltbot` serves Moltbot's side of the protocol on an ephemeral local
+//! port, checking signatures the way Moltbot must and recording what it
+//! receives. It also plays Moltbot's client, signing requests to a
+//! `BridgeServer` started on another ephemeral port.
+
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use super::protocol::SharedNegotiation;
+use super::signing::{current_timestamp, sign_request, verify_request};
+use super::types::{BridgeApiResponse, HandshakeResponse, HealthStatus};
+use super::{BridgeEvent, BridgeServer};
+use crate::cli::http_server::{self, Handler, HttpRequest, HttpResponse, ServerLimits};
+
+/// Requests Moltbot received: path and JSON body.
+type Received = Arc<Mutex<Vec<(String, serde_json::Value)>>>;
+
+pub struct MockMoltbot {
+    pub port: u16,
+    secret: String,
+    received: Received,
+    shutdown: Arc<AtomicBool>,
+}
+
+struct MockHandler {
+    secret: String,
+    handshake: HandshakeResponse,
+    received: Received,
+}
+
+impl Handler for MockHandler {
+    fn handle(&self, request: HttpRequest) -> HttpResponse {
+        if request.method == "GET" && request.path == "/bridge/health" {
+            let health = HealthStatus {
+                status: "ok".to_string(),
+                uptime_secs: 0,
+                version: self.handshake.version.clone().unwrap_or_default(),
+            };
+            return HttpResponse::json(200, &BridgeApiResponse::ok(health));
+        }
+
+        let header = |name: &str| request.headers.get(name).map(String::as_str).unwrap_or("");
+        let verified = verify_request(
+            &self.secret,
+            header("x-bridge-timestamp"),
+            header("x-bridge-nonce"),
+            &request.body,
+            header("x-bridge-signature"),
+        );
+        if let Err(e) = verified {
+            return HttpResponse::json(401, &BridgeApiResponse::<()>::err(e.to_string()));
+        }
+
+        let body = serde_json::from_slice(&request.body).unwrap_or(serde_json::Value::Null);
+        if let Ok(mut received) = self.received.lock() {
+            received.push((request.path.clone(), body));
+        }
+        match request.path.as_str() {
+            "/bridge/handshake" => {
+                HttpResponse::json(200, &BridgeApiResponse::ok(self.handshake.clone()))
+            }
+            _ => HttpResponse::json(200, &BridgeApiResponse::ok(())),
+        }
+    }
+}
+
+impl MockMoltbot {
+    /// Serve Moltbot's endpoints, answering handshakes with `handshake`.
+    pub fn start(secret: &str, handshake: HandshakeResponse) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Received::default();
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handler = MockHandler {
+            secret: secret.to_string(),
+            handshake,
+            received: received.clone(),
+        };
+        let flag = shutdown.clone();
+        std::thread::spawn(move || {
+            http_server::serve(
+                listener,
+                Arc::new(handler),
+                ServerLimits::default(),
+                None,
+                flag,
+            )
+        });
+
+        Self {
+            port,
+            secret: secret.to_string(),
+            received,
+            shutdown,
+        }
+    }
+
+    /// A handshake answer from a Moltbot speaking `version` with `capabilities`.
+    pub fn handshake(version: &str, capabilities: &[&str]) -> HandshakeResponse {
+        HandshakeResponse {
+            accepted: true,
+            session_token: Some("session-1".to_string()),
+            error: None,
+            version: Some(version.to_string()),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            expires_in_secs: Some(600),
+        }
+    }
+
+    /// Bodies of the signed requests received on `path`, oldest first.
+    pub fn received(&self, path: &str) -> Vec<serde_json::Value> {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(p, _)| p == path)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+
+    /// POST to a bridge as Moltbot, with the given nonce. Returns the status.
+    pub fn post(&self, bridge_port: u16, path: &str, body: &serde_json::Value, nonce: &str) -> u16 {
+        let body = body.to_string();
+        let timestamp = current_timestamp();
+        let signature = sign_request(&self.secret, &timestamp, nonce, body.as_bytes());
+        reqwest::blocking::Client::new()
+            .post(format!("http://127.0.0.1:{}{}", bridge_port, path))
+            .header("X-Bridge-Timestamp", timestamp)
+            .header("X-Bridge-Nonce", nonce)
+            .header("X-Bridge-Signature", signature)
+            .body(body)
+            .send()
+            .unwrap()
+            .status()
+            .as_u16()
+    }
+}
+
+impl Drop for MockMoltbot {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Start a bridge server on an ephemeral port, returning the port and its events.
+pub fn start_bridge(
+    secret: &str,
+    negotiated: SharedNegotiation,
+    shutdown: Arc<AtomicBool>,
+) -> (u16, mpsc::UnboundedReceiver<BridgeEvent>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let rx = BridgeServer::new(port, secret.to_string(), None)
+        .with_negotiation(negotiated)
+        .start_on(listener, shutdown)
+        .unwrap();
+    (port, rx)
+}
+
+/// Wait up to a few seconds for the next bridge event.
+pub fn next_event(rx: &mut mpsc::UnboundedReceiver<BridgeEvent>) -> Option<BridgeEvent> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if let Ok(event) = rx.try_recv() {
+            return Some(event);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::bridge::connection::Connection;
+    use crate::cli::bridge::outbound;
+    use crate::cli::bridge::types::{BridgeChannel, InboundMessage, OutboundStatus};
+    use crate::cli::bridge::{BridgeClient, ConnectionState, ConnectionStatus};
+    use crate::cli::gateway::audit;
+    use crate::cli::gateway::intake::{self, SendChecks};
+    use crate::cli::gateway::transport::{self, TransportConfig};
+    use crate::cli::gateway::types::GatewayChannel;
+    use crate::db::Database;
+    use serde_json::json;
+
+    const SECRET: &str = "mock-shared-secret";
+
+    #[test]
+    fn test_handshake_negotiates_with_peer() {
+        let db = Database::open_memory().unwrap();
+        let moltbot = MockMoltbot::start(
+            SECRET,
+            MockMoltbot::handshake("1.3", &["sms", "typing", "kill", "teleport"]),
+        );
+
+        let mut connection = Connection::default();
+        let client = connection
+            .tick(&db, || {
+                BridgeClient::new(moltbot.port, SECRET.to_string(), None)
+            })
+            .unwrap()
+            .expect("connected");
+
+        // Requests after the handshake carry the session token and a valid signature
+        client
+            .send_inbound(&InboundMessage {
+                id: "in-1".to_string(),
+                sender: "+15551234567".to_string(),
+                content: "Hi".to_string(),
+                channel: BridgeChannel::Sms,
+                timestamp: 1_700_000_000,
+                sender_name: None,
+            })
+            .unwrap();
+        assert_eq!(client.token(), Some("session-1"));
+        assert_eq!(moltbot.received("/bridge/inbound")[0]["id"], "in-1");
+
+        let handshake = &moltbot.received("/bridge/handshake")[0];
+        assert_eq!(handshake["version"], "1.0");
+        assert_eq!(
+            handshake["capabilities"],
+            json!(["imessage", "sms", "kill"])
+        );
+
+        let status = ConnectionStatus::load(&db).unwrap().unwrap();
+        assert_eq!(status.state, ConnectionState::Connected);
+        assert_eq!(status.peer_version.as_deref(), Some("1.3"));
+        assert_eq!(status.features, vec!["kill".to_string()]);
+    }
+
+    #[test]
+    fn test_incompatible_peer_is_refused() {
+        let db = Database::open_memory().unwrap();
+        let moltbot = MockMoltbot::start(SECRET, MockMoltbot::handshake("2.0", &["kill"]));
+
+        let mut connection = Connection::default();
+        let client = connection
+            .tick(&db, || {
+                BridgeClient::new(moltbot.port, SECRET.to_string(), None)
+            })
+            .unwrap();
+        assert!(client.is_none());
+
+        let status = ConnectionStatus::load(&db).unwrap().unwrap();
+        assert_eq!(status.state, ConnectionState::Disconnected);
+        let error = status.last_error.unwrap();
+        assert!(error.contains("Incompatible protocol"), "{}", error);
+        assert!(error.contains("Moltbot speaks 2.0"), "{}", error);
+    }
+
+    /// Handshake with `moltbot` and start a bridge server enforcing the result.
+    fn connect(
+        moltbot: &MockMoltbot,
+        shutdown: Arc<AtomicBool>,
+    ) -> (u16, mpsc::UnboundedReceiver<BridgeEvent>) {
+        let db = Database::open_memory().unwrap();
+        let mut connection = Connection::default();
+        connection
+            .tick(&db, || {
+                BridgeClient::new(moltbot.port, SECRET.to_string(), None)
+            })
+            .unwrap()
+            .expect("connected");
+        start_bridge(SECRET, connection.negotiation(), shutdown)
+    }
+
+    #[test]
+    fn test_bridge_server_against_peer() {
+        let moltbot = MockMoltbot::start(SECRET, MockMoltbot::handshake("1.0", &["kill"]));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (port, mut rx) = connect(&moltbot, shutdown.clone());
+
+        let outbound = json!({
+            "id": "out-1",
+            "recipient": "+15551234567",
+            "content": "Running late",
+            "channel": "sms",
+        });
+        assert_eq!(
+            moltbot.post(port, "/bridge/outbound", &outbound, "nonce-1"),
+            200
+        );
+        match next_event(&mut rx) {
+            Some(BridgeEvent::OutboundMessage(msg)) => assert_eq!(msg.id, "out-1"),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // A replayed request and a request from someone without the secret are refused
+        assert_eq!(
+            moltbot.post(port, "/bridge/outbound", &outbound, "nonce-1"),
+            401
+        );
+        let stranger = MockMoltbot::start("wrong-secret", MockMoltbot::handshake("1.0", &[]));
+        assert_eq!(
+            stranger.post(port, "/bridge/outbound", &outbound, "nonce-2"),
+            401
+        );
+
+        let kill = json!({ "reason": "test over" });
+        assert_eq!(moltbot.post(port, "/bridge/kill", &kill, "nonce-3"), 200);
+        match next_event(&mut rx) {
+            Some(BridgeEvent::Kill { reason }) => assert_eq!(reason.as_deref(), Some("test over")),
+            other => panic!("unexpected event {:?}", other),
+        }
+        shutdown.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_outbound_is_approved_sent_and_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_memory().unwrap();
+        let outbox = dir.path().join("outbox.jsonl");
+        let file = TransportConfig::File {
+            path: outbox.clone(),
+        };
+        transport::set_for_channel(&db, GatewayChannel::Sms, Some(&file)).unwrap();
+
+        let moltbot = MockMoltbot::start(SECRET, MockMoltbot::handshake("1.0", &["sms"]));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (port, mut rx) = start_bridge(SECRET, SharedNegotiation::default(), shutdown.clone());
+        let mut connection = Connection::default();
+        let client = connection
+            .tick(&db, || {
+                BridgeClient::new(moltbot.port, SECRET.to_string(), None)
+            })
+            .unwrap()
+            .expect("connected");
+
+        let message = json!({
+            "id": "out-1",
+            "recipient": "+15551234567",
+            "content": "Running late",
+            "channel": "sms",
+        });
+        assert_eq!(
+            moltbot.post(port, "/bridge/outbound", &message, "nonce-1"),
+            200
+        );
+        let Some(BridgeEvent::OutboundMessage(msg)) = next_event(&mut rx) else {
+            panic!("no outbound message");
+        };
+        let checks = SendChecks::load(&db).unwrap();
+        assert_eq!(
+            outbound::queue_outbound(&db, &checks, &msg).unwrap(),
+            Some(OutboundStatus::Queued)
+        );
+        assert_eq!(outbound::report_results(&db, client).unwrap(), 1);
+
+        // Nothing is sent until a human approves it
+        assert!(!outbox.exists());
+        let queue_id = db.list_pending_queue().unwrap()[0].id.clone();
+        intake::approve(&db, &queue_id, audit::ACTOR_TUI)
+            .unwrap()
+            .unwrap();
+        let sent = std::fs::read_to_string(&outbox).unwrap();
+        assert_eq!(sent.lines().count(), 1);
+        assert!(sent.contains("Running late"));
+
+        // Moltbot hears each step through signed callbacks, which it verified
+        assert_eq!(outbound::report_results(&db, client).unwrap(), 1);
+        let results = moltbot.received("/bridge/outbound/result");
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            (&results[0]["id"], &results[0]["status"]),
+            (&json!("out-1"), &json!("queued"))
+        );
+        assert_eq!(
+            (&results[1]["id"], &results[1]["status"]),
+            (&json!("out-1"), &json!("sent"))
+        );
+        assert!(results[1]["sent_at"].is_string());
+        assert_eq!(outbound::report_results(&db, client).unwrap(), 0);
+        shutdown.store(true, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_kill_honoured_without_negotiation() {
+        let moltbot = MockMoltbot::start(SECRET, MockMoltbot::handshake("1.0", &["sms"]));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (port, mut rx) = connect(&moltbot, shutdown.clone());
+
+        let kill = json!({ "reason": "not agreed" });
+        assert_eq!(moltbot.post(port, "/bridge/kill", &kill, "nonce-1"), 200);
+        assert!(matches!(
+            next_event(&mut rx),
+            Some(BridgeEvent::Kill { .. })
+        ));
+
+        // Nor does it wait for a handshake, e.g. while reconnecting
+        let (port, mut rx) = start_bridge(SECRET, SharedNegotiation::default(), shutdown.clone());
+        assert_eq!(moltbot.post(port, "/bridge/kill", &kill, "nonce-2"), 200);
+        assert!(matches!(
+            next_event(&mut rx),
+            Some(BridgeEvent::Kill { .. })
+        ));
+        shutdown.store(true, Ordering::SeqCst);
+    }
+}
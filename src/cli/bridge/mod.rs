@@ -5,32 +5,60 @@
 
 use anyhow::{anyhow, Result};
 use clap::{Args, Subcommand};
-use std::fs;
-use std::path::PathBuf;
+use daemonize::Daemonize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TryRecvError;
 
 mod client;
+mod connection;
+mod inbound;
+#[cfg(test)]
+mod mock_peer;
+mod nonce;
+mod outbound;
+mod protocol;
 mod server;
 mod signing;
 mod types;
 
 pub use client::BridgeClient;
+pub use connection::{ConnectionState, ConnectionStatus};
+pub use inbound::InboundSource;
+pub use protocol::{Feature, Negotiated, ProtocolVersion, PROTOCOL_VERSION};
 pub use server::{BridgeEvent, BridgeServer};
-pub use signing::{compute_signature, generate_secret, generate_token, verify_signature};
+pub use signing::{
+    compute_signature, current_timestamp, generate_nonce, generate_secret, generate_token,
+    sign_request, verify_request, verify_signature, SigningSecrets,
+};
 pub use types::*;
 
+use crate::cli::gateway::intake::SendChecks;
+use crate::cli::gateway::keys;
 use crate::db::Database;
+use connection::Connection;
+use inbound::InboundPump;
 
 /// Settings keys for bridge configuration
 const SETTING_BRIDGE_SECRET: &str = "bridge_shared_secret";
 const SETTING_BRIDGE_TOKEN: &str = "bridge_token";
 const SETTING_BRIDGE_MOLTBOT_PORT: &str = "bridge_moltbot_port";
+/// Secret and token replaced by a rotation, accepted until the rotation ends
+const SETTING_BRIDGE_PREVIOUS_SECRET: &str = "bridge_previous_secret";
+const SETTING_BRIDGE_PREVIOUS_TOKEN: &str = "bridge_previous_token";
+const SETTING_BRIDGE_ROTATION_EXPIRES: &str = "bridge_rotation_expires";
 
 /// Default ports
 const DEFAULT_BRIDGE_PORT: u16 = 9800;
 const DEFAULT_MOLTBOT_PORT: u16 = 9801;
 
+/// How often delivery results are checked and reported to Moltbot.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Args)]
 pub struct BridgeArgs {
     #[command(subcommand)]
@@ -39,7 +67,7 @@ pub struct BridgeArgs {
 
 #[derive(Subcommand)]
 pub enum BridgeCommands {
-    /// Start the bridge server
+    /// Start the bridge server (in the background unless --foreground)
     Start {
         /// Port to listen on (default: 9800)
         #[arg(short, long, default_value_t = DEFAULT_BRIDGE_PORT)]
@@ -48,6 +76,14 @@ pub enum BridgeCommands {
         /// Run in foreground (don't daemonize)
         #[arg(short, long)]
         foreground: bool,
+
+        /// Forward new messages from macOS Messages to Moltbot
+        #[arg(long, conflicts_with = "inbound_spool")]
+        inbound_chatdb: bool,
+
+        /// Forward messages appended to JSONL files in this directory to Moltbot
+        #[arg(long, value_name = "PATH")]
+        inbound_spool: Option<PathBuf>,
     },
     /// Stop the bridge server
     Stop,
@@ -74,13 +110,29 @@ pub enum BridgeCommands {
         /// Generate new secret and token
         #[arg(long)]
         generate: bool,
+
+        /// How long a replaced secret and token keep working (e.g., "24h", "7d", or "0" to stop now)
+        #[arg(long, value_name = "DURATION", default_value = "24h")]
+        grace: String,
     },
 }
 
 /// Run the bridge command.
 pub fn run_bridge(db: &Database, args: BridgeArgs) -> Result<()> {
     match args.command {
-        BridgeCommands::Start { port, foreground } => start_bridge(db, port, foreground),
+        BridgeCommands::Start {
+            port,
+            foreground,
+            inbound_chatdb,
+            inbound_spool,
+        } => {
+            let inbound = match inbound_spool {
+                Some(dir) => Some(InboundSource::Spool(dir)),
+                None if inbound_chatdb => Some(InboundSource::ChatDb),
+                None => None,
+            };
+            start_bridge(db, port, foreground, inbound)
+        }
         BridgeCommands::Stop => stop_bridge(),
         BridgeCommands::Status => show_status(db),
         BridgeCommands::Config {
@@ -89,12 +141,18 @@ pub fn run_bridge(db: &Database, args: BridgeArgs) -> Result<()> {
             moltbot_port,
             show,
             generate,
-        } => configure_bridge(db, secret, token, moltbot_port, show, generate),
+            grace,
+        } => configure_bridge(db, secret, token, moltbot_port, show, generate, &grace),
     }
 }
 
 /// Start the bridge server.
-fn start_bridge(db: &Database, port: u16, foreground: bool) -> Result<()> {
+fn start_bridge(
+    db: &Database,
+    port: u16,
+    foreground: bool,
+    inbound: Option<InboundSource>,
+) -> Result<()> {
     // Check if already running
     if let Some(pid) = read_pid_file()? {
         if is_process_running(pid) {
@@ -121,58 +179,218 @@ fn start_bridge(db: &Database, port: u16, foreground: bool) -> Result<()> {
         // Run in foreground
         write_pid_file(std::process::id())?;
 
-        let server = BridgeServer::new(port, secret, token);
-        let shutdown = Arc::new(AtomicBool::new(false));
-        let shutdown_clone = shutdown.clone();
-
-        // Set up Ctrl+C handler
-        ctrlc_handler(shutdown_clone);
-
         println!("Starting bridge server on port {}...", port);
         println!("Press Ctrl+C to stop");
 
-        match server.start(shutdown) {
-            Ok(mut rx) => {
-                // Process events until shutdown
-                while let Some(event) = rx.blocking_recv() {
-                    match event {
-                        BridgeEvent::OutboundMessage(msg) => {
-                            println!(
-                                "Received outbound message: {} -> {} via {}",
-                                msg.id, msg.recipient, msg.channel
-                            );
-                            // TODO: Actually send the message via iMessage/SMS
-                        }
-                        BridgeEvent::Kill { reason } => {
-                            println!(
-                                "Kill request received: {}",
-                                reason.unwrap_or_else(|| "no reason".to_string())
-                            );
-                            break;
-                        }
-                        BridgeEvent::Error(e) => {
-                            eprintln!("Bridge error: {}", e);
-                        }
-                    }
+        let result = run_server(db, port, secret, token, inbound);
+        remove_pid_file()?;
+        result?;
+        println!("Bridge stopped");
+    } else {
+        // Run as background daemon
+        let pid_path = pid_file_path()?;
+        let log_path = log_file_path()?;
+
+        // Ensure parent directories exist
+        if let Some(parent) = pid_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Open/create log file for appending
+        let log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+
+        // Print startup message before daemonizing (parent exits after fork)
+        println!("Starting bridge daemon on port {}...", port);
+        println!("Log file: {}", log_path.display());
+        println!("Stop with: contactcmd bridge stop");
+
+        let daemonize = Daemonize::new()
+            .pid_file(&pid_path)
+            .chown_pid_file(true)
+            .working_directory(".")
+            .stdout(log_file.try_clone()?)
+            .stderr(log_file);
+
+        match daemonize.start() {
+            Ok(_) => {
+                // We're now in the daemon process
+                log_line(&log_path, &format!("Bridge daemon started on port {}", port));
+
+                // Don't share the parent's SQLite connection across the fork
+                let result = Database::open().and_then(|db| run_server(&db, port, secret, token, inbound));
+                match result {
+                    Ok(()) => log_line(&log_path, "Bridge daemon stopped"),
+                    Err(e) => log_line(&log_path, &format!("Bridge daemon error: {}", e)),
                 }
+                remove_pid_file()?;
             }
             Err(e) => {
-                remove_pid_file()?;
-                return Err(e);
+                return Err(anyhow!("Failed to daemonize: {}", e));
             }
         }
+        // Note: Parent process exits after fork, so this is only reached by daemon
+    }
 
-        remove_pid_file()?;
-        println!("Bridge stopped");
-    } else {
-        // Daemonize not implemented yet
-        println!("Background mode not yet implemented. Use --foreground flag.");
-        println!("Example: contactcmd bridge start --foreground");
+    Ok(())
+}
+
+/// Run the HTTP server and its event loop until shutdown.
+fn run_server(
+    db: &Database,
+    port: u16,
+    secret: String,
+    token: Option<String>,
+    inbound: Option<InboundSource>,
+) -> Result<()> {
+    let connection = Connection::default();
+    let server = BridgeServer::new(port, secret, token)
+        .with_db(Database::default_path()?)
+        .with_negotiation(connection.negotiation());
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    // Set up Ctrl+C handler
+    ctrlc_handler(shutdown.clone());
+
+    let rx = server.start(shutdown.clone())?;
+    run_events(db, rx, &shutdown, connection, inbound)
+}
+
+/// Append a timestamped line to the daemon log.
+fn log_line(log_path: &Path, message: &str) {
+    if let Ok(mut f) = OpenOptions::new().append(true).open(log_path) {
+        let _ = writeln!(
+            f,
+            "[{}] {}",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
+            message
+        );
+    }
+}
+
+/// Queue outbound messages for approval, report their results to Moltbot and
+/// forward inbound messages until the server shuts down or a kill request arrives.
+fn run_events(
+    db: &Database,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<BridgeEvent>,
+    shutdown: &AtomicBool,
+    mut connection: Connection,
+    inbound: Option<InboundSource>,
+) -> Result<()> {
+    let checks = SendChecks::load(db)?;
+    let key = outbound::bridge_api_key(db)?;
+    println!("Outbound messages are queued for approval as API key '{}'", key.name);
+
+    let mut pump = match inbound.map(|config| InboundPump::open(db, config)).transpose() {
+        Ok(pump) => pump,
+        Err(e) => {
+            eprintln!("Inbound forwarding disabled: {}", e);
+            None
+        }
+    };
+    if let Some(ref pump) = pump {
+        println!("Forwarding inbound messages from {} source", pump.name());
     }
 
+    let mut last_report: Option<Instant> = None;
+    let mut unrecorded: Vec<OutboundResult> = Vec::new();
+    loop {
+        match rx.try_recv() {
+            Ok(BridgeEvent::OutboundMessage(msg)) => {
+                match outbound::queue_outbound(db, &checks, &msg) {
+                    Ok(Some(status)) => println!(
+                        "Outbound message {} -> {} via {}: {}",
+                        msg.id, msg.recipient, msg.channel, status
+                    ),
+                    Ok(None) => println!("Outbound message {} already received, ignoring", msg.id),
+                    Err(e) => {
+                        eprintln!("Outbound message {} could not be recorded: {}", msg.id, e);
+                        unrecorded.push(OutboundResult {
+                            id: msg.id,
+                            status: OutboundStatus::Rejected,
+                            error: Some(format!("Could not record message: {}", e)),
+                            sent_at: None,
+                        });
+                    }
+                }
+                // Tell Moltbot straight away
+                last_report = None;
+                continue;
+            }
+            Ok(BridgeEvent::Kill { reason }) => {
+                println!(
+                    "Kill request received: {}",
+                    reason.unwrap_or_else(|| "no reason".to_string())
+                );
+                shutdown.store(true, Ordering::SeqCst);
+                break;
+            }
+            Ok(BridgeEvent::Error(e)) => {
+                eprintln!("Bridge error: {}", e);
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => break,
+            Err(TryRecvError::Empty) => {}
+        }
+
+        // Save rejections so they survive a restart; those that still can't be
+        // saved are told to Moltbot directly
+        unrecorded.retain(|result| {
+            let error = result.error.as_deref().unwrap_or_default();
+            db.reject_bridge_outbound(&result.id, error).is_err()
+        });
+
+        // Results wait while Moltbot is away and go out once it's back
+        let was_connected = connection.state() == ConnectionState::Connected;
+        if let Some(client) = connection.tick(db, || moltbot_client(db))? {
+            if !was_connected || !last_report.is_some_and(|t| t.elapsed() < REPORT_INTERVAL) {
+                last_report = Some(Instant::now());
+                let reported = unrecorded
+                    .iter()
+                    .try_for_each(|result| client.report_result(result))
+                    .and_then(|()| {
+                        unrecorded.clear();
+                        outbound::report_results(db, client)
+                    });
+                if let Err(e) = reported {
+                    connection.lost(db, &format!("could not report results: {}", e))?;
+                    continue;
+                }
+            }
+            if let Some(ref mut pump) = pump {
+                match pump.pump(db, client) {
+                    Ok(0) => {}
+                    Ok(n) => println!("Forwarded {} inbound message(s) to Moltbot", n),
+                    Err(e) => {
+                        connection.lost(db, &format!("could not forward inbound: {}", e))?
+                    }
+                }
+            }
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
     Ok(())
 }
 
+/// Client for Moltbot using the configured port and credentials.
+///
+/// During a rotation requests use the previous secret and token, which Moltbot
+/// is known to have, until it signs a request with the new secret.
+fn moltbot_client(db: &Database) -> Result<BridgeClient> {
+    let secrets = load_secrets(db)?.ok_or_else(|| anyhow!("No shared secret configured"))?;
+    let token = match secrets.previous {
+        Some(_) => db.get_setting(SETTING_BRIDGE_PREVIOUS_TOKEN)?,
+        None => db.get_setting(SETTING_BRIDGE_TOKEN)?,
+    };
+    let port = db
+        .get_setting(SETTING_BRIDGE_MOLTBOT_PORT)?
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_MOLTBOT_PORT);
+    BridgeClient::new(port, secrets.for_signing().to_string(), token)
+}
+
 /// Stop the bridge server.
 fn stop_bridge() -> Result<()> {
     match read_pid_file()? {
@@ -212,8 +430,9 @@ fn show_status(db: &Database) -> Result<()> {
     println!("─────────────");
 
     // Check if running
+    let running = matches!(read_pid_file()?, Some(pid) if is_process_running(pid));
     match read_pid_file()? {
-        Some(pid) if is_process_running(pid) => {
+        Some(pid) if running => {
             println!("Status:       Running (PID {})", pid);
         }
         Some(_) => {
@@ -236,6 +455,38 @@ fn show_status(db: &Database) -> Result<()> {
     println!("Token:        {}", if has_token { "configured" } else { "not set" });
     println!("Moltbot port: {}", moltbot_port);
 
+    // Connection state as last saved by the running bridge
+    if let Some(conn) = ConnectionStatus::load(db)?.filter(|_| running) {
+        let fmt = |at: chrono::DateTime<chrono::Utc>| at.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+        println!("Connection:   {} since {}", conn.state, fmt(conn.since));
+        if let Some(at) = conn.last_handshake_at {
+            println!("Handshake:    {}", fmt(at));
+        }
+        if let Some(ref version) = conn.peer_version {
+            println!("Peer version: {}", version);
+        }
+        if !conn.capabilities.is_empty() {
+            println!("Capabilities: {}", conn.capabilities.join(", "));
+        }
+        if conn.last_handshake_at.is_some() {
+            let features = if conn.features.is_empty() {
+                "none".to_string()
+            } else {
+                conn.features.join(", ")
+            };
+            println!("Features:     {}", features);
+        }
+        if let Some(at) = conn.session_expires_at {
+            println!("Session ends: {}", fmt(at));
+        }
+        if let Some(ref error) = conn.last_error {
+            println!("Last error:   {}", error);
+        }
+        if let Some(at) = conn.next_attempt_at {
+            println!("Next attempt: {}", fmt(at));
+        }
+    }
+
     // Try to check Moltbot health
     if let Some(secret) = db.get_setting(SETTING_BRIDGE_SECRET)? {
         let token = db.get_setting(SETTING_BRIDGE_TOKEN)?;
@@ -265,16 +516,19 @@ fn configure_bridge(
     moltbot_port: Option<u16>,
     show: bool,
     generate: bool,
+    grace: &str,
 ) -> Result<()> {
+    let grace = keys::parse_duration(grace)?;
+
     // Generate new credentials if requested
     if generate {
         let new_secret = generate_secret();
         let new_token = generate_token();
-        db.set_setting(SETTING_BRIDGE_SECRET, &new_secret)?;
-        db.set_setting(SETTING_BRIDGE_TOKEN, &new_token)?;
+        let until = rotate_credentials(db, Some(&new_secret), Some(&new_token), grace)?;
         println!("Generated new credentials:");
         println!("  Secret: {}", new_secret);
         println!("  Token:  {}", new_token);
+        print_rotation(until);
         return Ok(());
     }
 
@@ -282,15 +536,15 @@ fn configure_bridge(
     let mut changed = false;
 
     // Set individual values
-    if let Some(ref s) = secret {
-        db.set_setting(SETTING_BRIDGE_SECRET, s)?;
-        println!("Secret updated");
-        changed = true;
-    }
-
-    if let Some(ref t) = token {
-        db.set_setting(SETTING_BRIDGE_TOKEN, t)?;
-        println!("Token updated");
+    if secret.is_some() || token.is_some() {
+        let until = rotate_credentials(db, secret.as_deref(), token.as_deref(), grace)?;
+        if secret.is_some() {
+            println!("Secret updated");
+        }
+        if token.is_some() {
+            println!("Token updated");
+        }
+        print_rotation(until);
         changed = true;
     }
 
@@ -315,6 +569,10 @@ fn configure_bridge(
             None => println!("Token:        (not set)"),
         }
 
+        if let Some(until) = rotation_expires(db)? {
+            println!("Rotation:     previous secret accepted until {}", until.format("%Y-%m-%d %H:%M UTC"));
+        }
+
         let port = db
             .get_setting(SETTING_BRIDGE_MOLTBOT_PORT)?
             .unwrap_or_else(|| DEFAULT_MOLTBOT_PORT.to_string());
@@ -324,6 +582,91 @@ fn configure_bridge(
     Ok(())
 }
 
+/// Replace the secret and/or token, keeping the old ones working for `grace`
+/// so a running Moltbot isn't cut off. Returns when the old ones stop working.
+fn rotate_credentials(
+    db: &Database,
+    secret: Option<&str>,
+    token: Option<&str>,
+    grace: chrono::Duration,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+    let until = (grace > chrono::Duration::zero()).then(|| chrono::Utc::now() + grace);
+    match until {
+        Some(until) => {
+            // Mid-rotation, the peer still has the credentials from before it started
+            if rotation_expires(db)?.is_none() {
+                for (key, previous_key) in [
+                    (SETTING_BRIDGE_SECRET, SETTING_BRIDGE_PREVIOUS_SECRET),
+                    (SETTING_BRIDGE_TOKEN, SETTING_BRIDGE_PREVIOUS_TOKEN),
+                ] {
+                    match db.get_setting(key)? {
+                        Some(value) => db.set_setting(previous_key, &value)?,
+                        None => db.delete_setting(previous_key)?,
+                    }
+                }
+            }
+            db.set_setting(SETTING_BRIDGE_ROTATION_EXPIRES, &until.to_rfc3339())?;
+        }
+        None => complete_rotation(db)?,
+    }
+
+    if let Some(secret) = secret {
+        db.set_setting(SETTING_BRIDGE_SECRET, secret)?;
+    }
+    if let Some(token) = token {
+        db.set_setting(SETTING_BRIDGE_TOKEN, token)?;
+    }
+    Ok(until)
+}
+
+fn print_rotation(until: Option<chrono::DateTime<chrono::Utc>>) {
+    match until {
+        Some(until) => println!(
+            "The previous secret and token keep working until {}, or until Moltbot signs with the new secret.",
+            until.format("%Y-%m-%d %H:%M UTC")
+        ),
+        None => println!("The previous secret and token no longer work."),
+    }
+}
+
+/// End of the current rotation's grace period, if one is in progress.
+fn rotation_expires(db: &Database) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+    Ok(db
+        .get_setting(SETTING_BRIDGE_ROTATION_EXPIRES)?
+        .and_then(|at| chrono::DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| at.with_timezone(&chrono::Utc))
+        .filter(|at| *at > chrono::Utc::now()))
+}
+
+/// Forget the credentials a rotation replaced.
+fn complete_rotation(db: &Database) -> Result<()> {
+    db.delete_setting(SETTING_BRIDGE_PREVIOUS_SECRET)?;
+    db.delete_setting(SETTING_BRIDGE_PREVIOUS_TOKEN)?;
+    db.delete_setting(SETTING_BRIDGE_ROTATION_EXPIRES)
+}
+
+/// The configured secret, plus the previous one while a rotation is in progress.
+fn load_secrets(db: &Database) -> Result<Option<SigningSecrets>> {
+    let Some(current) = db.get_setting(SETTING_BRIDGE_SECRET)? else {
+        return Ok(None);
+    };
+    let previous = match rotation_expires(db)? {
+        Some(_) => db.get_setting(SETTING_BRIDGE_PREVIOUS_SECRET)?,
+        None => None,
+    };
+    Ok(Some(SigningSecrets { current, previous }))
+}
+
+/// Tokens Moltbot may present: the configured one, plus the previous one
+/// while a rotation is in progress. Empty when no token is required.
+fn accepted_tokens(db: &Database) -> Result<Vec<String>> {
+    let mut tokens: Vec<String> = db.get_setting(SETTING_BRIDGE_TOKEN)?.into_iter().collect();
+    if rotation_expires(db)?.is_some() {
+        tokens.extend(db.get_setting(SETTING_BRIDGE_PREVIOUS_TOKEN)?);
+    }
+    Ok(tokens)
+}
+
 // PID file management
 
 fn pid_file_path() -> Result<PathBuf> {
@@ -332,6 +675,12 @@ fn pid_file_path() -> Result<PathBuf> {
     Ok(config_dir.join("contactcmd").join("bridge.pid"))
 }
 
+fn log_file_path() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| anyhow!("Could not find config directory"))?;
+    Ok(config_dir.join("contactcmd").join("bridge.log"))
+}
+
 fn write_pid_file(pid: u32) -> Result<()> {
     let path = pid_file_path()?;
     if let Some(parent) = path.parent() {
@@ -0,0 +1,111 @@
+//! Replay protection for signed bridge requests.
+//!
+//! Every signed request carries a nonce covered by its signature. A nonce is
+//! remembered for as long as its timestamp could still pass the drift check,
+//! so a captured request can't be sent again. Recent nonces are kept in memory
+//! (bounded) and in the database, so a restart doesn't reopen the window.
+
+use anyhow::{anyhow, Result};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+use super::signing::MAX_TIMESTAMP_DRIFT_SECS;
+use crate::db::Database;
+
+/// How long a nonce is remembered: a request timestamped at the edge of the
+/// future drift window stays valid until the edge of the past one.
+pub const NONCE_TTL_SECS: u64 = 2 * MAX_TIMESTAMP_DRIFT_SECS;
+
+/// Nonces kept in memory; older ones are still caught by the database.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Nonces seen recently, oldest first.
+#[derive(Default)]
+struct Seen {
+    nonces: HashSet<String>,
+    order: VecDeque<(u64, String)>,
+}
+
+/// Remembers nonces of verified requests and refuses repeats.
+pub struct NonceCache {
+    seen: Mutex<Seen>,
+    capacity: usize,
+}
+
+impl NonceCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            seen: Mutex::new(Seen::default()),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Record the nonce of a request whose signature checked out, or fail if
+    /// it was used before. `now` is Unix seconds.
+    pub fn check(&self, nonce: &str, now: u64, db: Option<&Database>) -> Result<()> {
+        let mut seen = self
+            .seen
+            .lock()
+            .map_err(|_| anyhow!("Nonce cache poisoned"))?;
+
+        // Forget nonces that can no longer pass the timestamp check
+        while let Some((at, _)) = seen.order.front() {
+            if now.saturating_sub(*at) <= NONCE_TTL_SECS && seen.order.len() < self.capacity {
+                break;
+            }
+            if let Some((_, old)) = seen.order.pop_front() {
+                seen.nonces.remove(&old);
+            }
+        }
+
+        let replayed = || anyhow!("Request replayed: nonce '{}' was already used", nonce);
+        if seen.nonces.contains(nonce) {
+            return Err(replayed());
+        }
+        if let Some(db) = db {
+            let expire_before = now.saturating_sub(NONCE_TTL_SECS) as i64;
+            if !db.record_bridge_nonce(nonce, now as i64, expire_before)? {
+                return Err(replayed());
+            }
+        }
+
+        seen.nonces.insert(nonce.to_string());
+        seen.order.push_back((now, nonce.to_string()));
+        Ok(())
+    }
+}
+
+impl Default for NonceCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replays_refused() {
+        let cache = NonceCache::new(2);
+        cache.check("a", 1000, None).unwrap();
+        let err = cache.check("a", 1001, None).unwrap_err();
+        assert!(err.to_string().contains("replayed"));
+
+        // Past the TTL a nonce is forgotten (its timestamp would be rejected anyway)
+        cache.check("a", 1000 + NONCE_TTL_SECS + 1, None).unwrap();
+
+        // The memory bound evicts the oldest nonce; the database still has it
+        let db = Database::open_memory().unwrap();
+        let cache = NonceCache::new(2);
+        for nonce in ["x", "y", "z"] {
+            cache.check(nonce, 2000, Some(&db)).unwrap();
+        }
+        assert!(cache.check("x", 2001, Some(&db)).is_err());
+
+        // A fresh cache (after a restart) still refuses what the database saw
+        let restarted = NonceCache::new(2);
+        assert!(restarted.check("y", 2002, Some(&db)).is_err());
+        restarted.check("w", 2002, Some(&db)).unwrap();
+    }
+}
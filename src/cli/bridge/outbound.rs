@@ -0,0 +1,236 @@
+//! Delivery of messages Moltbot asks the bridge to send.
+//!
+//! Outbound messages go into the gateway approval queue under the bridge's own
+//! API key, with the same checks as `/gateway/send`, and are sent by the
+//! gateway once a human approves them. Their progress is reported back to
+//! Moltbot with signed callbacks until each reaches a final status.
+
+use anyhow::Result;
+use serde_json::json;
+
+use super::client::BridgeClient;
+use super::types::{OutboundMessage, OutboundResult, OutboundStatus};
+use crate::cli::gateway::audit::{self, AuditAction};
+use crate::cli::gateway::keys;
+use crate::cli::gateway::types::SendRequest;
+use crate::cli::gateway::intake::{self, SendChecks};
+use crate::db::gateway::ApiKey;
+use crate::db::Database;
+
+/// Settings key for the ID of the gateway API key bridge messages are queued under.
+const SETTING_BRIDGE_API_KEY: &str = "bridge_api_key_id";
+
+/// Name of the API key created for the bridge.
+const BRIDGE_KEY_NAME: &str = "moltbot-bridge";
+
+/// The gateway API key bridge messages are queued under, created on first use.
+///
+/// The key's secret is never shown, so it can't be used over HTTP; manage its
+/// allowlist, scopes, limits and webhook with `gateway keys` like any other.
+pub fn bridge_api_key(db: &Database) -> Result<ApiKey> {
+    if let Some(id) = db.get_setting(SETTING_BRIDGE_API_KEY)? {
+        if let Some(key) = db.list_api_keys()?.into_iter().find(|k| k.id == id) {
+            return Ok(key);
+        }
+    }
+
+    let (_, key_hash, key_prefix) = keys::generate_api_key();
+    let id = uuid::Uuid::new_v4().to_string();
+    db.insert_api_key(&id, BRIDGE_KEY_NAME, &key_hash, &key_prefix)?;
+    audit::record(
+        db,
+        AuditAction::KeyCreated,
+        audit::ACTOR_BRIDGE,
+        Some(&id),
+        json!({ "name": BRIDGE_KEY_NAME, "key_prefix": key_prefix }),
+    )?;
+    db.set_setting(SETTING_BRIDGE_API_KEY, &id)?;
+    println!(
+        "Created gateway API key '{}' for bridge messages",
+        BRIDGE_KEY_NAME
+    );
+
+    db.list_api_keys()?
+        .into_iter()
+        .find(|k| k.id == id)
+        .ok_or_else(|| anyhow::anyhow!("Bridge API key was not saved"))
+}
+
+/// Queue an outbound message for approval and record it for reporting.
+///
+/// Messages whose ID was already seen are ignored, so Moltbot can safely
+/// resend after a timeout. A message that can't be queued for any reason is
+/// recorded as rejected, so Moltbot hears why instead of the message being
+/// stuck. Returns the status to report.
+pub fn queue_outbound(
+    db: &Database,
+    checks: &SendChecks,
+    msg: &OutboundMessage,
+) -> Result<Option<OutboundStatus>> {
+    if !db.insert_bridge_outbound(&msg.id)? {
+        return Ok(None);
+    }
+
+    let (queue_id, status, error) = match submit(db, checks, msg) {
+        Ok(Ok(action_id)) => (Some(action_id), OutboundStatus::Queued, None),
+        Ok(Err(error)) => (None, OutboundStatus::Rejected, Some(error)),
+        Err(e) => (None, OutboundStatus::Rejected, Some(format!("Could not queue: {}", e))),
+    };
+    db.set_bridge_outbound_status(
+        &msg.id,
+        queue_id.as_deref(),
+        &status.to_string(),
+        error.as_deref(),
+    )?;
+    Ok(Some(status))
+}
+
+/// Hand the message to the gateway; the error explains a refusal.
+fn submit(
+    db: &Database,
+    checks: &SendChecks,
+    msg: &OutboundMessage,
+) -> Result<std::result::Result<String, String>> {
+    let key = bridge_api_key(db)?;
+    if key.revoked_at.is_some() {
+        return Ok(Err("Bridge API key has been revoked".to_string()));
+    }
+    if key.is_expired(chrono::Utc::now()) {
+        return Ok(Err("Bridge API key has expired".to_string()));
+    }
+
+    let mut context = json!({ "source": "moltbot", "bridge_message_id": msg.id });
+    if let Some(ref reply_to) = msg.reply_to {
+        context["reply_to"] = json!(reply_to);
+    }
+    let body = json!({
+        "channel": msg.channel.to_string(),
+        "recipient_address": msg.recipient,
+        "body": msg.content,
+        "context": context,
+    });
+    let req = match SendRequest::from_json(body.to_string().as_bytes()) {
+        Ok(req) => req,
+        Err(error) => return Ok(Err(error.error)),
+    };
+
+    Ok(intake::submit(db, checks, &key, req)?
+        .map(|sent| sent.action_id)
+        .map_err(|body| rejection_message(&body)))
+}
+
+/// The message from a gateway error body; validation errors already list their fields.
+fn rejection_message(body: &serde_json::Value) -> String {
+    body["error"]
+        .as_str()
+        .unwrap_or("Rejected by the gateway")
+        .to_string()
+}
+
+/// Results Moltbot hasn't heard yet: each message's status when it changed
+/// since the last report.
+pub fn pending_results(db: &Database) -> Result<Vec<OutboundResult>> {
+    let results = db
+        .list_unreported_bridge_outbound()?
+        .into_iter()
+        .filter(|e| e.status != "received")
+        .filter_map(|e| {
+            let status = OutboundStatus::from_queue_status(&e.status);
+            if e.reported_status.as_deref() == Some(status.to_string().as_str()) {
+                return None;
+            }
+            // Retry errors on queued messages are the gateway's business
+            let error = match status {
+                OutboundStatus::Failed | OutboundStatus::Rejected => e.error,
+                _ => None,
+            };
+            Some(OutboundResult {
+                id: e.message_id,
+                status,
+                error,
+                sent_at: e.sent_at.map(|at| at.to_rfc3339()),
+            })
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Send pending results to Moltbot, stopping at the first failure so the rest
+/// are retried in order. Returns how many were delivered.
+pub fn report_results(db: &Database, client: &BridgeClient) -> Result<usize> {
+    let mut delivered = 0;
+    for result in pending_results(db)? {
+        client.report_result(&result)?;
+        db.mark_bridge_outbound_reported(&result.id, &result.status.to_string())?;
+        delivered += 1;
+    }
+    Ok(delivered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::bridge::types::BridgeChannel;
+    use crate::models::{Person, Phone};
+
+    fn outbound(id: &str, recipient: &str) -> OutboundMessage {
+        OutboundMessage {
+            id: id.to_string(),
+            recipient: recipient.to_string(),
+            content: "Running ten minutes late".to_string(),
+            channel: BridgeChannel::Sms,
+            reply_to: None,
+        }
+    }
+
+    #[test]
+    fn test_outbound_goes_through_approval_queue() {
+        let db = Database::open_memory().unwrap();
+        let checks = SendChecks::load(&db).unwrap();
+
+        assert_eq!(
+            queue_outbound(&db, &checks, &outbound("mb-1", "+15551234567")).unwrap(),
+            Some(OutboundStatus::Queued)
+        );
+        // Resending the same ID doesn't queue it twice
+        assert_eq!(
+            queue_outbound(&db, &checks, &outbound("mb-1", "+15551234567")).unwrap(),
+            None
+        );
+        assert_eq!(db.count_pending_queue().unwrap(), 1);
+
+        // Contacts who opted out of AI contact are refused before queueing
+        let mut person = Person::new();
+        person.ai_contact_allowed = false;
+        db.insert_person(&person).unwrap();
+        db.insert_phone(&Phone::new(person.id, "+15557654321".to_string()))
+            .unwrap();
+        assert_eq!(
+            queue_outbound(&db, &checks, &outbound("mb-2", "+15557654321")).unwrap(),
+            Some(OutboundStatus::Rejected)
+        );
+
+        let results = pending_results(&db).unwrap();
+        let statuses: Vec<(&str, OutboundStatus)> =
+            results.iter().map(|r| (r.id.as_str(), r.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("mb-1", OutboundStatus::Queued),
+                ("mb-2", OutboundStatus::Rejected)
+            ]
+        );
+        assert!(results[1].error.as_deref().unwrap().contains("opted out"));
+
+        // Once "queued" is reported, nothing is pending until the message is sent
+        db.mark_bridge_outbound_reported("mb-1", "queued").unwrap();
+        db.mark_bridge_outbound_reported("mb-2", "rejected")
+            .unwrap();
+        assert!(pending_results(&db).unwrap().is_empty());
+        let queue_id = db.list_pending_queue().unwrap()[0].id.clone();
+        db.mark_queue_sent(&queue_id).unwrap();
+        let results = pending_results(&db).unwrap();
+        assert_eq!(results[0].status, OutboundStatus::Sent);
+        assert!(results[0].sent_at.is_some());
+    }
+}
@@ -0,0 +1,230 @@
+//! Bridge protocol version and feature negotiation.
+//!
+//! The protocol is versioned `major.minor`. Peers with the same major version
+//! can talk to each other: a new minor version only adds optional features,
+//! and a new major version changes something existing (paths, signing, message
+//! fields). Version 1.0 is the protocol as documented in
+//! `docs/features/gateway.md`: signed JSON over HTTP with handshake, inbound,
+//! outbound, result, health and kill endpoints.
+//!
+//! Each side lists capabilities in the handshake: the channels it carries
+//! (`imessage`, `sms`) and the optional features below. A feature is used only
+//! when both sides list it, and requests for a feature that wasn't agreed are
+//! refused, except a signed kill, which is always honoured; unknown capabilities
+//! are ignored, so either side can add features without breaking the other.
+
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex};
+
+use super::types::BridgeChannel;
+
+/// Protocol version spoken by this bridge.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+
+/// Version assumed for peers that don't send one (they predate versioning).
+const UNVERSIONED_PEER: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+
+/// Optional features this bridge implements.
+pub const SUPPORTED_FEATURES: &[Feature] = &[Feature::Kill];
+
+/// A `major.minor` protocol version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ProtocolVersion {
+    /// Parse `major.minor` (a bare `major` means `major.0`). A patch component,
+    /// as in `1.0.3`, is accepted and ignored.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().map_or(Some(0), |m| m.parse().ok())?;
+        if let Some(patch) = parts.next() {
+            patch.parse::<u32>().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { major, minor })
+    }
+
+    /// Whether a peer speaking `other` can talk to us.
+    pub fn is_compatible_with(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Optional protocol features, enabled per session by negotiation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Media attached to inbound and outbound messages
+    Attachments,
+    /// Delivery and read receipts for sent messages
+    ReadReceipts,
+    /// Typing indicators
+    Typing,
+    /// Tapbacks and emoji reactions
+    Reactions,
+    /// Remote stop via `/bridge/kill`
+    Kill,
+}
+
+impl Feature {
+    pub const ALL: &'static [Feature] = &[
+        Feature::Attachments,
+        Feature::ReadReceipts,
+        Feature::Typing,
+        Feature::Reactions,
+        Feature::Kill,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Attachments => "attachments",
+            Feature::ReadReceipts => "read_receipts",
+            Feature::Typing => "typing",
+            Feature::Reactions => "reactions",
+            Feature::Kill => "kill",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.as_str() == s)
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Capabilities this bridge advertises in its handshake.
+pub fn local_capabilities() -> Vec<String> {
+    [BridgeChannel::IMessage, BridgeChannel::Sms]
+        .iter()
+        .map(|c| c.to_string())
+        .chain(SUPPORTED_FEATURES.iter().map(|f| f.to_string()))
+        .collect()
+}
+
+/// What both sides agreed on at handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    pub peer_version: ProtocolVersion,
+    /// Features both sides support, in `Feature::ALL` order
+    pub features: Vec<Feature>,
+}
+
+impl Negotiated {
+    pub fn has(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+/// The current session's negotiation, shared between the connection that
+/// handshakes and the server that enforces it. `None` while disconnected.
+pub type SharedNegotiation = Arc<Mutex<Option<Negotiated>>>;
+
+/// Whether the current session agreed on `feature`.
+pub fn is_negotiated(shared: &SharedNegotiation, feature: Feature) -> bool {
+    shared
+        .lock()
+        .map(|n| n.as_ref().is_some_and(|n| n.has(feature)))
+        .unwrap_or(false)
+}
+
+/// Check the peer's version and agree on the features both sides support.
+pub fn negotiate(peer_version: Option<&str>, peer_capabilities: &[String]) -> Result<Negotiated> {
+    let peer_version = match peer_version {
+        Some(v) => ProtocolVersion::parse(v)
+            .ok_or_else(|| anyhow!("Moltbot sent an invalid protocol version '{}'", v))?,
+        None => UNVERSIONED_PEER,
+    };
+    if !PROTOCOL_VERSION.is_compatible_with(&peer_version) {
+        return Err(anyhow!(
+            "Incompatible protocol: Moltbot speaks {}, this bridge speaks {} (major versions must match)",
+            peer_version,
+            PROTOCOL_VERSION
+        ));
+    }
+
+    let features = Feature::ALL
+        .iter()
+        .copied()
+        .filter(|f| SUPPORTED_FEATURES.contains(f))
+        .filter(|f| {
+            peer_capabilities
+                .iter()
+                .any(|c| Feature::parse(c) == Some(*f))
+        })
+        .collect();
+    Ok(Negotiated {
+        peer_version,
+        features,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        // Same major: features are the intersection, unknown ones ignored
+        let negotiated =
+            negotiate(Some("1.4"), &caps(&["sms", "typing", "kill", "teleport"])).unwrap();
+        assert_eq!(
+            negotiated.peer_version,
+            ProtocolVersion { major: 1, minor: 4 }
+        );
+        assert_eq!(negotiated.features, vec![Feature::Kill]);
+        assert!(!negotiated.has(Feature::Typing));
+
+        // Unversioned peers predate versioning and are treated as 1.0
+        let negotiated = negotiate(None, &[]).unwrap();
+        assert_eq!(negotiated.peer_version, UNVERSIONED_PEER);
+        assert!(negotiated.features.is_empty());
+
+        let err = negotiate(Some("2.0"), &caps(&["kill"])).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Moltbot speaks 2.0, this bridge speaks 1.0"));
+        assert!(negotiate(Some("one"), &[])
+            .unwrap_err()
+            .to_string()
+            .contains("invalid"));
+    }
+
+    #[test]
+    fn test_local_capabilities() {
+        assert_eq!(local_capabilities(), caps(&["imessage", "sms", "kill"]));
+        assert_eq!(
+            ProtocolVersion::parse("3"),
+            Some(ProtocolVersion { major: 3, minor: 0 })
+        );
+        assert_eq!(Feature::parse("read_receipts"), Some(Feature::ReadReceipts));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let v1_2 = Some(ProtocolVersion { major: 1, minor: 2 });
+        assert_eq!(ProtocolVersion::parse("1.2"), v1_2);
+        assert_eq!(ProtocolVersion::parse(" 1.2.7 "), v1_2);
+        assert_eq!(ProtocolVersion::parse("1.2.x"), None);
+        assert_eq!(ProtocolVersion::parse("1.2.3.4"), None);
+        assert_eq!(ProtocolVersion::parse("1."), None);
+    }
+}
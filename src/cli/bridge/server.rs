@@ -1,15 +1,20 @@
 //! HTTP server for receiving messages from Moltbot.
 
 use anyhow::{anyhow, Result};
-use std::io::{BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::mpsc;
 
-use super::signing::verify_signature;
+use super::nonce::NonceCache;
+use super::protocol::{self, Feature, SharedNegotiation, PROTOCOL_VERSION};
+use super::signing::SigningSecrets;
 use super::types::{BridgeApiResponse, HealthStatus, KillRequest, OutboundMessage};
+use crate::cli::http_server::{self, Handler, HttpRequest, HttpResponse, ServerLimits};
+use crate::db::Database;
 
 /// Events emitted by the bridge server.
 #[derive(Debug)]
@@ -25,9 +30,14 @@ pub enum BridgeEvent {
 /// HTTP server for receiving messages from Moltbot.
 pub struct BridgeServer {
     port: u16,
-    secret: String,
+    secrets: SigningSecrets,
     token: Option<String>,
     start_time: Instant,
+    nonces: NonceCache,
+    /// When set, nonces are kept on disk and secrets re-read per request
+    db_path: Option<PathBuf>,
+    /// Features agreed with Moltbot at the last handshake
+    negotiated: SharedNegotiation,
 }
 
 impl BridgeServer {
@@ -40,127 +50,105 @@ impl BridgeServer {
     pub fn new(port: u16, secret: String, token: Option<String>) -> Self {
         Self {
             port,
-            secret,
+            secrets: SigningSecrets::new(secret),
             token,
             start_time: Instant::now(),
+            nonces: NonceCache::default(),
+            db_path: None,
+            negotiated: SharedNegotiation::default(),
         }
     }
 
-    /// Start the server, returning an event channel and a shutdown flag.
+    /// Keep seen nonces in the database and pick up secret rotations from its
+    /// settings without a restart.
+    pub fn with_db(mut self, db_path: PathBuf) -> Self {
+        self.db_path = Some(db_path);
+        self
+    }
+
+    /// Honour optional features only as negotiated by the connection's handshake.
     ///
-    /// The server runs in the current thread (blocking).
-    pub fn start(
-        &self,
-        shutdown: Arc<AtomicBool>,
-    ) -> Result<mpsc::UnboundedReceiver<BridgeEvent>> {
-        let (tx, rx) = mpsc::unbounded_channel();
+    /// Without this no optional feature is ever agreed. `/bridge/kill` is the
+    /// exception: it's honoured whenever it's properly signed.
+    pub fn with_negotiation(mut self, negotiated: SharedNegotiation) -> Self {
+        self.negotiated = negotiated;
+        self
+    }
 
+    /// Start the server on a background thread, returning its event channel.
+    ///
+    /// The channel closes once the server has shut down.
+    pub fn start(self, shutdown: Arc<AtomicBool>) -> Result<mpsc::UnboundedReceiver<BridgeEvent>> {
         let listener = TcpListener::bind(format!("127.0.0.1:{}", self.port))?;
-        listener.set_nonblocking(true)?;
-
         println!("Bridge server listening on localhost:{}", self.port);
+        self.start_on(listener, shutdown)
+    }
+
+    /// Start the server on an already bound listener (e.g. an ephemeral port).
+    pub fn start_on(
+        self,
+        listener: TcpListener,
+        shutdown: Arc<AtomicBool>,
+    ) -> Result<mpsc::UnboundedReceiver<BridgeEvent>> {
+        let (tx, rx) = mpsc::unbounded_channel();
 
-        while !shutdown.load(Ordering::SeqCst) {
-            match listener.accept() {
-                Ok((stream, _)) => {
-                    if let Err(e) = self.handle_connection(stream, &tx) {
-                        let _ = tx.send(BridgeEvent::Error(e.to_string()));
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    // No connection ready, sleep briefly
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                }
-                Err(e) => {
-                    let _ = tx.send(BridgeEvent::Error(format!("Accept error: {}", e)));
-                }
+        let handler = BridgeHandler {
+            server: self,
+            tx: tx.clone(),
+        };
+        std::thread::spawn(move || {
+            if let Err(e) = http_server::serve(
+                listener,
+                Arc::new(handler),
+                ServerLimits::default(),
+                None,
+                shutdown,
+            ) {
+                let _ = tx.send(BridgeEvent::Error(e.to_string()));
             }
-        }
+            println!("Bridge server shutting down");
+        });
 
-        println!("Bridge server shutting down");
         Ok(rx)
     }
 
-    fn handle_connection(
+    fn route(
         &self,
-        mut stream: TcpStream,
+        request: &HttpRequest,
         tx: &mpsc::UnboundedSender<BridgeEvent>,
-    ) -> Result<()> {
-        stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
-        stream.set_write_timeout(Some(std::time::Duration::from_secs(30)))?;
-
-        let mut reader = BufReader::new(stream.try_clone()?);
-        let mut request_line = String::new();
-        reader.read_line(&mut request_line)?;
-
-        let parts: Vec<&str> = request_line.trim().split_whitespace().collect();
-        if parts.len() < 2 {
-            return self.send_response(&mut stream, 400, "Bad Request");
-        }
-
-        let method = parts[0];
-        let path = parts[1];
-
-        // Parse headers
-        let mut headers = std::collections::HashMap::new();
-        let mut content_length = 0usize;
-
-        loop {
-            let mut header_line = String::new();
-            reader.read_line(&mut header_line)?;
-            let header_line = header_line.trim();
-            if header_line.is_empty() {
-                break;
-            }
-            if let Some((key, value)) = header_line.split_once(':') {
-                let key = key.trim().to_lowercase();
-                let value = value.trim().to_string();
-                if key == "content-length" {
-                    content_length = value.parse().unwrap_or(0);
-                }
-                headers.insert(key, value);
-            }
-        }
-
-        // Read body
-        let mut body = vec![0u8; content_length];
-        if content_length > 0 {
-            std::io::Read::read_exact(&mut reader, &mut body)?;
-        }
-
-        // Route request
-        match (method, path) {
-            ("GET", "/bridge/health") => self.handle_health(&mut stream),
-            ("POST", "/bridge/outbound") => {
-                self.handle_outbound(&mut stream, &headers, &body, tx)
-            }
-            ("POST", "/bridge/kill") => self.handle_kill(&mut stream, &headers, &body, tx),
-            _ => self.send_response(&mut stream, 404, "Not Found"),
+    ) -> Result<HttpResponse> {
+        let headers = &request.headers;
+        let body = request.body.as_slice();
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/bridge/health") => self.handle_health(),
+            ("POST", "/bridge/outbound") => self.handle_outbound(headers, body, tx),
+            ("POST", "/bridge/kill") => self.handle_kill(headers, body, tx),
+            _ => Ok(HttpResponse::text(404, "Not Found")),
         }
     }
 
-    fn handle_health(&self, stream: &mut TcpStream) -> Result<()> {
+    fn handle_health(&self) -> Result<HttpResponse> {
         let status = HealthStatus {
             status: "ok".to_string(),
             uptime_secs: self.start_time.elapsed().as_secs(),
-            version: "1.0".to_string(),
+            version: PROTOCOL_VERSION.to_string(),
         };
 
         let response: BridgeApiResponse<HealthStatus> = BridgeApiResponse::ok(status);
-        self.send_json_response(stream, 200, &response)
+        self.send_json_response(200, &response)
     }
 
     fn handle_outbound(
         &self,
-        stream: &mut TcpStream,
-        headers: &std::collections::HashMap<String, String>,
+        headers: &HashMap<String, String>,
         body: &[u8],
         tx: &mpsc::UnboundedSender<BridgeEvent>,
-    ) -> Result<()> {
+    ) -> Result<HttpResponse> {
         // Verify signature
         if let Err(e) = self.verify_request(headers, body) {
             let response: BridgeApiResponse<()> = BridgeApiResponse::err(e.to_string());
-            return self.send_json_response(stream, 401, &response);
+            return self.send_json_response(401, &response);
         }
 
         // Parse message
@@ -169,7 +157,7 @@ impl BridgeServer {
             Err(e) => {
                 let response: BridgeApiResponse<()> =
                     BridgeApiResponse::err(format!("Invalid message format: {}", e));
-                return self.send_json_response(stream, 400, &response);
+                return self.send_json_response(400, &response);
             }
         };
 
@@ -177,20 +165,25 @@ impl BridgeServer {
         let _ = tx.send(BridgeEvent::OutboundMessage(message));
 
         let response: BridgeApiResponse<()> = BridgeApiResponse::ok(());
-        self.send_json_response(stream, 200, &response)
+        self.send_json_response(200, &response)
     }
 
     fn handle_kill(
         &self,
-        stream: &mut TcpStream,
-        headers: &std::collections::HashMap<String, String>,
+        headers: &HashMap<String, String>,
         body: &[u8],
         tx: &mpsc::UnboundedSender<BridgeEvent>,
-    ) -> Result<()> {
+    ) -> Result<HttpResponse> {
         // Verify signature
         if let Err(e) = self.verify_request(headers, body) {
             let response: BridgeApiResponse<()> = BridgeApiResponse::err(e.to_string());
-            return self.send_json_response(stream, 401, &response);
+            return self.send_json_response(401, &response);
+        }
+
+        // A signed kill is always honoured, even between sessions: that's when
+        // an operator is most likely to need it
+        if !protocol::is_negotiated(&self.negotiated, Feature::Kill) {
+            eprintln!("Note: stopping on a kill from Moltbot although this session didn't negotiate 'kill'");
         }
 
         // Parse kill request
@@ -202,20 +195,29 @@ impl BridgeServer {
         });
 
         let response: BridgeApiResponse<()> = BridgeApiResponse::ok(());
-        self.send_json_response(stream, 200, &response)
+        self.send_json_response(200, &response)
     }
 
     fn verify_request(
         &self,
-        headers: &std::collections::HashMap<String, String>,
+        headers: &HashMap<String, String>,
         body: &[u8],
     ) -> Result<()> {
+        let db = match self.db_path {
+            Some(ref path) => Some(Database::open_at(path.clone())?),
+            None => None,
+        };
+
         // Check token if configured
-        if let Some(ref expected_token) = self.token {
+        let tokens = match db {
+            Some(ref db) => super::accepted_tokens(db)?,
+            None => self.token.iter().cloned().collect(),
+        };
+        if !tokens.is_empty() {
             let token = headers
                 .get("x-bridge-token")
                 .ok_or_else(|| anyhow!("Missing X-Bridge-Token header"))?;
-            if token != expected_token {
+            if !tokens.contains(token) {
                 return Err(anyhow!("Invalid token"));
             }
         }
@@ -225,58 +227,48 @@ impl BridgeServer {
             .get("x-bridge-timestamp")
             .ok_or_else(|| anyhow!("Missing X-Bridge-Timestamp header"))?;
 
+        let nonce = headers
+            .get("x-bridge-nonce")
+            .ok_or_else(|| anyhow!("Missing X-Bridge-Nonce header"))?;
+
         let signature = headers
             .get("x-bridge-signature")
             .ok_or_else(|| anyhow!("Missing X-Bridge-Signature header"))?;
 
-        verify_signature(&self.secret, timestamp, body, signature)
-    }
-
-    fn send_response(&self, stream: &mut TcpStream, status: u16, message: &str) -> Result<()> {
-        let status_text = match status {
-            200 => "OK",
-            400 => "Bad Request",
-            401 => "Unauthorized",
-            404 => "Not Found",
-            500 => "Internal Server Error",
-            _ => "Unknown",
+        let secrets = match db {
+            Some(ref db) => super::load_secrets(db)?.unwrap_or_else(|| self.secrets.clone()),
+            None => self.secrets.clone(),
         };
+        let signed_with_current = secrets.verify(timestamp, nonce, body, signature)?;
 
-        let response = format!(
-            "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-            status, status_text, message.len(), message
-        );
+        // Only authentic requests reach the cache, so it can't be flooded
+        self.nonces.check(nonce, super::signing::unix_now(), db.as_ref())?;
 
-        stream.write_all(response.as_bytes())?;
-        stream.flush()?;
+        // Moltbot has the new secret, so stop signing with the old one
+        if let (true, Some(_), Some(db)) = (signed_with_current, &secrets.previous, &db) {
+            super::complete_rotation(db)?;
+            println!("Moltbot signed with the new secret; secret rotation complete");
+        }
         Ok(())
     }
 
-    fn send_json_response<T: serde::Serialize>(
-        &self,
-        stream: &mut TcpStream,
-        status: u16,
-        body: &T,
-    ) -> Result<()> {
-        let status_text = match status {
-            200 => "OK",
-            400 => "Bad Request",
-            401 => "Unauthorized",
-            404 => "Not Found",
-            500 => "Internal Server Error",
-            _ => "Unknown",
-        };
-
-        let json_body = serde_json::to_string(body)?;
+    fn send_json_response<T: serde::Serialize>(&self, status: u16, body: &T) -> Result<HttpResponse> {
+        Ok(HttpResponse::json(status, body))
+    }
+}
 
-        let response = format!(
-            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-            status, status_text, json_body.len(), json_body
-        );
+/// Request handler pairing the server with its event channel.
+struct BridgeHandler {
+    server: BridgeServer,
+    tx: mpsc::UnboundedSender<BridgeEvent>,
+}
 
-        stream.write_all(response.as_bytes())?;
-        stream.flush()?;
-        Ok(())
+impl Handler for BridgeHandler {
+    fn handle(&self, request: HttpRequest) -> HttpResponse {
+        self.server.route(&request, &self.tx).unwrap_or_else(|e| {
+            let _ = self.tx.send(BridgeEvent::Error(e.to_string()));
+            HttpResponse::text(500, "Internal Server Error")
+        })
     }
 }
 
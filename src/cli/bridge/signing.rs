@@ -8,7 +8,10 @@ use std::time::{SystemTime, UNIX_EPOCH};
 type HmacSha256 = Hmac<Sha256>;
 
 /// Maximum allowed timestamp drift in seconds (5 minutes).
-const MAX_TIMESTAMP_DRIFT_SECS: u64 = 300;
+pub const MAX_TIMESTAMP_DRIFT_SECS: u64 = 300;
+
+/// Longest nonce accepted in `X-Bridge-Nonce`.
+const MAX_NONCE_LEN: usize = 128;
 
 /// Compute HMAC-SHA256 signature for a request.
 ///
@@ -78,6 +81,75 @@ pub fn verify_signature(secret: &str, timestamp: &str, body: &[u8], signature: &
     }
 }
 
+/// Sign a bridge request over `timestamp.nonce.body`, so neither the nonce
+/// nor the timestamp can be swapped on a captured request.
+pub fn sign_request(secret: &str, timestamp: &str, nonce: &str, body: &[u8]) -> String {
+    compute_signature(secret, timestamp, &nonce_and_body(nonce, body))
+}
+
+/// Verify a bridge request signed with [`sign_request`].
+///
+/// Replays within the drift window are caught by the nonce cache, not here.
+pub fn verify_request(
+    secret: &str,
+    timestamp: &str,
+    nonce: &str,
+    body: &[u8],
+    signature: &str,
+) -> Result<()> {
+    if nonce.is_empty()
+        || nonce.len() > MAX_NONCE_LEN
+        || !nonce.bytes().all(|b| b.is_ascii_graphic())
+    {
+        return Err(anyhow!(
+            "Invalid X-Bridge-Nonce (1-{} printable ASCII characters)",
+            MAX_NONCE_LEN
+        ));
+    }
+    verify_signature(secret, timestamp, &nonce_and_body(nonce, body), signature)
+}
+
+fn nonce_and_body(nonce: &str, body: &[u8]) -> Vec<u8> {
+    [nonce.as_bytes(), b".", body].concat()
+}
+
+/// The shared secret and, during a rotation, the one it replaced.
+#[derive(Debug, Clone)]
+pub struct SigningSecrets {
+    pub current: String,
+    /// Still accepted until the peer signs with `current` or the grace period ends
+    pub previous: Option<String>,
+}
+
+impl SigningSecrets {
+    pub fn new(current: String) -> Self {
+        Self {
+            current,
+            previous: None,
+        }
+    }
+
+    /// Secret for outgoing requests: the one the peer is known to have.
+    pub fn for_signing(&self) -> &str {
+        self.previous.as_deref().unwrap_or(&self.current)
+    }
+
+    /// Verify a request against either secret. Returns true when signed with `current`.
+    pub fn verify(&self, timestamp: &str, nonce: &str, body: &[u8], signature: &str) -> Result<bool> {
+        match verify_request(&self.current, timestamp, nonce, body, signature) {
+            Ok(()) => Ok(true),
+            Err(e) => match self.previous {
+                Some(ref previous)
+                    if verify_request(previous, timestamp, nonce, body, signature).is_ok() =>
+                {
+                    Ok(false)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+}
+
 /// Constant-time byte comparison to prevent timing attacks.
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
@@ -91,13 +163,17 @@ fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     result == 0
 }
 
-/// Get current Unix timestamp as string.
-pub fn current_timestamp() -> String {
+/// Get current Unix timestamp in seconds.
+pub fn unix_now() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("System time before Unix epoch")
         .as_secs()
-        .to_string()
+}
+
+/// Get current Unix timestamp as string.
+pub fn current_timestamp() -> String {
+    unix_now().to_string()
 }
 
 /// Generate a random shared secret.
@@ -108,6 +184,14 @@ pub fn generate_secret() -> String {
     hex::encode(bytes)
 }
 
+/// Generate a random nonce for `X-Bridge-Nonce`.
+pub fn generate_nonce() -> String {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    let bytes: [u8; 16] = rng.gen();
+    hex::encode(bytes)
+}
+
 /// Generate a random token.
 pub fn generate_token() -> String {
     use rand::Rng;
@@ -182,6 +266,33 @@ mod tests {
         assert!(result.unwrap_err().to_string().contains("Timestamp"));
     }
 
+    #[test]
+    fn test_signing_secrets_rotation() {
+        let timestamp = current_timestamp();
+        let body = b"hello world";
+        let secrets = SigningSecrets {
+            current: "new-secret".to_string(),
+            previous: Some("old-secret".to_string()),
+        };
+        assert_eq!(secrets.for_signing(), "old-secret");
+
+        let with_new = sign_request("new-secret", &timestamp, "n-1", body);
+        let with_old = sign_request("old-secret", &timestamp, "n-1", body);
+        let with_other = sign_request("other-secret", &timestamp, "n-1", body);
+        assert!(secrets.verify(&timestamp, "n-1", body, &with_new).unwrap());
+        assert!(!secrets.verify(&timestamp, "n-1", body, &with_old).unwrap());
+        assert!(secrets.verify(&timestamp, "n-1", body, &with_other).is_err());
+
+        // The nonce is covered by the signature
+        assert!(secrets.verify(&timestamp, "n-2", body, &with_new).is_err());
+        assert!(secrets.verify(&timestamp, "", body, &with_new).is_err());
+
+        // Once the rotation is over only the new secret works
+        let secrets = SigningSecrets::new("new-secret".to_string());
+        assert_eq!(secrets.for_signing(), "new-secret");
+        assert!(secrets.verify(&timestamp, "n-1", body, &with_old).is_err());
+    }
+
     #[test]
     fn test_constant_time_eq() {
         assert!(constant_time_eq(b"hello", b"hello"));
@@ -57,6 +57,63 @@ pub struct OutboundMessage {
     pub reply_to: Option<String>,
 }
 
+/// What happened to an outbound message, as reported back to Moltbot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboundStatus {
+    /// Waiting for human approval, or approved and waiting to send
+    Queued,
+    /// Delivered to the transport
+    Sent,
+    /// Delivery failed
+    Failed,
+    /// A reviewer denied it
+    Denied,
+    /// The gateway refused it (filter, consent, allowlist, rate limit)
+    Rejected,
+}
+
+impl OutboundStatus {
+    /// Status for a gateway queue status; the queue's "rejected" is the bridge's own.
+    pub fn from_queue_status(status: &str) -> Self {
+        match status {
+            "sent" => Self::Sent,
+            "failed" => Self::Failed,
+            "denied" => Self::Denied,
+            "rejected" => Self::Rejected,
+            _ => Self::Queued,
+        }
+    }
+}
+
+impl std::fmt::Display for OutboundStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutboundStatus::Queued => write!(f, "queued"),
+            OutboundStatus::Sent => write!(f, "sent"),
+            OutboundStatus::Failed => write!(f, "failed"),
+            OutboundStatus::Denied => write!(f, "denied"),
+            OutboundStatus::Rejected => write!(f, "rejected"),
+        }
+    }
+}
+
+/// Delivery result for an outbound message.
+/// contactcmd → Moltbot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundResult {
+    /// ID of the outbound message this reports on
+    pub id: String,
+    /// Current status
+    pub status: OutboundStatus,
+    /// Why it failed or was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// RFC 3339 time it was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sent_at: Option<String>,
+}
+
 /// Initial handshake from contactcmd to Moltbot.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HandshakeRequest {
@@ -64,18 +121,18 @@ pub struct HandshakeRequest {
     pub version: String,
     /// Hostname of the Mac
     pub hostname: String,
-    /// Capabilities supported by this bridge
+    /// Channels and optional features supported by this bridge
     pub capabilities: Vec<String>,
 }
 
 impl Default for HandshakeRequest {
     fn default() -> Self {
         Self {
-            version: "1.0".to_string(),
+            version: super::protocol::PROTOCOL_VERSION.to_string(),
             hostname: hostname::get()
                 .map(|h| h.to_string_lossy().to_string())
                 .unwrap_or_else(|_| "unknown".to_string()),
-            capabilities: vec!["imessage".to_string(), "sms".to_string()],
+            capabilities: super::protocol::local_capabilities(),
         }
     }
 }
@@ -91,6 +148,15 @@ pub struct HandshakeResponse {
     /// Error message if not accepted
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Moltbot's protocol version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Channels and optional features Moltbot supports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    /// Seconds until the session token expires; None if it doesn't
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in_secs: Option<u64>,
 }
 
 /// Standard API response wrapper.
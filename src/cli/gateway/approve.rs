@@ -1,6 +1,9 @@
 //! Interactive approval TUI for the gateway.
 //!
 //! Displays pending messages in a DOS-style list with keyboard navigation.
+//! `f` switches to failed sends (and those waiting to be retried), where `r`
+//! retries a failed one. Opening a message from a batch shows the whole batch, where
+//! recipients can be unticked before approving the rest.
 
 use anyhow::Result;
 use crossterm::{
@@ -10,7 +13,9 @@ use crossterm::{
 };
 use std::io::{self, Write};
 
-use super::execute;
+use super::audit::{self, AuditAction};
+use super::execute::{self, SendOutcome};
+use super::thread;
 use super::webhook;
 use crate::cli::ui::{clear_screen, truncate, RawModeGuard, StatusBar};
 use crate::db::gateway::QueueEntry;
@@ -21,9 +26,18 @@ pub fn run_approve(db: &Database) -> Result<bool> {
     let mut selected_idx: usize = 0;
     let mut show_detail = false;
     let mut detail_entry: Option<QueueEntry> = None;
+    let mut show_failed = false;
 
     loop {
-        let entries = db.list_pending_queue()?;
+        let entries: Vec<QueueEntry> = if show_failed {
+            db.list_failed_queue()?
+        } else {
+            // Keep messages from the same batch or conversation together
+            thread::group_by_thread(db.list_pending_queue()?, group_key)
+                .into_iter()
+                .flatten()
+                .collect()
+        };
 
         // Clamp selection
         if entries.is_empty() {
@@ -39,24 +53,32 @@ pub fn run_approve(db: &Database) -> Result<bool> {
             if let Some(ref entry) = detail_entry {
                 render_detail(&mut stdout, db, entry)?;
 
-                let status = StatusBar::new()
-                    .action("a", "pprove")
-                    .action("d", "eny")
-                    .action("esc", " back")
-                    .action("q", "uit")
-                    .render();
+                let status = if show_failed {
+                    StatusBar::new().action("r", "etry")
+                } else {
+                    StatusBar::new().action("a", "pprove").action("d", "eny")
+                }
+                .action("esc", " back")
+                .action("q", "uit")
+                .render();
                 println!("{}", status);
                 stdout.flush()?;
 
                 let code = read_key()?;
                 match code {
-                    KeyCode::Char('a') | KeyCode::Char('A') => {
+                    KeyCode::Char('r') | KeyCode::Char('R') if show_failed => {
+                        let result = retry_entry(db, entry);
+                        show_result(&mut stdout, &result)?;
+                        show_detail = false;
+                        detail_entry = None;
+                    }
+                    KeyCode::Char('a') | KeyCode::Char('A') if !show_failed => {
                         let result = approve_entry(db, entry);
                         show_result(&mut stdout, &result)?;
                         show_detail = false;
                         detail_entry = None;
                     }
-                    KeyCode::Char('d') | KeyCode::Char('D') => {
+                    KeyCode::Char('d') | KeyCode::Char('D') if !show_failed => {
                         deny_entry(db, entry)?;
                         show_detail = false;
                         detail_entry = None;
@@ -74,23 +96,36 @@ pub fn run_approve(db: &Database) -> Result<bool> {
         }
 
         // Header
-        println!("GATEWAY QUEUE ({} pending)\n", entries.len());
+        if show_failed {
+            println!("FAILED SENDS ({})\n", entries.len());
+        } else {
+            println!("GATEWAY QUEUE ({} pending)\n", entries.len());
+        }
         print_header();
 
         if entries.is_empty() {
-            println!("  No pending messages.\n");
+            if show_failed {
+                println!("  No failed messages.\n");
+            } else {
+                println!("  No pending messages.\n");
+            }
         } else {
             for (idx, entry) in entries.iter().enumerate() {
-                print_row(&mut stdout, db, entry, idx == selected_idx)?;
+                let marker = if show_failed { " " } else { group_marker(&entries, idx) };
+                print_row(&mut stdout, db, entry, marker, idx == selected_idx)?;
             }
         }
 
         println!();
         let status = StatusBar::new()
             .counter(if entries.is_empty() { 0 } else { selected_idx + 1 }, entries.len())
-            .action("enter", " view")
-            .action("a", "pprove")
-            .action("d", "eny")
+            .action("enter", " view");
+        let status = if show_failed {
+            status.action("r", "etry").action("f", " pending")
+        } else {
+            status.action("a", "pprove").action("d", "eny").action("f", "ailed")
+        };
+        let status = status
             .action("↑/↓", "")
             .action("q", "/esc")
             .action("Q", "uit")
@@ -110,24 +145,38 @@ pub fn run_approve(db: &Database) -> Result<bool> {
                     selected_idx += 1;
                 }
             }
+            KeyCode::Enter
+                if !show_failed && entries.get(selected_idx).is_some_and(|e| e.batch_id.is_some()) =>
+            {
+                let batch_id = &entries[selected_idx].batch_id;
+                let batch: Vec<QueueEntry> =
+                    entries.iter().filter(|e| &e.batch_id == batch_id).cloned().collect();
+                if let Some(quit) = run_batch(db, &batch)? {
+                    return Ok(quit);
+                }
+            }
             KeyCode::Enter => {
                 if !entries.is_empty() {
                     detail_entry = Some(entries[selected_idx].clone());
                     show_detail = true;
                 }
             }
-            KeyCode::Char('a') | KeyCode::Char('A') => {
-                if !entries.is_empty() {
-                    let entry = &entries[selected_idx];
-                    let result = approve_entry(db, entry);
-                    show_result(&mut stdout, &result)?;
-                }
+            KeyCode::Char('f') | KeyCode::Char('F') => {
+                show_failed = !show_failed;
+                selected_idx = 0;
             }
-            KeyCode::Char('d') | KeyCode::Char('D') => {
-                if !entries.is_empty() {
-                    let entry = &entries[selected_idx];
-                    deny_entry(db, entry)?;
-                }
+            KeyCode::Char('r') | KeyCode::Char('R') if show_failed && !entries.is_empty() => {
+                let result = retry_entry(db, &entries[selected_idx]);
+                show_result(&mut stdout, &result)?;
+            }
+            KeyCode::Char('a') | KeyCode::Char('A') if !show_failed && !entries.is_empty() => {
+                let entry = &entries[selected_idx];
+                let result = approve_entry(db, entry);
+                show_result(&mut stdout, &result)?;
+            }
+            KeyCode::Char('d') | KeyCode::Char('D') if !show_failed && !entries.is_empty() => {
+                let entry = &entries[selected_idx];
+                deny_entry(db, entry)?;
             }
             _ => {}
         }
@@ -149,7 +198,7 @@ fn read_key() -> Result<KeyCode> {
 fn print_header() {
     let layout = QueueLayout::default();
     println!(
-        "{:<ch$}  {:<to$}  {:<subj$}  {:<agent$}  {:<pri$}",
+        "  {:<ch$}  {:<to$}  {:<subj$}  {:<agent$}  {:<pri$}",
         "CH",
         "TO",
         "SUBJECT/BODY",
@@ -197,11 +246,168 @@ impl Default for QueueLayout {
     }
 }
 
-fn print_row(stdout: &mut io::Stdout, db: &Database, entry: &QueueEntry, selected: bool) -> Result<()> {
+/// Review a batch: every recipient starts ticked, `a` approves the ticked ones
+/// and denies the rest, `d` denies them all. Returns `Some(quit)` to leave the
+/// approval screen, `None` to go back to the list.
+fn run_batch(db: &Database, entries: &[QueueEntry]) -> Result<Option<bool>> {
+    let mut ticked = vec![true; entries.len()];
+    let mut selected_idx: usize = 0;
+    let mut stdout = io::stdout();
+
+    loop {
+        clear_screen()?;
+        let count = ticked.iter().filter(|t| **t).count();
+        let first = &entries[0];
+        println!("BATCH ({} recipients, {} ticked)\n", entries.len(), count);
+        println!("Agent:     {}", get_agent_name(db, &first.api_key_id));
+        println!("Channel:   {}", first.channel.to_uppercase());
+        if let Some(ref template_id) = first.template_id {
+            println!("Template:  {} (shown for the first recipient)", template_id);
+        }
+        if let Some(ref subject) = first.subject {
+            println!("Subject:   {}", subject);
+        }
+        println!("─────────────────────────────────────────────────────────────");
+        for line in first.body.lines().take(8) {
+            println!("  {}", line);
+        }
+        if first.body.lines().count() > 8 {
+            println!("  ...(truncated)");
+        }
+        println!("─────────────────────────────────────────────────────────────\n");
+
+        for (idx, entry) in entries.iter().enumerate() {
+            let flag = if entry.status == "flagged" { "!" } else { " " };
+            let tick = if ticked[idx] { "[x]" } else { "[ ]" };
+            let line = match entry.recipient_name {
+                Some(ref name) => format!("{}{} {} ({})", flag, tick, entry.recipient_address, name),
+                None => format!("{}{} {}", flag, tick, entry.recipient_address),
+            };
+            if idx == selected_idx {
+                stdout.execute(SetAttribute(Attribute::Reverse))?;
+                print!("{}", line);
+                stdout.execute(SetAttribute(Attribute::Reset))?;
+                println!();
+            } else {
+                println!("{}", line);
+            }
+        }
+
+        println!();
+        let status = StatusBar::new()
+            .counter(selected_idx + 1, entries.len())
+            .action("space", " tick")
+            .action("a", "pprove ticked")
+            .action("d", "eny all")
+            .action("↑/↓", "")
+            .action("esc", " back")
+            .action("Q", "uit")
+            .render();
+        println!("{}", status);
+        stdout.flush()?;
+
+        match read_key()? {
+            KeyCode::Esc | KeyCode::Backspace | KeyCode::Char('q') => return Ok(None),
+            KeyCode::Char('Q') => return Ok(Some(true)),
+            KeyCode::Up | KeyCode::Char('k') => selected_idx = selected_idx.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') if selected_idx + 1 < entries.len() => selected_idx += 1,
+            KeyCode::Char(' ') => ticked[selected_idx] = !ticked[selected_idx],
+            KeyCode::Char('a') | KeyCode::Char('A') => {
+                let prompt = format!("Approve {} and deny {}?", count, entries.len() - count);
+                if confirm(&mut stdout, &prompt)? {
+                    let mut results = Vec::new();
+                    for (entry, tick) in entries.iter().zip(&ticked) {
+                        if *tick {
+                            results.push((entry, approve_entry(db, entry)));
+                        } else {
+                            deny_entry(db, entry)?;
+                        }
+                    }
+                    show_batch_results(&mut stdout, &results)?;
+                    return Ok(None);
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Char('D') => {
+                let prompt = format!("Deny all {}?", entries.len());
+                if confirm(&mut stdout, &prompt)? {
+                    for entry in entries {
+                        deny_entry(db, entry)?;
+                    }
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn confirm(stdout: &mut io::Stdout, prompt: &str) -> Result<bool> {
+    print!("\n{} (y/n) ", prompt);
+    stdout.flush()?;
+    Ok(matches!(read_key()?, KeyCode::Char('y') | KeyCode::Char('Y')))
+}
+
+fn show_batch_results(stdout: &mut io::Stdout, results: &[(&QueueEntry, ApproveResult)]) -> Result<()> {
+    clear_screen()?;
+    for (entry, result) in results {
+        let outcome = match result {
+            ApproveResult::Sent => "sent".to_string(),
+            ApproveResult::Retrying(e, at) => {
+                format!("failed ({}), retrying at {}", e, at.with_timezone(&chrono::Local).format("%H:%M"))
+            }
+            ApproveResult::Failed(e) => format!("failed: {}", e),
+            ApproveResult::Error(e) => format!("error: {}", e),
+        };
+        println!("  {:<30}  {}", truncate(&entry.recipient_address, 30), outcome);
+    }
+    println!("\nPress any key to continue...");
+    stdout.flush()?;
+    let _ = read_key()?;
+    Ok(())
+}
+
+/// Rows from the same batch, or failing that the same thread, belong together.
+fn group_key(entry: &QueueEntry) -> Option<String> {
+    match entry.batch_id {
+        Some(ref batch_id) => Some(format!("batch:{}", batch_id)),
+        None => thread::thread_key(entry),
+    }
+}
+
+/// Box-drawing marker linking consecutive rows from the same batch or thread.
+fn group_marker(entries: &[QueueEntry], idx: usize) -> &'static str {
+    let key = group_key(&entries[idx]);
+    if key.is_none() {
+        return " ";
+    }
+    let same_as = |other: usize| group_key(&entries[other]) == key;
+    let continues_prev = idx > 0 && same_as(idx - 1);
+    let continues_next = idx + 1 < entries.len() && same_as(idx + 1);
+
+    match (continues_prev, continues_next) {
+        (false, true) => "┌",
+        (true, true) => "│",
+        (true, false) => "└",
+        (false, false) => " ",
+    }
+}
+
+fn print_row(
+    stdout: &mut io::Stdout,
+    db: &Database,
+    entry: &QueueEntry,
+    thread_marker: &str,
+    selected: bool,
+) -> Result<()> {
     let layout = QueueLayout::default();
 
-    // Show flag indicator for flagged entries
-    let flag_indicator = if entry.status == "flagged" { "!" } else { " " };
+    // Show flag indicator for flagged entries and failed sends
+    let flag_indicator = match entry.status.as_str() {
+        "flagged" => "!",
+        "failed" => "x",
+        _ if entry.next_retry_at.is_some() => "~",
+        _ => " ",
+    };
     let channel = entry.channel.to_uppercase();
     let to = entry.recipient_name.as_deref().unwrap_or(&entry.recipient_address);
     let subject = entry.subject.as_deref().unwrap_or(&entry.body);
@@ -209,8 +415,9 @@ fn print_row(stdout: &mut io::Stdout, db: &Database, entry: &QueueEntry, selecte
     let priority = &entry.priority;
 
     let line = format!(
-        "{}{:<ch$}  {:<to$}  {:<subj$}  {:<agent$}  {:<pri$}",
+        "{}{}{:<ch$}  {:<to$}  {:<subj$}  {:<agent$}  {:<pri$}",
         flag_indicator,
+        thread_marker,
         truncate(&channel, layout.channel_width),
         truncate(to, layout.to_width),
         truncate(subject, layout.subject_width),
@@ -240,18 +447,52 @@ fn render_detail(stdout: &mut io::Stdout, db: &Database, entry: &QueueEntry) ->
 
     println!("MESSAGE DETAIL\n");
     if entry.status == "flagged" {
-        println!("!! FLAGGED - Review carefully (matched content filter) !!\n");
+        let why = if entry.risk_score.is_some() || !entry.risk_reasons.is_empty() {
+            "content filter or risk classifier"
+        } else {
+            "matched content filter"
+        };
+        println!("!! FLAGGED - Review carefully ({}) !!\n", why);
     }
     println!("Agent:     {}", agent);
     println!("Channel:   {}", entry.channel.to_uppercase());
     println!("Priority:  {}", entry.priority);
     println!("Status:    {}", entry.status);
     println!("Queued:    {}", entry.created_at.format("%Y-%m-%d %H:%M"));
+    if let Some(ref thread_id) = entry.thread_id {
+        println!("Thread:    {}", thread_id);
+    }
+    if let Some(ref template_id) = entry.template_id {
+        println!("Template:  {} (rendered below)", template_id);
+    }
+    match entry.risk_score {
+        Some(score) => println!("Risk:      {}/100", score),
+        None if !entry.risk_reasons.is_empty() => println!("Risk:      unscored"),
+        None => {}
+    }
+    for reason in &entry.risk_reasons {
+        println!("           - {}", reason);
+    }
+    if entry.send_attempts > 0 {
+        println!("Attempts:  {} of {}", entry.send_attempts, execute::MAX_SEND_ATTEMPTS);
+    }
+    if let Some(at) = entry.last_attempt_at {
+        println!("Last try:  {}", at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"));
+    }
+    if let Some(at) = entry.next_retry_at {
+        println!("Next try:  {}", at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"));
+    }
+    if let Some(ref error) = entry.error_message {
+        println!("Error:     {}", error);
+    }
     println!();
     println!("To:        {}", entry.recipient_address);
     if let Some(ref name) = entry.recipient_name {
         println!("           ({})", name);
     }
+    if entry.recipient_contact.is_some() {
+        println!("           (addressed by contact; agent didn't see the address)");
+    }
     println!();
 
     if let Some(ref subject) = entry.subject {
@@ -282,53 +523,81 @@ fn render_detail(stdout: &mut io::Stdout, db: &Database, entry: &QueueEntry) ->
         }
     }
 
+    render_conversation(db, entry);
+
     stdout.flush()?;
     Ok(())
 }
 
+/// Show earlier messages in the thread and recent replies from the recipient.
+fn render_conversation(db: &Database, entry: &QueueEntry) {
+    let earlier = thread::earlier_in_thread(db, entry).unwrap_or_default();
+    if !earlier.is_empty() {
+        println!("Earlier in thread ({}):", earlier.len());
+        for prev in &earlier {
+            let first_line = prev.body.lines().next().unwrap_or("");
+            println!(
+                "  {}  {:<8}  {}",
+                prev.created_at.format("%m-%d %H:%M"),
+                prev.status,
+                truncate(first_line, 50)
+            );
+        }
+        println!();
+    }
+
+    let inbound = thread::recent_inbound(db, entry, thread::INBOUND_CONTEXT_LIMIT).unwrap_or_default();
+    if !inbound.is_empty() {
+        println!("Recent messages from recipient:");
+        for msg in inbound.iter().rev() {
+            let first_line = msg.text.lines().next().unwrap_or("");
+            println!("  {}  {}", msg.date.format("%m-%d %H:%M"), truncate(first_line, 60));
+        }
+        println!();
+    }
+}
+
 fn approve_entry(db: &Database, entry: &QueueEntry) -> ApproveResult {
-    if let Err(e) = db.update_queue_status(&entry.id, "approved") {
+    // The dashboard or the local API may have reviewed it since the list was loaded
+    match db.review_queue_entry(&entry.id, "approved") {
+        Ok(true) => {}
+        Ok(false) => return ApproveResult::Error("already reviewed elsewhere".to_string()),
+        Err(e) => return ApproveResult::Error(e.to_string()),
+    }
+    let approved = audit::record(
+        db,
+        AuditAction::Approved,
+        audit::ACTOR_TUI,
+        Some(&entry.id),
+        serde_json::json!({ "content_sha256": audit::content_hash(entry.subject.as_deref(), &entry.body) }),
+    );
+    if let Err(e) = approved {
         return ApproveResult::Error(e.to_string());
     }
 
-    match execute::execute_send(db, entry) {
-        Ok(()) => {
-            let _ = db.mark_queue_sent(&entry.id);
-            let sent_at = chrono::Utc::now().to_rfc3339();
-            // Send webhook notification (non-blocking for errors)
-            let _ = webhook::notify_status_change(
-                db,
-                &entry.api_key_id,
-                &entry.id,
-                "sent",
-                &entry.recipient_address,
-                &entry.channel,
-                Some(&sent_at),
-                None,
-            );
-            ApproveResult::Sent
-        }
-        Err(e) => {
-            let error_msg = e.to_string();
-            let _ = db.mark_queue_failed(&entry.id, &error_msg);
-            // Send webhook notification (non-blocking for errors)
-            let _ = webhook::notify_status_change(
-                db,
-                &entry.api_key_id,
-                &entry.id,
-                "failed",
-                &entry.recipient_address,
-                &entry.channel,
-                None,
-                Some(&error_msg),
-            );
-            ApproveResult::Failed(error_msg)
-        }
+    match execute::send_approved(db, entry) {
+        Ok(SendOutcome::Sent(_)) => ApproveResult::Sent,
+        Ok(SendOutcome::Failed(e)) => ApproveResult::Failed(e),
+        Ok(SendOutcome::Retrying(e, at)) => ApproveResult::Retrying(e, at),
+        Err(e) => ApproveResult::Error(e.to_string()),
+    }
+}
+
+fn retry_entry(db: &Database, entry: &QueueEntry) -> ApproveResult {
+    match execute::retry(db, &entry.id, audit::ACTOR_TUI) {
+        Ok(SendOutcome::Sent(_)) => ApproveResult::Sent,
+        Ok(SendOutcome::Failed(e)) => ApproveResult::Failed(e),
+        Ok(SendOutcome::Retrying(e, at)) => ApproveResult::Retrying(e, at),
+        Err(e) => ApproveResult::Error(e.to_string()),
     }
 }
 
 fn deny_entry(db: &Database, entry: &QueueEntry) -> Result<()> {
-    db.update_queue_status(&entry.id, "denied")?;
+    // Already reviewed elsewhere; it drops off the list on the next refresh
+    if !db.review_queue_entry(&entry.id, "denied")? {
+        return Ok(());
+    }
+    audit::record(db, AuditAction::Denied, audit::ACTOR_TUI, Some(&entry.id), serde_json::json!({}))?;
     // Send webhook notification (non-blocking for errors)
     let _ = webhook::notify_status_change(
         db,
@@ -345,6 +614,7 @@ fn deny_entry(db: &Database, entry: &QueueEntry) -> Result<()> {
 
 enum ApproveResult {
     Sent,
+    Retrying(String, chrono::DateTime<chrono::Utc>),
     Failed(String),
     Error(String),
 }
@@ -355,6 +625,13 @@ fn show_result(stdout: &mut io::Stdout, result: &ApproveResult) -> Result<()> {
         ApproveResult::Sent => {
             println!("Sent.\n");
         }
+        ApproveResult::Retrying(e, at) => {
+            println!(
+                "Send failed: {}\nWill retry at {}.\n",
+                e,
+                at.with_timezone(&chrono::Local).format("%H:%M")
+            );
+        }
         ApproveResult::Failed(e) => {
             println!("Send failed: {}\n", e);
         }
@@ -375,3 +652,31 @@ fn get_agent_name(db: &Database, api_key_id: &str) -> String {
         .map(|k| k.name)
         .unwrap_or_else(|| "Unknown".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::gateway::transport::{self, TransportConfig};
+    use crate::cli::gateway::types::GatewayChannel;
+
+    #[test]
+    fn test_tui_skips_entries_reviewed_elsewhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_memory().unwrap();
+        let outbox = dir.path().join("outbox.jsonl");
+        let file = TransportConfig::File { path: outbox.clone() };
+        transport::set_for_channel(&db, GatewayChannel::Sms, Some(&file)).unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        db.insert_queue_entry("msg-1", "key-1", "sms", "+15551234567", None, None, "Hi", "normal", None)
+            .unwrap();
+
+        let stale = db.get_queue_entry("msg-1").unwrap().unwrap();
+        assert!(matches!(approve_entry(&db, &stale), ApproveResult::Sent));
+
+        // The same entry from a list loaded before it was sent
+        assert!(matches!(approve_entry(&db, &stale), ApproveResult::Error(_)));
+        deny_entry(&db, &stale).unwrap();
+        assert_eq!(db.get_queue_entry("msg-1").unwrap().unwrap().status, "sent");
+        assert_eq!(std::fs::read_to_string(&outbox).unwrap().lines().count(), 1);
+    }
+}
@@ -0,0 +1,264 @@
+//! Tamper-evident audit log for the gateway.
+//!
+//! `communication_queue` rows change as messages move through review, so they
+//! can't prove what was approved. Every gateway decision is also appended to
+//! `gateway_audit_log`, and each event carries the SHA-256 of the one before
+//! it. Changing or removing an earlier event breaks the chain from that point;
+//! triggers reject UPDATE and DELETE outright. Removing the newest events
+//! leaves a valid but shorter chain, so keep a copy of the head hash printed by
+//! `gateway audit verify` somewhere else and pass it back with `--head`.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::db::gateway::{AuditEvent, AUDIT_GENESIS_HASH};
+use crate::db::Database;
+
+/// Command-line changes (keys, allowlists)
+pub const ACTOR_CLI: &str = "cli";
+/// `gateway approve`
+pub const ACTOR_TUI: &str = "tui";
+/// Browser dashboard
+pub const ACTOR_WEB: &str = "web";
+/// Local approve/deny endpoints
+pub const ACTOR_LOCAL_API: &str = "local-api";
+/// The gateway itself (sending, retries)
+pub const ACTOR_GATEWAY: &str = "gateway";
+/// The Moltbot bridge
+pub const ACTOR_BRIDGE: &str = "bridge";
+
+/// Actor for requests made with an API key.
+pub fn agent_actor(key_id: &str) -> String {
+    format!("agent:{}", key_id)
+}
+
+/// Actor for messages approved because their template is pre-approved.
+pub fn template_actor(template_id: &str) -> String {
+    format!("template:{}", template_id)
+}
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Queued,
+    Filtered,
+    RateLimited,
+    Approved,
+    Denied,
+    Edited,
+    Retried,
+    Sent,
+    Failed,
+    KeyCreated,
+    KeyRotated,
+    KeyRevoked,
+    AllowlistAdded,
+    AllowlistRemoved,
+    TemplatePreapproval,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Filtered => "filtered",
+            Self::RateLimited => "rate_limited",
+            Self::Approved => "approved",
+            Self::Denied => "denied",
+            Self::Edited => "edited",
+            Self::Retried => "retried",
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+            Self::KeyCreated => "key_created",
+            Self::KeyRotated => "key_rotated",
+            Self::KeyRevoked => "key_revoked",
+            Self::AllowlistAdded => "allowlist_added",
+            Self::AllowlistRemoved => "allowlist_removed",
+            Self::TemplatePreapproval => "template_preapproval",
+        }
+    }
+}
+
+/// Append an event. `details` should be a JSON object.
+pub fn record(
+    db: &Database,
+    action: AuditAction,
+    actor: &str,
+    subject_id: Option<&str>,
+    details: serde_json::Value,
+) -> Result<()> {
+    db.append_audit_event(action.as_str(), actor, subject_id, &details.to_string())?;
+    Ok(())
+}
+
+/// Fingerprint of a message's subject and body, recorded instead of the text.
+pub fn content_hash(subject: Option<&str>, body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(subject.unwrap_or("").as_bytes());
+    hasher.update([0u8]);
+    hasher.update(body.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Check sequence numbers, links and hashes. Returns one line per problem.
+pub fn verify_chain(events: &[AuditEvent]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut prev_hash = AUDIT_GENESIS_HASH;
+    for (i, event) in events.iter().enumerate() {
+        let expected_seq = i as i64 + 1;
+        if event.seq != expected_seq {
+            problems.push(format!(
+                "Event {}: expected sequence number {} (events removed or inserted)",
+                event.seq, expected_seq
+            ));
+        }
+        if event.prev_hash != prev_hash {
+            problems.push(format!("Event {}: does not follow the event before it", event.seq));
+        }
+        if event.compute_hash() != event.hash {
+            problems.push(format!("Event {}: changed since it was written", event.seq));
+        }
+        prev_hash = &event.hash;
+    }
+    problems
+}
+
+/// Compare queue entries with what the log says happened to them: the last
+/// recorded content and status. Entries queued before the log existed are
+/// skipped.
+pub fn check_queue(db: &Database, events: &[AuditEvent]) -> Result<Vec<String>> {
+    // id -> (content hash, status), in the order first seen
+    let mut expected: Vec<(String, Option<String>, Option<String>)> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for event in events {
+        let Some(ref id) = event.subject_id else { continue };
+        let details: serde_json::Value = serde_json::from_str(&event.details).unwrap_or_default();
+        let status = match event.event.as_str() {
+            "queued" => details["status"].as_str().map(String::from),
+            "approved" | "denied" | "sent" | "failed" => Some(event.event.clone()),
+            "retried" => Some("approved".to_string()),
+            "edited" => None,
+            _ => continue,
+        };
+        let slot = *index.entry(id.clone()).or_insert_with(|| {
+            expected.push((id.clone(), None, None));
+            expected.len() - 1
+        });
+        if let Some(hash) = details["content_sha256"].as_str() {
+            expected[slot].1 = Some(hash.to_string());
+        }
+        if status.is_some() {
+            expected[slot].2 = status;
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (id, content, status) in expected {
+        let Some(entry) = db.get_queue_entry(&id)? else {
+            problems.push(format!("Message {}: missing from the queue", id));
+            continue;
+        };
+        if content.is_some_and(|c| c != content_hash(entry.subject.as_deref(), &entry.body)) {
+            problems.push(format!("Message {}: subject or body differs from the log", id));
+        }
+        if let Some(status) = status.filter(|s| *s != entry.status) {
+            problems.push(format!(
+                "Message {}: status is {} but the log says {}",
+                id, entry.status, status
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// One JSONL export line. `details` is kept as the exact string that was
+/// hashed, so the export can be verified on its own.
+pub fn to_json(event: &AuditEvent) -> serde_json::Value {
+    serde_json::json!({
+        "seq": event.seq,
+        "created_at": event.created_at.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        "event": event.event,
+        "actor": event.actor,
+        "subject_id": event.subject_id,
+        "details": event.details,
+        "prev_hash": event.prev_hash,
+        "hash": event.hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn queue_and_log(db: &Database, id: &str, body: &str) {
+        db.insert_queue_entry(id, "key-1", "sms", "+15551234567", None, None, body, "normal", None)
+            .unwrap();
+        record(
+            db,
+            AuditAction::Queued,
+            &agent_actor("key-1"),
+            Some(id),
+            json!({ "status": "pending", "content_sha256": content_hash(None, body) }),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_verify_chain_detects_tampering() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        queue_and_log(&db, "msg-1", "Running late");
+        record(&db, AuditAction::Approved, ACTOR_TUI, Some("msg-1"), json!({})).unwrap();
+        record(&db, AuditAction::Sent, ACTOR_GATEWAY, Some("msg-1"), json!({})).unwrap();
+
+        let events = db.list_audit_events(0).unwrap();
+        assert!(verify_chain(&events).is_empty());
+
+        // Rewriting who approved it
+        let mut edited = events.clone();
+        edited[1].actor = ACTOR_WEB.to_string();
+        assert_eq!(verify_chain(&edited), vec!["Event 2: changed since it was written"]);
+
+        // ...and re-hashing it to cover up
+        edited[1].hash = edited[1].compute_hash();
+        assert_eq!(verify_chain(&edited), vec!["Event 3: does not follow the event before it"]);
+
+        // Dropping an event
+        let dropped = vec![events[0].clone(), events[2].clone()];
+        assert_eq!(verify_chain(&dropped).len(), 2);
+    }
+
+    #[test]
+    fn test_check_queue_against_log() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        queue_and_log(&db, "msg-1", "Running late");
+        queue_and_log(&db, "msg-2", "See you at 3");
+
+        db.update_queue_content("msg-2", None, "See you at 4").unwrap();
+        record(
+            &db,
+            AuditAction::Edited,
+            ACTOR_WEB,
+            Some("msg-2"),
+            json!({ "content_sha256": content_hash(None, "See you at 4") }),
+        )
+        .unwrap();
+        let events = db.list_audit_events(0).unwrap();
+        assert!(check_queue(&db, &events).unwrap().is_empty());
+
+        // Changed behind the log's back
+        db.update_queue_content("msg-1", None, "Wire $5000 today").unwrap();
+        db.update_queue_status("msg-2", "approved").unwrap();
+        assert_eq!(
+            check_queue(&db, &events).unwrap(),
+            vec![
+                "Message msg-1: subject or body differs from the log",
+                "Message msg-2: status is approved but the log says pending",
+            ]
+        );
+    }
+}
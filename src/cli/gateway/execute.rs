@@ -1,157 +1,307 @@
 //! Message execution for the gateway.
 //!
-//! Sends approved messages via email, SMS, or iMessage.
+//! Sends approved messages through each channel's transport. Sends that fail
+//! for a reason likely to pass (network errors, rate limits, Messages not
+//! running) stay approved and are retried with backoff by a background
+//! worker; anything else, or the last attempt, marks them failed.
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use super::audit::{self, AuditAction};
+use super::transport;
+use super::types::GatewayChannel;
+use super::webhook;
 use crate::db::gateway::QueueEntry;
 use crate::db::Database;
 
-/// Execute sending a queued message.
-pub fn execute_send(db: &Database, entry: &QueueEntry) -> Result<()> {
-    match entry.channel.as_str() {
-        "email" => send_email(db, entry),
-        "sms" | "imessage" => send_message(entry),
-        other => anyhow::bail!("Unknown channel: {}", other),
-    }
+/// How often due retries are checked.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Attempts before a message is given up on and marked failed.
+pub const MAX_SEND_ATTEMPTS: i32 = 5;
+
+/// Delay before the first retry; doubles after each further failure.
+const RETRY_BASE_DELAY_SECS: i64 = 60;
+
+/// Upper bound on the delay between retries.
+const RETRY_MAX_DELAY_SECS: i64 = 60 * 60;
+
+/// Error text (lowercase) that marks a failure as temporary.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "timed out",
+    "error sending request", // reqwest: DNS, connect, reset
+    "connection refused",    // SMTP and socket errors
+    "connection reset",
+    "connection error",
+    "temporarily",
+    "try again",
+    "api error (401",        // Gmail: token expired mid-send
+    "api error (429",        // Gmail: rate limited
+    "api error (5",          // Gmail: server error
+    "http 408",              // Webhook: request timeout
+    "http 429",              // Webhook: rate limited
+    "http 5",                // Webhook: server error
+    "isn't running",         // Messages.app not running
+    "(-600)",
+    "(-1712)",               // AppleScript event timed out
+];
+
+/// Result of sending an approved message.
+pub enum SendOutcome {
+    /// Sent at the given RFC 3339 time
+    Sent(String),
+    /// Delivery failed with this error
+    Failed(String),
+    /// Failed with this error; retried at the given time
+    Retrying(String, DateTime<Utc>),
 }
 
-/// Send email via Gmail API.
-fn send_email(db: &Database, entry: &QueueEntry) -> Result<()> {
-    use base64::Engine;
+/// Whether a send error is likely to go away on its own.
+pub fn is_retryable_send_error(err: &anyhow::Error) -> bool {
+    // Include the cause chain; reqwest keeps the useful part in its sources
+    let msg = format!("{:#}", err).to_lowercase();
+    TRANSIENT_ERRORS.iter().any(|marker| msg.contains(marker))
+}
 
-    use crate::cli::google_auth::{get_google_email, is_google_auth_configured, refresh_access_token_if_needed};
+/// Delay before retrying after `attempts` failed attempts.
+pub fn retry_delay(attempts: i32) -> chrono::Duration {
+    let exponent = (attempts.max(1) - 1).min(20) as u32;
+    let secs = RETRY_BASE_DELAY_SECS
+        .saturating_mul(2i64.saturating_pow(exponent))
+        .min(RETRY_MAX_DELAY_SECS);
+    chrono::Duration::seconds(secs)
+}
+
+/// Send an approved entry.
+///
+/// Records the outcome on the queue entry and notifies the agent's webhook.
+/// Temporary failures are left approved for the retry worker; the agent only
+/// hears about the final outcome.
+pub fn send_approved(db: &Database, entry: &QueueEntry) -> Result<SendOutcome> {
+    match execute_send(db, entry) {
+        Ok(()) => {
+            db.mark_queue_sent(&entry.id)?;
+            audit::record(db, AuditAction::Sent, audit::ACTOR_GATEWAY, Some(&entry.id), serde_json::json!({}))?;
+            let sent_at = Utc::now().to_rfc3339();
+            // Send webhook notification (non-blocking for errors)
+            let _ = webhook::notify_status_change(
+                db,
+                &entry.api_key_id,
+                &entry.id,
+                "sent",
+                &entry.recipient_address,
+                &entry.channel,
+                Some(&sent_at),
+                None,
+            );
+            Ok(SendOutcome::Sent(sent_at))
+        }
+        Err(e) => {
+            let error_msg = e.to_string();
+            let attempts = entry.send_attempts + 1;
+            if attempts < MAX_SEND_ATTEMPTS && is_retryable_send_error(&e) {
+                let at = Utc::now() + retry_delay(attempts);
+                db.mark_queue_retry(&entry.id, &error_msg, at)?;
+                return Ok(SendOutcome::Retrying(error_msg, at));
+            }
 
-    if !is_google_auth_configured(db) {
-        anyhow::bail!("Gmail not configured. Run setup first.");
+            db.mark_queue_failed(&entry.id, &error_msg)?;
+            audit::record(
+                db,
+                AuditAction::Failed,
+                audit::ACTOR_GATEWAY,
+                Some(&entry.id),
+                serde_json::json!({ "error": error_msg }),
+            )?;
+            // Send webhook notification (non-blocking for errors)
+            let _ = webhook::notify_status_change(
+                db,
+                &entry.api_key_id,
+                &entry.id,
+                "failed",
+                &entry.recipient_address,
+                &entry.channel,
+                None,
+                Some(&error_msg),
+            );
+            Ok(SendOutcome::Failed(error_msg))
+        }
     }
+}
+
+/// Retry failed sends whose retry time has passed, until shutdown.
+pub fn run_retry_worker(db_path: PathBuf, shutdown: Arc<AtomicBool>) {
+    let mut last_run: Option<Instant> = None;
+    while !shutdown.load(Ordering::SeqCst) {
+        if last_run.is_some_and(|t| t.elapsed() < RETRY_INTERVAL) {
+            std::thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        last_run = Some(Instant::now());
 
-    let from_addr = get_google_email(db).unwrap_or_default();
-    let to_addr = &entry.recipient_address;
-    let subject = entry.subject.as_deref().unwrap_or("(no subject)");
-    let body = &entry.body;
-
-    // Get fresh access token
-    let access_token = refresh_access_token_if_needed(db)?;
-
-    // Build RFC 2822 email message
-    let email_content = format!(
-        "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
-        from_addr, to_addr, subject, body
-    );
-
-    // Base64url encode the message (Gmail API requirement)
-    let encoded = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(email_content.as_bytes());
-
-    // Send via Gmail API
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .post("https://gmail.googleapis.com/gmail/v1/users/me/messages/send")
-        .bearer_auth(&access_token)
-        .json(&serde_json::json!({
-            "raw": encoded
-        }))
-        .send()?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_body = response.text().unwrap_or_default();
-        anyhow::bail!("Gmail API error ({}): {}", status, error_body);
+        let result = Database::open_at(db_path.clone()).and_then(|db| send_due(&db));
+        if let Err(e) = result {
+            eprintln!("Retry error: {}", e);
+        }
     }
+}
 
+fn send_due(db: &Database) -> Result<()> {
+    let now = Utc::now();
+    for entry in db.list_due_sends(now)? {
+        // Whoever clears the retry time sends it; anyone else leaves it alone
+        if !db.claim_due_send(&entry.id, now)? {
+            continue;
+        }
+        match send_approved(db, &entry)? {
+            SendOutcome::Failed(e) => eprintln!("Send of {} failed: {}", entry.id, e),
+            SendOutcome::Retrying(e, at) => eprintln!(
+                "Send of {} failed (attempt {} of {}), retrying at {}: {}",
+                entry.id,
+                entry.send_attempts + 1,
+                MAX_SEND_ATTEMPTS,
+                at.format("%H:%M:%S UTC"),
+                e
+            ),
+            SendOutcome::Sent(_) => {}
+        }
+    }
     Ok(())
 }
 
-/// Send SMS or iMessage.
-#[cfg(target_os = "macos")]
-fn send_message(entry: &QueueEntry) -> Result<()> {
-    use std::process::Command;
-
-    let recipient = &entry.recipient_address;
-    let message = &entry.body;
-
-    // Escape for AppleScript
-    let escaped_message = message.replace('\\', "\\\\").replace('"', "\\\"");
-    let escaped_recipient = recipient.replace('\\', "\\\\").replace('"', "\\\"");
-
-    // Determine if this looks like a phone number
-    let is_phone = recipient.chars().any(|c| c.is_ascii_digit()) && !recipient.contains('@');
-
-    // Build AppleScript based on channel preference
-    let script = if entry.channel == "sms" {
-        // Force SMS
-        format!(
-            r#"
-            tell application "Messages"
-                set smsService to 1st account whose service type = SMS
-                set targetBuddy to participant "{0}" of smsService
-                send "{1}" to targetBuddy
-            end tell
-            "#,
-            escaped_recipient, escaped_message
-        )
-    } else if entry.channel == "imessage" || !is_phone {
-        // Force iMessage (or default for email addresses)
-        format!(
-            r#"
-            tell application "Messages"
-                set imsgService to 1st account whose service type = iMessage
-                set targetBuddy to participant "{0}" of imsgService
-                send "{1}" to targetBuddy
-            end tell
-            "#,
-            escaped_recipient, escaped_message
-        )
-    } else {
-        // Auto-detect: try iMessage first, fall back to SMS
-        format!(
-            r#"
-            tell application "Messages"
-                try
-                    set imsgService to 1st account whose service type = iMessage
-                    set targetBuddy to participant "{0}" of imsgService
-                    send "{1}" to targetBuddy
-                    return "sent"
-                end try
-
-                try
-                    set smsService to 1st account whose service type = SMS
-                    set targetBuddy to participant "{0}" of smsService
-                    send "{1}" to targetBuddy
-                    return "sent"
-                end try
-
-                error "Could not find iMessage or SMS service"
-            end tell
-            "#,
-            escaped_recipient, escaped_message
-        )
-    };
-
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(&script)
-        .output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stderr_lower = stderr.to_lowercase();
-
-        if stderr_lower.contains("not authorized") || stderr_lower.contains("assistive access") {
-            anyhow::bail!(
-                "Permission required. Grant access in: System Settings > Privacy & Security > Accessibility"
-            );
+/// Put a failed (or retry-waiting) message back to approved and send it now.
+pub fn retry(db: &Database, id: &str, actor: &str) -> Result<SendOutcome> {
+    if !db.reset_queue_for_retry(id)? {
+        anyhow::bail!("Only failed messages can be retried; the gateway retries the others itself");
+    }
+    audit::record(db, AuditAction::Retried, actor, Some(id), serde_json::json!({}))?;
+    let entry = db
+        .get_queue_entry(id)?
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+    send_approved(db, &entry)
+}
+
+/// Execute sending a queued message through its channel's transport.
+pub fn execute_send(db: &Database, entry: &QueueEntry) -> Result<()> {
+    let channel: GatewayChannel = entry.channel.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+    transport::for_channel(db, channel)?.build().send(db, entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::gateway::transport::{TransportConfig, WebhookFormat};
+
+    #[test]
+    fn test_is_retryable_send_error() {
+        let retryable = [
+            "Gmail API error (503 Service Unavailable): backend error",
+            "Gmail API error (401 Unauthorized): invalid credentials",
+            "Webhook returned HTTP 429: slow down",
+            "error sending request for url (http://127.0.0.1:1/): connection refused",
+            "SMTP error: Connection error: timed out",
+            "Send failed: Messages got an error: Application isn't running. (-600)",
+        ];
+        for msg in retryable {
+            assert!(is_retryable_send_error(&anyhow::anyhow!("{}", msg)), "{}", msg);
         }
-        if stderr_lower.contains("can't get account") || stderr_lower.contains("no account") {
-            anyhow::bail!("Messages.app is not set up. Open Messages.app and sign in first.");
+
+        let permanent = [
+            "Gmail not configured. Run setup first.",
+            "Gmail API error (400 Bad Request): invalid To header",
+            "Webhook returned HTTP 404: no such hook",
+            "Webhook returned HTTP 400: unknown connection id",
+            "Matrix recipient must be a room ID like !abc123:example.org",
+            "SMS/iMessage sending is only available on macOS",
+        ];
+        for msg in permanent {
+            assert!(!is_retryable_send_error(&anyhow::anyhow!("{}", msg)), "{}", msg);
         }
-        anyhow::bail!("Send failed: {}", stderr.trim());
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_retry_delay_backoff() {
+        assert_eq!(retry_delay(1).num_seconds(), 60);
+        assert_eq!(retry_delay(2).num_seconds(), 120);
+        assert_eq!(retry_delay(4).num_seconds(), 480);
+        assert_eq!(retry_delay(30).num_seconds(), RETRY_MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn test_send_retries_then_dead_letters() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        db.insert_queue_entry("msg-1", "key-1", "slack", "#ops", None, None, "Deploy done", "normal", None)
+            .unwrap();
+        db.update_queue_status("msg-1", "approved").unwrap();
+        // Nothing listens on port 1, so every attempt fails with a connection error
+        let unreachable = TransportConfig::Webhook {
+            url: "http://127.0.0.1:1/hook".to_string(),
+            format: WebhookFormat::Json,
+            token: None,
+        };
+        transport::set_for_channel(&db, GatewayChannel::Slack, Some(&unreachable)).unwrap();
+
+        for attempt in 1..MAX_SEND_ATTEMPTS {
+            let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
+            let SendOutcome::Retrying(_, at) = send_approved(&db, &entry).unwrap() else {
+                panic!("attempt {} should be retried", attempt);
+            };
+            let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
+            assert_eq!(entry.status, "approved");
+            assert_eq!(entry.send_attempts, attempt);
+            assert!(db.list_due_sends(Utc::now()).unwrap().is_empty());
+            assert_eq!(db.list_due_sends(at + chrono::Duration::seconds(1)).unwrap().len(), 1);
+        }
+
+        let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
+        assert!(matches!(send_approved(&db, &entry).unwrap(), SendOutcome::Failed(_)));
+        let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
+        assert_eq!(entry.status, "failed");
+        assert_eq!(entry.send_attempts, MAX_SEND_ATTEMPTS);
+        assert!(entry.next_retry_at.is_none());
+        assert_eq!(db.list_failed_queue().unwrap().len(), 1);
+
+        // A manual retry starts over with a fresh set of attempts
+        assert!(matches!(retry(&db, "msg-1", audit::ACTOR_CLI).unwrap(), SendOutcome::Retrying(..)));
+        let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
+        assert_eq!((entry.status.as_str(), entry.send_attempts), ("approved", 1));
+        // Waiting retries belong to the retry worker
+        assert!(retry(&db, "msg-1", audit::ACTOR_CLI).is_err());
 
-#[cfg(not(target_os = "macos"))]
-fn send_message(_entry: &QueueEntry) -> Result<()> {
-    anyhow::bail!("SMS/iMessage sending is only available on macOS")
+        let events: Vec<String> = db.list_audit_events(0).unwrap().into_iter().map(|e| e.event).collect();
+        assert_eq!(events, vec!["failed", "retried"]);
+    }
+
+    #[test]
+    fn test_due_retry_is_sent_once() {
+        let db = Database::open_memory().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let outbox = dir.path().join("outbox.jsonl");
+        let file = TransportConfig::File { path: outbox.clone() };
+        transport::set_for_channel(&db, GatewayChannel::Sms, Some(&file)).unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        for id in ["msg-1", "msg-2"] {
+            db.insert_queue_entry(id, "key-1", "sms", "+15551234567", None, None, "Hi", "normal", None)
+                .unwrap();
+            db.update_queue_status(id, "approved").unwrap();
+            db.mark_queue_retry(id, "timed out", Utc::now() - chrono::Duration::seconds(1))
+                .unwrap();
+        }
+
+        // Another sender already claimed msg-2, so only msg-1 goes out
+        assert!(db.claim_due_send("msg-2", Utc::now()).unwrap());
+        send_due(&db).unwrap();
+        send_due(&db).unwrap();
+        let sent = std::fs::read_to_string(&outbox).unwrap();
+        assert_eq!(sent.lines().count(), 1);
+        assert!(sent.contains("msg-1"));
+        assert_eq!(db.get_queue_entry("msg-1").unwrap().unwrap().status, "sent");
+        assert_eq!(db.get_queue_entry("msg-2").unwrap().unwrap().status, "approved");
+    }
 }
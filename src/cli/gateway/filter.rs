@@ -1,10 +1,12 @@
 //! Content filter matching for message safety screening.
 //!
 //! Filters can either deny messages outright or flag them for extra review.
-//! Patterns can be regex or literal (case-insensitive) strings.
+//! Patterns can be regex or literal (case-insensitive) strings. A regex filter
+//! may name a validator that each match must also pass (e.g. a Luhn checksum),
+//! which keeps number patterns from flagging every long digit string.
 
 use regex::Regex;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 
 use crate::db::gateway::ContentFilter;
 use crate::db::Database;
@@ -36,9 +38,45 @@ struct CompiledFilter {
 
 enum CompiledPattern {
     Regex(Regex),
+    Validated(Regex, Validator),
     Literal(String), // Stored lowercase for case-insensitive matching
 }
 
+/// Extra check applied to each regex match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Validator {
+    /// Payment card number (Luhn checksum)
+    Luhn,
+    /// International Bank Account Number (ISO 7064 mod 97)
+    Iban,
+    /// US Social Security Number (excludes never-issued ranges)
+    Ssn,
+}
+
+impl std::str::FromStr for Validator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "luhn" => Ok(Validator::Luhn),
+            "iban" => Ok(Validator::Iban),
+            "ssn" => Ok(Validator::Ssn),
+            other => Err(anyhow::anyhow!("Unknown validator: {}", other)),
+        }
+    }
+}
+
+impl Validator {
+    /// Whether the matched text passes the check.
+    pub fn is_valid(self, text: &str) -> bool {
+        match self {
+            Validator::Luhn => luhn_valid(text),
+            Validator::Iban => iban_valid(text),
+            Validator::Ssn => ssn_valid(text),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 enum FilterAction {
     Deny,
@@ -50,6 +88,7 @@ enum FilterAction {
 /// Thread-safe via RwLock for concurrent access.
 pub struct ContentFilterMatcher {
     filters: RwLock<Vec<CompiledFilter>>,
+    revision: Mutex<Option<String>>,
 }
 
 impl ContentFilterMatcher {
@@ -57,7 +96,19 @@ impl ContentFilterMatcher {
     pub fn new() -> Self {
         Self {
             filters: RwLock::new(Vec::new()),
+            revision: Mutex::new(None),
+        }
+    }
+
+    /// Reload if filters changed in the database since the last load.
+    ///
+    /// Returns the new filter count when a reload happened.
+    pub fn reload_if_changed(&self, db: &Database) -> anyhow::Result<Option<usize>> {
+        let current = db.content_filters_revision()?;
+        if *self.revision.lock().unwrap() == current {
+            return Ok(None);
         }
+        self.reload(db).map(Some)
     }
 
     /// Load/reload filters from database.
@@ -65,6 +116,7 @@ impl ContentFilterMatcher {
     /// Only loads enabled filters. Compiles regex patterns once.
     /// Invalid regex patterns are skipped with a warning.
     pub fn reload(&self, db: &Database) -> anyhow::Result<usize> {
+        let revision = db.content_filters_revision()?;
         let db_filters = db.list_enabled_content_filters()?;
         let mut compiled = Vec::with_capacity(db_filters.len());
 
@@ -84,6 +136,7 @@ impl ContentFilterMatcher {
         let count = compiled.len();
         let mut filters = self.filters.write().unwrap();
         *filters = compiled;
+        *self.revision.lock().unwrap() = revision;
 
         Ok(count)
     }
@@ -94,29 +147,31 @@ impl ContentFilterMatcher {
     /// For emails, both subject and body should be checked by calling this
     /// method twice or using `check_email`.
     pub fn check(&self, content: &str) -> FilterResult {
-        let filters = self.filters.read().unwrap();
+        // Deny filters come first (they're sorted first from the DB query)
+        self.matches(content)
+            .into_iter()
+            .next()
+            .unwrap_or(FilterResult::Passed)
+    }
 
-        // Check deny filters first (they're sorted first from the DB query)
-        for filter in filters.iter() {
-            if pattern_matches(&filter.pattern, content) {
-                match filter.action {
-                    FilterAction::Deny => {
-                        return FilterResult::Denied {
-                            filter_name: filter.id.clone(),
-                            description: filter.description.clone(),
-                        };
-                    }
-                    FilterAction::Flag => {
-                        return FilterResult::Flagged {
-                            filter_name: filter.id.clone(),
-                            description: filter.description.clone(),
-                        };
-                    }
-                }
-            }
-        }
+    /// Every filter that matches `content`, deny filters first.
+    pub fn matches(&self, content: &str) -> Vec<FilterResult> {
+        let filters = self.filters.read().unwrap();
 
-        FilterResult::Passed
+        filters
+            .iter()
+            .filter(|filter| pattern_matches(&filter.pattern, content))
+            .map(|filter| match filter.action {
+                FilterAction::Deny => FilterResult::Denied {
+                    filter_name: filter.id.clone(),
+                    description: filter.description.clone(),
+                },
+                FilterAction::Flag => FilterResult::Flagged {
+                    filter_name: filter.id.clone(),
+                    description: filter.description.clone(),
+                },
+            })
+            .collect()
     }
 
     /// Check email content (subject + body) against filters.
@@ -159,7 +214,10 @@ fn compile_filter(filter: &ContentFilter) -> anyhow::Result<CompiledFilter> {
             // Build case-insensitive regex
             let regex = Regex::new(&format!("(?i){}", filter.pattern))
                 .map_err(|e| anyhow::anyhow!("Invalid regex: {}", e))?;
-            CompiledPattern::Regex(regex)
+            match filter.validator.as_deref() {
+                Some(name) => CompiledPattern::Validated(regex, name.parse()?),
+                None => CompiledPattern::Regex(regex),
+            }
         }
         "literal" => {
             // Store lowercase for case-insensitive matching
@@ -192,14 +250,86 @@ fn compile_filter(filter: &ContentFilter) -> anyhow::Result<CompiledFilter> {
     })
 }
 
+/// Check that a filter would compile, for validating user input.
+pub fn validate_filter(filter: &ContentFilter) -> anyhow::Result<()> {
+    compile_filter(filter).map(|_| ())
+}
+
 /// Check if content matches a compiled pattern.
 fn pattern_matches(pattern: &CompiledPattern, content: &str) -> bool {
     match pattern {
         CompiledPattern::Regex(re) => re.is_match(content),
+        CompiledPattern::Validated(re, validator) => {
+            re.find_iter(content).any(|m| validator.is_valid(m.as_str()))
+        }
         CompiledPattern::Literal(literal) => content.to_lowercase().contains(literal),
     }
 }
 
+/// Luhn checksum over the digits in `text` (separators ignored).
+fn luhn_valid(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let doubled = d * 2;
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// IBAN check digits: move the first four characters to the end, map letters
+/// to 10..35, and the number mod 97 must be 1.
+fn iban_valid(text: &str) -> bool {
+    let iban: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if !(15..=34).contains(&iban.len()) || !iban.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    let (head, tail) = iban.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let value = match c.to_digit(36) {
+            Some(v) => v,
+            None => return false,
+        };
+        remainder = if value >= 10 {
+            (remainder * 100 + value) % 97
+        } else {
+            (remainder * 10 + value) % 97
+        };
+    }
+    remainder == 1
+}
+
+/// SSN area, group and serial must not be in ranges that are never issued.
+fn ssn_valid(text: &str) -> bool {
+    let digits: String = text.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() != 9 {
+        return false;
+    }
+    let (area, rest) = digits.split_at(3);
+    let (group, serial) = rest.split_at(2);
+    area != "000" && area != "666" && !area.starts_with('9') && group != "00" && serial != "0000"
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -286,6 +416,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deny_wins_over_flag() {
+        let db = Database::open_memory().unwrap();
+        let matcher = ContentFilterMatcher::new();
+        matcher.reload(&db).unwrap();
+
+        // Matches both the 'password' flag filter and the SSN deny filter
+        let result = matcher.check("password reset for 123-45-6789");
+        assert!(matches!(result, FilterResult::Denied { .. }));
+    }
+
     #[test]
     fn test_check_email() {
         let db = Database::open_memory().unwrap();
@@ -309,6 +450,50 @@ mod tests {
         assert!(matches!(result, FilterResult::Passed));
     }
 
+    #[test]
+    fn test_validators() {
+        assert!(Validator::Luhn.is_valid("4111 1111 1111 1111"));
+        assert!(Validator::Luhn.is_valid("5500-0000-0000-0004"));
+        assert!(!Validator::Luhn.is_valid("4111 1111 1111 1112"));
+        assert!(!Validator::Luhn.is_valid("1234"));
+
+        assert!(Validator::Iban.is_valid("GB82 WEST 1234 5698 7654 32"));
+        assert!(Validator::Iban.is_valid("de89370400440532013000"));
+        assert!(!Validator::Iban.is_valid("GB82 WEST 1234 5698 7654 33"));
+
+        assert!(Validator::Ssn.is_valid("123-45-6789"));
+        assert!(!Validator::Ssn.is_valid("000-12-3456"));
+        assert!(!Validator::Ssn.is_valid("666-12-3456"));
+        assert!(!Validator::Ssn.is_valid("912-34-5678"));
+
+        assert!("crc".parse::<Validator>().is_err());
+    }
+
+    #[test]
+    fn test_validated_pattern_needs_valid_match() {
+        let regex = Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap();
+        let pattern = CompiledPattern::Validated(regex, Validator::Luhn);
+        assert!(pattern_matches(&pattern, "card 4111 1111 1111 1111 exp 12/29"));
+        assert!(!pattern_matches(&pattern, "order 1234 5678 9012 3456"));
+    }
+
+    #[test]
+    fn test_reload_if_changed() {
+        let db = Database::open_memory().unwrap();
+        let matcher = ContentFilterMatcher::new();
+        let count = matcher.reload(&db).unwrap();
+        assert_eq!(matcher.reload_if_changed(&db).unwrap(), None);
+
+        db.insert_content_filter("filter-1", "top secret", "literal", "deny", None)
+            .unwrap();
+        assert_eq!(matcher.reload_if_changed(&db).unwrap(), Some(count + 1));
+        assert!(matches!(matcher.check("TOP SECRET plans"), FilterResult::Denied { .. }));
+
+        db.set_content_filter_enabled("filter-1", false).unwrap();
+        assert_eq!(matcher.reload_if_changed(&db).unwrap(), Some(count));
+        assert!(matches!(matcher.check("TOP SECRET plans"), FilterResult::Passed));
+    }
+
     #[test]
     fn test_filter_count() {
         let db = Database::open_memory().unwrap();
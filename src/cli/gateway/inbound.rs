@@ -0,0 +1,343 @@
+//! Inbound message relay to agents.
+//!
+//! Polls a `MessageSource` for newly received messages and delivers each one
+//! to every agent whose allowlist covers the sender, via the key's webhook URL.
+//! Deliveries go through the signed, retried webhook delivery log. The
+//! source's cursor is saved only once a whole poll has been relayed; after a
+//! failure the rest of the poll is relayed before the source is polled again.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::server::{normalize_recipient, recipient_matches_allowlist};
+use super::webhook::{self, WebhookResult};
+use crate::cli::messages::source::{
+    ChatDbSource, DropDirSource, MessageSource, ReceivedMessage, ResumableSource,
+};
+use crate::db::gateway::ApiKey;
+use crate::db::Database;
+
+/// Settings key for the chat.db resume position.
+const SETTING_INBOUND_CHATDB_ROWID: &str = "gateway_inbound_chatdb_rowid";
+
+/// How often the source is polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Webhook event name for inbound deliveries.
+pub const INBOUND_EVENT: &str = "inbound_message";
+
+/// Payload POSTed to an agent's webhook for each inbound message.
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundPayload {
+    pub event: String,
+    pub message_id: String,
+    pub sender: String,
+    pub content: String,
+    pub channel: String,
+    pub received_at: String,
+    /// Most recent thread this agent started with the sender, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+}
+
+/// Where inbound messages come from.
+#[derive(Debug, Clone)]
+pub enum InboundSourceConfig {
+    /// macOS Messages database
+    ChatDb,
+    /// Directory of JSON message files
+    DropDir(PathBuf),
+}
+
+impl InboundSourceConfig {
+    /// Open the configured source, resuming from the saved cursor if any.
+    pub fn open(&self, db: &Database) -> Result<Box<dyn MessageSource>> {
+        match self {
+            InboundSourceConfig::ChatDb => {
+                let cursor = db.get_setting(SETTING_INBOUND_CHATDB_ROWID)?;
+                Ok(Box::new(ChatDbSource::new(cursor.as_deref())?))
+            }
+            InboundSourceConfig::DropDir(dir) => Ok(Box::new(DropDirSource::new(dir.clone())?)),
+        }
+    }
+
+    fn cursor_setting(&self) -> Option<&'static str> {
+        match self {
+            InboundSourceConfig::ChatDb => Some(SETTING_INBOUND_CHATDB_ROWID),
+            InboundSourceConfig::DropDir(_) => None,
+        }
+    }
+}
+
+/// Relay one received message to every eligible agent.
+///
+/// A key is eligible when it is active, has a webhook URL, and has a
+/// non-empty allowlist matching the sender. Messages from contacts who
+/// have opted out of AI contact are never relayed. Returns the number
+/// of successful deliveries.
+pub fn relay_message(
+    db: &Database,
+    msg: &ReceivedMessage,
+    deliver: &mut dyn FnMut(&ApiKey, &InboundPayload) -> Result<()>,
+) -> Result<usize> {
+    let contact = if msg.sender.contains('@') {
+        db.get_person_by_email(&msg.sender)?
+    } else {
+        db.get_person_by_phone(&msg.sender)?
+    };
+    if contact.is_some_and(|person| !person.ai_contact_allowed) {
+        return Ok(0);
+    }
+
+    let mut delivered = 0;
+    for key in db.list_api_keys()? {
+        if key.revoked_at.is_some() || key.is_expired(chrono::Utc::now()) || key.webhook_url.is_none() {
+            continue;
+        }
+
+        let patterns: Vec<String> = db
+            .list_allowlist_entries(&key.id)?
+            .into_iter()
+            .map(|e| e.recipient_pattern)
+            .collect();
+        if patterns.is_empty() || !recipient_matches_allowlist(&msg.sender, &patterns) {
+            continue;
+        }
+
+        let payload = InboundPayload {
+            event: INBOUND_EVENT.to_string(),
+            message_id: msg.id.clone(),
+            sender: msg.sender.clone(),
+            content: msg.content.clone(),
+            channel: msg.channel.clone(),
+            received_at: msg.received_at.to_rfc3339(),
+            thread_id: latest_thread_with(db, &key.id, &msg.sender)?,
+        };
+
+        match deliver(&key, &payload) {
+            Ok(()) => delivered += 1,
+            Err(e) => eprintln!(
+                "Warning: Inbound delivery of {} to '{}' failed: {}",
+                msg.id, key.name, e
+            ),
+        }
+    }
+
+    Ok(delivered)
+}
+
+/// Most recent thread an agent started with `sender`.
+fn latest_thread_with(db: &Database, api_key_id: &str, sender: &str) -> Result<Option<String>> {
+    let sender = normalize_recipient(sender);
+    Ok(db
+        .list_threaded_recipients(api_key_id)?
+        .into_iter()
+        .find(|(recipient, _)| normalize_recipient(recipient) == sender)
+        .map(|(_, thread_id)| thread_id))
+}
+
+/// Deliver a payload to the key's webhook via the delivery log (signed, retried on failure).
+fn deliver_webhook(db: &Database, key: &ApiKey, payload: &InboundPayload) -> Result<()> {
+    let body = serde_json::to_string(payload)?;
+    match webhook::deliver_event(db, &key.id, INBOUND_EVENT, None, &body) {
+        WebhookResult::Delivered | WebhookResult::NoWebhook | WebhookResult::InFlight => Ok(()),
+        WebhookResult::Failed(e) => Err(anyhow::anyhow!("{} (will retry)", e)),
+    }
+}
+
+/// Poll the inbound source until shutdown, relaying each new message.
+pub fn run_poller(db_path: PathBuf, config: InboundSourceConfig, shutdown: Arc<AtomicBool>) {
+    let mut relay =
+        match Database::open_at(db_path.clone()).and_then(|db| InboundRelay::open(&db, config)) {
+            Ok(relay) => relay,
+            Err(e) => {
+                eprintln!("Inbound relay disabled: {}", e);
+                return;
+            }
+        };
+    println!("Inbound relay polling {} source", relay.messages.name());
+
+    let mut last_poll: Option<Instant> = None;
+    while !shutdown.load(Ordering::SeqCst) {
+        if last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+            std::thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        last_poll = Some(Instant::now());
+
+        let result = Database::open_at(db_path.clone()).and_then(|db| relay.relay(&db));
+        if let Err(e) = result {
+            eprintln!("Inbound poll error: {}", e);
+        }
+    }
+}
+
+/// Reads new messages from the source and relays them to agents.
+struct InboundRelay {
+    messages: ResumableSource,
+}
+
+impl InboundRelay {
+    fn open(db: &Database, config: InboundSourceConfig) -> Result<Self> {
+        let source = config.open(db)?;
+        Ok(Self {
+            messages: ResumableSource::new(source, config.cursor_setting()),
+        })
+    }
+
+    /// Relay every message left over from a failed attempt, or else poll the
+    /// source, stopping at the first failure so the rest are retried in order.
+    fn relay(&mut self, db: &Database) -> Result<usize> {
+        self.messages.drain(db, &mut |msg| {
+            relay_message(db, msg, &mut |key, payload| {
+                deliver_webhook(db, key, payload)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Email, Person};
+    use chrono::Utc;
+
+    fn received(sender: &str) -> ReceivedMessage {
+        ReceivedMessage {
+            id: "m-1".to_string(),
+            sender: sender.to_string(),
+            content: "Sounds good".to_string(),
+            channel: "sms".to_string(),
+            received_at: Utc::now(),
+        }
+    }
+
+    fn setup_key(db: &Database, id: &str, pattern: Option<&str>) {
+        db.insert_api_key(id, id, &format!("hash-{}", id), "gw_abc").unwrap();
+        db.set_api_key_webhook(id, Some("https://agent.example.com/hook")).unwrap();
+        if let Some(pattern) = pattern {
+            db.insert_allowlist_entry(&format!("al-{}", id), id, pattern).unwrap();
+        }
+    }
+
+    fn relay(db: &Database, msg: &ReceivedMessage) -> Vec<(String, InboundPayload)> {
+        let mut calls = Vec::new();
+        relay_message(db, msg, &mut |key, payload| {
+            calls.push((key.id.clone(), payload.clone()));
+            Ok(())
+        })
+        .unwrap();
+        calls
+    }
+
+    #[test]
+    fn test_relay_requires_allowlist_match() {
+        let db = Database::open_memory().unwrap();
+        setup_key(&db, "key-match", Some("+15551234567"));
+        setup_key(&db, "key-other", Some("+15559999999"));
+        setup_key(&db, "key-open", None);
+        setup_key(&db, "key-revoked", Some("+15551234567"));
+        db.revoke_api_key("key-revoked").unwrap();
+
+        let calls = relay(&db, &received("+1 (555) 123-4567"));
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "key-match");
+        assert_eq!(calls[0].1.event, INBOUND_EVENT);
+        assert!(calls[0].1.thread_id.is_none());
+    }
+
+    #[test]
+    fn test_relay_respects_contact_consent() {
+        let db = Database::open_memory().unwrap();
+        setup_key(&db, "key-1", Some("*@example.com"));
+
+        let mut person = Person::new();
+        person.ai_contact_allowed = false;
+        db.insert_person(&person).unwrap();
+        db.insert_email(&Email::new(person.id, "bob@example.com".to_string())).unwrap();
+
+        assert!(relay(&db, &received("bob@example.com")).is_empty());
+        assert_eq!(relay(&db, &received("carol@example.com")).len(), 1);
+    }
+
+    #[test]
+    fn test_relay_links_latest_thread() {
+        let db = Database::open_memory().unwrap();
+        setup_key(&db, "key-1", Some("+15551234567"));
+        db.insert_queue_entry("msg-1", "key-1", "sms", "+15551234567", None, None, "Hi", "normal", None)
+            .unwrap();
+        db.set_queue_thread("msg-1", "conv-1", None).unwrap();
+
+        let calls = relay(&db, &received("+15551234567"));
+        assert_eq!(calls[0].1.thread_id.as_deref(), Some("conv-1"));
+    }
+
+    /// A source that returns `messages` on its first poll.
+    struct Batch {
+        messages: Vec<ReceivedMessage>,
+    }
+
+    impl MessageSource for Batch {
+        fn name(&self) -> &str {
+            "batch"
+        }
+
+        fn poll(&mut self) -> Result<Vec<ReceivedMessage>> {
+            Ok(std::mem::take(&mut self.messages))
+        }
+
+        fn cursor(&self) -> Option<String> {
+            Some("2".to_string())
+        }
+    }
+
+    #[test]
+    fn test_failed_relay_keeps_rest_of_batch() {
+        let db = Database::open_memory().unwrap();
+        let mut second = received("+15551234567");
+        second.id = "m-2".to_string();
+        let mut relay = ResumableSource::new(
+            Box::new(Batch {
+                messages: vec![received("+15551234567"), second],
+            }),
+            InboundSourceConfig::ChatDb.cursor_setting(),
+        );
+
+        // A failure part way leaves the rest pending and the cursor unsaved
+        let mut relayed = Vec::new();
+        let err = relay
+            .drain(&db, &mut |msg| {
+                if msg.id == "m-2" {
+                    return Err(anyhow::anyhow!("database is locked"));
+                }
+                relayed.push(msg.id.clone());
+                Ok(1)
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("locked"));
+        assert!(db
+            .get_setting(SETTING_INBOUND_CHATDB_ROWID)
+            .unwrap()
+            .is_none());
+
+        // The next attempt picks up where it stopped
+        let delivered = relay
+            .drain(&db, &mut |msg| {
+                relayed.push(msg.id.clone());
+                Ok(1)
+            })
+            .unwrap();
+        assert_eq!(delivered, 1);
+        assert_eq!(relayed, vec!["m-1", "m-2"]);
+        assert_eq!(
+            db.get_setting(SETTING_INBOUND_CHATDB_ROWID)
+                .unwrap()
+                .as_deref(),
+            Some("2")
+        );
+    }
+}
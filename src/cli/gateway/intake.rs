@@ -0,0 +1,494 @@
+//! Checks and queueing shared by `/gateway/send` and the Moltbot bridge.
+//!
+//! Every message an agent asks to send goes through `queue_message`: scopes,
+//! recipient resolution, allowlist, consent, templates, content filters,
+//! threads, rate limits and risk classification, then the approval queue.
+//! `approve` sends a reviewed message; it serves the review endpoints and
+//! pre-approved templates alike.
+
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+use super::audit::{self, AuditAction};
+use super::classify::{self, RiskAssessment};
+use super::execute::{self, SendOutcome};
+use super::filter::{ContentFilterMatcher, FilterResult};
+use super::ratelimit;
+use super::recipient;
+use super::scope;
+use super::server::recipient_matches_allowlist;
+use super::template;
+use super::thread;
+use super::types::{
+    ActionStatusResponse, AllowlistErrorResponse, ConsentDeniedErrorResponse,
+    ContentBlockedErrorResponse, ErrorCode, ErrorResponse, FieldError, GatewayChannel,
+    QueueStatus, SendRequest, SendResponse,
+};
+use crate::cli::ai::{self, AiConfig, AiProvider};
+use crate::cli::http_server::HttpResponse;
+use crate::db::gateway::ApiKey;
+use crate::db::Database;
+
+/// What the checks keep between messages: compiled content filters and the
+/// risk classifier.
+pub struct SendChecks {
+    content_filter: ContentFilterMatcher,
+    /// Created on first use, for keys with risk classification enabled
+    risk_provider: Mutex<Option<Arc<dyn AiProvider>>>,
+}
+
+impl SendChecks {
+    /// Compile the content filters.
+    pub fn load(db: &Database) -> Result<Self> {
+        let content_filter = ContentFilterMatcher::new();
+        let filter_count = content_filter.reload(db)?;
+        if filter_count > 0 {
+            println!("Loaded {} content filter(s)", filter_count);
+        }
+        Ok(Self {
+            content_filter,
+            risk_provider: Mutex::new(None),
+        })
+    }
+
+    pub fn content_filter(&self) -> &ContentFilterMatcher {
+        &self.content_filter
+    }
+
+    /// Run the risk classifier over a message body. Only the body is sent.
+    fn classify_body(&self, db: &Database, body: &str) -> Result<RiskAssessment> {
+        let provider = {
+            let mut slot = self.risk_provider.lock().unwrap();
+            match slot.as_ref() {
+                Some(p) => p.clone(),
+                None => {
+                    let config = AiConfig::load(db)?;
+                    let p: Arc<dyn AiProvider> = Arc::from(ai::create_provider(&config)?);
+                    *slot = Some(p.clone());
+                    p
+                }
+            }
+        };
+        classify::classify_with_timeout(provider, body, classify::CLASSIFY_TIMEOUT)
+    }
+}
+
+/// A message the gateway refused to queue: the response for a single send and
+/// the error body for a batch result.
+pub(super) struct Rejected(pub(super) HttpResponse, pub(super) serde_json::Value);
+
+impl Rejected {
+    pub(super) fn new<T: serde::Serialize>(code: ErrorCode, body: &T) -> Self {
+        Self(
+            HttpResponse::json(code.http_status(), body),
+            serde_json::to_value(body).unwrap_or_default(),
+        )
+    }
+}
+
+/// Queue a message for an in-process agent such as the Moltbot bridge,
+/// with the same checks as `/gateway/send`. Rejections carry the error body.
+pub fn submit(
+    db: &Database,
+    checks: &SendChecks,
+    api_key: &ApiKey,
+    req: SendRequest,
+) -> Result<Result<SendResponse, serde_json::Value>> {
+    Ok(queue_message(db, checks, api_key, req, None, None)?.map_err(|Rejected(_, body)| body))
+}
+
+/// Check, render and queue one message, approving it straight away if its
+/// template is pre-approved. Rejections carry the error body for the agent.
+/// An idempotency key is recorded in the same transaction as the queue entry.
+pub(super) fn queue_message(
+    db: &Database,
+    checks: &SendChecks,
+    api_key: &ApiKey,
+    mut req: SendRequest,
+    batch_id: Option<&str>,
+    idempotency_key: Option<&str>,
+) -> Result<Result<SendResponse, Rejected>> {
+    // Check the key's scopes
+    if let Err(response) = scope::check_send(&api_key.scopes, &req) {
+        return Ok(Err(Rejected::new(response.code, &response)));
+    }
+
+    // Address a contact by its primary address for the channel; the agent
+    // only ever sees the contact reference
+    let mut resolved_contact = None;
+    if let Some(ref reference) = req.recipient_contact {
+        match recipient::resolve(db, reference, req.channel)? {
+            Ok(resolved) => {
+                req.recipient_address = resolved.address;
+                req.recipient_name = resolved.person.display_name.clone();
+                resolved_contact = Some(resolved.person);
+            }
+            Err(problem) => {
+                let response = ErrorResponse::validation(vec![FieldError::new("recipient_contact", problem)]);
+                return Ok(Err(Rejected::new(response.code, &response)));
+            }
+        }
+    }
+    let contact_ref = resolved_contact.as_ref().map(|p| recipient::contact_ref(p.id));
+
+    // Check recipient allowlist
+    let allowlist = db.list_allowlist_entries(&api_key.id)?;
+    if !allowlist.is_empty() {
+        let patterns: Vec<String> = allowlist.iter().map(|e| e.recipient_pattern.clone()).collect();
+        if !recipient_matches_allowlist(&req.recipient_address, &patterns) {
+            let response = AllowlistErrorResponse {
+                success: false,
+                code: ErrorCode::AllowlistDenied,
+                error: "Recipient is not on this key's allowlist".to_string(),
+                allowed_patterns: patterns,
+            };
+            return Ok(Err(Rejected::new(response.code, &response)));
+        }
+    }
+
+    // Check contact AI consent flag
+    // Look up recipient by email or phone to check if they've opted out
+    let contact = if resolved_contact.is_some() {
+        resolved_contact
+    } else if req.recipient_address.contains('@') {
+        db.get_person_by_email(&req.recipient_address)?
+    } else {
+        db.get_person_by_phone(&req.recipient_address)?
+    };
+
+    if let Some(ref person) = contact {
+        if !person.ai_contact_allowed {
+            let response = ConsentDeniedErrorResponse {
+                success: false,
+                code: ErrorCode::ConsentDenied,
+                error: "Contact has opted out of AI contact".to_string(),
+                recipient: contact_ref.clone().unwrap_or_else(|| req.recipient_address.clone()),
+            };
+            return Ok(Err(Rejected::new(response.code, &response)));
+        }
+    }
+
+    // Render templated messages so everything below sees the final text
+    let template = match req.template_id.as_deref() {
+        Some(template_id) => {
+            let Some(template) = db.get_template(template_id)? else {
+                let response =
+                    ErrorResponse::validation(vec![FieldError::new("template_id", "no such template")]);
+                return Ok(Err(Rejected::new(response.code, &response)));
+            };
+            let fields = template::contact_fields(db, contact.as_ref())?;
+            match template::render(&template, &fields, &req.variables) {
+                Ok(rendered) if req.channel == GatewayChannel::Email && rendered.subject.is_none() => {
+                    let response = ErrorResponse::validation(vec![FieldError::new(
+                        "template_id",
+                        "has no subject, which email requires",
+                    )]);
+                    return Ok(Err(Rejected::new(response.code, &response)));
+                }
+                Ok(rendered) => {
+                    req.subject = rendered.subject;
+                    req.body = rendered.body;
+                }
+                Err(problems) => {
+                    let response =
+                        ErrorResponse::validation(vec![FieldError::new("variables", problems.join("; "))]);
+                    return Ok(Err(Rejected::new(response.code, &response)));
+                }
+            }
+            Some(template)
+        }
+        None => None,
+    };
+
+    // Pick up filter changes made since the last request
+    if let Some(count) = checks.content_filter.reload_if_changed(db)? {
+        println!("Reloaded {} content filter(s)", count);
+    }
+
+    // Check content filters (subject + body for email, body only for SMS/iMessage)
+    let filter_result = if matches!(req.channel, GatewayChannel::Email) {
+        checks.content_filter.check_email(req.subject.as_deref(), &req.body)
+    } else {
+        checks.content_filter.check_message(&req.body)
+    };
+
+    // Handle deny filter match - reject immediately
+    if let FilterResult::Denied {
+        filter_name,
+        description,
+    } = filter_result
+    {
+        audit::record(
+            db,
+            AuditAction::Filtered,
+            &audit::agent_actor(&api_key.id),
+            None,
+            json!({
+                "channel": req.channel.to_string(),
+                "recipient": req.recipient_address,
+                "filter": filter_name,
+                "content_sha256": audit::content_hash(req.subject.as_deref(), &req.body),
+            }),
+        )?;
+        let response = ContentBlockedErrorResponse {
+            success: false,
+            code: ErrorCode::ContentBlocked,
+            error: "Message blocked by content filter".to_string(),
+            filter: filter_name,
+            description,
+        };
+        return Ok(Err(Rejected::new(response.code, &response)));
+    }
+
+    // Determine initial status (flagged if filter matched, pending otherwise)
+    let mut initial_status = if matches!(filter_result, FilterResult::Flagged { .. }) {
+        "flagged"
+    } else {
+        "pending"
+    };
+
+    // Resolve conversation thread before queueing
+    let id = uuid::Uuid::new_v4().to_string();
+    let thread_id = match thread::resolve_thread(
+        db,
+        &api_key.id,
+        &id,
+        req.thread_id.as_deref(),
+        req.in_reply_to.as_deref(),
+    ) {
+        Ok(t) => t,
+        Err(e) => {
+            // thread_id was validated with the request, so this is a bad in_reply_to
+            let response =
+                ErrorResponse::validation(vec![FieldError::new("in_reply_to", e.to_string())]);
+            return Ok(Err(Rejected::new(response.code, &response)));
+        }
+    };
+
+    // Spend rate limit tokens last, so rejected requests don't count
+    let recipient = ratelimit::recipient_identity(
+        contact.as_ref().map(|p| p.id.to_string()).as_deref(),
+        &req.recipient_address,
+    );
+    let limits = ratelimit::limits_for(&ratelimit::GlobalLimits::load(db)?, api_key, &recipient);
+    if let Err(exceeded) = ratelimit::acquire(db, &limits, chrono::Utc::now())? {
+        let response = exceeded.to_response();
+        ratelimit::record_rejection(
+            db,
+            &api_key.id,
+            &response.limit_type,
+            &req.channel.to_string(),
+            chrono::Utc::now(),
+        )?;
+        return Ok(Err(Rejected::new(response.code, &response)));
+    }
+
+    // Score the body for keys that opted in; flag if risky or unscored
+    let risk = if api_key.risk_classification {
+        let risk = checks.classify_body(db, &req.body);
+        let threshold = classify::threshold(db)?;
+        if risk.as_ref().map_or(true, |r| r.score >= threshold) {
+            initial_status = "flagged";
+        }
+        Some(risk)
+    } else {
+        None
+    };
+
+    // Insert into queue
+    let context_json = req.context.and_then(|c| serde_json::to_string(&c).ok());
+
+    db.in_transaction(|| {
+        db.insert_queue_entry(
+            &id,
+            &api_key.id,
+            &req.channel.to_string(),
+            &req.recipient_address,
+            req.recipient_name.as_deref(),
+            req.subject.as_deref(),
+            &req.body,
+            &req.priority.to_string(),
+            context_json.as_deref(),
+        )?;
+        if let Some(key) = idempotency_key {
+            db.insert_idempotency_key(&api_key.id, key, &id)?;
+        }
+        Ok(())
+    })?;
+
+    db.set_queue_thread(&id, &thread_id, req.in_reply_to.as_deref())?;
+    if let Some(batch_id) = batch_id {
+        db.set_queue_batch(&id, batch_id)?;
+    }
+    if let Some(ref template) = template {
+        db.set_queue_template(&id, &template.id)?;
+    }
+    if let Some(ref reference) = contact_ref {
+        db.set_queue_recipient_contact(&id, reference)?;
+    }
+    match risk {
+        Some(Ok(r)) => {
+            db.set_queue_risk(&id, Some(r.score), &r.reasons)?;
+        }
+        Some(Err(e)) => {
+            db.set_queue_risk(&id, None, &[format!("Classification failed: {}", e)])?;
+        }
+        None => {}
+    }
+
+    // If flagged, update status from pending to flagged
+    if initial_status == "flagged" {
+        db.update_queue_status(&id, "flagged")?;
+    }
+    let mut details = json!({
+        "channel": req.channel.to_string(),
+        "recipient": req.recipient_address,
+        "priority": req.priority.to_string(),
+        "status": initial_status,
+        "content_sha256": audit::content_hash(req.subject.as_deref(), &req.body),
+    });
+    if let FilterResult::Flagged { ref filter_name, .. } = filter_result {
+        details["filter"] = json!(filter_name);
+    }
+    if let Some(ref template) = template {
+        details["template"] = json!(template.id);
+    }
+    if let Some(batch_id) = batch_id {
+        details["batch"] = json!(batch_id);
+    }
+    if let Some(ref reference) = contact_ref {
+        details["contact"] = json!(reference);
+    }
+    audit::record(db, AuditAction::Queued, &audit::agent_actor(&api_key.id), Some(&id), details)?;
+
+    // Update key last_used
+    db.touch_api_key(&api_key.id)?;
+
+    let mut response_status = if initial_status == "flagged" {
+        QueueStatus::Flagged
+    } else {
+        QueueStatus::Pending
+    };
+
+    // Pre-approved templates skip review unless a filter or the classifier flagged
+    // them, or the agent's variables carry more than the template vouches for
+    let auto_approve = template
+        .filter(|t| t.pre_approved && initial_status == "pending")
+        .filter(|_| template::allows_pre_approval(&req.variables));
+    if let Some(template) = auto_approve {
+        match approve(db, &id, &audit::template_actor(&template.id)) {
+            Ok(Ok(status)) => response_status = status.status,
+            // The message is queued either way; report where it stands
+            result => {
+                if let Err(e) = result {
+                    eprintln!("Warning: Failed to approve {} from template {}: {}", id, template.id, e);
+                }
+                if let Some(entry) = db.get_queue_entry(&id)? {
+                    response_status = entry.status.parse().unwrap_or(response_status);
+                }
+            }
+        }
+    }
+
+    Ok(Ok(SendResponse {
+        action_id: id,
+        status: response_status,
+        thread_id,
+        recipient_contact: contact_ref,
+    }))
+}
+
+/// Approve a pending or flagged message and send it.
+pub fn approve(
+    db: &Database,
+    id: &str,
+    actor: &str,
+) -> Result<Result<ActionStatusResponse, ErrorResponse>> {
+    if db.get_queue_entry(id)?.is_none() {
+        return Ok(Err(ErrorResponse::new(ErrorCode::NotFound, "Message not found")));
+    }
+    // Only one reviewer can move the entry on, so it is never sent twice
+    if !db.review_queue_entry(id, "approved")? {
+        return Ok(Err(already_reviewed(db, id, "approve")?));
+    }
+    // Read it again: an edit just before approval must be what gets sent
+    let entry = db
+        .get_queue_entry(id)?
+        .ok_or_else(|| anyhow!("Message disappeared after approval"))?;
+    audit::record(
+        db,
+        AuditAction::Approved,
+        actor,
+        Some(id),
+        json!({ "content_sha256": audit::content_hash(entry.subject.as_deref(), &entry.body) }),
+    )?;
+
+    // Execute send
+    let (status, error_message, sent_at) = match execute::send_approved(db, &entry)? {
+        SendOutcome::Sent(sent_at) => (QueueStatus::Sent, None, Some(sent_at)),
+        SendOutcome::Failed(e) => (QueueStatus::Failed, Some(e), None),
+        SendOutcome::Retrying(e, _) => (QueueStatus::Approved, Some(e), None),
+    };
+    Ok(Ok(ActionStatusResponse {
+        action_id: id.to_string(),
+        status,
+        error_message,
+        sent_at,
+        thread_id: entry.thread_id.clone(),
+        batch_id: entry.batch_id.clone(),
+    }))
+}
+
+/// Error for an entry that is no longer awaiting review.
+pub(super) fn already_reviewed(db: &Database, id: &str, action: &str) -> Result<ErrorResponse> {
+    let status = db
+        .get_queue_entry(id)?
+        .map(|entry| entry.status)
+        .unwrap_or_else(|| "unknown".to_string());
+    Ok(ErrorResponse::new(
+        ErrorCode::InvalidState,
+        format!("Cannot {}: status is {}", action, status),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::gateway::transport::{self, TransportConfig};
+
+    #[test]
+    fn test_pre_approved_template_needs_plain_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_memory().unwrap();
+        let checks = SendChecks::load(&db).unwrap();
+
+        let outbox = dir.path().join("outbox.jsonl");
+        let file = TransportConfig::File { path: outbox.clone() };
+        transport::set_for_channel(&db, GatewayChannel::Sms, Some(&file)).unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        let key = db.find_api_key_by_hash("hash").unwrap().unwrap();
+        db.insert_template("confirm", None, "Confirmed for {time}").unwrap();
+        db.set_template_pre_approved("confirm", true).unwrap();
+
+        let send = |time: &str| {
+            let req = SendRequest::from_json(
+                json!({
+                    "channel": "sms",
+                    "recipient_address": "+15551234567",
+                    "template_id": "confirm",
+                    "variables": { "time": time },
+                })
+                .to_string()
+                .as_bytes(),
+            )
+            .unwrap();
+            submit(&db, &checks, &key, req).unwrap().unwrap()
+        };
+
+        assert_eq!(send("3pm").status, QueueStatus::Sent);
+        // A link smuggled into a variable isn't covered by the template's approval
+        assert_eq!(send("3pm, see https://evil.example").status, QueueStatus::Pending);
+        assert_eq!(std::fs::read_to_string(&outbox).unwrap().lines().count(), 1);
+    }
+}
@@ -1,9 +1,12 @@
 //! API key generation and validation for the gateway.
 
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 use rand::Rng;
 use sha2::{Digest, Sha256};
 
+use crate::db::gateway::ApiKey;
+
 /// Prefix for gateway API keys.
 const KEY_PREFIX: &str = "gw_";
 
@@ -59,9 +62,91 @@ pub fn validate_key_format(key: &str) -> Result<()> {
     Ok(())
 }
 
+/// Parse a duration like "30m", "24h", "90d" or "2w". A bare "0" means no time.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    if s == "0" {
+        return Ok(Duration::zero());
+    }
+    let split = s.len() - s.chars().last().map_or(0, |c| c.len_utf8());
+    let (number, unit) = s.split_at(split);
+    let n: i64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration '{}': expected e.g. 30m, 24h, 90d, 2w", s))?;
+    if n < 0 {
+        anyhow::bail!("Invalid duration '{}': must not be negative", s);
+    }
+    match unit {
+        "m" => Ok(Duration::minutes(n)),
+        "h" => Ok(Duration::hours(n)),
+        "d" => Ok(Duration::days(n)),
+        "w" => Ok(Duration::weeks(n)),
+        _ => anyhow::bail!("Invalid duration '{}': unit must be m, h, d or w", s),
+    }
+}
+
+/// Problems with an active key worth surfacing in `gateway status`:
+/// expired, expiring within `expiry_warning_days`, or unused for `unused_days`.
+pub fn key_warnings(
+    key: &ApiKey,
+    now: DateTime<Utc>,
+    expiry_warning_days: i64,
+    unused_days: i64,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if key.revoked_at.is_some() {
+        return warnings;
+    }
+
+    if let Some(expires_at) = key.expires_at {
+        if expires_at <= now {
+            warnings.push(format!("expired {}", expires_at.format("%Y-%m-%d %H:%M")));
+        } else if expires_at - now <= Duration::days(expiry_warning_days) {
+            warnings.push(format!(
+                "expires in {} ({})",
+                format_duration(expires_at - now),
+                expires_at.format("%Y-%m-%d %H:%M")
+            ));
+        }
+    }
+
+    let idle_since = key.last_used_at.unwrap_or(key.created_at);
+    let idle = now - idle_since;
+    if idle >= Duration::days(unused_days) {
+        if key.last_used_at.is_some() {
+            warnings.push(format!("unused for {} days", idle.num_days()));
+        } else {
+            warnings.push(format!("never used (created {} days ago)", idle.num_days()));
+        }
+    }
+
+    warnings
+}
+
+/// New expiry for a key being rotated. An expired key gets a fresh term as long
+/// as its first one (at least a day), so the new secret isn't dead on arrival;
+/// otherwise `None` keeps the current expiry.
+pub fn renewed_expiry(key: &ApiKey, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    key.expires_at
+        .filter(|expires_at| *expires_at <= now)
+        .map(|expires_at| now + (expires_at - key.created_at).max(Duration::days(1)))
+}
+
+/// Coarse human-readable duration, e.g. "3d", "5h", "12m".
+pub fn format_duration(d: Duration) -> String {
+    if d.num_days() > 0 {
+        format!("{}d", d.num_days())
+    } else if d.num_hours() > 0 {
+        format!("{}h", d.num_hours())
+    } else {
+        format!("{}m", d.num_minutes().max(1))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::Database;
 
     #[test]
     fn test_generate_api_key() {
@@ -109,4 +194,67 @@ mod tests {
         let bad_key = format!("gw_{}", "g".repeat(48));
         assert!(validate_key_format(&bad_key).is_err());
     }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_duration("24h").unwrap(), Duration::hours(24));
+        assert_eq!(parse_duration("90d").unwrap(), Duration::days(90));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::weeks(2));
+        assert_eq!(parse_duration("0").unwrap(), Duration::zero());
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("24").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("-1d").is_err());
+    }
+
+    fn api_key() -> ApiKey {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Agent", "hash", "gw_abc").unwrap();
+        db.find_api_key_by_hash("hash").unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_key_warnings() {
+        let now = Utc::now();
+        let mut key = api_key();
+        key.created_at = now - Duration::days(1);
+        key.last_used_at = Some(now);
+        assert!(key_warnings(&key, now, 7, 30).is_empty());
+
+        key.expires_at = Some(now + Duration::days(3));
+        let warnings = key_warnings(&key, now, 7, 30);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("expires in 3d"));
+
+        key.expires_at = Some(now - Duration::hours(1));
+        assert!(key_warnings(&key, now, 7, 30)[0].starts_with("expired"));
+
+        key.expires_at = None;
+        key.last_used_at = Some(now - Duration::days(45));
+        assert_eq!(key_warnings(&key, now, 7, 30), vec!["unused for 45 days"]);
+
+        key.revoked_at = Some(now);
+        assert!(key_warnings(&key, now, 7, 30).is_empty());
+    }
+
+    #[test]
+    fn test_renewed_expiry() {
+        let now = Utc::now();
+        let mut key = api_key();
+        key.created_at = now - Duration::days(100);
+        assert_eq!(renewed_expiry(&key, now), None);
+
+        key.expires_at = Some(now + Duration::days(1));
+        assert_eq!(renewed_expiry(&key, now), None);
+
+        // Expired after a 90-day term: the rotated key gets another 90 days
+        key.expires_at = Some(now - Duration::days(10));
+        assert_eq!(renewed_expiry(&key, now), Some(now + Duration::days(90)));
+
+        key.expires_at = Some(key.created_at);
+        assert_eq!(renewed_expiry(&key, now), Some(now + Duration::days(1)));
+    }
 }
@@ -0,0 +1,395 @@
+//! Gateway metrics, served at `/gateway/metrics` and summarised by `gateway status`.
+//!
+//! Counters come from the audit log rather than from memory, so they survive
+//! restarts and the CLI can read them without asking the running server.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::db::gateway::{MessageEventCount, QueueBacklog};
+use crate::db::Database;
+
+use super::keys::format_duration;
+
+/// Upper bounds of the review latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS_SECS: [i64; 8] =
+    [60, 300, 900, 1800, 3600, 4 * 3600, 12 * 3600, 24 * 3600];
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Everything `/gateway/metrics` reports.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub messages: Vec<MessageEventCount>,
+    /// (filter ID, "deny" or "flag", count)
+    pub filter_hits: Vec<(String, String, i64)>,
+    /// (key name, limit type, count)
+    pub rate_limited: Vec<(String, String, i64)>,
+    /// Seconds from queueing to review, sorted, keyed by "approved" or "denied"
+    pub review_latencies: BTreeMap<String, Vec<i64>>,
+    /// (key name, count)
+    pub webhook_failures: Vec<(String, i64)>,
+    pub backlog: QueueBacklog,
+}
+
+impl Metrics {
+    pub fn collect(db: &Database) -> Result<Self> {
+        let mut review_latencies: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for (decision, secs) in db.list_review_latencies()? {
+            review_latencies.entry(decision).or_default().push(secs);
+        }
+        for latencies in review_latencies.values_mut() {
+            latencies.sort_unstable();
+        }
+
+        Ok(Self {
+            messages: db.count_message_events()?,
+            filter_hits: db.count_filter_hits()?,
+            rate_limited: db.count_rate_limited()?,
+            review_latencies,
+            webhook_failures: db.count_webhook_failures()?,
+            backlog: db.queue_backlog()?,
+        })
+    }
+
+    /// Seconds the oldest message has been waiting for review.
+    pub fn oldest_waiting_secs(&self, now: DateTime<Utc>) -> Option<i64> {
+        self.backlog
+            .oldest_waiting
+            .map(|at| (now - at).num_seconds().max(0))
+    }
+
+    /// Render in the Prometheus text exposition format.
+    pub fn to_prometheus(&self, now: DateTime<Utc>) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "gateway_messages_total",
+            "counter",
+            "Message events by API key, channel and event.",
+        );
+        for m in &self.messages {
+            let labels = labels(&[
+                ("key", &m.key_name),
+                ("channel", &m.channel),
+                ("event", &m.event),
+            ]);
+            let _ = writeln!(out, "gateway_messages_total{} {}", labels, m.count);
+        }
+
+        header(
+            &mut out,
+            "gateway_filter_hits_total",
+            "counter",
+            "Content filter matches by filter and action.",
+        );
+        for (filter, action, count) in &self.filter_hits {
+            let _ = writeln!(
+                out,
+                "gateway_filter_hits_total{} {}",
+                labels(&[("filter", filter), ("action", action)]),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "gateway_rate_limited_total",
+            "counter",
+            "Requests rejected by a rate limit.",
+        );
+        for (key, limit, count) in &self.rate_limited {
+            let _ = writeln!(
+                out,
+                "gateway_rate_limited_total{} {}",
+                labels(&[("key", key), ("limit", limit)]),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "gateway_review_latency_seconds",
+            "histogram",
+            "Time from queueing to a reviewer's decision.",
+        );
+        for (decision, latencies) in &self.review_latencies {
+            for le in LATENCY_BUCKETS_SECS {
+                let count = latencies.partition_point(|s| *s <= le);
+                let labels = labels(&[("decision", decision), ("le", &le.to_string())]);
+                let _ = writeln!(
+                    out,
+                    "gateway_review_latency_seconds_bucket{} {}",
+                    labels, count
+                );
+            }
+            let labels_inf = labels(&[("decision", decision), ("le", "+Inf")]);
+            let _ = writeln!(
+                out,
+                "gateway_review_latency_seconds_bucket{} {}",
+                labels_inf,
+                latencies.len()
+            );
+            let decision = labels(&[("decision", decision)]);
+            let _ = writeln!(
+                out,
+                "gateway_review_latency_seconds_sum{} {}",
+                decision,
+                latencies.iter().sum::<i64>()
+            );
+            let _ = writeln!(
+                out,
+                "gateway_review_latency_seconds_count{} {}",
+                decision,
+                latencies.len()
+            );
+        }
+
+        header(
+            &mut out,
+            "gateway_webhook_failures_total",
+            "counter",
+            "Webhook deliveries that gave up.",
+        );
+        for (key, count) in &self.webhook_failures {
+            let _ = writeln!(
+                out,
+                "gateway_webhook_failures_total{} {}",
+                labels(&[("key", key)]),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "gateway_queue_waiting",
+            "gauge",
+            "Messages awaiting review.",
+        );
+        let _ = writeln!(
+            out,
+            "gateway_queue_waiting{{status=\"pending\"}} {}",
+            self.backlog.pending
+        );
+        let _ = writeln!(
+            out,
+            "gateway_queue_waiting{{status=\"flagged\"}} {}",
+            self.backlog.flagged
+        );
+
+        header(
+            &mut out,
+            "gateway_queue_retrying",
+            "gauge",
+            "Approved messages waiting to retry a failed send.",
+        );
+        let _ = writeln!(out, "gateway_queue_retrying {}", self.backlog.retrying);
+
+        header(
+            &mut out,
+            "gateway_queue_oldest_waiting_seconds",
+            "gauge",
+            "Age of the oldest message awaiting review (0 when none).",
+        );
+        let _ = writeln!(
+            out,
+            "gateway_queue_oldest_waiting_seconds {}",
+            self.oldest_waiting_secs(now).unwrap_or(0)
+        );
+
+        out
+    }
+
+    /// Print the summary shown by `gateway status`.
+    pub fn print_summary(&self) {
+        let mut by_key: BTreeMap<(&str, &str), [i64; 5]> = BTreeMap::new();
+        for m in &self.messages {
+            let row = by_key.entry((&m.key_name, &m.channel)).or_default();
+            let column = match m.event.as_str() {
+                "queued" => 0,
+                "approved" => 1,
+                "denied" => 2,
+                "sent" => 3,
+                _ => 4,
+            };
+            row[column] += m.count;
+        }
+        if !by_key.is_empty() {
+            println!();
+            println!(
+                "  {:<16} {:<8} {:>7} {:>8} {:>7} {:>6} {:>6}",
+                "KEY", "CHANNEL", "QUEUED", "APPROVED", "DENIED", "SENT", "FAILED"
+            );
+            for ((key, channel), [queued, approved, denied, sent, failed]) in by_key {
+                println!(
+                    "  {:<16} {:<8} {:>7} {:>8} {:>7} {:>6} {:>6}",
+                    crate::cli::ui::truncate(key, 16),
+                    channel,
+                    queued,
+                    approved,
+                    denied,
+                    sent,
+                    failed
+                );
+            }
+        }
+
+        if !self.review_latencies.is_empty() {
+            println!();
+            for (decision, latencies) in &self.review_latencies {
+                println!(
+                    "Review time ({}): median {}, 90th percentile {} over {}",
+                    decision,
+                    format_duration(Duration::seconds(percentile(latencies, 50))),
+                    format_duration(Duration::seconds(percentile(latencies, 90))),
+                    latencies.len()
+                );
+            }
+        }
+
+        let lines: Vec<String> = self
+            .filter_hits
+            .iter()
+            .map(|(filter, action, count)| format!("filter {} ({}): {}", filter, action, count))
+            .chain(self.rate_limited.iter().map(|(key, limit, count)| {
+                format!("rate limit {} for '{}': {}", limit, key, count)
+            }))
+            .chain(
+                self.webhook_failures
+                    .iter()
+                    .map(|(key, count)| format!("failed webhooks for '{}': {}", key, count)),
+            )
+            .collect();
+        if !lines.is_empty() {
+            println!();
+            println!("Rejections and failures:");
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[i64], pct: usize) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let parts: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", parts.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::gateway::audit::{self, AuditAction};
+    use crate::cli::gateway::ratelimit;
+    use serde_json::json;
+
+    #[test]
+    fn test_collect_and_render() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Agent \"A\"", "hash", "prefix")
+            .unwrap();
+        db.insert_queue_entry(
+            "msg-1",
+            "key-1",
+            "sms",
+            "+15551234567",
+            None,
+            None,
+            "Hi",
+            "normal",
+            None,
+        )
+        .unwrap();
+        db.insert_queue_entry(
+            "msg-2",
+            "key-1",
+            "sms",
+            "+15551234567",
+            None,
+            None,
+            "Hi",
+            "normal",
+            None,
+        )
+        .unwrap();
+        let agent = audit::agent_actor("key-1");
+        audit::record(&db, AuditAction::Queued, &agent, Some("msg-1"), json!({})).unwrap();
+        audit::record(
+            &db,
+            AuditAction::Queued,
+            &agent,
+            Some("msg-2"),
+            json!({ "filter": "pii-ssn" }),
+        )
+        .unwrap();
+        audit::record(
+            &db,
+            AuditAction::Approved,
+            audit::ACTOR_TUI,
+            Some("msg-1"),
+            json!({}),
+        )
+        .unwrap();
+        audit::record(
+            &db,
+            AuditAction::Filtered,
+            &agent,
+            None,
+            json!({ "filter": "pii-card" }),
+        )
+        .unwrap();
+        ratelimit::record_rejection(&db, "key-1", "hourly", "sms", Utc::now()).unwrap();
+        db.update_queue_status("msg-2", "flagged").unwrap();
+
+        let metrics = Metrics::collect(&db).unwrap();
+        let text = metrics.to_prometheus(Utc::now());
+        assert!(text.contains(
+            r#"gateway_messages_total{key="Agent \"A\"",channel="sms",event="queued"} 2"#
+        ));
+        assert!(text.contains(
+            r#"gateway_messages_total{key="Agent \"A\"",channel="sms",event="approved"} 1"#
+        ));
+        assert!(text.contains(r#"gateway_filter_hits_total{filter="pii-card",action="deny"} 1"#));
+        assert!(text.contains(r#"gateway_filter_hits_total{filter="pii-ssn",action="flag"} 1"#));
+        assert!(text.contains(r#"gateway_rate_limited_total{key="Agent \"A\"",limit="hourly"} 1"#));
+        assert!(text
+            .contains(r#"gateway_review_latency_seconds_bucket{decision="approved",le="60"} 1"#));
+        assert!(text.contains(r#"gateway_review_latency_seconds_count{decision="approved"} 1"#));
+        assert!(text.contains(r#"gateway_queue_waiting{status="flagged"} 1"#));
+        assert!(metrics.oldest_waiting_secs(Utc::now()).is_some());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted = [10, 20, 30, 40, 50, 60, 70, 80, 90, 100];
+        assert_eq!(percentile(&sorted, 50), 50);
+        assert_eq!(percentile(&sorted, 90), 90);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+}
@@ -0,0 +1,530 @@
+//! Notifications for messages awaiting approval.
+//!
+//! A background worker announces new pending and flagged queue entries to
+//! every enabled notifier: desktop notifications, ntfy or Gotify push, or an
+//! email via the connected Gmail account. Urgent messages are announced as
+//! soon as they are queued; the rest are collected into one digest once the
+//! oldest has waited the batch interval. Unless a notifier has `preview`
+//! set, notifications name only the agent and channel, never the recipient
+//! or message text. Push tokens are kept out of the database, in files only
+//! the current user can read.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::tls;
+use super::transport;
+use crate::db::gateway::{NotifierConfig, QueueEntry};
+use crate::db::Database;
+
+/// Settings key for how long non-urgent messages wait to be batched.
+pub const SETTING_BATCH_MINUTES: &str = "gateway_notify_batch_minutes";
+
+/// Batch interval when none is configured.
+pub const DEFAULT_BATCH_MINUTES: i64 = 15;
+
+/// How often the worker looks for new entries.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Timeout for push requests.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Entries listed individually in a digest.
+const MAX_LISTED: usize = 10;
+
+/// Characters of message text shown with `preview`.
+const PREVIEW_CHARS: usize = 200;
+
+/// Where notifications go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifierKind {
+    /// `notify-send` (D-Bus) on Linux, Notification Center on macOS
+    Desktop,
+    /// ntfy topic URL
+    Ntfy,
+    /// Gotify server URL
+    Gotify,
+    /// Email through the connected Gmail account
+    Email,
+}
+
+impl NotifierKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Desktop => "desktop",
+            Self::Ntfy => "ntfy",
+            Self::Gotify => "gotify",
+            Self::Email => "email",
+        }
+    }
+}
+
+impl fmt::Display for NotifierKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NotifierKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "desktop" => Ok(Self::Desktop),
+            "ntfy" => Ok(Self::Ntfy),
+            "gotify" => Ok(Self::Gotify),
+            "email" => Ok(Self::Email),
+            other => Err(anyhow!(
+                "Unknown notifier '{}'. Use desktop, ntfy, gotify or email",
+                other
+            )),
+        }
+    }
+}
+
+/// One notification, already worded for its notifier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    pub urgent: bool,
+}
+
+impl Notification {
+    /// Announce `entries` (each with its agent name).
+    pub fn for_entries(entries: &[(&QueueEntry, &str)], urgent: bool, preview: bool) -> Self {
+        let title = match (entries.len(), urgent) {
+            (1, true) => "Urgent message awaiting approval".to_string(),
+            (1, false) => "Message awaiting approval".to_string(),
+            (n, true) => format!("{} urgent messages awaiting approval", n),
+            (n, false) => format!("{} messages awaiting approval", n),
+        };
+
+        let mut lines: Vec<String> = entries
+            .iter()
+            .take(MAX_LISTED)
+            .map(|(entry, agent)| describe(entry, agent, preview))
+            .collect();
+        if entries.len() > MAX_LISTED {
+            lines.push(format!("...and {} more", entries.len() - MAX_LISTED));
+        }
+        lines.push("Review with: contactcmd gateway approve".to_string());
+
+        Self {
+            title,
+            message: lines.join("\n"),
+            urgent,
+        }
+    }
+}
+
+fn describe(entry: &QueueEntry, agent: &str, preview: bool) -> String {
+    let flagged = if entry.status == "flagged" { " [flagged]" } else { "" };
+    let channel = entry.channel.to_uppercase();
+    if !preview {
+        return format!("{} queued a message via {}{}", agent, channel, flagged);
+    }
+    let to = match entry.recipient_name {
+        Some(ref name) => format!("{} ({})", name, entry.recipient_address),
+        None => entry.recipient_address.clone(),
+    };
+    let mut text: String = entry.body.chars().take(PREVIEW_CHARS).collect();
+    if entry.body.chars().count() > PREVIEW_CHARS {
+        text.push_str("...");
+    }
+    format!("{} → {} via {}{}: {}", agent, to, channel, flagged, text)
+}
+
+/// Something that can deliver a notification.
+pub trait Notifier {
+    fn send(&self, db: &Database, notification: &Notification) -> Result<()>;
+}
+
+/// Desktop notification on this machine.
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    #[cfg(target_os = "macos")]
+    fn send(&self, _db: &Database, notification: &Notification) -> Result<()> {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let script = format!(
+            "display notification \"{}\" with title \"contactcmd\" subtitle \"{}\"",
+            escape(&notification.message),
+            escape(&notification.title)
+        );
+        let status = std::process::Command::new("osascript")
+            .arg("-e")
+            .arg(script)
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("osascript exited with {}", status);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn send(&self, _db: &Database, notification: &Notification) -> Result<()> {
+        let urgency = if notification.urgent { "critical" } else { "normal" };
+        let status = std::process::Command::new("notify-send")
+            .args(["--app-name=contactcmd", "--urgency", urgency])
+            .arg(&notification.title)
+            .arg(&notification.message)
+            .status()
+            .map_err(|e| anyhow!("Could not run notify-send: {}", e))?;
+        if !status.success() {
+            anyhow::bail!("notify-send exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// HTTP push to an ntfy topic or a Gotify server.
+pub struct PushNotifier {
+    pub kind: NotifierKind,
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl Notifier for PushNotifier {
+    fn send(&self, _db: &Database, notification: &Notification) -> Result<()> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(PUSH_TIMEOUT)
+            .build()?;
+
+        let request = if self.kind == NotifierKind::Gotify {
+            // Gotify: JSON message, app token header, priority 0-10
+            let url = if self.url.trim_end_matches('/').ends_with("/message") {
+                self.url.clone()
+            } else {
+                format!("{}/message", self.url.trim_end_matches('/'))
+            };
+            let mut request = client.post(url).json(&serde_json::json!({
+                "title": notification.title,
+                "message": notification.message,
+                "priority": if notification.urgent { 8 } else { 4 },
+            }));
+            if let Some(ref token) = self.token {
+                request = request.header("X-Gotify-Key", token);
+            }
+            request
+        } else {
+            // ntfy: plain-text body, metadata in headers
+            let mut request = client
+                .post(&self.url)
+                .header("Title", &notification.title)
+                .header("Priority", if notification.urgent { "urgent" } else { "default" })
+                .header("Tags", "envelope")
+                .body(notification.message.clone());
+            if let Some(ref token) = self.token {
+                request = request.bearer_auth(token);
+            }
+            request
+        };
+
+        let response = request.send()?;
+        if !response.status().is_success() {
+            anyhow::bail!("{} returned HTTP {}", self.kind, response.status().as_u16());
+        }
+        Ok(())
+    }
+}
+
+/// Email through the connected Gmail account.
+pub struct EmailNotifier {
+    /// Defaults to the Gmail account's own address
+    pub to: Option<String>,
+}
+
+impl Notifier for EmailNotifier {
+    fn send(&self, db: &Database, notification: &Notification) -> Result<()> {
+        let to = match self.to {
+            Some(ref to) => to.clone(),
+            None => crate::cli::google_auth::get_google_email(db)
+                .ok_or_else(|| anyhow!("Gmail not configured. Run setup first."))?,
+        };
+        let subject = format!("contactcmd: {}", notification.title);
+        transport::send_gmail(db, &to, &subject, &notification.message)
+    }
+}
+
+/// Build the notifier a stored configuration describes.
+pub fn notifier_for(config: &NotifierConfig) -> Result<Box<dyn Notifier>> {
+    let kind: NotifierKind = config.kind.parse()?;
+    Ok(match kind {
+        NotifierKind::Desktop => Box::new(DesktopNotifier),
+        NotifierKind::Ntfy | NotifierKind::Gotify => Box::new(PushNotifier {
+            kind,
+            url: config
+                .target
+                .clone()
+                .ok_or_else(|| anyhow!("{} notifier has no URL", kind))?,
+            token: load_token(&config.id)?,
+        }),
+        NotifierKind::Email => Box::new(EmailNotifier {
+            to: config.target.clone(),
+        }),
+    })
+}
+
+fn token_path(id: &str) -> Result<PathBuf> {
+    let config_dir =
+        dirs::config_dir().ok_or_else(|| anyhow!("Could not find config directory"))?;
+    Ok(config_dir.join("contactcmd").join("gateway-notifiers").join(format!("{}.token", id)))
+}
+
+/// Store a notifier's push token in a file only the current user can read.
+pub fn save_token(id: &str, token: &str) -> Result<()> {
+    let path = token_path(id)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    tls::write_private(&path, token.as_bytes())
+}
+
+/// A notifier's push token, if it has one.
+pub fn load_token(id: &str) -> Result<Option<String>> {
+    match std::fs::read_to_string(token_path(id)?) {
+        Ok(token) => Ok(Some(token)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remove a notifier's push token.
+pub fn delete_token(id: &str) -> Result<()> {
+    match std::fs::remove_file(token_path(id)?) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// How long non-urgent messages wait before their digest goes out.
+pub fn batch_interval(db: &Database) -> Result<chrono::Duration> {
+    let minutes = db
+        .get_setting(SETTING_BATCH_MINUTES)?
+        .and_then(|v| v.parse().ok())
+        .filter(|m: &i64| *m >= 0)
+        .unwrap_or(DEFAULT_BATCH_MINUTES);
+    Ok(chrono::Duration::minutes(minutes))
+}
+
+/// Announce new entries until shutdown.
+pub fn run_notifier(db_path: PathBuf, shutdown: Arc<AtomicBool>) {
+    let mut last_run: Option<Instant> = None;
+    while !shutdown.load(Ordering::SeqCst) {
+        if last_run.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+            std::thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        last_run = Some(Instant::now());
+
+        let result = Database::open_at(db_path.clone()).and_then(|db| {
+            notify_pending(&db, Utc::now(), |config, notification| {
+                notifier_for(config)?.send(&db, notification)
+            })
+        });
+        if let Err(e) = result {
+            eprintln!("Notification error: {}", e);
+        }
+    }
+}
+
+/// Announce entries that are due: urgent ones now, the rest once the oldest
+/// has waited the batch interval. Failures are recorded on the notifier and
+/// not retried, so a broken notifier can't repeat itself every few seconds.
+pub fn notify_pending<F>(db: &Database, now: DateTime<Utc>, mut deliver: F) -> Result<()>
+where
+    F: FnMut(&NotifierConfig, &Notification) -> Result<()>,
+{
+    let entries = db.list_unnotified_queue()?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let notifiers: Vec<NotifierConfig> =
+        db.list_notifiers()?.into_iter().filter(|n| n.enabled).collect();
+    if notifiers.is_empty() {
+        // Nothing to announce to; don't save them up for a notifier added later
+        let ids: Vec<String> = entries.into_iter().map(|e| e.id).collect();
+        return db.mark_queue_notified(&ids);
+    }
+
+    let agents: HashMap<String, String> = db
+        .list_api_keys()?
+        .into_iter()
+        .map(|k| (k.id, k.name))
+        .collect();
+    let named: Vec<(&QueueEntry, &str)> = entries
+        .iter()
+        .map(|e| {
+            let agent = agents.get(&e.api_key_id).map_or("Unknown agent", |n| n.as_str());
+            (e, agent)
+        })
+        .collect();
+
+    let (urgent, rest): (Vec<_>, Vec<_>) = named.into_iter().partition(|(e, _)| e.priority == "urgent");
+    let batch = batch_interval(db)?;
+    let rest_due = rest
+        .first()
+        .is_some_and(|(oldest, _)| oldest.created_at + batch <= now);
+
+    let mut batches = Vec::new();
+    if !urgent.is_empty() {
+        batches.push((urgent, true));
+    }
+    if rest_due {
+        batches.push((rest, false));
+    }
+
+    for (batch, is_urgent) in &batches {
+        for notifier in &notifiers {
+            let notification = Notification::for_entries(batch, *is_urgent, notifier.preview);
+            let error = deliver(notifier, &notification).err().map(|e| e.to_string());
+            db.set_notifier_error(&notifier.id, error.as_deref())?;
+        }
+        let ids: Vec<String> = batch.iter().map(|(e, _)| e.id.clone()).collect();
+        db.mark_queue_notified(&ids)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::gateway::test_support::stand_in_server;
+
+    fn queue(db: &Database, id: &str, priority: &str, body: &str) {
+        db.insert_queue_entry(id, "key-1", "sms", "+15551234567", Some("Alice"), None, body, priority, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_urgent_now_others_batched() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        db.insert_notifier("n-1", "ntfy", Some("http://localhost/topic"), false).unwrap();
+        db.insert_notifier("n-2", "desktop", None, true).unwrap();
+        queue(&db, "low-1", "low", "See you Tuesday");
+        queue(&db, "urgent-1", "urgent", "Call me now");
+
+        let mut sent: Vec<(String, Notification)> = Vec::new();
+        let now = Utc::now();
+        notify_pending(&db, now, |n, note| {
+            sent.push((n.id.clone(), note.clone()));
+            if n.id == "n-2" {
+                Err(anyhow!("no display"))
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap();
+
+        // Only the urgent message, to both notifiers; only n-2 previews it
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|(_, n)| n.urgent && n.title == "Urgent message awaiting approval"));
+        assert_eq!(sent[0].1.message.lines().next(), Some("Bot queued a message via SMS"));
+        assert!(sent[1].1.message.contains("Alice (+15551234567) via SMS: Call me now"));
+        let errors: Vec<_> = db.list_notifiers().unwrap().into_iter().map(|n| n.last_error).collect();
+        assert_eq!(errors, vec![None, Some("no display".to_string())]);
+
+        // Nothing new until the batch interval passes
+        sent.clear();
+        notify_pending(&db, now, |n, note| {
+            sent.push((n.id.clone(), note.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert!(sent.is_empty());
+
+        let later = now + chrono::Duration::minutes(DEFAULT_BATCH_MINUTES + 1);
+        notify_pending(&db, later, |n, note| {
+            sent.push((n.id.clone(), note.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sent.len(), 2);
+        assert!(!sent[0].1.urgent);
+        assert!(db.list_unnotified_queue().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_no_notifiers_marks_entries_seen() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        queue(&db, "msg-1", "urgent", "Hello");
+        notify_pending(&db, Utc::now(), |_, _| panic!("no notifiers")).unwrap();
+        assert!(db.list_unnotified_queue().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_digest_lists_entries() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        for i in 0..12 {
+            let id = i.to_string();
+            let body = "x".repeat(300);
+            db.insert_queue_entry(&id, "key-1", "email", "bob@example.com", None, Some("Hi"), &body, "low", None)
+                .unwrap();
+            db.update_queue_status(&id, "flagged").unwrap();
+        }
+        let entries = db.list_unnotified_queue().unwrap();
+        let named: Vec<(&QueueEntry, &str)> = entries.iter().map(|e| (e, "Bot")).collect();
+
+        let digest = Notification::for_entries(&named, false, false);
+        assert_eq!(digest.title, "12 messages awaiting approval");
+        let lines: Vec<&str> = digest.message.lines().collect();
+        assert_eq!(lines[0], "Bot queued a message via EMAIL [flagged]");
+        assert_eq!(lines[10], "...and 2 more");
+        assert!(!digest.message.contains("bob@example.com"));
+
+        let preview = Notification::for_entries(&named[..1], false, true);
+        assert!(preview.message.contains(&format!("{}...", "x".repeat(PREVIEW_CHARS))));
+    }
+
+    #[test]
+    fn test_push_formats() {
+        let db = Database::open_memory().unwrap();
+        let note = Notification {
+            title: "Message awaiting approval".to_string(),
+            message: "Bot queued a message via SMS".to_string(),
+            urgent: true,
+        };
+
+        let (url, server) = stand_in_server();
+        let ntfy = PushNotifier {
+            kind: NotifierKind::Ntfy,
+            url: format!("{}/approvals", url),
+            token: Some("tk_secret".to_string()),
+        };
+        ntfy.send(&db, &note).unwrap();
+        let request = server.join().unwrap().to_lowercase();
+        assert!(request.starts_with("post /approvals "));
+        assert!(request.contains("title: message awaiting approval"));
+        assert!(request.contains("priority: urgent"));
+        assert!(request.contains("authorization: bearer tk_secret"));
+        assert!(request.ends_with("bot queued a message via sms"));
+
+        let (url, server) = stand_in_server();
+        let gotify = PushNotifier {
+            kind: NotifierKind::Gotify,
+            url,
+            token: Some("app-token".to_string()),
+        };
+        gotify.send(&db, &note).unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /message "));
+        assert!(request.to_lowercase().contains("x-gotify-key: app-token"));
+        let body: serde_json::Value =
+            serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        assert_eq!(body["priority"], 8);
+        assert_eq!(body["title"], "Message awaiting approval");
+    }
+}
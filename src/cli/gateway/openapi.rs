@@ -0,0 +1,502 @@
+//! OpenAPI 3 description of the gateway HTTP API.
+//!
+//! Served at `/gateway/openapi.json`. Enumerations (channels, priorities,
+//! statuses, error codes) are generated from the Rust types so the document
+//! can't drift from what the server accepts.
+
+use serde_json::{json, Value};
+
+use super::template::MAX_VARIABLE_LEN;
+use super::thread::MAX_THREAD_ID_LEN;
+use super::types::{
+    ErrorCode, GatewayChannel, Priority, QueueStatus, MAX_BATCH_RECIPIENTS, MAX_BODY_LEN,
+    MAX_IDEMPOTENCY_KEY_LEN, MAX_SUBJECT_LEN,
+};
+
+/// Build the OpenAPI document.
+pub fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "contactcmd Communication Gateway",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Queue SMS, iMessage and email messages for human approval before they are sent."
+        },
+        "paths": paths(),
+        "components": {
+            "securitySchemes": {
+                "ApiKey": { "type": "apiKey", "in": "header", "name": "X-Gateway-Key" }
+            },
+            "schemas": schemas()
+        }
+    })
+}
+
+fn paths() -> Value {
+    json!({
+        "/gateway/health": {
+            "get": {
+                "summary": "Health check",
+                "description": "Backlog counts are included for localhost and for API keys with the read-all scope.",
+                "operationId": "health",
+                "security": [],
+                "responses": { "200": ok_response("HealthResponse") }
+            }
+        },
+        "/gateway/metrics": {
+            "get": {
+                "summary": "Counters and gauges in the Prometheus text format",
+                "description": "Open to localhost. Other hosts need an API key with the read-all scope.",
+                "operationId": "metrics",
+                "security": [{}, { "ApiKey": [] }],
+                "responses": {
+                    "200": {
+                        "description": "Prometheus text exposition format",
+                        "content": { "text/plain": { "schema": { "type": "string" } } }
+                    },
+                    "401": error_response("Called from another host without a valid API key", &["ErrorResponse"]),
+                    "403": error_response("Key lacks the read-all scope", &["ErrorResponse"])
+                }
+            }
+        },
+        "/gateway/openapi.json": {
+            "get": {
+                "summary": "This document",
+                "operationId": "openapi",
+                "security": [],
+                "responses": { "200": { "description": "OpenAPI 3 document" } }
+            }
+        },
+        "/gateway/send": {
+            "post": {
+                "summary": "Queue a message for approval",
+                "operationId": "send",
+                "description": "Send an Idempotency-Key to make retries safe: a key reused by the same API key returns the message it first queued instead of queueing another.",
+                "parameters": [idempotency_param()],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": schema_ref("SendRequest") } }
+                },
+                "responses": {
+                    "200": ok_response("SendResponse"),
+                    "400": error_response(
+                        "Invalid request, validation failure or content blocked",
+                        &["ErrorResponse", "ContentBlockedErrorResponse"],
+                    ),
+                    "401": error_response("Missing or invalid API key", &["ErrorResponse"]),
+                    "403": error_response(
+                        "Recipient not allowed, contact opted out, or outside the key's scopes",
+                        &["AllowlistErrorResponse", "ConsentDeniedErrorResponse", "ErrorResponse"],
+                    ),
+                    "429": error_response("Rate limit exceeded", &["RateLimitErrorResponse"]),
+                    "504": error_response(
+                        "Timed out; the message may still be queued, so retry only with the same Idempotency-Key",
+                        &["ErrorResponse"],
+                    )
+                }
+            }
+        },
+        "/gateway/send/batch": {
+            "post": {
+                "summary": "Queue one message per recipient as a batch",
+                "description": "Each recipient is checked like /gateway/send; refused recipients carry the error body /gateway/send would have returned. Resending with the same Idempotency-Key keeps the batch ID and reports recipients already queued instead of queueing them again.",
+                "operationId": "sendBatch",
+                "parameters": [idempotency_param()],
+                "requestBody": {
+                    "required": true,
+                    "content": { "application/json": { "schema": schema_ref("BatchSendRequest") } }
+                },
+                "responses": {
+                    "200": ok_response("BatchSendResponse"),
+                    "400": error_response("Invalid request or validation failure", &["ErrorResponse"]),
+                    "401": error_response("Missing or invalid API key", &["ErrorResponse"]),
+                    "504": error_response(
+                        "Timed out; some or all messages may still be queued, so retry only with the same Idempotency-Key",
+                        &["ErrorResponse"],
+                    )
+                }
+            }
+        },
+        "/gateway/actions/{id}": {
+            "get": {
+                "summary": "Get the status of a queued message or batch",
+                "description": "Keys without the read-all scope can only see their own messages. A batch ID returns every message in the batch.",
+                "operationId": "getAction",
+                "parameters": [id_param()],
+                "responses": {
+                    "200": ok_envelope(json!({
+                        "oneOf": [schema_ref("ActionStatusResponse"), schema_ref("BatchStatusResponse")]
+                    })),
+                    "401": error_response("Missing or invalid API key", &["ErrorResponse"]),
+                    "404": error_response("Action not found", &["ErrorResponse"])
+                }
+            }
+        },
+        "/gateway/queue": {
+            "get": {
+                "summary": "List messages awaiting review",
+                "description": "Open to localhost. Other hosts need an API key and see only that key's messages unless it has the read-all scope.",
+                "operationId": "listQueue",
+                "security": [{}, { "ApiKey": [] }],
+                "responses": {
+                    "200": ok_response("QueueListResponse"),
+                    "401": error_response("Invalid API key", &["ErrorResponse"]),
+                    "403": error_response("Called from another host without an API key", &["ErrorResponse"])
+                }
+            }
+        },
+        "/gateway/queue/{id}/approve": {
+            "post": local_action("Approve and send a queued message (localhost only)", "approve")
+        },
+        "/gateway/queue/{id}/deny": {
+            "post": local_action("Deny a queued message (localhost only)", "deny")
+        }
+    })
+}
+
+fn local_action(summary: &str, operation_id: &str) -> Value {
+    json!({
+        "summary": summary,
+        "operationId": operation_id,
+        "security": [],
+        "parameters": [id_param()],
+        "responses": {
+            "200": ok_response("ActionStatusResponse"),
+            "400": error_response("Message is not pending", &["ErrorResponse"]),
+            "403": error_response("Called from another host", &["ErrorResponse"]),
+            "404": error_response("Message not found", &["ErrorResponse"])
+        }
+    })
+}
+
+fn schemas() -> Value {
+    let channels: Vec<String> = GatewayChannel::ALL.iter().map(|c| c.to_string()).collect();
+    let priorities: Vec<String> = [Priority::Urgent, Priority::High, Priority::Normal, Priority::Low]
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+    let statuses: Vec<String> = [
+        QueueStatus::Pending,
+        QueueStatus::Flagged,
+        QueueStatus::Approved,
+        QueueStatus::Denied,
+        QueueStatus::Sent,
+        QueueStatus::Failed,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    let error_codes: Vec<String> = ErrorCode::ALL.iter().map(|c| c.to_string()).collect();
+
+    let send_request = json!({
+        "type": "object",
+        "required": ["channel"],
+        "properties": {
+            "channel": schema_ref("Channel"),
+            "recipient_address": {
+                "type": "string",
+                "description": "Phone number or email address; required unless recipient_contact is given"
+            },
+            "recipient_contact": {
+                "type": "string",
+                "description": "Contact ID, contact:<id> reference or a tag naming one contact; the gateway fills in the contact's primary address for the channel"
+            },
+            "recipient_name": { "type": "string", "description": "Not allowed with recipient_contact" },
+            "subject": {
+                "type": "string",
+                "maxLength": MAX_SUBJECT_LEN,
+                "description": "Required for email; not allowed with template_id"
+            },
+            "body": {
+                "type": "string",
+                "minLength": 1,
+                "maxLength": MAX_BODY_LEN,
+                "description": "Required unless template_id is given"
+            },
+            "template_id": {
+                "type": "string",
+                "description": "Render subject and body from this server-side template; contact fields are filled in by the gateway"
+            },
+            "variables": {
+                "type": "object",
+                "additionalProperties": { "type": "string", "maxLength": MAX_VARIABLE_LEN },
+                "description": "Values for the template's own placeholders"
+            },
+            "priority": schema_ref("Priority"),
+            "context": { "type": "object", "description": "Shown to the reviewer" },
+            "thread_id": {
+                "type": "string",
+                "maxLength": MAX_THREAD_ID_LEN,
+                "description": "Conversation ID; omit to start a new thread or inherit from in_reply_to"
+            },
+            "in_reply_to": { "type": "string", "description": "Action ID of the previous message in the conversation" }
+        }
+    });
+
+    // A batch shares every field except the recipient and threading
+    let mut batch_request = send_request.clone();
+    batch_request["required"] = json!(["channel", "recipients"]);
+    if let Some(props) = batch_request["properties"].as_object_mut() {
+        for field in ["recipient_address", "recipient_contact", "recipient_name", "thread_id", "in_reply_to"] {
+            props.remove(field);
+        }
+        props.insert(
+            "recipients".to_string(),
+            json!({
+                "type": "array",
+                "minItems": 1,
+                "maxItems": MAX_BATCH_RECIPIENTS,
+                "items": {
+                    "oneOf": [
+                        { "type": "string", "description": "Phone number or email address" },
+                        object(&["address"], json!({
+                            "address": { "type": "string" },
+                            "name": { "type": "string" }
+                        })),
+                        object(&["contact"], json!({
+                            "contact": { "type": "string", "description": "As recipient_contact" }
+                        }))
+                    ]
+                }
+            }),
+        );
+    }
+
+    json!({
+        "Channel": { "type": "string", "enum": channels },
+        "Priority": { "type": "string", "enum": priorities, "default": "normal" },
+        "QueueStatus": { "type": "string", "enum": statuses },
+        "ErrorCode": { "type": "string", "enum": error_codes },
+        "SendRequest": send_request,
+        "BatchSendRequest": batch_request,
+        "SendResponse": object(
+            &["action_id", "status", "thread_id"],
+            json!({
+                "action_id": { "type": "string" },
+                "status": schema_ref("QueueStatus"),
+                "thread_id": { "type": "string" },
+                "recipient_contact": { "type": "string", "description": "contact:<id> reference when addressed by contact" }
+            }),
+        ),
+        "ActionStatusResponse": object(
+            &["action_id", "status"],
+            json!({
+                "action_id": { "type": "string" },
+                "status": schema_ref("QueueStatus"),
+                "error_message": { "type": "string" },
+                "sent_at": { "type": "string", "format": "date-time" },
+                "thread_id": { "type": "string" },
+                "batch_id": { "type": "string" }
+            }),
+        ),
+        "BatchSendResponse": object(
+            &["batch_id", "results"],
+            json!({
+                "batch_id": { "type": "string" },
+                "results": { "type": "array", "items": schema_ref("BatchSendResult") }
+            }),
+        ),
+        "BatchSendResult": object(
+            &[],
+            json!({
+                "recipient_address": { "type": "string" },
+                "recipient_contact": { "type": "string", "description": "Set instead of recipient_address for contact recipients" },
+                "action_id": { "type": "string", "description": "Set when the message was queued" },
+                "status": schema_ref("QueueStatus"),
+                "error": { "type": "object", "description": "Set when the recipient was refused; the body /gateway/send would have returned" }
+            }),
+        ),
+        "BatchStatusResponse": object(
+            &["batch_id", "actions"],
+            json!({
+                "batch_id": { "type": "string" },
+                "actions": {
+                    "type": "array",
+                    "items": {
+                        "allOf": [
+                            schema_ref("ActionStatusResponse"),
+                            object(&[], json!({
+                                "recipient_address": { "type": "string" },
+                                "recipient_contact": { "type": "string" }
+                            }))
+                        ]
+                    }
+                }
+            }),
+        ),
+        "HealthResponse": object(
+            &["status", "uptime_secs", "version"],
+            json!({
+                "status": { "type": "string" },
+                "uptime_secs": { "type": "integer" },
+                "pending_count": { "type": "integer", "description": "Messages awaiting review, including flagged ones" },
+                "version": { "type": "string" },
+                "flagged_count": { "type": "integer" },
+                "retrying_count": { "type": "integer", "description": "Approved messages waiting to retry a failed send" },
+                "oldest_pending_secs": { "type": "integer", "description": "Age of the oldest message awaiting review" }
+            }),
+        ),
+        "QueueEntryResponse": object(
+            &["id", "channel", "recipient_address", "body", "priority", "status", "created_at", "agent_name"],
+            json!({
+                "id": { "type": "string" },
+                "channel": schema_ref("Channel"),
+                "recipient_address": { "type": "string" },
+                "recipient_name": { "type": "string" },
+                "subject": { "type": "string" },
+                "body": { "type": "string" },
+                "priority": schema_ref("Priority"),
+                "status": schema_ref("QueueStatus"),
+                "agent_context": { "type": "object" },
+                "created_at": { "type": "string", "format": "date-time" },
+                "agent_name": { "type": "string" },
+                "thread_id": { "type": "string" },
+                "in_reply_to": { "type": "string" },
+                "recipient_contact": {
+                    "type": "string",
+                    "description": "Set for messages addressed by contact; callers with an API key see it in place of the address"
+                }
+            }),
+        ),
+        "QueueListResponse": object(
+            &["entries", "total"],
+            json!({
+                "entries": { "type": "array", "items": schema_ref("QueueEntryResponse") },
+                "total": { "type": "integer" }
+            }),
+        ),
+        "FieldError": object(
+            &["field", "message"],
+            json!({
+                "field": { "type": "string" },
+                "message": { "type": "string" }
+            }),
+        ),
+        "ErrorResponse": error_schema(json!({
+            "field_errors": { "type": "array", "items": schema_ref("FieldError") }
+        })),
+        "RateLimitErrorResponse": error_schema(json!({
+            "retry_after_seconds": { "type": "integer", "description": "Seconds until the next message would be accepted" },
+            "limit_type": {
+                "type": "string",
+                "enum": ["hourly", "daily", "recipient_daily", "global_hourly", "global_daily"]
+            },
+            "current_count": { "type": "integer" },
+            "limit": { "type": "integer" }
+        })),
+        "AllowlistErrorResponse": error_schema(json!({
+            "allowed_patterns": { "type": "array", "items": { "type": "string" } }
+        })),
+        "ContentBlockedErrorResponse": error_schema(json!({
+            "filter": { "type": "string" },
+            "description": { "type": "string" }
+        })),
+        "ConsentDeniedErrorResponse": error_schema(json!({
+            "recipient": { "type": "string" }
+        }))
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+/// Error body schema: the shared `success`/`code`/`error` fields plus `extra`.
+fn error_schema(extra: Value) -> Value {
+    let mut properties = json!({
+        "success": { "type": "boolean", "enum": [false] },
+        "code": schema_ref("ErrorCode"),
+        "error": { "type": "string", "description": "Human-readable message" }
+    });
+    if let (Some(props), Some(extra)) = (properties.as_object_mut(), extra.as_object()) {
+        props.extend(extra.clone());
+    }
+    object(&["success", "code", "error"], properties)
+}
+
+/// Successful response wrapped in the standard `{success, data}` envelope.
+fn ok_response(schema: &str) -> Value {
+    ok_envelope(schema_ref(schema))
+}
+
+/// The `{success, data}` envelope around an arbitrary data schema.
+fn ok_envelope(data: Value) -> Value {
+    json!({
+        "description": "Success",
+        "content": {
+            "application/json": {
+                "schema": object(
+                    &["success", "data"],
+                    json!({
+                        "success": { "type": "boolean", "enum": [true] },
+                        "data": data
+                    }),
+                )
+            }
+        }
+    })
+}
+
+fn error_response(description: &str, schemas: &[&str]) -> Value {
+    let schema = match schemas {
+        [single] => schema_ref(single),
+        many => json!({ "oneOf": many.iter().map(|s| schema_ref(s)).collect::<Vec<_>>() }),
+    };
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } }
+    })
+}
+
+fn id_param() -> Value {
+    json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+fn idempotency_param() -> Value {
+    json!({
+        "name": "Idempotency-Key",
+        "in": "header",
+        "required": false,
+        "schema": { "type": "string", "minLength": 1, "maxLength": MAX_IDEMPOTENCY_KEY_LEN }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_lists_routes_and_error_codes() {
+        let spec = spec();
+        assert_eq!(spec["openapi"], "3.0.3");
+
+        let paths = spec["paths"].as_object().unwrap();
+        for route in ["/gateway/send", "/gateway/send/batch", "/gateway/actions/{id}", "/gateway/queue/{id}/approve"] {
+            assert!(paths.contains_key(route), "missing {}", route);
+        }
+
+        let codes = spec["components"]["schemas"]["ErrorCode"]["enum"].as_array().unwrap();
+        for code in ["rate_limited", "allowlist_denied", "consent_denied", "content_blocked", "invalid_channel"] {
+            assert!(codes.iter().any(|c| c == code), "missing {}", code);
+        }
+        assert_eq!(
+            spec["components"]["schemas"]["Channel"]["enum"],
+            json!(["sms", "imessage", "email", "slack", "matrix"])
+        );
+    }
+
+    #[test]
+    fn test_spec_refs_resolve() {
+        let spec = spec();
+        let text = spec.to_string();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        for part in text.split("#/components/schemas/").skip(1) {
+            let name: String = part.chars().take_while(|c| c.is_alphanumeric()).collect();
+            assert!(schemas.contains_key(&name), "dangling $ref {}", name);
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Token-bucket rate limits for the gateway.
+//!
+//! A limit of N per period is a bucket holding up to N tokens that refills at
+//! N/period. Each queued message spends one token from every bucket that
+//! applies to it: the key's hourly and daily limits, the recipient's daily
+//! limit, and the gateway-wide caps. Buckets are stored in the database so
+//! they survive restarts.
+//!
+//! Rejections are counted per key, limit and hour, and only the first in each
+//! hour goes to the audit log, so an agent stuck at its limit can't flood it.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use super::audit::{self, AuditAction};
+use super::server::normalize_recipient;
+use super::types::{ErrorCode, RateLimitErrorResponse};
+use crate::db::gateway::{ApiKey, RateBucket};
+use crate::db::Database;
+
+/// Settings key: messages to one recipient per day, across all keys.
+pub const SETTING_RECIPIENT_PER_DAY: &str = "gateway_limit_recipient_per_day";
+/// Settings key: messages per hour across the whole gateway.
+pub const SETTING_GLOBAL_PER_HOUR: &str = "gateway_limit_global_per_hour";
+/// Settings key: messages per day across the whole gateway.
+pub const SETTING_GLOBAL_PER_DAY: &str = "gateway_limit_global_per_day";
+
+const HOUR_SECS: i64 = 3600;
+const DAY_SECS: i64 = 86400;
+
+/// One rate limit and the bucket that tracks it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+    /// Reported as `limit_type` in 429 responses
+    pub limit_type: &'static str,
+    pub bucket: String,
+    pub capacity: i32,
+    pub period_secs: i64,
+}
+
+impl Limit {
+    fn new(limit_type: &'static str, bucket: String, capacity: i32, period_secs: i64) -> Self {
+        Self {
+            limit_type,
+            bucket,
+            capacity,
+            period_secs,
+        }
+    }
+
+    /// Tokens refilled per second.
+    fn rate(&self) -> f64 {
+        self.capacity as f64 / self.period_secs as f64
+    }
+
+    /// Tokens available at `now`; a bucket that was never used is full.
+    pub fn available(&self, bucket: Option<&RateBucket>, now: DateTime<Utc>) -> f64 {
+        let capacity = self.capacity.max(0) as f64;
+        match bucket {
+            None => capacity,
+            Some(b) => {
+                let elapsed = (now - b.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+                (b.tokens + elapsed * self.rate()).min(capacity)
+            }
+        }
+    }
+
+    /// Seconds until a bucket holding `tokens` has a whole token again.
+    pub fn retry_after(&self, tokens: f64) -> i64 {
+        if tokens >= 1.0 {
+            0
+        } else if self.capacity <= 0 {
+            self.period_secs
+        } else {
+            (((1.0 - tokens) / self.rate()).ceil() as i64).max(1)
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self.limit_type {
+            "hourly" => "Hourly rate limit exceeded",
+            "daily" => "Daily rate limit exceeded",
+            "recipient_daily" => "Daily limit for this recipient exceeded",
+            "global_hourly" => "Gateway hourly limit exceeded",
+            _ => "Gateway daily limit exceeded",
+        }
+    }
+}
+
+/// A limit with no token left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exceeded {
+    pub limit: Limit,
+    pub retry_after_seconds: i64,
+    /// Messages currently counted against the limit
+    pub current_count: i64,
+}
+
+impl Exceeded {
+    pub fn to_response(&self) -> RateLimitErrorResponse {
+        RateLimitErrorResponse {
+            success: false,
+            code: ErrorCode::RateLimited,
+            error: self.limit.message().to_string(),
+            retry_after_seconds: self.retry_after_seconds,
+            limit_type: self.limit.limit_type.to_string(),
+            current_count: self.current_count,
+            limit: self.limit.capacity,
+        }
+    }
+}
+
+/// Gateway-wide limits; `None` means unlimited.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlobalLimits {
+    pub recipient_per_day: Option<i32>,
+    pub per_hour: Option<i32>,
+    pub per_day: Option<i32>,
+}
+
+impl GlobalLimits {
+    pub fn load(db: &Database) -> Result<Self> {
+        let get = |key: &str| -> Result<Option<i32>> {
+            Ok(db
+                .get_setting(key)?
+                .and_then(|v| v.parse().ok())
+                .filter(|n: &i32| *n > 0))
+        };
+        Ok(Self {
+            recipient_per_day: get(SETTING_RECIPIENT_PER_DAY)?,
+            per_hour: get(SETTING_GLOBAL_PER_HOUR)?,
+            per_day: get(SETTING_GLOBAL_PER_DAY)?,
+        })
+    }
+
+    pub fn save(&self, db: &Database) -> Result<()> {
+        for (key, value) in [
+            (SETTING_RECIPIENT_PER_DAY, self.recipient_per_day),
+            (SETTING_GLOBAL_PER_HOUR, self.per_hour),
+            (SETTING_GLOBAL_PER_DAY, self.per_day),
+        ] {
+            match value {
+                Some(n) if n > 0 => db.set_setting(key, &n.to_string())?,
+                _ => db.delete_setting(key)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bucket prefix for an API key's own limits.
+pub fn key_bucket_prefix(api_key_id: &str) -> String {
+    format!("key:{}:", api_key_id)
+}
+
+/// Bucket identity for a recipient: the contact when known, so one person's
+/// phone numbers and email addresses share a limit.
+pub fn recipient_identity(person_id: Option<&str>, address: &str) -> String {
+    match person_id {
+        Some(id) => format!("person:{}", id),
+        None => normalize_recipient(address),
+    }
+}
+
+/// The API key's own hourly and daily limits.
+pub fn key_limits(api_key: &ApiKey) -> Vec<Limit> {
+    let prefix = key_bucket_prefix(&api_key.id);
+    vec![
+        Limit::new("hourly", format!("{}hour", prefix), api_key.rate_limit_per_hour, HOUR_SECS),
+        Limit::new("daily", format!("{}day", prefix), api_key.rate_limit_per_day, DAY_SECS),
+    ]
+}
+
+/// Every limit that applies to a message from `api_key` to `recipient`
+/// (from [`recipient_identity`]).
+pub fn limits_for(global: &GlobalLimits, api_key: &ApiKey, recipient: &str) -> Vec<Limit> {
+    let mut limits = key_limits(api_key);
+    if let Some(n) = global.recipient_per_day {
+        limits.push(Limit::new(
+            "recipient_daily",
+            format!("recipient:{}:day", recipient),
+            n,
+            DAY_SECS,
+        ));
+    }
+    if let Some(n) = global.per_hour {
+        limits.push(Limit::new("global_hourly", "global:hour".to_string(), n, HOUR_SECS));
+    }
+    if let Some(n) = global.per_day {
+        limits.push(Limit::new("global_daily", "global:day".to_string(), n, DAY_SECS));
+    }
+    limits
+}
+
+/// Spend one token from every limit, or none if any is exhausted. When several
+/// are exhausted, reports the one that frees up last.
+pub fn acquire(db: &Database, limits: &[Limit], now: DateTime<Utc>) -> Result<Result<(), Exceeded>> {
+    let keys: Vec<String> = limits.iter().map(|l| l.bucket.clone()).collect();
+    db.update_rate_buckets(&keys, |current| {
+        let mut updated = Vec::with_capacity(limits.len());
+        let mut exceeded: Option<Exceeded> = None;
+
+        for (limit, bucket) in limits.iter().zip(&current) {
+            let tokens = limit.available(bucket.as_ref(), now);
+            if tokens >= 1.0 {
+                updated.push(RateBucket {
+                    key: limit.bucket.clone(),
+                    tokens: tokens - 1.0,
+                    updated_at: now,
+                });
+                continue;
+            }
+
+            let candidate = Exceeded {
+                limit: limit.clone(),
+                retry_after_seconds: limit.retry_after(tokens),
+                current_count: (limit.capacity as f64 - tokens).floor().max(0.0) as i64,
+            };
+            if exceeded
+                .as_ref()
+                .map_or(true, |e| candidate.retry_after_seconds > e.retry_after_seconds)
+            {
+                exceeded = Some(candidate);
+            }
+        }
+
+        match exceeded {
+            Some(e) => Err(e),
+            None => Ok(updated),
+        }
+    })
+}
+
+/// Tokens currently left in each limit.
+pub fn remaining(db: &Database, limits: &[Limit], now: DateTime<Utc>) -> Result<Vec<f64>> {
+    limits
+        .iter()
+        .map(|limit| Ok(limit.available(db.get_rate_bucket(&limit.bucket)?.as_ref(), now)))
+        .collect()
+}
+
+/// Count a rejection and audit the first one in its hour.
+pub fn record_rejection(
+    db: &Database,
+    api_key_id: &str,
+    limit_type: &str,
+    channel: &str,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let hour = now.timestamp() - now.timestamp().rem_euclid(HOUR_SECS);
+    let window_start = DateTime::from_timestamp(hour, 0).unwrap_or(now);
+    if db.count_rate_limit_rejection(api_key_id, limit_type, window_start)? {
+        audit::record(
+            db,
+            AuditAction::RateLimited,
+            &audit::agent_actor(api_key_id),
+            None,
+            json!({
+                "channel": channel,
+                "limit": limit_type,
+                "window_start": window_start.to_rfc3339(),
+            }),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn api_key(db: &Database, id: &str, per_hour: i32, per_day: i32) -> ApiKey {
+        let hash = format!("hash-{}", id);
+        db.insert_api_key(id, "Agent", &hash, "gw_abc").unwrap();
+        db.set_api_key_rate_limits(id, per_hour, per_day).unwrap();
+        db.find_api_key_by_hash(&hash).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_bucket_refills_and_reports_retry_after() {
+        let db = Database::open_memory().unwrap();
+        let now = Utc::now();
+        let limits = key_limits(&api_key(&db, "key-1", 2, 50));
+
+        assert!(acquire(&db, &limits, now).unwrap().is_ok());
+        assert!(acquire(&db, &limits, now).unwrap().is_ok());
+
+        // Hourly bucket is empty; one token refills every 30 minutes
+        let exceeded = acquire(&db, &limits, now).unwrap().unwrap_err();
+        assert_eq!(exceeded.limit.limit_type, "hourly");
+        assert!((1800..=1801).contains(&exceeded.retry_after_seconds));
+        assert_eq!(exceeded.current_count, 2);
+
+        // A denied request spends nothing from the daily bucket
+        let left = remaining(&db, &limits, now).unwrap();
+        assert_eq!(left[1], 48.0);
+
+        let later = now + Duration::minutes(20);
+        let retry = acquire(&db, &limits, later).unwrap().unwrap_err().retry_after_seconds;
+        assert!((600..=601).contains(&retry), "retry_after {}", retry);
+        assert!(acquire(&db, &limits, now + Duration::minutes(31)).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_recipient_and_global_limits_span_keys() {
+        let db = Database::open_memory().unwrap();
+        let now = Utc::now();
+        let global = GlobalLimits {
+            recipient_per_day: Some(1),
+            per_hour: None,
+            per_day: Some(3),
+        };
+        let other = api_key(&db, "key-2", 10, 50);
+
+        let bob = recipient_identity(None, "+1 (555) 123-4567");
+        assert_eq!(bob, recipient_identity(None, "+15551234567"));
+
+        let first = limits_for(&global, &api_key(&db, "key-1", 10, 50), &bob);
+        assert!(acquire(&db, &first, now).unwrap().is_ok());
+        let exceeded = acquire(&db, &limits_for(&global, &other, &bob), now)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(exceeded.limit.limit_type, "recipient_daily");
+        assert!((DAY_SECS..=DAY_SECS + 1).contains(&exceeded.retry_after_seconds));
+
+        for n in 0..2 {
+            let carol = recipient_identity(Some(&format!("person-{}", n)), "ignored");
+            assert!(acquire(&db, &limits_for(&global, &other, &carol), now)
+                .unwrap()
+                .is_ok());
+        }
+        let dave = recipient_identity(None, "dave@example.com");
+        let exceeded = acquire(&db, &limits_for(&global, &other, &dave), now)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(exceeded.limit.limit_type, "global_daily");
+        assert_eq!(exceeded.to_response().limit, 3);
+    }
+
+    #[test]
+    fn test_global_limits_settings_round_trip() {
+        let db = Database::open_memory().unwrap();
+        assert_eq!(GlobalLimits::load(&db).unwrap(), GlobalLimits::default());
+
+        let limits = GlobalLimits {
+            recipient_per_day: Some(5),
+            per_hour: Some(20),
+            per_day: None,
+        };
+        limits.save(&db).unwrap();
+        assert_eq!(GlobalLimits::load(&db).unwrap(), limits);
+    }
+
+    #[test]
+    fn test_rejections_audited_once_per_hour() {
+        let db = Database::open_memory().unwrap();
+        api_key(&db, "key-1", 1, 50);
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+
+        for _ in 0..3 {
+            record_rejection(&db, "key-1", "hourly", "sms", now).unwrap();
+        }
+        record_rejection(&db, "key-1", "hourly", "sms", now + Duration::hours(1)).unwrap();
+
+        let audited = db
+            .list_audit_events(0)
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.event == "rate_limited")
+            .count();
+        assert_eq!(audited, 2);
+        assert_eq!(
+            db.count_rate_limited().unwrap(),
+            vec![("Agent".to_string(), "hourly".to_string(), 4)]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Recipients addressed by contact instead of by address.
+//!
+//! Agents can send `recipient_contact` (a contact ID, a `contact:<id>`
+//! reference or a tag naming exactly one contact) and the gateway fills in the
+//! contact's primary address for the channel. Agents only ever see the
+//! `contact:<id>` reference, never the address.
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use super::types::GatewayChannel;
+use crate::db::Database;
+use crate::models::Person;
+
+/// Prefix of the opaque reference returned to agents.
+pub const CONTACT_REF_PREFIX: &str = "contact:";
+
+/// The opaque reference agents see for a contact.
+pub fn contact_ref(person_id: Uuid) -> String {
+    format!("{}{}", CONTACT_REF_PREFIX, person_id)
+}
+
+/// A contact and the address a message to them goes to.
+#[derive(Debug)]
+pub struct Resolved {
+    pub person: Person,
+    pub address: String,
+}
+
+/// Resolve a `recipient_contact` value for a channel. The inner error explains
+/// why it couldn't be resolved, without revealing any address.
+pub fn resolve(
+    db: &Database,
+    reference: &str,
+    channel: GatewayChannel,
+) -> Result<std::result::Result<Resolved, String>> {
+    let reference = reference.trim();
+    let person = match reference.strip_prefix(CONTACT_REF_PREFIX).unwrap_or(reference).parse::<Uuid>() {
+        Ok(id) => db.get_person_by_id(id)?,
+        Err(_) if reference.starts_with(CONTACT_REF_PREFIX) => {
+            return Ok(Err("is not a valid contact reference".to_string()))
+        }
+        Err(_) => {
+            let mut tagged = db.get_persons_by_tag(reference)?;
+            match tagged.len() {
+                0 | 1 => tagged.pop(),
+                n => return Ok(Err(format!("tag matches {} contacts; use a contact ID", n))),
+            }
+        }
+    };
+    let Some(person) = person else {
+        return Ok(Err("no contact with that ID or tag".to_string()));
+    };
+
+    // Lists come back with the primary entry first
+    let address = match channel {
+        GatewayChannel::Email => db
+            .get_emails_for_person(person.id)?
+            .into_iter()
+            .map(|e| e.email_address)
+            .next(),
+        GatewayChannel::Sms | GatewayChannel::IMessage => db
+            .get_phones_for_person(person.id)?
+            .into_iter()
+            .map(|p| p.phone_number)
+            .next(),
+        GatewayChannel::Slack | GatewayChannel::Matrix => {
+            return Ok(Err(format!("contacts have no {} address", channel)))
+        }
+    };
+    match address.filter(|a| !a.trim().is_empty()) {
+        Some(address) => Ok(Ok(Resolved { person, address })),
+        None => Ok(Err(format!("contact has no address for {}", channel))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Email, Phone};
+
+    #[test]
+    fn test_resolve_by_id_and_tag() {
+        let db = Database::open_memory().unwrap();
+        let ada = Person::new();
+        db.insert_person(&ada).unwrap();
+        db.insert_email(&Email::new(ada.id, "ada@example.com".to_string())).unwrap();
+        let tag = db.get_or_create_tag("board-chair").unwrap();
+        db.add_tag_to_person(ada.id, tag.id).unwrap();
+
+        let by_id = resolve(&db, &ada.id.to_string(), GatewayChannel::Email).unwrap().unwrap();
+        assert_eq!(by_id.address, "ada@example.com");
+        let by_ref = resolve(&db, &contact_ref(ada.id), GatewayChannel::Email).unwrap().unwrap();
+        assert_eq!(by_ref.person.id, ada.id);
+        let by_tag = resolve(&db, "board-chair", GatewayChannel::Email).unwrap().unwrap();
+        assert_eq!(by_tag.person.id, ada.id);
+
+        // No phone on file, and a tag shared by two contacts is ambiguous
+        assert!(resolve(&db, "board-chair", GatewayChannel::Sms).unwrap().is_err());
+        let bo = Person::new();
+        db.insert_person(&bo).unwrap();
+        db.insert_phone(&Phone::new(bo.id, "+15551234567".to_string())).unwrap();
+        db.add_tag_to_person(bo.id, tag.id).unwrap();
+        assert!(resolve(&db, "board-chair", GatewayChannel::Email).unwrap().is_err());
+        assert_eq!(
+            resolve(&db, &contact_ref(bo.id), GatewayChannel::Sms).unwrap().unwrap().address,
+            "+15551234567"
+        );
+
+        assert!(resolve(&db, "nobody", GatewayChannel::Email).unwrap().is_err());
+        assert!(resolve(&db, "contact:nope", GatewayChannel::Email).unwrap().is_err());
+    }
+}
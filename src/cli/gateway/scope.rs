@@ -0,0 +1,130 @@
+//! Per-key permission scopes.
+//!
+//! Scopes narrow what an API key may do: which channels and priorities it may
+//! send with, whether it may read other keys' actions, and whether it may send
+//! at all.
+
+use super::types::{ErrorCode, ErrorResponse, FieldError, Priority, SendRequest};
+use crate::db::gateway::{ApiKey, ApiKeyScopes};
+
+/// Check a send request against the key's scopes.
+pub fn check_send(scopes: &ApiKeyScopes, request: &SendRequest) -> Result<(), ErrorResponse> {
+    if scopes.read_only {
+        return Err(ErrorResponse::new(
+            ErrorCode::ScopeDenied,
+            "This API key is read-only",
+        ));
+    }
+
+    let mut errors = Vec::new();
+
+    if let Some(ref channels) = scopes.channels {
+        let channel = request.channel.to_string();
+        if !channels.contains(&channel) {
+            errors.push(FieldError::new(
+                "channel",
+                format!("this key may only send on: {}", channels.join(", ")),
+            ));
+        }
+    }
+
+    if let Some(max) = max_priority(scopes) {
+        if request.priority.rank() > max.rank() {
+            errors.push(FieldError::new(
+                "priority",
+                format!("this key may not use priorities above {}", max),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let message = errors
+        .iter()
+        .map(|e| format!("{}: {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(ErrorResponse {
+        field_errors: errors,
+        ..ErrorResponse::new(ErrorCode::ScopeDenied, message)
+    })
+}
+
+/// Whether `key` may read an action queued by `owner_key_id`.
+pub fn can_read(key: &ApiKey, owner_key_id: &str) -> bool {
+    key.scopes.read_all || key.id == owner_key_id
+}
+
+/// Highest priority the key may use, if limited.
+pub fn max_priority(scopes: &ApiKeyScopes) -> Option<Priority> {
+    scopes.max_priority.as_deref().and_then(|p| p.parse().ok())
+}
+
+/// Human-readable scope summary, one `(label, value)` per line.
+pub fn describe(scopes: &ApiKeyScopes) -> Vec<(&'static str, String)> {
+    let yes_no = |b: bool| if b { "yes" } else { "no" }.to_string();
+    vec![
+        (
+            "Channels",
+            scopes
+                .channels
+                .as_ref()
+                .map_or("all".to_string(), |c| c.join(", ")),
+        ),
+        (
+            "Max priority",
+            scopes
+                .max_priority
+                .clone()
+                .unwrap_or_else(|| "any".to_string()),
+        ),
+        (
+            "Read",
+            if scopes.read_all { "all actions" } else { "own actions" }.to_string(),
+        ),
+        ("Read-only", yes_no(scopes.read_only)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> SendRequest {
+        SendRequest::from_json(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_check_send_enforces_scopes() {
+        let sms = request(r#"{"channel":"sms","recipient_address":"+15551234567","body":"Hi"}"#);
+        let urgent_email = request(
+            r#"{"channel":"email","recipient_address":"a@example.com","subject":"S","body":"Hi",
+                "priority":"urgent"}"#,
+        );
+
+        let unrestricted = ApiKeyScopes::default();
+        assert!(check_send(&unrestricted, &sms).is_ok());
+        assert!(check_send(&unrestricted, &urgent_email).is_ok());
+
+        let limited = ApiKeyScopes {
+            channels: Some(vec!["sms".to_string()]),
+            max_priority: Some("high".to_string()),
+            ..ApiKeyScopes::default()
+        };
+        assert!(check_send(&limited, &sms).is_ok());
+        let err = check_send(&limited, &urgent_email).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ScopeDenied);
+        let fields: Vec<&str> = err.field_errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["channel", "priority"]);
+
+        let read_only = ApiKeyScopes {
+            read_only: true,
+            ..ApiKeyScopes::default()
+        };
+        assert_eq!(
+            check_send(&read_only, &sms).unwrap_err().code,
+            ErrorCode::ScopeDenied
+        );
+    }
+}
@@ -1,32 +1,55 @@
 //! HTTP server for the communication gateway.
 
 use anyhow::{anyhow, Result};
+use serde_json::json;
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
+use super::audit::{self, AuditAction};
 use super::execute;
-use super::filter::{ContentFilterMatcher, FilterResult};
+use super::inbound::{self, InboundSourceConfig};
+use super::intake::{self, Rejected, SendChecks};
 use super::keys;
-use super::webhook;
+use super::metrics::{self, Metrics};
+use super::notify;
+use super::openapi;
+use super::scope;
+use super::tls::{self, GatewayTls};
 use super::types::{
-    ActionStatusResponse, AllowlistErrorResponse, ConsentDeniedErrorResponse,
-    ContentBlockedErrorResponse, GatewayApiResponse, GatewayChannel, HealthResponse,
-    QueueEntryResponse, QueueListResponse, QueueStatus, RateLimitErrorResponse, SendRequest,
-    SendResponse,
+    ActionStatusResponse, BatchActionStatus, BatchSendRequest, BatchSendResponse,
+    BatchSendResult, BatchStatusResponse, ErrorCode, ErrorResponse, FieldError,
+    GatewayApiResponse, HealthResponse, QueueEntryResponse, QueueListResponse, QueueStatus,
+    SendRequest, SendResponse,
 };
+use super::types::{MAX_BODY_LEN, MAX_IDEMPOTENCY_KEY_LEN, MAX_SUBJECT_LEN};
+use super::web::{self, WebSessions};
+use super::webhook;
+use crate::cli::http_server::{self, Handler, HttpRequest, HttpResponse, ServerLimits};
 use crate::db::Database;
 
+/// Settings key for the URL the running gateway listens on.
+pub const SETTING_LISTEN_URL: &str = "gateway_listen_url";
+
+/// Settings key for the running gateway's TLS certificate fingerprint.
+pub const SETTING_TLS_FINGERPRINT: &str = "gateway_tls_fingerprint";
+
 /// HTTP server for the communication gateway.
 pub struct GatewayServer {
     port: u16,
+    bind: IpAddr,
+    tls: Option<GatewayTls>,
+    client_certs: bool,
     db_path: PathBuf,
     start_time: Instant,
-    content_filter: ContentFilterMatcher,
+    checks: SendChecks,
+    inbound: Option<InboundSourceConfig>,
+    web: WebSessions,
+    /// Serializes sends that carry an `Idempotency-Key`
+    idempotency: Mutex<()>,
 }
 
 impl GatewayServer {
@@ -40,364 +63,596 @@ impl GatewayServer {
         // Verify DB is accessible
         let _ = db.count_pending_queue()?;
 
-        // Initialize and load content filters (compiled once for performance)
-        let content_filter = ContentFilterMatcher::new();
-        let filter_count = content_filter.reload(db)?;
-        if filter_count > 0 {
-            println!("Loaded {} content filter(s)", filter_count);
-        }
+        // Content filters are compiled once for performance
+        let checks = SendChecks::load(db)?;
 
         Ok(Self {
             port,
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            tls: None,
+            client_certs: false,
             db_path,
             start_time: Instant::now(),
-            content_filter,
+            checks,
+            inbound: None,
+            web: WebSessions::new(),
+            idempotency: Mutex::new(()),
         })
     }
 
-    /// Start the server (blocking).
-    pub fn start(&self, shutdown: Arc<AtomicBool>) -> Result<()> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.port))?;
-        listener.set_nonblocking(true)?;
-
-        println!("Gateway server listening on 0.0.0.0:{}", self.port);
-
-        while !shutdown.load(Ordering::SeqCst) {
-            match listener.accept() {
-                Ok((stream, peer_addr)) => {
-                    if let Err(e) = self.handle_connection(stream, peer_addr) {
-                        eprintln!("Request error: {}", e);
-                    }
-                }
-                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                }
-                Err(e) => {
-                    eprintln!("Accept error: {}", e);
-                }
-            }
-        }
-
-        Ok(())
+    /// Relay inbound messages from `source` to agent webhooks while running.
+    pub fn with_inbound(mut self, source: InboundSourceConfig) -> Self {
+        self.inbound = Some(source);
+        self
     }
 
-    fn handle_connection(&self, mut stream: TcpStream, peer_addr: SocketAddr) -> Result<()> {
-        stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
-        stream.set_write_timeout(Some(std::time::Duration::from_secs(30)))?;
+    /// Listen on `addr` instead of all interfaces.
+    pub fn with_bind(mut self, addr: IpAddr) -> Self {
+        self.bind = addr;
+        self
+    }
 
-        let mut reader = BufReader::new(stream.try_clone()?);
-        let mut request_line = String::new();
-        reader.read_line(&mut request_line)?;
+    /// Serve HTTPS. With `client_certs`, agents may authenticate with a
+    /// registered client certificate instead of an API key.
+    pub fn with_tls(mut self, tls: GatewayTls, client_certs: bool) -> Self {
+        self.tls = Some(tls);
+        self.client_certs = client_certs;
+        self
+    }
 
-        let parts: Vec<&str> = request_line.trim().split_whitespace().collect();
-        if parts.len() < 2 {
-            return self.send_response(&mut stream, 400, "Bad Request");
+    /// Start the server (blocking).
+    pub fn start(self, shutdown: Arc<AtomicBool>) -> Result<()> {
+        let addr = SocketAddr::new(self.bind, self.port);
+        let listener = TcpListener::bind(addr)?;
+
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        println!("Gateway server listening on {}://{}", scheme, addr);
+        if let Some(ref tls) = self.tls {
+            println!("TLS certificate SHA-256: {}", tls.fingerprint);
         }
+        self.record_listener(&format!("{}://{}", scheme, addr))?;
 
-        let method = parts[0];
-        let path = parts[1];
+        let retry_worker = {
+            let db_path = self.db_path.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || webhook::run_delivery_worker(db_path, shutdown))
+        };
 
-        // Parse headers
-        let mut headers = HashMap::new();
-        let mut content_length = 0usize;
+        let send_retrier = {
+            let db_path = self.db_path.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || execute::run_retry_worker(db_path, shutdown))
+        };
 
-        loop {
-            let mut header_line = String::new();
-            reader.read_line(&mut header_line)?;
-            let header_line = header_line.trim();
-            if header_line.is_empty() {
-                break;
-            }
-            if let Some((key, value)) = header_line.split_once(':') {
-                let key = key.trim().to_lowercase();
-                let value = value.trim().to_string();
-                if key == "content-length" {
-                    content_length = value.parse().unwrap_or(0);
-                }
-                headers.insert(key, value);
-            }
+        let notifier = {
+            let db_path = self.db_path.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || notify::run_notifier(db_path, shutdown))
+        };
+
+        let poller = self.inbound.clone().map(|source| {
+            let db_path = self.db_path.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || inbound::run_poller(db_path, source, shutdown))
+        });
+
+        let tls_config = self.tls.as_ref().map(|tls| tls.config.clone());
+        let result = http_server::serve(
+            listener,
+            Arc::new(self),
+            ServerLimits::default(),
+            tls_config,
+            shutdown.clone(),
+        );
+
+        if let Some(poller) = poller {
+            let _ = poller.join();
         }
+        let _ = notifier.join();
+        let _ = send_retrier.join();
+        let _ = retry_worker.join();
 
-        // Read body
-        let mut body = vec![0u8; content_length];
-        if content_length > 0 {
-            std::io::Read::read_exact(&mut reader, &mut body)?;
+        result
+    }
+
+    /// Save the listen URL and certificate fingerprint for `gateway status`.
+    fn record_listener(&self, url: &str) -> Result<()> {
+        let db = Database::open_at(self.db_path.clone())?;
+        db.set_setting(SETTING_LISTEN_URL, url)?;
+        match self.tls {
+            Some(ref tls) => db.set_setting(SETTING_TLS_FINGERPRINT, &tls.fingerprint),
+            None => db.delete_setting(SETTING_TLS_FINGERPRINT),
         }
+    }
 
+    fn route(&self, request: &HttpRequest) -> Result<HttpResponse> {
         // Check if request is from localhost
-        let is_local = peer_addr.ip().is_loopback();
+        let is_local = request.peer_addr.ip().is_loopback();
+
+        // Browser dashboard, localhost only
+        if request.path == web::UI_PATH || request.path.starts_with("/gateway/ui/") {
+            if !is_local || !web::is_loopback_host(request) {
+                return self.send_error(&ErrorResponse::new(
+                    ErrorCode::LocalOnly,
+                    "The dashboard is only accessible from localhost",
+                ));
+            }
+            return self.route_web(request);
+        }
 
         // Route request
-        match (method, path) {
+        match (request.method.as_str(), request.path.as_str()) {
             // Public endpoints (require API key)
-            ("GET", "/gateway/health") => self.handle_health(&mut stream),
-            ("POST", "/gateway/send") => self.handle_send(&mut stream, &headers, &body),
+            ("GET", "/gateway/health") => self.handle_health(is_local || self.may_read_metrics(request)),
+            ("GET", "/gateway/openapi.json") => self.send_json_response(200, &openapi::spec()),
+            ("POST", "/gateway/send") => self.handle_send(request),
+            ("POST", "/gateway/send/batch") => self.handle_send_batch(request),
             ("GET", p) if p.starts_with("/gateway/actions/") => {
                 let id = p.strip_prefix("/gateway/actions/").unwrap_or("");
-                self.handle_action_status(&mut stream, &headers, id)
+                self.handle_action_status(request, id)
             }
 
+            // Metrics: localhost, or an API key that may read all actions
+            ("GET", "/gateway/metrics") if is_local => self.handle_metrics(),
+            ("GET", "/gateway/metrics") if self.has_credentials(request) => match self.authenticate(request) {
+                Ok(key) if key.scopes.read_all => self.handle_metrics(),
+                Ok(_) => self.send_error(&ErrorResponse::new(
+                    ErrorCode::ScopeDenied,
+                    "Metrics need a key with the read-all scope",
+                )),
+                Err(e) => self.send_error(&ErrorResponse::new(ErrorCode::Unauthorized, e.to_string())),
+            },
+            ("GET", "/gateway/metrics") => self.send_error(&ErrorResponse::new(
+                ErrorCode::Unauthorized,
+                "Metrics need an API key when called from another host",
+            )),
+
             // Local-only endpoints
-            ("GET", "/gateway/queue") if is_local => self.handle_list_queue(&mut stream),
+            ("GET", "/gateway/queue") if is_local => self.handle_list_queue(None),
             ("POST", p) if is_local && p.starts_with("/gateway/queue/") && p.ends_with("/approve") => {
                 let id = p
                     .strip_prefix("/gateway/queue/")
                     .and_then(|s| s.strip_suffix("/approve"))
                     .unwrap_or("");
-                self.handle_approve(&mut stream, id)
+                self.handle_approve(id)
             }
             ("POST", p) if is_local && p.starts_with("/gateway/queue/") && p.ends_with("/deny") => {
                 let id = p
                     .strip_prefix("/gateway/queue/")
                     .and_then(|s| s.strip_suffix("/deny"))
                     .unwrap_or("");
-                self.handle_deny(&mut stream, id)
+                self.handle_deny(id)
+            }
+
+            // Remote queue listing requires an API key
+            ("GET", "/gateway/queue") if self.has_credentials(request) => {
+                match self.authenticate(request) {
+                    Ok(key) => self.handle_list_queue(Some(&key)),
+                    Err(e) => self.send_error(&ErrorResponse::new(
+                        ErrorCode::Unauthorized,
+                        e.to_string(),
+                    )),
+                }
             }
 
             // Local-only but accessed from non-local
-            ("GET", "/gateway/queue") => {
-                let response: GatewayApiResponse<()> =
-                    GatewayApiResponse::err("This endpoint is only accessible from localhost");
-                self.send_json_response(&mut stream, 403, &response)
+            ("GET", "/gateway/queue") => self.send_error(
+                &ErrorResponse::new(
+                    ErrorCode::LocalOnly,
+                    "This endpoint is only accessible from localhost",
+                ),
+            ),
+            ("POST", p) if p.contains("/gateway/queue/") => self.send_error(
+                &ErrorResponse::new(
+                    ErrorCode::LocalOnly,
+                    "This endpoint is only accessible from localhost",
+                ),
+            ),
+
+            _ => self.send_error(
+                &ErrorResponse::new(ErrorCode::NotFound, "Not Found"),
+            ),
+        }
+    }
+
+    // ========== Web Dashboard ==========
+
+    fn route_web(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let now = chrono::Utc::now();
+        let db = Database::open_at(self.db_path.clone())?;
+        let path = request.path.strip_prefix(web::UI_PATH).unwrap_or("");
+
+        // Login is the only page that needs no session
+        match (request.method.as_str(), path) {
+            ("GET", "/login") => return Ok(web::page_response(200, web::login_page(None))),
+            ("GET", p) if p.starts_with("/login/") => {
+                return self.web_login(&db, &p["/login/".len()..]);
             }
-            ("POST", p) if p.contains("/gateway/queue/") => {
-                let response: GatewayApiResponse<()> =
-                    GatewayApiResponse::err("This endpoint is only accessible from localhost");
-                self.send_json_response(&mut stream, 403, &response)
+            ("POST", "/login") if web::is_same_origin(request) => {
+                let form = web::parse_form(&request.body);
+                return self.web_login(&db, form.get("code").map_or("", |c| c.as_str()));
             }
+            _ => {}
+        }
 
-            _ => self.send_response(&mut stream, 404, "Not Found"),
+        let login = format!("{}/login", web::UI_PATH);
+        let Some(session) = self.web.get(request, now) else {
+            return Ok(HttpResponse::redirect(&login));
+        };
+
+        if request.method == "POST" {
+            let form = web::parse_form(&request.body);
+            if !web::is_same_origin(request) || !web::csrf_matches(&session, form.get("csrf")) {
+                return Ok(HttpResponse::text(403, "Invalid or missing CSRF token"));
+            }
+            if path == "/logout" {
+                self.web.remove(request);
+                return Ok(HttpResponse::redirect(&login)
+                    .with_header("set-cookie", web::clear_cookie_header()));
+            }
+            let flash = match queue_action(path) {
+                Some((id, "approve")) => match self.approve(id, audit::ACTOR_WEB)? {
+                    Ok(status) => match status.status {
+                        QueueStatus::Sent => "Approved and sent".to_string(),
+                        QueueStatus::Approved => format!(
+                            "Approved; sending failed and will be retried: {}",
+                            status.error_message.unwrap_or_default()
+                        ),
+                        _ => format!(
+                            "Approved, but sending failed: {}",
+                            status.error_message.unwrap_or_default()
+                        ),
+                    },
+                    Err(error) => error.error,
+                },
+                Some((id, "deny")) => match self.deny(id, audit::ACTOR_WEB)? {
+                    Ok(_) => "Denied".to_string(),
+                    Err(error) => error.error,
+                },
+                Some((id, "edit")) => self.web_edit(&db, id, &form)?,
+                _ => return Ok(HttpResponse::text(404, "Not Found")),
+            };
+            self.web.set_flash(request, flash);
+            return Ok(HttpResponse::redirect(web::UI_PATH));
+        }
+
+        match (path, queue_action(path)) {
+            ("" | "/", _) => {
+                let key_names: HashMap<String, String> = db
+                    .list_api_keys()?
+                    .into_iter()
+                    .map(|k| (k.id, k.name))
+                    .collect();
+                let views = db
+                    .list_pending_queue()?
+                    .into_iter()
+                    .map(|entry| {
+                        let agent = key_names
+                            .get(&entry.api_key_id)
+                            .cloned()
+                            .unwrap_or_else(|| "Unknown".to_string());
+                        web::entry_view(&db, self.checks.content_filter(), entry, agent)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let flash = self.web.take_flash(request);
+                Ok(web::page_response(
+                    200,
+                    web::dashboard_page(&views, &session, flash.as_deref()),
+                ))
+            }
+            (_, Some((id, "edit"))) => match db.get_queue_entry(id)? {
+                Some(entry) if entry.status == "pending" || entry.status == "flagged" => {
+                    Ok(web::page_response(200, web::edit_page(&entry, &session)))
+                }
+                Some(_) => Ok(HttpResponse::redirect(web::UI_PATH)),
+                None => Ok(HttpResponse::text(404, "Message not found")),
+            },
+            _ => Ok(HttpResponse::text(404, "Not Found")),
         }
     }
 
-    /// Health check endpoint.
-    fn handle_health(&self, stream: &mut TcpStream) -> Result<()> {
-        let db = Database::open_at(self.db_path.clone())?;
-        let pending_count = db.count_pending_queue().unwrap_or(0);
+    /// Exchange a login code for a session cookie.
+    fn web_login(&self, db: &Database, code: &str) -> Result<HttpResponse> {
+        if !web::redeem_login_code(db, code, chrono::Utc::now())? {
+            let page = web::login_page(Some("That login code is invalid, used or expired."));
+            return Ok(web::page_response(403, page));
+        }
+        let id = self.web.create(chrono::Utc::now());
+        Ok(HttpResponse::redirect(web::UI_PATH)
+            .with_header("set-cookie", web::session_cookie_header(&id, self.tls.is_some())))
+    }
+
+    /// Save an edited message. Returns the message to show the reviewer.
+    fn web_edit(&self, db: &Database, id: &str, form: &HashMap<String, String>) -> Result<String> {
+        let Some(entry) = db.get_queue_entry(id)? else {
+            return Ok("Message not found".to_string());
+        };
+        let body = form.get("body").map_or("", |b| b.as_str()).replace("\r\n", "\n");
+        if body.trim().is_empty() {
+            return Ok("Message body can't be empty; nothing was changed".to_string());
+        }
+        if body.chars().count() > MAX_BODY_LEN {
+            return Ok(format!(
+                "Message is longer than {} characters; nothing was changed",
+                MAX_BODY_LEN
+            ));
+        }
+        // Email keeps a subject; other channels never get one
+        let subject = entry
+            .subject
+            .as_ref()
+            .map(|s| form.get("subject").unwrap_or(s).trim().to_string());
+        if subject
+            .as_ref()
+            .is_some_and(|s| s.is_empty() || s.chars().count() > MAX_SUBJECT_LEN)
+        {
+            return Ok(format!(
+                "Subject must be 1 to {} characters; nothing was changed",
+                MAX_SUBJECT_LEN
+            ));
+        }
+
+        if db.update_queue_content(id, subject.as_deref(), &body)? {
+            audit::record(
+                db,
+                AuditAction::Edited,
+                audit::ACTOR_WEB,
+                Some(id),
+                json!({
+                    "previous_sha256": audit::content_hash(entry.subject.as_deref(), &entry.body),
+                    "content_sha256": audit::content_hash(subject.as_deref(), &body),
+                }),
+            )?;
+            Ok("Message updated".to_string())
+        } else {
+            Ok(format!("Cannot edit: status is {}", entry.status))
+        }
+    }
+
+    /// Health check endpoint. The review backlog is only reported to callers
+    /// that may read metrics.
+    fn handle_health(&self, with_backlog: bool) -> Result<HttpResponse> {
+        let backlog = if with_backlog {
+            let db = Database::open_at(self.db_path.clone())?;
+            Some(db.queue_backlog().unwrap_or_default())
+        } else {
+            None
+        };
 
         let health = HealthResponse {
             status: "ok".to_string(),
             uptime_secs: self.start_time.elapsed().as_secs(),
-            pending_count,
+            pending_count: backlog.as_ref().map(|b| b.pending + b.flagged),
             version: "1.0".to_string(),
+            flagged_count: backlog.as_ref().map(|b| b.flagged),
+            retrying_count: backlog.as_ref().map(|b| b.retrying),
+            oldest_pending_secs: backlog
+                .and_then(|b| b.oldest_waiting)
+                .map(|at| (chrono::Utc::now() - at).num_seconds().max(0)),
         };
 
         let response = GatewayApiResponse::ok(health);
-        self.send_json_response(stream, 200, &response)
+        self.send_json_response(200, &response)
+    }
+
+    /// Counters and gauges in the Prometheus text format.
+    fn handle_metrics(&self) -> Result<HttpResponse> {
+        let db = Database::open_at(self.db_path.clone())?;
+        let mut text = Metrics::collect(&db)?.to_prometheus(chrono::Utc::now());
+        text.push_str("# HELP gateway_uptime_seconds Seconds since the gateway started.\n");
+        text.push_str("# TYPE gateway_uptime_seconds gauge\n");
+        text.push_str(&format!("gateway_uptime_seconds {}\n", self.start_time.elapsed().as_secs()));
+        Ok(HttpResponse {
+            content_type: metrics::CONTENT_TYPE,
+            ..HttpResponse::text(200, &text)
+        })
     }
 
     /// Queue a message for approval.
-    fn handle_send(
-        &self,
-        stream: &mut TcpStream,
-        headers: &HashMap<String, String>,
-        body: &[u8],
-    ) -> Result<()> {
+    fn handle_send(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let body = request.body.as_slice();
+
         // Authenticate
-        let api_key = match self.authenticate(headers) {
+        let api_key = match self.authenticate(request) {
             Ok(key) => key,
             Err(e) => {
-                let response: GatewayApiResponse<()> = GatewayApiResponse::err(e.to_string());
-                return self.send_json_response(stream, 401, &response);
+                let response = ErrorResponse::new(ErrorCode::Unauthorized, e.to_string());
+                return self.send_error(&response);
             }
         };
 
-        // Open database early for rate limit check
         let db = Database::open_at(self.db_path.clone())?;
 
-        // Check rate limits
-        let now = chrono::Utc::now();
-        let one_hour_ago = now - chrono::Duration::hours(1);
-        let one_day_ago = now - chrono::Duration::days(1);
-
-        let hourly_count = db.count_queue_since(&api_key.id, one_hour_ago)?;
-        if hourly_count >= api_key.rate_limit_per_hour as i64 {
-            let response = RateLimitErrorResponse {
-                error: "rate_limit_exceeded".to_string(),
-                retry_after_seconds: 3600,
-                limit_type: "hourly".to_string(),
-                current_count: hourly_count,
-                limit: api_key.rate_limit_per_hour,
-            };
-            return self.send_json_response(stream, 429, &response);
-        }
-
-        let daily_count = db.count_queue_since(&api_key.id, one_day_ago)?;
-        if daily_count >= api_key.rate_limit_per_day as i64 {
-            let response = RateLimitErrorResponse {
-                error: "rate_limit_exceeded".to_string(),
-                retry_after_seconds: 86400,
-                limit_type: "daily".to_string(),
-                current_count: daily_count,
-                limit: api_key.rate_limit_per_day,
-            };
-            return self.send_json_response(stream, 429, &response);
-        }
-
-        // Parse request
-        let req: SendRequest = match serde_json::from_slice(body) {
+        // Parse and validate request
+        let req = match SendRequest::from_json(body) {
             Ok(r) => r,
-            Err(e) => {
-                let response: GatewayApiResponse<()> =
-                    GatewayApiResponse::err(format!("Invalid request: {}", e));
-                return self.send_json_response(stream, 400, &response);
-            }
+            Err(response) => return self.send_error(&response),
         };
 
-        // Validate
-        if req.body.trim().is_empty() {
-            let response: GatewayApiResponse<()> =
-                GatewayApiResponse::err("Message body cannot be empty");
-            return self.send_json_response(stream, 400, &response);
-        }
-
-        if req.recipient_address.trim().is_empty() {
-            let response: GatewayApiResponse<()> =
-                GatewayApiResponse::err("Recipient address is required");
-            return self.send_json_response(stream, 400, &response);
-        }
-
-        // Email requires subject
-        if matches!(req.channel, GatewayChannel::Email) && req.subject.is_none() {
-            let response: GatewayApiResponse<()> =
-                GatewayApiResponse::err("Email requires a subject");
-            return self.send_json_response(stream, 400, &response);
-        }
+        let idempotency_key = match idempotency_key(request) {
+            Ok(key) => key,
+            Err(response) => return self.send_error(&response),
+        };
 
-        // Check recipient allowlist
-        let allowlist = db.list_allowlist_entries(&api_key.id)?;
-        if !allowlist.is_empty() {
-            let patterns: Vec<String> = allowlist.iter().map(|e| e.recipient_pattern.clone()).collect();
-            if !recipient_matches_allowlist(&req.recipient_address, &patterns) {
-                let response = AllowlistErrorResponse {
-                    error: "recipient_not_allowed".to_string(),
-                    allowed_patterns: patterns,
-                };
-                return self.send_json_response(stream, 403, &response);
+        // Held until the key is recorded, so a retry arriving while the first
+        // attempt is still running waits for it instead of queueing again
+        let _guard = idempotency_key.map(|_| self.idempotency.lock().unwrap_or_else(|e| e.into_inner()));
+        if let Some(key) = idempotency_key {
+            if let Some(response) = Self::idempotent_response(&db, &api_key.id, key)? {
+                return self.send_json_response(200, &GatewayApiResponse::ok(response));
             }
         }
+        match intake::queue_message(&db, &self.checks, &api_key, req, None, idempotency_key)? {
+            Ok(response) => self.send_json_response(200, &GatewayApiResponse::ok(response)),
+            // Refusals aren't remembered: nothing was queued, so a retry is checked afresh
+            Err(Rejected(response, _)) => Ok(response),
+        }
+    }
 
-        // Check contact AI consent flag
-        // Look up recipient by email or phone to check if they've opted out
-        let contact = if req.recipient_address.contains('@') {
-            db.get_person_by_email(&req.recipient_address)?
-        } else {
-            db.get_person_by_phone(&req.recipient_address)?
+    /// What an earlier send under this idempotency key queued, if anything.
+    fn idempotent_response(db: &Database, api_key_id: &str, idempotency_key: &str) -> Result<Option<SendResponse>> {
+        let Some(action_id) = db.get_idempotent_action(api_key_id, idempotency_key)? else {
+            return Ok(None);
         };
+        Ok(db.get_queue_entry(&action_id)?.map(|entry| SendResponse {
+            action_id: entry.id,
+            status: entry.status.parse().unwrap_or(QueueStatus::Pending),
+            thread_id: entry.thread_id.unwrap_or_default(),
+            recipient_contact: entry.recipient_contact,
+        }))
+    }
 
-        if let Some(person) = contact {
-            if !person.ai_contact_allowed {
-                let response = ConsentDeniedErrorResponse {
-                    error: "contact_consent_denied".to_string(),
-                    recipient: req.recipient_address.clone(),
-                };
-                return self.send_json_response(stream, 403, &response);
+    /// Queue one message per recipient under a shared batch ID. Each recipient
+    /// goes through the same checks as `/gateway/send`, so one refusal doesn't
+    /// stop the rest.
+    fn handle_send_batch(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let api_key = match self.authenticate(request) {
+            Ok(key) => key,
+            Err(e) => {
+                let response = ErrorResponse::new(ErrorCode::Unauthorized, e.to_string());
+                return self.send_error(&response);
             }
-        }
-
-        // Check content filters (subject + body for email, body only for SMS/iMessage)
-        let filter_result = if matches!(req.channel, GatewayChannel::Email) {
-            self.content_filter.check_email(req.subject.as_deref(), &req.body)
-        } else {
-            self.content_filter.check_message(&req.body)
         };
 
-        // Handle deny filter match - reject immediately
-        if let FilterResult::Denied {
-            filter_name,
-            description,
-        } = filter_result
-        {
-            let response = ContentBlockedErrorResponse {
-                error: "content_blocked".to_string(),
-                filter: filter_name,
-                description,
-            };
-            return self.send_json_response(stream, 400, &response);
-        }
+        let db = Database::open_at(self.db_path.clone())?;
 
-        // Determine initial status (flagged if filter matched, pending otherwise)
-        let initial_status = if matches!(filter_result, FilterResult::Flagged { .. }) {
-            "flagged"
-        } else {
-            "pending"
+        let batch = match BatchSendRequest::from_json(request.body.as_slice()) {
+            Ok(b) => b,
+            Err(response) => return self.send_error(&response),
         };
 
-        // Insert into queue (db already opened for rate limit check)
-        let id = uuid::Uuid::new_v4().to_string();
-        let context_json = req.context.map(|c| serde_json::to_string(&c).ok()).flatten();
-
-        db.insert_queue_entry(
-            &id,
-            &api_key.id,
-            &req.channel.to_string(),
-            &req.recipient_address,
-            req.recipient_name.as_deref(),
-            req.subject.as_deref(),
-            &req.body,
-            &req.priority.to_string(),
-            context_json.as_deref(),
-        )?;
-
-        // If flagged, update status from pending to flagged
-        if initial_status == "flagged" {
-            db.update_queue_status(&id, "flagged")?;
-        }
-
-        // Update key last_used
-        db.touch_api_key(&api_key.id)?;
+        let idempotency_key = match idempotency_key(request) {
+            Ok(key) => key,
+            Err(response) => return self.send_error(&response),
+        };
 
-        let response_status = if initial_status == "flagged" {
-            QueueStatus::Flagged
-        } else {
-            QueueStatus::Pending
+        // A retried batch keeps its batch ID, and each recipient queued the
+        // first time is reported rather than queued again
+        let _guard = idempotency_key.map(|_| self.idempotency.lock().unwrap_or_else(|e| e.into_inner()));
+        let batch_key = idempotency_key.map(|key| format!("batch:{}", key));
+        let batch_id = match batch_key.as_deref() {
+            Some(key) => match db.get_idempotent_action(&api_key.id, key)? {
+                Some(batch_id) => batch_id,
+                None => {
+                    let batch_id = uuid::Uuid::new_v4().to_string();
+                    db.insert_idempotency_key(&api_key.id, key, &batch_id)?;
+                    batch_id
+                }
+            },
+            None => uuid::Uuid::new_v4().to_string(),
         };
 
-        let response = GatewayApiResponse::ok(SendResponse {
-            action_id: id,
-            status: response_status,
-        });
-        self.send_json_response(stream, 200, &response)
+        let mut results = Vec::with_capacity(batch.requests.len());
+        for (index, req) in batch.requests.into_iter().enumerate() {
+            // Contact recipients are reported by reference, never by address
+            let (recipient_address, recipient_contact) = match req.recipient_contact {
+                Some(ref reference) => (None, Some(reference.clone())),
+                None => (Some(req.recipient_address.clone()), None),
+            };
+            let item_key = batch_key.as_ref().map(|key| format!("{}#{}", key, index));
+            let earlier = match item_key.as_deref() {
+                Some(key) => Self::idempotent_response(&db, &api_key.id, key)?,
+                None => None,
+            };
+            let outcome = match earlier {
+                Some(sent) => Ok(sent),
+                None => intake::queue_message(&db, &self.checks, &api_key, req, Some(&batch_id), item_key.as_deref())?,
+            };
+            let result = match outcome {
+                Ok(sent) => BatchSendResult {
+                    recipient_address,
+                    recipient_contact: sent.recipient_contact.or(recipient_contact),
+                    action_id: Some(sent.action_id),
+                    status: Some(sent.status),
+                    error: None,
+                },
+                Err(Rejected(_, body)) => BatchSendResult {
+                    recipient_address,
+                    recipient_contact,
+                    action_id: None,
+                    status: None,
+                    error: Some(body),
+                },
+            };
+            results.push(result);
+        }
+
+        let response = GatewayApiResponse::ok(BatchSendResponse { batch_id, results });
+        self.send_json_response(200, &response)
     }
 
     /// Get action status.
-    fn handle_action_status(
-        &self,
-        stream: &mut TcpStream,
-        headers: &HashMap<String, String>,
-        id: &str,
-    ) -> Result<()> {
+    fn handle_action_status(&self, request: &HttpRequest, id: &str) -> Result<HttpResponse> {
         // Authenticate
-        if let Err(e) = self.authenticate(headers) {
-            let response: GatewayApiResponse<()> = GatewayApiResponse::err(e.to_string());
-            return self.send_json_response(stream, 401, &response);
-        }
+        let api_key = match self.authenticate(request) {
+            Ok(key) => key,
+            Err(e) => {
+                let response = ErrorResponse::new(ErrorCode::Unauthorized, e.to_string());
+                return self.send_error(&response);
+            }
+        };
 
         let db = Database::open_at(self.db_path.clone())?;
 
+        // Other keys' actions are reported as missing unless the key may read them
         match db.get_queue_entry(id)? {
-            Some(entry) => {
+            Some(entry) if scope::can_read(&api_key, &entry.api_key_id) => {
                 let status: QueueStatus = entry.status.parse().unwrap_or(QueueStatus::Pending);
                 let response = GatewayApiResponse::ok(ActionStatusResponse {
                     action_id: entry.id,
                     status,
                     error_message: entry.error_message,
                     sent_at: entry.sent_at.map(|dt| dt.to_rfc3339()),
+                    thread_id: entry.thread_id,
+                            batch_id: entry.batch_id,
                 });
-                self.send_json_response(stream, 200, &response)
+                self.send_json_response(200, &response)
             }
+            Some(_) => self.send_error(&ErrorResponse::new(ErrorCode::NotFound, "Action not found")),
             None => {
-                let response: GatewayApiResponse<()> =
-                    GatewayApiResponse::err("Action not found");
-                self.send_json_response(stream, 404, &response)
+                // A batch ID reports every message in the batch the key may read
+                let actions: Vec<BatchActionStatus> = db
+                    .list_batch_entries(id)?
+                    .into_iter()
+                    .filter(|e| scope::can_read(&api_key, &e.api_key_id))
+                    .map(|entry| BatchActionStatus {
+                        recipient_address: match entry.recipient_contact {
+                            Some(_) => None,
+                            None => Some(entry.recipient_address),
+                        },
+                        recipient_contact: entry.recipient_contact,
+                        action: ActionStatusResponse {
+                            action_id: entry.id,
+                            status: entry.status.parse().unwrap_or(QueueStatus::Pending),
+                            error_message: entry.error_message,
+                            sent_at: entry.sent_at.map(|dt| dt.to_rfc3339()),
+                            thread_id: entry.thread_id,
+                                            batch_id: entry.batch_id,
+                        },
+                    })
+                    .collect();
+                if actions.is_empty() {
+                    return self.send_error(&ErrorResponse::new(ErrorCode::NotFound, "Action not found"));
+                }
+                let response = GatewayApiResponse::ok(BatchStatusResponse {
+                    batch_id: id.to_string(),
+                    actions,
+                });
+                self.send_json_response(200, &response)
             }
         }
     }
 
-    /// List pending queue entries (local only).
-    fn handle_list_queue(&self, stream: &mut TcpStream) -> Result<()> {
+    /// List pending queue entries.
+    ///
+    /// Local callers see everything. Remote callers need an API key and see
+    /// only their own entries unless the key may read all actions.
+    fn handle_list_queue(&self, viewer: Option<&crate::db::gateway::ApiKey>) -> Result<HttpResponse> {
         let db = Database::open_at(self.db_path.clone())?;
-        let entries = db.list_pending_queue()?;
+        let entries = match viewer {
+            Some(key) if !key.scopes.read_all => db.list_pending_queue_for_key(&key.id)?,
+            _ => db.list_pending_queue()?,
+        };
         let api_keys = db.list_api_keys()?;
 
         // Build a map of key ID -> name
@@ -414,11 +669,17 @@ impl GatewayServer {
                     .cloned()
                     .unwrap_or_else(|| "Unknown".to_string());
 
+                // Agents see the contact reference, not the address it resolved to
+                let (recipient_address, recipient_name) = match e.recipient_contact {
+                    Some(ref reference) if viewer.is_some() => (reference.clone(), None),
+                    _ => (e.recipient_address, e.recipient_name),
+                };
+
                 QueueEntryResponse {
                     id: e.id,
                     channel: e.channel,
-                    recipient_address: e.recipient_address,
-                    recipient_name: e.recipient_name,
+                    recipient_address,
+                    recipient_name,
                     subject: e.subject,
                     body: e.body,
                     priority: e.priority,
@@ -426,6 +687,9 @@ impl GatewayServer {
                     agent_context: e.agent_context.and_then(|s| serde_json::from_str(&s).ok()),
                     created_at: e.created_at.to_rfc3339(),
                     agent_name,
+                    thread_id: e.thread_id,
+                    in_reply_to: e.in_reply_to,
+                    recipient_contact: e.recipient_contact,
                 }
             })
             .collect();
@@ -435,139 +699,115 @@ impl GatewayServer {
             entries: response_entries,
             total,
         });
-        self.send_json_response(stream, 200, &response)
+        self.send_json_response(200, &response)
     }
 
     /// Approve and send a message (local only).
-    fn handle_approve(&self, stream: &mut TcpStream, id: &str) -> Result<()> {
-        let db = Database::open_at(self.db_path.clone())?;
-
-        match db.get_queue_entry(id)? {
-            Some(entry) => {
-                if entry.status != "pending" && entry.status != "flagged" {
-                    let response: GatewayApiResponse<()> =
-                        GatewayApiResponse::err(format!("Cannot approve: status is {}", entry.status));
-                    return self.send_json_response(stream, 400, &response);
-                }
-
-                // Mark as approved
-                db.update_queue_status(id, "approved")?;
-
-                // Execute send
-                match execute::execute_send(&db, &entry) {
-                    Ok(()) => {
-                        db.mark_queue_sent(id)?;
-                        let sent_at = chrono::Utc::now().to_rfc3339();
-                        // Send webhook notification (non-blocking for errors)
-                        let _ = webhook::notify_status_change(
-                            &db,
-                            &entry.api_key_id,
-                            id,
-                            "sent",
-                            &entry.recipient_address,
-                            &entry.channel,
-                            Some(&sent_at),
-                            None,
-                        );
-                        let response = GatewayApiResponse::ok(ActionStatusResponse {
-                            action_id: id.to_string(),
-                            status: QueueStatus::Sent,
-                            error_message: None,
-                            sent_at: Some(sent_at),
-                        });
-                        self.send_json_response(stream, 200, &response)
-                    }
-                    Err(e) => {
-                        let error_msg = e.to_string();
-                        db.mark_queue_failed(id, &error_msg)?;
-                        // Send webhook notification (non-blocking for errors)
-                        let _ = webhook::notify_status_change(
-                            &db,
-                            &entry.api_key_id,
-                            id,
-                            "failed",
-                            &entry.recipient_address,
-                            &entry.channel,
-                            None,
-                            Some(&error_msg),
-                        );
-                        let response = GatewayApiResponse::ok(ActionStatusResponse {
-                            action_id: id.to_string(),
-                            status: QueueStatus::Failed,
-                            error_message: Some(error_msg),
-                            sent_at: None,
-                        });
-                        self.send_json_response(stream, 200, &response)
-                    }
-                }
-            }
-            None => {
-                let response: GatewayApiResponse<()> =
-                    GatewayApiResponse::err("Message not found");
-                self.send_json_response(stream, 404, &response)
-            }
+    fn handle_approve(&self, id: &str) -> Result<HttpResponse> {
+        match self.approve(id, audit::ACTOR_LOCAL_API)? {
+            Ok(status) => self.send_json_response(200, &GatewayApiResponse::ok(status)),
+            Err(error) => self.send_error(&error),
         }
     }
 
     /// Deny a message (local only).
-    fn handle_deny(&self, stream: &mut TcpStream, id: &str) -> Result<()> {
-        let db = Database::open_at(self.db_path.clone())?;
-
-        match db.get_queue_entry(id)? {
-            Some(entry) => {
-                if entry.status != "pending" && entry.status != "flagged" {
-                    let response: GatewayApiResponse<()> =
-                        GatewayApiResponse::err(format!("Cannot deny: status is {}", entry.status));
-                    return self.send_json_response(stream, 400, &response);
-                }
+    fn handle_deny(&self, id: &str) -> Result<HttpResponse> {
+        match self.deny(id, audit::ACTOR_LOCAL_API)? {
+            Ok(status) => self.send_json_response(200, &GatewayApiResponse::ok(status)),
+            Err(error) => self.send_error(&error),
+        }
+    }
 
-                db.update_queue_status(id, "denied")?;
+    /// Approve a pending or flagged message and send it.
+    pub(super) fn approve(
+        &self,
+        id: &str,
+        actor: &str,
+    ) -> Result<Result<ActionStatusResponse, ErrorResponse>> {
+        let db = Database::open_at(self.db_path.clone())?;
+        intake::approve(&db, id, actor)
+    }
 
-                // Send webhook notification (non-blocking for errors)
-                let _ = webhook::notify_status_change(
-                    &db,
-                    &entry.api_key_id,
-                    id,
-                    "denied",
-                    &entry.recipient_address,
-                    &entry.channel,
-                    None,
-                    None,
-                );
+    /// Deny a pending or flagged message.
+    pub(super) fn deny(
+        &self,
+        id: &str,
+        actor: &str,
+    ) -> Result<Result<ActionStatusResponse, ErrorResponse>> {
+        let db = Database::open_at(self.db_path.clone())?;
 
-                let response = GatewayApiResponse::ok(ActionStatusResponse {
-                    action_id: id.to_string(),
-                    status: QueueStatus::Denied,
-                    error_message: None,
-                    sent_at: None,
-                });
-                self.send_json_response(stream, 200, &response)
-            }
-            None => {
-                let response: GatewayApiResponse<()> =
-                    GatewayApiResponse::err("Message not found");
-                self.send_json_response(stream, 404, &response)
-            }
+        let entry = match db.get_queue_entry(id)? {
+            Some(entry) => entry,
+            None => return Ok(Err(ErrorResponse::new(ErrorCode::NotFound, "Message not found"))),
+        };
+        if !db.review_queue_entry(id, "denied")? {
+            return Ok(Err(intake::already_reviewed(&db, id, "deny")?));
         }
+        audit::record(&db, AuditAction::Denied, actor, Some(id), json!({}))?;
+
+        // Send webhook notification (non-blocking for errors)
+        let _ = webhook::notify_status_change(
+            &db,
+            &entry.api_key_id,
+            id,
+            "denied",
+            &entry.recipient_address,
+            &entry.channel,
+            None,
+            None,
+        );
+
+        Ok(Ok(ActionStatusResponse {
+            action_id: id.to_string(),
+            status: QueueStatus::Denied,
+            error_message: None,
+            sent_at: None,
+            thread_id: entry.thread_id.clone(),
+            batch_id: entry.batch_id.clone(),
+        }))
     }
 
-    /// Authenticate request using X-Gateway-Key header.
-    fn authenticate(&self, headers: &HashMap<String, String>) -> Result<crate::db::gateway::ApiKey> {
-        let key = headers
-            .get("x-gateway-key")
-            .ok_or_else(|| anyhow!("Missing X-Gateway-Key header"))?;
 
-        // Validate format
-        keys::validate_key_format(key)?;
+    /// Whether the request carries an API key or a client certificate.
+    fn has_credentials(&self, request: &HttpRequest) -> bool {
+        request.headers.contains_key("x-gateway-key")
+            || (self.client_certs && request.peer_certificate.is_some())
+    }
 
-        // Hash and lookup
-        let key_hash = keys::hash_key(key);
+    /// Whether the request carries a valid key with the read-all scope.
+    fn may_read_metrics(&self, request: &HttpRequest) -> bool {
+        self.has_credentials(request)
+            && self.authenticate(request).is_ok_and(|key| key.scopes.read_all)
+    }
+
+    /// Authenticate by `X-Gateway-Key` header, or by a registered TLS client certificate.
+    fn authenticate(&self, request: &HttpRequest) -> Result<crate::db::gateway::ApiKey> {
         let db = Database::open_at(self.db_path.clone())?;
 
-        match db.find_api_key_by_hash(&key_hash)? {
+        let found = match (request.headers.get("x-gateway-key"), &request.peer_certificate) {
+            (Some(key), _) => {
+                // Validate format
+                keys::validate_key_format(key)?;
+
+                // Hash and lookup
+                db.find_api_key_by_hash(&keys::hash_key(key))?
+            }
+            (None, Some(cert)) if self.client_certs => {
+                let found = db.find_api_key_by_client_cert(&tls::fingerprint(cert))?;
+                if found.is_none() {
+                    return Err(anyhow!("Client certificate is not registered to an API key"));
+                }
+                found
+            }
+            (None, _) => return Err(anyhow!("Missing X-Gateway-Key header")),
+        };
+
+        match found {
             Some(api_key) => {
                 if api_key.revoked_at.is_some() {
                     Err(anyhow!("API key has been revoked"))
+                } else if api_key.is_expired(chrono::Utc::now()) {
+                    Err(anyhow!("API key has expired"))
                 } else {
                     Ok(api_key)
                 }
@@ -576,54 +816,51 @@ impl GatewayServer {
         }
     }
 
-    fn send_response(&self, stream: &mut TcpStream, status: u16, message: &str) -> Result<()> {
-        let status_text = match status {
-            200 => "OK",
-            400 => "Bad Request",
-            401 => "Unauthorized",
-            403 => "Forbidden",
-            404 => "Not Found",
-            500 => "Internal Server Error",
-            _ => "Unknown",
-        };
+    fn send_error(&self, error: &ErrorResponse) -> Result<HttpResponse> {
+        self.send_json_response(error.code.http_status(), error)
+    }
 
-        let response = format!(
-            "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-            status, status_text, message.len(), message
-        );
+    fn send_json_response<T: serde::Serialize>(&self, status: u16, body: &T) -> Result<HttpResponse> {
+        Ok(HttpResponse::json(status, body))
+    }
+}
 
-        stream.write_all(response.as_bytes())?;
-        stream.flush()?;
-        Ok(())
+impl Handler for GatewayServer {
+    fn handle(&self, request: HttpRequest) -> HttpResponse {
+        self.route(&request).unwrap_or_else(|e| {
+            eprintln!("Request error: {}", e);
+            self.error_response(500, "Internal Server Error")
+        })
     }
 
-    fn send_json_response<T: serde::Serialize>(
-        &self,
-        stream: &mut TcpStream,
-        status: u16,
-        body: &T,
-    ) -> Result<()> {
-        let status_text = match status {
-            200 => "OK",
-            400 => "Bad Request",
-            401 => "Unauthorized",
-            403 => "Forbidden",
-            404 => "Not Found",
-            429 => "Too Many Requests",
-            500 => "Internal Server Error",
-            _ => "Unknown",
+    fn error_response(&self, status: u16, message: &str) -> HttpResponse {
+        let code = match status {
+            400 => ErrorCode::InvalidRequest,
+            408 => ErrorCode::Timeout,
+            413 => ErrorCode::PayloadTooLarge,
+            504 => ErrorCode::OutcomeUnknown,
+            _ => ErrorCode::Internal,
         };
+        HttpResponse::json(code.http_status(), &ErrorResponse::new(code, message))
+    }
+}
 
-        let json_body = serde_json::to_string(body)?;
-
-        let response = format!(
-            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-            status, status_text, json_body.len(), json_body
-        );
+/// Split a dashboard path like `/queue/<id>/approve` into the ID and action.
+fn queue_action(path: &str) -> Option<(&str, &str)> {
+    let (id, action) = path.strip_prefix("/queue/")?.split_once('/')?;
+    (!id.is_empty()).then_some((id, action))
+}
 
-        stream.write_all(response.as_bytes())?;
-        stream.flush()?;
-        Ok(())
+/// The request's `Idempotency-Key` header, if it sent a valid one.
+fn idempotency_key(request: &HttpRequest) -> Result<Option<&str>, ErrorResponse> {
+    match request.headers.get("idempotency-key") {
+        Some(key) if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN => {
+            Err(ErrorResponse::validation(vec![FieldError::new(
+                "Idempotency-Key",
+                format!("must be 1 to {} characters", MAX_IDEMPOTENCY_KEY_LEN),
+            )]))
+        }
+        key => Ok(key.map(String::as_str)),
     }
 }
 
@@ -632,7 +869,7 @@ impl GatewayServer {
 /// - Exact match (case-insensitive for emails)
 /// - Wildcard domain match: `*@domain.com` matches any email at that domain
 /// - Phone normalization: strips spaces, dashes, parentheses for comparison
-fn recipient_matches_allowlist(recipient: &str, patterns: &[String]) -> bool {
+pub(super) fn recipient_matches_allowlist(recipient: &str, patterns: &[String]) -> bool {
     let normalized_recipient = normalize_recipient(recipient);
 
     for pattern in patterns {
@@ -646,7 +883,7 @@ fn recipient_matches_allowlist(recipient: &str, patterns: &[String]) -> bool {
 /// Normalize a recipient address for comparison.
 /// - Emails: lowercase
 /// - Phones: remove spaces, dashes, parentheses, dots
-fn normalize_recipient(recipient: &str) -> String {
+pub(super) fn normalize_recipient(recipient: &str) -> String {
     let trimmed = recipient.trim();
 
     // Check if it looks like a phone number (starts with + or digit, contains mostly digits)
@@ -749,3 +986,216 @@ mod allowlist_tests {
         assert!(!recipient_matches_allowlist("anyone@example.com", &patterns));
     }
 }
+
+#[cfg(test)]
+mod review_tests {
+    use super::*;
+    use crate::cli::gateway::transport::{self, TransportConfig};
+    use crate::cli::gateway::types::GatewayChannel;
+
+    #[test]
+    fn test_entry_is_reviewed_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("contacts.db");
+        let db = Database::open_at(db_path.clone()).unwrap();
+        let mut server = GatewayServer::new(0, &db).unwrap();
+        server.db_path = db_path;
+
+        let outbox = dir.path().join("outbox.jsonl");
+        let file = TransportConfig::File { path: outbox.clone() };
+        transport::set_for_channel(&db, GatewayChannel::Sms, Some(&file)).unwrap();
+        db.insert_api_key("key-1", "Bot", "hash", "gw_abc").unwrap();
+        for id in ["msg-1", "msg-2"] {
+            db.insert_queue_entry(id, "key-1", "sms", "+15551234567", None, None, "Hi", "normal", None)
+                .unwrap();
+        }
+
+        let sent = server.approve("msg-1", audit::ACTOR_CLI).unwrap().unwrap();
+        assert_eq!(sent.status, QueueStatus::Sent);
+
+        // A second approval (double click, another tab) sends nothing
+        let again = server.approve("msg-1", audit::ACTOR_CLI).unwrap().unwrap_err();
+        assert_eq!(again.code, ErrorCode::InvalidState);
+        assert!(again.error.contains("status is sent"));
+        let denied = server.deny("msg-1", audit::ACTOR_CLI).unwrap().unwrap_err();
+        assert_eq!(denied.code, ErrorCode::InvalidState);
+        assert_eq!(std::fs::read_to_string(&outbox).unwrap().lines().count(), 1);
+
+        // Nor can a denied entry be approved afterwards
+        server.deny("msg-2", audit::ACTOR_CLI).unwrap().unwrap();
+        assert!(server.approve("msg-2", audit::ACTOR_CLI).unwrap().is_err());
+        assert_eq!(db.get_queue_entry("msg-2").unwrap().unwrap().status, "denied");
+        assert_eq!(std::fs::read_to_string(&outbox).unwrap().lines().count(), 1);
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use super::*;
+
+    fn send(server: &GatewayServer, key: &str, idempotency_key: Option<&str>) -> (u16, serde_json::Value) {
+        let mut headers = HashMap::from([("x-gateway-key".to_string(), key.to_string())]);
+        if let Some(idempotency_key) = idempotency_key {
+            headers.insert("idempotency-key".to_string(), idempotency_key.to_string());
+        }
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/gateway/send".to_string(),
+            headers,
+            body: br#"{"channel": "sms", "recipient_address": "+15551234567", "body": "Hi"}"#.to_vec(),
+            peer_addr: "127.0.0.1:50000".parse().unwrap(),
+            peer_certificate: None,
+        };
+        let response = server.handle_send(&request).unwrap();
+        (response.status, serde_json::from_slice(&response.body).unwrap())
+    }
+
+    #[test]
+    fn test_retry_with_idempotency_key_queues_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("contacts.db");
+        let db = Database::open_at(db_path.clone()).unwrap();
+        let mut server = GatewayServer::new(0, &db).unwrap();
+        server.db_path = db_path;
+
+        let (key, hash, prefix) = keys::generate_api_key();
+        db.insert_api_key("key-1", "Bot", &hash, &prefix).unwrap();
+
+        // A retry after a timeout gets the original action back
+        let (status, first) = send(&server, &key, Some("order-42"));
+        assert_eq!(status, 200);
+        let (status, retry) = send(&server, &key, Some("order-42"));
+        assert_eq!(status, 200);
+        assert_eq!(retry["data"]["action_id"], first["data"]["action_id"]);
+        assert_eq!(retry["data"]["thread_id"], first["data"]["thread_id"]);
+        assert_eq!(db.count_pending_queue().unwrap(), 1);
+
+        // Other keys and keyless sends queue as usual
+        let (_, other) = send(&server, &key, Some("order-43"));
+        assert_ne!(other["data"]["action_id"], first["data"]["action_id"]);
+        send(&server, &key, None);
+        assert_eq!(db.count_pending_queue().unwrap(), 3);
+
+        let (status, body) = send(&server, &key, Some(""));
+        assert_eq!(status, 400);
+        assert_eq!(body["field_errors"][0]["field"], "Idempotency-Key");
+    }
+
+    #[test]
+    fn test_batch_retry_with_idempotency_key_queues_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("contacts.db");
+        let db = Database::open_at(db_path.clone()).unwrap();
+        let mut server = GatewayServer::new(0, &db).unwrap();
+        server.db_path = db_path;
+
+        let (key, hash, prefix) = keys::generate_api_key();
+        db.insert_api_key("key-1", "Bot", &hash, &prefix).unwrap();
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/gateway/send/batch".to_string(),
+            headers: HashMap::from([
+                ("x-gateway-key".to_string(), key),
+                ("idempotency-key".to_string(), "launch-1".to_string()),
+            ]),
+            body: br#"{"channel": "sms", "recipients": ["+15551234567", "+15557654321"], "body": "Hi"}"#.to_vec(),
+            peer_addr: "127.0.0.1:50000".parse().unwrap(),
+            peer_certificate: None,
+        };
+
+        let first: serde_json::Value =
+            serde_json::from_slice(&server.handle_send_batch(&request).unwrap().body).unwrap();
+        let retry: serde_json::Value =
+            serde_json::from_slice(&server.handle_send_batch(&request).unwrap().body).unwrap();
+        assert_eq!(retry["data"]["batch_id"], first["data"]["batch_id"]);
+        for i in 0..2 {
+            assert!(first["data"]["results"][i]["action_id"].is_string());
+            assert_eq!(retry["data"]["results"][i]["action_id"], first["data"]["results"][i]["action_id"]);
+        }
+        assert_eq!(db.count_pending_queue().unwrap(), 2);
+    }
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+    use crate::cli::gateway::ratelimit;
+
+    #[test]
+    fn test_batch_checks_each_recipient() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("contacts.db");
+        let db = Database::open_at(db_path.clone()).unwrap();
+        let mut server = GatewayServer::new(0, &db).unwrap();
+        server.db_path = db_path;
+
+        let (key, hash, prefix) = keys::generate_api_key();
+        db.insert_api_key("key-1", "Bot", &hash, &prefix).unwrap();
+        db.insert_allowlist_entry("allow-1", "key-1", "+15551234567").unwrap();
+        db.insert_allowlist_entry("allow-2", "key-1", "+15557654321").unwrap();
+        let limits = ratelimit::GlobalLimits {
+            recipient_per_day: Some(1),
+            ..Default::default()
+        };
+        limits.save(&db).unwrap();
+
+        let batch = |recipients: serde_json::Value| -> serde_json::Value {
+            let request = HttpRequest {
+                method: "POST".to_string(),
+                path: "/gateway/send/batch".to_string(),
+                headers: HashMap::from([("x-gateway-key".to_string(), key.clone())]),
+                body: json!({ "channel": "sms", "recipients": recipients, "body": "Hi" })
+                    .to_string()
+                    .into_bytes(),
+                peer_addr: "127.0.0.1:50000".parse().unwrap(),
+                peer_certificate: None,
+            };
+            let response: serde_json::Value =
+                serde_json::from_slice(&server.handle_send_batch(&request).unwrap().body).unwrap();
+            response["data"]["results"].clone()
+        };
+
+        let results = batch(json!(["+15551234567", "+15557654321", "+15550000000"]));
+        assert_eq!(results[0]["status"], "pending");
+        assert_eq!(results[1]["status"], "pending");
+        assert_eq!(results[2]["error"]["code"], "allowlist_denied");
+
+        // The recipient's daily limit applies however the number is written
+        let results = batch(json!(["+1 (555) 123-4567"]));
+        assert_eq!(results[0]["error"]["code"], "rate_limited");
+        assert_eq!(results[0]["error"]["limit_type"], "recipient_daily");
+        assert_eq!(db.count_pending_queue().unwrap(), 2);
+    }
+}
+
+#[cfg(test)]
+mod health_tests {
+    use super::*;
+
+    #[test]
+    fn test_health_hides_backlog_from_other_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("contacts.db");
+        let db = Database::open_at(db_path.clone()).unwrap();
+        let mut server = GatewayServer::new(0, &db).unwrap();
+        server.db_path = db_path;
+
+        let health = |peer_addr: &str| -> serde_json::Value {
+            let request = HttpRequest {
+                method: "GET".to_string(),
+                path: "/gateway/health".to_string(),
+                headers: HashMap::new(),
+                body: Vec::new(),
+                peer_addr: peer_addr.parse().unwrap(),
+                peer_certificate: None,
+            };
+            serde_json::from_slice(&server.route(&request).unwrap().body).unwrap()
+        };
+
+        assert_eq!(health("127.0.0.1:50000")["data"]["pending_count"], 0);
+        let remote = health("192.0.2.10:50000");
+        assert_eq!(remote["data"]["status"], "ok");
+        assert!(remote["data"].get("pending_count").is_none());
+        assert!(remote["data"].get("flagged_count").is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Server-side message templates.
+//!
+//! Agents send `template_id` and `variables` instead of a subject and body.
+//! `{first_name}`, `{last_name}`, `{full_name}`, `{org}` and `{title}` are
+//! filled from the recipient's contact record, so agents never handle it; any
+//! other `{name}` comes from `variables`. Write `{{` and `}}` for literal braces.
+
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashMap};
+
+use super::types::{MAX_BODY_LEN, MAX_SUBJECT_LEN};
+use crate::db::gateway::MessageTemplate;
+use crate::db::Database;
+use crate::models::Person;
+
+/// Placeholders filled from the recipient's contact record.
+pub const CONTACT_FIELDS: [&str; 5] = ["first_name", "last_name", "full_name", "org", "title"];
+
+/// Longest value accepted for an agent-supplied variable.
+pub const MAX_VARIABLE_LEN: usize = 200;
+
+/// Longest agent-supplied variable a pre-approved template sends without review.
+pub const MAX_PRE_APPROVED_VARIABLE_LEN: usize = 40;
+
+/// Longest template ID.
+pub const MAX_TEMPLATE_ID_LEN: usize = 64;
+
+/// Check a template ID: letters, digits, `-` and `_`.
+pub fn validate_id(id: &str) -> Result<()> {
+    if id.is_empty() || id.len() > MAX_TEMPLATE_ID_LEN {
+        return Err(anyhow!("Template ID must be 1 to {} characters", MAX_TEMPLATE_ID_LEN));
+    }
+    if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(anyhow!("Template ID may only contain letters, digits, '-' and '_'"));
+    }
+    Ok(())
+}
+
+enum Piece {
+    Text(String),
+    Placeholder(String),
+}
+
+fn parse(text: &str) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+                        Some(c) => {
+                            return Err(anyhow!("Invalid character '{}' in placeholder {{{}", c, name))
+                        }
+                        None => return Err(anyhow!("Unclosed placeholder {{{}", name)),
+                    }
+                }
+                if name.is_empty() {
+                    return Err(anyhow!("Empty placeholder {{}} (write {{{{ for a literal brace)"));
+                }
+                if !literal.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut literal)));
+                }
+                pieces.push(Piece::Placeholder(name));
+            }
+            '}' => return Err(anyhow!("Unmatched }} (write }}}} for a literal brace)")),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Text(literal));
+    }
+    Ok(pieces)
+}
+
+/// Placeholder names used by a subject and body, in order of first use.
+/// Fails if either has a malformed placeholder.
+pub fn placeholders(subject: Option<&str>, body: &str) -> Result<Vec<String>> {
+    let mut names: Vec<String> = Vec::new();
+    for text in subject.into_iter().chain(std::iter::once(body)) {
+        for piece in parse(text)? {
+            if let Piece::Placeholder(name) = piece {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Contact values for the recipient, keyed by placeholder. Empty fields are left out.
+pub fn contact_fields(db: &Database, person: Option<&Person>) -> Result<HashMap<&'static str, String>> {
+    let mut fields = HashMap::new();
+    let Some(person) = person else {
+        return Ok(fields);
+    };
+
+    let first_name = person.preferred_name.as_ref().or(person.name_given.as_ref());
+    for (key, value) in [
+        ("first_name", first_name),
+        ("last_name", person.name_family.as_ref()),
+        ("full_name", person.display_name.as_ref()),
+    ] {
+        if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+            fields.insert(key, value.clone());
+        }
+    }
+
+    if let Some((po, org)) = db
+        .get_organizations_for_person(person.id)?
+        .into_iter()
+        .find(|(po, _)| po.is_current)
+    {
+        fields.insert("org", org.name);
+        if let Some(title) = po.title.filter(|t| !t.trim().is_empty()) {
+            fields.insert("title", title);
+        }
+    }
+    Ok(fields)
+}
+
+/// Whether agent-supplied variables are plain enough to send under a template's
+/// pre-approval: short values with no links, addresses or markup. A template
+/// filled only from contacts always qualifies.
+pub fn allows_pre_approval(variables: &BTreeMap<String, String>) -> bool {
+    variables.values().all(|value| {
+        let lower = value.to_lowercase();
+        value.chars().count() <= MAX_PRE_APPROVED_VARIABLE_LEN
+            && !["://", "www.", "@", "<", ">"].iter().any(|marker| lower.contains(marker))
+    })
+}
+
+/// A rendered subject and body.
+#[derive(Debug, PartialEq)]
+pub struct Rendered {
+    pub subject: Option<String>,
+    pub body: String,
+}
+
+/// Fill a template. Returns every problem found so the agent can fix them at once.
+pub fn render(
+    template: &MessageTemplate,
+    contact: &HashMap<&str, String>,
+    variables: &BTreeMap<String, String>,
+) -> std::result::Result<Rendered, Vec<String>> {
+    let mut problems = Vec::new();
+    let mut used: Vec<String> = Vec::new();
+
+    for (name, value) in variables {
+        if CONTACT_FIELDS.contains(&name.as_str()) {
+            problems.push(format!("{} is filled from contacts and can't be set", name));
+        } else if value.chars().count() > MAX_VARIABLE_LEN || value.chars().any(char::is_control) {
+            problems.push(format!("{} must be one line of at most {} characters", name, MAX_VARIABLE_LEN));
+        }
+    }
+
+    let mut fill = |text: &str| -> String {
+        let pieces = match parse(text) {
+            Ok(pieces) => pieces,
+            Err(e) => {
+                problems.push(format!("template is invalid: {}", e));
+                return String::new();
+            }
+        };
+        let mut out = String::new();
+        for piece in pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(&text),
+                Piece::Placeholder(name) => {
+                    let value = if CONTACT_FIELDS.contains(&name.as_str()) {
+                        contact.get(name.as_str())
+                    } else {
+                        variables.get(&name)
+                    };
+                    match value {
+                        Some(value) => out.push_str(value),
+                        None if used.contains(&name) => {}
+                        None if CONTACT_FIELDS.contains(&name.as_str()) => {
+                            problems.push(format!("recipient has no {} in contacts", name))
+                        }
+                        None => problems.push(format!("{} is required", name)),
+                    }
+                    if !used.contains(&name) {
+                        used.push(name);
+                    }
+                }
+            }
+        }
+        out
+    };
+
+    let subject = template.subject.as_deref().map(&mut fill);
+    let body = fill(&template.body);
+
+    for name in variables.keys().filter(|n| !used.contains(n)) {
+        if !CONTACT_FIELDS.contains(&name.as_str()) {
+            problems.push(format!("{} is not used by this template", name));
+        }
+    }
+    if subject.as_ref().is_some_and(|s| s.chars().count() > MAX_SUBJECT_LEN) {
+        problems.push(format!("subject renders longer than {} characters", MAX_SUBJECT_LEN));
+    }
+    if body.chars().count() > MAX_BODY_LEN {
+        problems.push(format!("body renders longer than {} characters", MAX_BODY_LEN));
+    }
+
+    if problems.is_empty() {
+        Ok(Rendered { subject, body })
+    } else {
+        Err(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(subject: Option<&str>, body: &str) -> MessageTemplate {
+        MessageTemplate {
+            id: "meeting-confirm".to_string(),
+            subject: subject.map(String::from),
+            body: body.to_string(),
+            pre_approved: false,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders(Some("Meeting at {time}"), "Hi {first_name}, see you at {time} {{sharp}}").unwrap(),
+            vec!["time", "first_name"]
+        );
+        assert!(placeholders(None, "Hi {first_name").is_err());
+        assert!(placeholders(None, "Hi {first name}").is_err());
+        assert!(placeholders(None, "Hi {}").is_err());
+        assert!(placeholders(None, "Hi }").is_err());
+    }
+
+    #[test]
+    fn test_render_fills_contact_fields_and_variables() {
+        let t = template(Some("Meeting {time}"), "Hi {first_name}, confirming {time} at {org}. {{ok}}");
+        let contact = HashMap::from([("first_name", "Ada".to_string()), ("org", "Acme".to_string())]);
+
+        let rendered = render(&t, &contact, &vars(&[("time", "3pm")])).unwrap();
+        assert_eq!(rendered.subject.as_deref(), Some("Meeting 3pm"));
+        assert_eq!(rendered.body, "Hi Ada, confirming 3pm at Acme. {ok}");
+    }
+
+    #[test]
+    fn test_render_reports_every_problem() {
+        let t = template(None, "Hi {first_name}, {title} at {time}");
+        let contact = HashMap::from([("first_name", "Ada".to_string())]);
+
+        let problems = render(
+            &t,
+            &contact,
+            &vars(&[("first_name", "Eve"), ("place", "HQ"), ("note", "two\nlines")]),
+        )
+        .unwrap_err();
+        assert_eq!(
+            problems,
+            vec![
+                "first_name is filled from contacts and can't be set",
+                "note must be one line of at most 200 characters",
+                "recipient has no title in contacts",
+                "time is required",
+                "note is not used by this template",
+                "place is not used by this template",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allows_pre_approval() {
+        assert!(allows_pre_approval(&vars(&[])));
+        assert!(allows_pre_approval(&vars(&[("time", "3pm"), ("place", "Room 4")])));
+        assert!(!allows_pre_approval(&vars(&[("time", "3pm, and click https://evil.example")])));
+        assert!(!allows_pre_approval(&vars(&[("place", "www.example.com")])));
+        assert!(!allows_pre_approval(&vars(&[("note", &"x".repeat(MAX_PRE_APPROVED_VARIABLE_LEN + 1))])));
+    }
+}
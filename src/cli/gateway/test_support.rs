@@ -0,0 +1,52 @@
+//! Fixtures shared by the gateway's unit tests.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use crate::db::gateway::QueueEntry;
+use crate::db::Database;
+
+/// Queue a message from API key `key-1` (created on first use) and read it back.
+pub fn queued_entry(db: &Database, id: &str, channel: &str, to: &str, body: &str) -> QueueEntry {
+    if db.find_api_key_by_hash("hash-key-1").unwrap().is_none() {
+        db.insert_api_key("key-1", "Bot", "hash-key-1", "gw_abc").unwrap();
+    }
+    db.insert_queue_entry(id, "key-1", channel, to, None, None, body, "normal", None)
+        .unwrap();
+    db.get_queue_entry(id).unwrap().unwrap()
+}
+
+/// Accept one HTTP request on a local port and return it as text.
+pub fn stand_in_server() -> (String, std::thread::JoinHandle<String>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap_or(0);
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| l.to_lowercase().strip_prefix("content-length: ").map(String::from))
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+            if n == 0 {
+                break;
+            }
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        String::from_utf8_lossy(&request).to_string()
+    });
+    (url, handle)
+}
@@ -0,0 +1,230 @@
+//! Conversation threading for gateway messages.
+//!
+//! Groups an agent's queued messages into threads so the reviewer can see
+//! the back-and-forth with a contact before approving the next message.
+
+use anyhow::{anyhow, Result};
+
+use crate::cli::messages::{get_messages_for_handles, LastMessage};
+use crate::db::gateway::QueueEntry;
+use crate::db::Database;
+
+/// Maximum length of an agent-supplied thread ID.
+pub const MAX_THREAD_ID_LEN: usize = 128;
+
+/// Number of inbound messages shown alongside a queued message.
+pub const INBOUND_CONTEXT_LIMIT: usize = 5;
+
+/// Resolve the thread a new queue entry belongs to.
+///
+/// Precedence: an explicit `thread_id`, then the thread of the `in_reply_to`
+/// action, then a new thread keyed by the action's own ID. `in_reply_to`
+/// must reference an action queued by the same API key.
+pub fn resolve_thread(
+    db: &Database,
+    api_key_id: &str,
+    action_id: &str,
+    thread_id: Option<&str>,
+    in_reply_to: Option<&str>,
+) -> Result<String> {
+    if let Some(thread_id) = thread_id {
+        let thread_id = thread_id.trim();
+        if thread_id.is_empty() {
+            return Err(anyhow!("thread_id cannot be empty"));
+        }
+        if thread_id.len() > MAX_THREAD_ID_LEN {
+            return Err(anyhow!(
+                "thread_id is too long (max {} characters)",
+                MAX_THREAD_ID_LEN
+            ));
+        }
+    }
+
+    let parent = match in_reply_to {
+        Some(parent_id) => match db.get_queue_entry(parent_id)? {
+            Some(parent) if parent.api_key_id == api_key_id => Some(parent),
+            _ => return Err(anyhow!("in_reply_to references an unknown action")),
+        },
+        None => None,
+    };
+
+    if let Some(thread_id) = thread_id {
+        return Ok(thread_id.trim().to_string());
+    }
+
+    match parent {
+        Some(parent) => match parent.thread_id {
+            Some(thread_id) => Ok(thread_id),
+            None => {
+                // Entry queued before threading existed: it becomes the thread root
+                db.set_queue_thread(&parent.id, &parent.id, parent.in_reply_to.as_deref())?;
+                Ok(parent.id)
+            }
+        },
+        None => Ok(action_id.to_string()),
+    }
+}
+
+/// Group items by thread, preserving the order in which each thread first appears.
+///
+/// Items without a thread key form their own single-item group.
+pub fn group_by_thread<T>(items: Vec<T>, key: impl Fn(&T) -> Option<String>) -> Vec<Vec<T>> {
+    let mut groups: Vec<(Option<String>, Vec<T>)> = Vec::new();
+
+    for item in items {
+        let item_key = key(&item);
+        let existing = item_key
+            .as_ref()
+            .and_then(|k| groups.iter_mut().find(|(gk, _)| gk.as_ref() == Some(k)));
+        match existing {
+            Some((_, group)) => group.push(item),
+            None => groups.push((item_key, vec![item])),
+        }
+    }
+
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Thread grouping key for a queue entry (threads are scoped to the API key).
+pub fn thread_key(entry: &QueueEntry) -> Option<String> {
+    entry
+        .thread_id
+        .as_ref()
+        .map(|thread_id| format!("{}:{}", entry.api_key_id, thread_id))
+}
+
+/// Messages queued earlier in the same thread as `entry`, oldest first.
+pub fn earlier_in_thread(db: &Database, entry: &QueueEntry) -> Result<Vec<QueueEntry>> {
+    let thread_id = match entry.thread_id {
+        Some(ref t) => t,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(db
+        .list_thread_entries(&entry.api_key_id, thread_id)?
+        .into_iter()
+        .filter(|e| e.id != entry.id && e.created_at <= entry.created_at)
+        .collect())
+}
+
+/// Most recent messages received from the recipient, newest first.
+///
+/// Uses every phone and email on the matching contact when one exists,
+/// otherwise just the recipient address.
+pub fn recent_inbound(db: &Database, entry: &QueueEntry, limit: usize) -> Result<Vec<LastMessage>> {
+    let address = entry.recipient_address.trim();
+    let contact = if address.contains('@') {
+        db.get_person_by_email(address)?
+    } else {
+        db.get_person_by_phone(address)?
+    };
+
+    let (mut phones, mut emails) = (Vec::new(), Vec::new());
+    if let Some(person) = contact {
+        phones.extend(db.get_phones_for_person(person.id)?.into_iter().map(|p| p.phone_number));
+        emails.extend(db.get_emails_for_person(person.id)?.into_iter().map(|e| e.email_address));
+    }
+    if address.contains('@') {
+        if !emails.iter().any(|e| e.eq_ignore_ascii_case(address)) {
+            emails.push(address.to_string());
+        }
+    } else if !phones.iter().any(|p| p == address) {
+        phones.push(address.to_string());
+    }
+
+    // Fetch extra rows since outgoing messages are interleaved with inbound ones
+    let messages = get_messages_for_handles(&phones, &emails, (limit * 4) as u32)?;
+    Ok(messages
+        .into_iter()
+        .filter(|m| !m.is_from_me)
+        .take(limit)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(db: &Database, id: &str, key: &str) {
+        db.insert_queue_entry(id, key, "sms", "+15551234567", None, None, "Hello", "normal", None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_resolve_thread_new_and_explicit() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Agent", "hash1", "gw_abc").unwrap();
+
+        // No hints: the action starts its own thread
+        assert_eq!(resolve_thread(&db, "key-1", "msg-1", None, None).unwrap(), "msg-1");
+
+        // Explicit thread ID wins
+        assert_eq!(
+            resolve_thread(&db, "key-1", "msg-2", Some(" conv-7 "), None).unwrap(),
+            "conv-7"
+        );
+
+        assert!(resolve_thread(&db, "key-1", "msg-3", Some("  "), None).is_err());
+        let long = "x".repeat(MAX_THREAD_ID_LEN + 1);
+        assert!(resolve_thread(&db, "key-1", "msg-3", Some(&long), None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_thread_inherits_from_parent() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Agent", "hash1", "gw_abc").unwrap();
+        db.insert_api_key("key-2", "Other", "hash2", "gw_def").unwrap();
+
+        queue(&db, "msg-1", "key-1");
+        db.set_queue_thread("msg-1", "conv-1", None).unwrap();
+        assert_eq!(
+            resolve_thread(&db, "key-1", "msg-2", None, Some("msg-1")).unwrap(),
+            "conv-1"
+        );
+
+        // Parent without a thread becomes the thread root
+        queue(&db, "msg-legacy", "key-1");
+        assert_eq!(
+            resolve_thread(&db, "key-1", "msg-3", None, Some("msg-legacy")).unwrap(),
+            "msg-legacy"
+        );
+        let parent = db.get_queue_entry("msg-legacy").unwrap().unwrap();
+        assert_eq!(parent.thread_id.as_deref(), Some("msg-legacy"));
+
+        // Cannot reply to another agent's action or an unknown one
+        assert!(resolve_thread(&db, "key-2", "msg-4", None, Some("msg-1")).is_err());
+        assert!(resolve_thread(&db, "key-1", "msg-4", None, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_group_by_thread_preserves_first_appearance() {
+        let items = vec![("a", Some("t1")), ("b", None), ("c", Some("t2")), ("d", Some("t1")), ("e", None)];
+        let groups = group_by_thread(items, |(_, t)| t.map(String::from));
+        let names: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|g| g.iter().map(|(n, _)| *n).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["a", "d"], vec!["b"], vec!["c"], vec!["e"]]);
+    }
+
+    #[test]
+    fn test_earlier_in_thread() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Agent", "hash1", "gw_abc").unwrap();
+
+        queue(&db, "msg-1", "key-1");
+        db.set_queue_thread("msg-1", "conv-1", None).unwrap();
+        queue(&db, "msg-2", "key-1");
+        db.set_queue_thread("msg-2", "conv-1", Some("msg-1")).unwrap();
+        queue(&db, "msg-other", "key-1");
+        db.set_queue_thread("msg-other", "conv-2", None).unwrap();
+
+        let entry = db.get_queue_entry("msg-2").unwrap().unwrap();
+        let earlier = earlier_in_thread(&db, &entry).unwrap();
+        assert_eq!(earlier.len(), 1);
+        assert_eq!(earlier[0].id, "msg-1");
+
+        let unthreaded = QueueEntry { thread_id: None, ..entry };
+        assert!(earlier_in_thread(&db, &unthreaded).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+//! TLS for the gateway listener.
+//!
+//! The server certificate comes from `--cert/--key`, or is a self-signed
+//! certificate generated on first use and kept in the config directory so its
+//! fingerprint stays stable across restarts. Agents pin that fingerprint.
+//!
+//! Client certificates are not chained to a CA: each one is pinned to an API
+//! key by its SHA-256 fingerprint (`gateway keys client-cert`).
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_rustls::rustls::client::danger::HandshakeSignatureValid;
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, UnixTime};
+use tokio_rustls::rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use tokio_rustls::rustls::{
+    DigitallySignedStruct, DistinguishedName, Error as TlsError, ServerConfig, SignatureScheme,
+};
+
+/// Names the self-signed certificate is valid for.
+const SELF_SIGNED_NAMES: &[&str] = &["localhost", "127.0.0.1", "::1"];
+
+/// TLS settings for a running gateway.
+#[derive(Clone)]
+pub struct GatewayTls {
+    pub config: Arc<ServerConfig>,
+    /// SHA-256 fingerprint of the server certificate
+    pub fingerprint: String,
+    pub self_signed: bool,
+}
+
+impl GatewayTls {
+    /// Load `cert`/`key` PEM files, or the self-signed certificate when `None`.
+    pub fn load(cert_and_key: Option<(&Path, &Path)>, client_certs: bool) -> Result<Self> {
+        let (certs, key, self_signed) = match cert_and_key {
+            Some((cert, key)) => (read_certs(cert)?, read_key(key)?, false),
+            None => {
+                let (cert, key) = ensure_self_signed(&default_tls_dir()?)?;
+                (read_certs(&cert)?, read_key(&key)?, true)
+            }
+        };
+        let fingerprint = fingerprint(&certs[0]);
+
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = if client_certs {
+            builder.with_client_cert_verifier(Arc::new(PinnedClientCerts { provider }))
+        } else {
+            builder.with_no_client_auth()
+        };
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .context("Certificate and private key do not match")?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(Self {
+            config: Arc::new(config),
+            fingerprint,
+            self_signed,
+        })
+    }
+}
+
+/// Uppercase, colon-separated SHA-256 of a DER certificate (as `openssl x509 -fingerprint -sha256`).
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Fingerprint of the first certificate in a PEM file.
+pub fn read_cert_fingerprint(path: &Path) -> Result<String> {
+    Ok(fingerprint(&read_certs(path)?[0]))
+}
+
+/// Directory holding the generated self-signed certificate.
+fn default_tls_dir() -> Result<PathBuf> {
+    let config_dir =
+        dirs::config_dir().ok_or_else(|| anyhow!("Could not find config directory"))?;
+    Ok(config_dir.join("contactcmd").join("gateway-tls"))
+}
+
+/// Return the self-signed cert and key paths in `dir`, generating them if missing.
+fn ensure_self_signed(dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    let names: Vec<String> = SELF_SIGNED_NAMES.iter().map(|n| n.to_string()).collect();
+    let generated = rcgen::generate_simple_self_signed(names)?;
+
+    fs::create_dir_all(dir)?;
+    write_private(&key_path, generated.key_pair.serialize_pem().as_bytes())?;
+    fs::write(&cert_path, generated.cert.pem())?;
+    Ok((cert_path, key_path))
+}
+
+/// Write a file readable only by the current user.
+pub(super) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice()).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let pem = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())?
+        .ok_or_else(|| anyhow!("No private key found in {}", path.display()))
+}
+
+/// Accepts any client certificate whose handshake signature is valid.
+///
+/// Authorization happens later, by matching the certificate's fingerprint
+/// against API keys. Client certificates are optional so bearer keys keep working.
+#[derive(Debug)]
+struct PinnedClientCerts {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ClientCertVerifier for PinnedClientCerts {
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, TlsError> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::http_server::{self, Handler, HttpRequest, HttpResponse, ServerLimits};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Replies with the fingerprint of the client certificate, if any.
+    struct WhoAmI;
+
+    impl Handler for WhoAmI {
+        fn handle(&self, request: HttpRequest) -> HttpResponse {
+            let who = request
+                .peer_certificate
+                .map(|der| fingerprint(&der))
+                .unwrap_or_else(|| "anonymous".to_string());
+            HttpResponse::text(200, &who)
+        }
+    }
+
+    #[test]
+    fn test_self_signed_with_optional_client_cert() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, key) = ensure_self_signed(dir.path()).unwrap();
+        // Second call reuses the stored certificate
+        let (cert_again, _) = ensure_self_signed(dir.path()).unwrap();
+        assert_eq!(
+            read_cert_fingerprint(&cert).unwrap(),
+            read_cert_fingerprint(&cert_again).unwrap()
+        );
+
+        let tls = GatewayTls::load(Some((&cert, &key)), true).unwrap();
+        assert_eq!(tls.fingerprint.len(), 32 * 3 - 1);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let flag = shutdown.clone();
+        let config = tls.config.clone();
+        let server = std::thread::spawn(move || {
+            http_server::serve(
+                listener,
+                Arc::new(WhoAmI),
+                ServerLimits::default(),
+                Some(config),
+                flag,
+            )
+            .unwrap()
+        });
+
+        let url = format!("https://localhost:{}/", port);
+        let anonymous = reqwest::blocking::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        assert_eq!(anonymous.get(&url).send().unwrap().text().unwrap(), "anonymous");
+
+        let client_dir = tempfile::tempdir().unwrap();
+        let (client_cert, client_key) = ensure_self_signed(client_dir.path()).unwrap();
+        let mut identity = fs::read(&client_key).unwrap();
+        identity.extend(fs::read(&client_cert).unwrap());
+        let with_cert = reqwest::blocking::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .identity(reqwest::Identity::from_pem(&identity).unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(
+            with_cert.get(&url).send().unwrap().text().unwrap(),
+            read_cert_fingerprint(&client_cert).unwrap()
+        );
+
+        shutdown.store(true, Ordering::SeqCst);
+        server.join().unwrap();
+    }
+}
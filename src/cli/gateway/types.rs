@@ -1,6 +1,10 @@
 //! Types for the communication gateway.
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::server::normalize_recipient;
+use super::thread::MAX_THREAD_ID_LEN;
 
 /// Communication channel for messages.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -9,6 +13,25 @@ pub enum GatewayChannel {
     Sms,
     IMessage,
     Email,
+    /// Slack (or Mattermost) channel, sent through a webhook transport
+    Slack,
+    /// Matrix room ID, sent through a webhook transport
+    Matrix,
+}
+
+impl GatewayChannel {
+    pub const ALL: [GatewayChannel; 5] = [
+        GatewayChannel::Sms,
+        GatewayChannel::IMessage,
+        GatewayChannel::Email,
+        GatewayChannel::Slack,
+        GatewayChannel::Matrix,
+    ];
+
+    /// "sms, imessage, email, slack, matrix"
+    pub fn names() -> String {
+        Self::ALL.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", ")
+    }
 }
 
 impl std::fmt::Display for GatewayChannel {
@@ -17,6 +40,8 @@ impl std::fmt::Display for GatewayChannel {
             GatewayChannel::Sms => write!(f, "sms"),
             GatewayChannel::IMessage => write!(f, "imessage"),
             GatewayChannel::Email => write!(f, "email"),
+            GatewayChannel::Slack => write!(f, "slack"),
+            GatewayChannel::Matrix => write!(f, "matrix"),
         }
     }
 }
@@ -29,6 +54,8 @@ impl std::str::FromStr for GatewayChannel {
             "sms" => Ok(GatewayChannel::Sms),
             "imessage" => Ok(GatewayChannel::IMessage),
             "email" => Ok(GatewayChannel::Email),
+            "slack" => Ok(GatewayChannel::Slack),
+            "matrix" => Ok(GatewayChannel::Matrix),
             _ => Err(format!("unknown channel: {}", s)),
         }
     }
@@ -70,6 +97,18 @@ impl std::str::FromStr for Priority {
     }
 }
 
+impl Priority {
+    /// Ordering from `Low` (0) to `Urgent` (3).
+    pub fn rank(self) -> u8 {
+        match self {
+            Priority::Low => 0,
+            Priority::Normal => 1,
+            Priority::High => 2,
+            Priority::Urgent => 3,
+        }
+    }
+}
+
 /// Queue entry status.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -117,16 +156,35 @@ impl std::str::FromStr for QueueStatus {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendRequest {
     pub channel: GatewayChannel,
+    /// Empty when `recipient_contact` is given; filled in by resolving the contact
+    #[serde(default)]
     pub recipient_address: String,
+    /// Contact ID, `contact:<id>` reference or tag to address instead of a raw address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_contact: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub recipient_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub subject: Option<String>,
+    /// Empty when `template_id` is given; filled in by rendering the template
+    #[serde(default)]
     pub body: String,
     #[serde(default)]
     pub priority: Priority,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context: Option<serde_json::Value>,
+    /// Agent-chosen conversation ID; omit to start a new thread or inherit from `in_reply_to`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    /// Action ID of the previous message in this conversation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<String>,
+    /// Render subject and body from this server-side template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_id: Option<String>,
+    /// Values for the template's own placeholders
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
 }
 
 /// Response after queueing a message.
@@ -134,6 +192,10 @@ pub struct SendRequest {
 pub struct SendResponse {
     pub action_id: String,
     pub status: QueueStatus,
+    pub thread_id: String,
+    /// `contact:<id>` reference when the message was addressed by contact
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_contact: Option<String>,
 }
 
 /// Response for action status query.
@@ -145,6 +207,51 @@ pub struct ActionStatusResponse {
     pub error_message: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub sent_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
+}
+
+/// Response after queueing a batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSendResponse {
+    pub batch_id: String,
+    pub results: Vec<BatchSendResult>,
+}
+
+/// Outcome for one recipient of a batch send.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSendResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_contact: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<QueueStatus>,
+    /// The error body `/gateway/send` would have returned for this recipient
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+/// Status of every message in a batch, from `/gateway/actions/{batch_id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchStatusResponse {
+    pub batch_id: String,
+    pub actions: Vec<BatchActionStatus>,
+}
+
+/// One recipient's message in a batch status.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchActionStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_contact: Option<String>,
+    #[serde(flatten)]
+    pub action: ActionStatusResponse,
 }
 
 /// Queue entry for listing.
@@ -164,6 +271,12 @@ pub struct QueueEntryResponse {
     pub agent_context: Option<serde_json::Value>,
     pub created_at: String,
     pub agent_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_contact: Option<String>,
 }
 
 /// List of pending queue entries.
@@ -201,18 +314,174 @@ impl<T> GatewayApiResponse<T> {
     }
 }
 
-/// Health check response.
+/// Health check response. The backlog fields are left out for callers that
+/// may not read metrics.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthResponse {
     pub status: String,
     pub uptime_secs: u64,
-    pub pending_count: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_count: Option<i64>,
     pub version: String,
+    /// Flagged messages, included in `pending_count`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flagged_count: Option<i64>,
+    /// Approved messages waiting to retry a failed send
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrying_count: Option<i64>,
+    /// Age of the oldest message awaiting review
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oldest_pending_secs: Option<i64>,
+}
+
+// ========== Error Types ==========
+
+/// Stable, machine-readable error code included in every error response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Missing, malformed or revoked API key
+    Unauthorized,
+    /// Body is not a JSON object
+    InvalidRequest,
+    /// One or more fields failed validation (see `field_errors`)
+    ValidationFailed,
+    /// `channel` is missing or not a known channel
+    InvalidChannel,
+    RateLimited,
+    AllowlistDenied,
+    ConsentDenied,
+    ContentBlocked,
+    /// The key's scopes don't permit this request
+    ScopeDenied,
+    NotFound,
+    /// Queue management endpoint called from another host
+    LocalOnly,
+    /// Action is not in a state that allows the operation
+    InvalidState,
+    /// Request body exceeds the server's size limit
+    PayloadTooLarge,
+    /// Request wasn't received in time
+    Timeout,
+    /// Request took too long; it may still complete, so check before retrying
+    OutcomeUnknown,
+    Internal,
+}
+
+impl ErrorCode {
+    /// Every code, in documentation order.
+    pub const ALL: [ErrorCode; 16] = [
+        ErrorCode::Unauthorized,
+        ErrorCode::InvalidRequest,
+        ErrorCode::ValidationFailed,
+        ErrorCode::InvalidChannel,
+        ErrorCode::RateLimited,
+        ErrorCode::AllowlistDenied,
+        ErrorCode::ConsentDenied,
+        ErrorCode::ContentBlocked,
+        ErrorCode::ScopeDenied,
+        ErrorCode::NotFound,
+        ErrorCode::LocalOnly,
+        ErrorCode::InvalidState,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::Timeout,
+        ErrorCode::OutcomeUnknown,
+        ErrorCode::Internal,
+    ];
+
+    /// HTTP status returned with this code.
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorCode::Unauthorized => 401,
+            ErrorCode::InvalidRequest
+            | ErrorCode::ValidationFailed
+            | ErrorCode::InvalidChannel
+            | ErrorCode::ContentBlocked
+            | ErrorCode::InvalidState => 400,
+            ErrorCode::AllowlistDenied
+            | ErrorCode::ConsentDenied
+            | ErrorCode::ScopeDenied
+            | ErrorCode::LocalOnly => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::Timeout => 408,
+            ErrorCode::PayloadTooLarge => 413,
+            ErrorCode::RateLimited => 429,
+            ErrorCode::Internal => 500,
+            ErrorCode::OutcomeUnknown => 504,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        write!(f, "{}", json.trim_matches('"'))
+    }
+}
+
+/// Validation problem with a single request field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Generic error response.
+///
+/// All error bodies share `success: false`, `code` and a human-readable `error`;
+/// the specific error responses below add their own details.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub success: bool,
+    pub code: ErrorCode,
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub field_errors: Vec<FieldError>,
+}
+
+impl ErrorResponse {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            code,
+            error: message.into(),
+            field_errors: Vec::new(),
+        }
+    }
+
+    /// Validation failure; reported as `invalid_channel` when only the channel is wrong.
+    pub fn validation(field_errors: Vec<FieldError>) -> Self {
+        let code = if field_errors.iter().all(|e| e.field == "channel") {
+            ErrorCode::InvalidChannel
+        } else {
+            ErrorCode::ValidationFailed
+        };
+        let message = field_errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Self {
+            field_errors,
+            ..Self::new(code, message)
+        }
+    }
 }
 
 /// Rate limit exceeded error response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RateLimitErrorResponse {
+    pub success: bool,
+    pub code: ErrorCode,
     pub error: String,
     pub retry_after_seconds: i64,
     pub limit_type: String,
@@ -223,6 +492,8 @@ pub struct RateLimitErrorResponse {
 /// Recipient not allowed error response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AllowlistErrorResponse {
+    pub success: bool,
+    pub code: ErrorCode,
     pub error: String,
     pub allowed_patterns: Vec<String>,
 }
@@ -230,6 +501,8 @@ pub struct AllowlistErrorResponse {
 /// Content blocked by filter error response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContentBlockedErrorResponse {
+    pub success: bool,
+    pub code: ErrorCode,
     pub error: String,
     pub filter: String,
     pub description: String,
@@ -238,10 +511,329 @@ pub struct ContentBlockedErrorResponse {
 /// Contact consent denied error response.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsentDeniedErrorResponse {
+    pub success: bool,
+    pub code: ErrorCode,
     pub error: String,
     pub recipient: String,
 }
 
+// ========== Request Validation ==========
+
+/// Maximum message body length accepted by `/gateway/send`.
+pub const MAX_BODY_LEN: usize = 10_000;
+
+/// Maximum subject length accepted by `/gateway/send`.
+pub const MAX_SUBJECT_LEN: usize = 500;
+
+/// Maximum `Idempotency-Key` header length accepted by `/gateway/send`.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+impl SendRequest {
+    /// Parse and validate a `/gateway/send` body, reporting every invalid field.
+    pub fn from_json(body: &[u8]) -> Result<Self, ErrorResponse> {
+        let value: serde_json::Value = serde_json::from_slice(body).map_err(|e| {
+            ErrorResponse::new(ErrorCode::InvalidRequest, format!("Invalid JSON: {}", e))
+        })?;
+        let obj = value.as_object().ok_or_else(|| {
+            ErrorResponse::new(ErrorCode::InvalidRequest, "Request body must be a JSON object")
+        })?;
+
+        let mut errors = Vec::new();
+
+        let channel = match obj.get("channel") {
+            None | Some(serde_json::Value::Null) => {
+                errors.push(FieldError::new("channel", "is required"));
+                None
+            }
+            Some(v) => match serde_json::from_value::<GatewayChannel>(v.clone()) {
+                Ok(channel) => Some(channel),
+                Err(_) => {
+                    errors.push(FieldError::new(
+                        "channel",
+                        format!("must be one of: {}", GatewayChannel::names()),
+                    ));
+                    None
+                }
+            },
+        };
+
+        let recipient_contact = string_field(obj, "recipient_contact", &mut errors);
+        if recipient_contact.is_some_and(|c| c.trim().is_empty()) {
+            errors.push(FieldError::new("recipient_contact", "cannot be empty"));
+        }
+        let by_contact = recipient_contact.is_some() || errors.iter().any(|e| e.field == "recipient_contact");
+
+        match string_field(obj, "recipient_address", &mut errors) {
+            Some(_) if by_contact => {
+                errors.push(FieldError::new("recipient_address", "cannot be combined with recipient_contact"))
+            }
+            Some(addr) if addr.trim().is_empty() => {
+                errors.push(FieldError::new("recipient_address", "cannot be empty"))
+            }
+            Some(_) => {}
+            None if !by_contact && !errors.iter().any(|e| e.field == "recipient_address") => errors.push(
+                FieldError::new("recipient_address", "is required unless recipient_contact is given"),
+            ),
+            None => {}
+        }
+        if by_contact && obj.get("recipient_name").is_some_and(|v| !v.is_null()) {
+            errors.push(FieldError::new("recipient_name", "comes from contacts with recipient_contact"));
+        }
+
+        let template_id = string_field(obj, "template_id", &mut errors);
+        if template_id.is_some_and(|t| t.trim().is_empty()) {
+            errors.push(FieldError::new("template_id", "cannot be empty"));
+        }
+        let templated = template_id.is_some();
+
+        match obj.get("variables") {
+            None | Some(serde_json::Value::Null) => {}
+            Some(serde_json::Value::Object(vars)) => {
+                if !templated {
+                    errors.push(FieldError::new("variables", "can only be used with template_id"));
+                } else if vars.values().any(|v| !v.is_string()) {
+                    errors.push(FieldError::new("variables", "values must be strings"));
+                }
+            }
+            Some(_) => errors.push(FieldError::new("variables", "must be an object of strings")),
+        }
+
+        match string_field(obj, "body", &mut errors) {
+            Some(_) if templated => {
+                errors.push(FieldError::new("body", "cannot be combined with template_id"))
+            }
+            Some(text) if text.trim().is_empty() => {
+                errors.push(FieldError::new("body", "cannot be empty"))
+            }
+            Some(text) if text.chars().count() > MAX_BODY_LEN => errors.push(FieldError::new(
+                "body",
+                format!("must be at most {} characters", MAX_BODY_LEN),
+            )),
+            Some(_) => {}
+            None if !templated && !errors.iter().any(|e| e.field == "body") => {
+                errors.push(FieldError::new("body", "is required unless template_id is given"))
+            }
+            None => {}
+        }
+
+        let subject = string_field(obj, "subject", &mut errors);
+        if let Some(subject) = subject {
+            if templated {
+                errors.push(FieldError::new("subject", "cannot be combined with template_id"));
+            } else if subject.chars().count() > MAX_SUBJECT_LEN {
+                errors.push(FieldError::new(
+                    "subject",
+                    format!("must be at most {} characters", MAX_SUBJECT_LEN),
+                ));
+            }
+        }
+        if channel == Some(GatewayChannel::Email)
+            && !templated
+            && subject.map_or(true, |s| s.trim().is_empty())
+            && !errors.iter().any(|e| e.field == "subject")
+        {
+            errors.push(FieldError::new("subject", "is required for email"));
+        }
+
+        if let Some(priority) = obj.get("priority").filter(|v| !v.is_null()) {
+            if serde_json::from_value::<Priority>(priority.clone()).is_err() {
+                errors.push(FieldError::new(
+                    "priority",
+                    "must be one of: urgent, high, normal, low",
+                ));
+            }
+        }
+
+        if let Some(thread_id) = string_field(obj, "thread_id", &mut errors) {
+            if thread_id.trim().is_empty() {
+                errors.push(FieldError::new("thread_id", "cannot be empty"));
+            } else if thread_id.trim().len() > MAX_THREAD_ID_LEN {
+                errors.push(FieldError::new(
+                    "thread_id",
+                    format!("must be at most {} characters", MAX_THREAD_ID_LEN),
+                ));
+            }
+        }
+
+        for field in ["recipient_name", "in_reply_to"] {
+            string_field(obj, field, &mut errors);
+        }
+
+        if !errors.is_empty() {
+            return Err(ErrorResponse::validation(errors));
+        }
+
+        serde_json::from_value(value)
+            .map_err(|e| ErrorResponse::new(ErrorCode::InvalidRequest, format!("Invalid request: {}", e)))
+    }
+}
+
+/// Most recipients accepted by one batch send.
+pub const MAX_BATCH_RECIPIENTS: usize = 100;
+
+/// A validated `/gateway/send/batch` body: one `SendRequest` per recipient.
+#[derive(Debug)]
+pub struct BatchSendRequest {
+    pub requests: Vec<SendRequest>,
+}
+
+impl BatchSendRequest {
+    /// Parse and validate a batch body. The shared fields are checked as for
+    /// `/gateway/send`; recipient problems are reported as `recipients[i]`.
+    pub fn from_json(body: &[u8]) -> Result<Self, ErrorResponse> {
+        let value: serde_json::Value = serde_json::from_slice(body).map_err(|e| {
+            ErrorResponse::new(ErrorCode::InvalidRequest, format!("Invalid JSON: {}", e))
+        })?;
+        let obj = value.as_object().ok_or_else(|| {
+            ErrorResponse::new(ErrorCode::InvalidRequest, "Request body must be a JSON object")
+        })?;
+
+        let mut errors = Vec::new();
+        for field in ["recipient_address", "recipient_name", "recipient_contact"] {
+            if obj.contains_key(field) {
+                errors.push(FieldError::new(field, "is not allowed in a batch; use recipients"));
+            }
+        }
+        for field in ["thread_id", "in_reply_to"] {
+            if obj.contains_key(field) {
+                errors.push(FieldError::new(field, "is not supported in a batch"));
+            }
+        }
+
+        let recipients: Vec<BatchRecipient> = match obj.get("recipients") {
+            None | Some(serde_json::Value::Null) => {
+                errors.push(FieldError::new("recipients", "is required"));
+                Vec::new()
+            }
+            Some(serde_json::Value::Array(items)) if items.is_empty() => {
+                errors.push(FieldError::new("recipients", "cannot be empty"));
+                Vec::new()
+            }
+            Some(serde_json::Value::Array(items)) if items.len() > MAX_BATCH_RECIPIENTS => {
+                errors.push(FieldError::new(
+                    "recipients",
+                    format!("must have at most {} entries", MAX_BATCH_RECIPIENTS),
+                ));
+                Vec::new()
+            }
+            Some(serde_json::Value::Array(items)) => {
+                let mut recipients: Vec<BatchRecipient> = Vec::new();
+                for (i, item) in items.iter().enumerate() {
+                    let field = format!("recipients[{}]", i);
+                    match BatchRecipient::parse(item) {
+                        Some(recipient) if recipients.iter().any(|r| r.same_as(&recipient)) => {
+                            errors.push(FieldError::new(&field, "duplicate recipient"))
+                        }
+                        Some(recipient) => recipients.push(recipient),
+                        None => errors.push(FieldError::new(
+                            &field,
+                            "must be an address, {address, name} or {contact}",
+                        )),
+                    }
+                }
+                recipients
+            }
+            Some(_) => {
+                errors.push(FieldError::new("recipients", "must be an array"));
+                Vec::new()
+            }
+        };
+
+        // Validate each recipient's message exactly as /gateway/send would
+        let mut requests = Vec::new();
+        for (i, recipient) in recipients.into_iter().enumerate() {
+            let mut single = obj.clone();
+            single.remove("recipients");
+            match recipient {
+                BatchRecipient::Address(address, name) => {
+                    single.insert("recipient_address".to_string(), address.into());
+                    if let Some(name) = name {
+                        single.insert("recipient_name".to_string(), name.into());
+                    }
+                }
+                BatchRecipient::Contact(reference) => {
+                    single.insert("recipient_contact".to_string(), reference.into());
+                }
+            }
+            let body = serde_json::Value::Object(single).to_string();
+            match SendRequest::from_json(body.as_bytes()) {
+                Ok(request) => requests.push(request),
+                Err(e) if e.field_errors.is_empty() => return Err(e),
+                Err(e) => {
+                    for error in e.field_errors {
+                        let error = match error.field.as_str() {
+                            "recipient_address" | "recipient_contact" => {
+                                FieldError::new(&format!("recipients[{}]", i), error.message)
+                            }
+                            "recipient_name" => {
+                                FieldError::new(&format!("recipients[{}].name", i), error.message)
+                            }
+                            _ => error,
+                        };
+                        if !errors.contains(&error) {
+                            errors.push(error);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ErrorResponse::validation(errors));
+        }
+        Ok(Self { requests })
+    }
+}
+
+/// One entry of a batch's `recipients`.
+enum BatchRecipient {
+    Address(String, Option<String>),
+    Contact(String),
+}
+
+impl BatchRecipient {
+    fn parse(item: &serde_json::Value) -> Option<Self> {
+        use serde_json::Value;
+        match item {
+            Value::String(address) => Some(Self::Address(address.clone(), None)),
+            Value::Object(r) => match (r.get("address"), r.get("name"), r.get("contact")) {
+                (Some(Value::String(a)), None | Some(Value::Null), None) => Some(Self::Address(a.clone(), None)),
+                (Some(Value::String(a)), Some(Value::String(n)), None) => {
+                    Some(Self::Address(a.clone(), Some(n.clone())))
+                }
+                (None, None, Some(Value::String(c))) => Some(Self::Contact(c.clone())),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            // "+1 555-123-4567" and "+15551234567" are the same phone
+            (Self::Address(a, _), Self::Address(b, _)) => normalize_recipient(a) == normalize_recipient(b),
+            (Self::Contact(a), Self::Contact(b)) => a.trim().eq_ignore_ascii_case(b.trim()),
+            _ => false,
+        }
+    }
+}
+
+/// Read an optional string field, recording a type error if it isn't a string.
+fn string_field<'a>(
+    obj: &'a serde_json::Map<String, serde_json::Value>,
+    field: &str,
+    errors: &mut Vec<FieldError>,
+) -> Option<&'a str> {
+    match obj.get(field) {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(s)) => Some(s),
+        Some(_) => {
+            errors.push(FieldError::new(field, "must be a string"));
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -261,15 +853,39 @@ mod tests {
         let req = SendRequest {
             channel: GatewayChannel::Email,
             recipient_address: "test@example.com".to_string(),
+            recipient_contact: None,
             recipient_name: Some("Test User".to_string()),
             subject: Some("Hello".to_string()),
             body: "This is a test".to_string(),
             priority: Priority::Normal,
             context: Some(serde_json::json!({"reason": "calendar followup"})),
+            thread_id: None,
+            in_reply_to: None,
+            template_id: None,
+            variables: BTreeMap::new(),
         };
         let json = serde_json::to_string(&req).unwrap();
         assert!(json.contains(r#""channel":"email""#));
         assert!(json.contains(r#""priority":"normal""#));
+        assert!(!json.contains("thread_id"));
+    }
+
+    #[test]
+    fn test_send_request_threading_fields() {
+        let req: SendRequest = serde_json::from_str(
+            r#"{"channel":"sms","recipient_address":"+15551234567","body":"Sounds good","thread_id":"conv-42","in_reply_to":"abc"}"#,
+        )
+        .unwrap();
+        assert_eq!(req.thread_id.as_deref(), Some("conv-42"));
+        assert_eq!(req.in_reply_to.as_deref(), Some("abc"));
+
+        // Threading fields are optional
+        let req: SendRequest = serde_json::from_str(
+            r#"{"channel":"sms","recipient_address":"+15551234567","body":"Hi"}"#,
+        )
+        .unwrap();
+        assert!(req.thread_id.is_none());
+        assert!(req.in_reply_to.is_none());
     }
 
     #[test]
@@ -282,4 +898,112 @@ mod tests {
         assert!(!resp.success);
         assert_eq!(resp.error, Some("failed".to_string()));
     }
+
+    #[test]
+    fn test_error_code_serialization() {
+        assert_eq!(serde_json::to_string(&ErrorCode::RateLimited).unwrap(), r#""rate_limited""#);
+        assert_eq!(ErrorCode::AllowlistDenied.to_string(), "allowlist_denied");
+        assert_eq!(ErrorCode::ConsentDenied.http_status(), 403);
+
+        let json = serde_json::to_string(&ErrorResponse::new(ErrorCode::NotFound, "Action not found")).unwrap();
+        assert!(json.contains(r#""success":false"#));
+        assert!(json.contains(r#""code":"not_found""#));
+        assert!(!json.contains("field_errors"));
+    }
+
+    #[test]
+    fn test_send_request_template_fields() {
+        let req = SendRequest::from_json(
+            br#"{"channel":"email","recipient_address":"a@example.com","template_id":"meeting-confirm","variables":{"time":"3pm"}}"#,
+        )
+        .unwrap();
+        assert_eq!(req.template_id.as_deref(), Some("meeting-confirm"));
+        assert_eq!(req.variables.get("time").map(String::as_str), Some("3pm"));
+        assert!(req.body.is_empty());
+
+        let err = SendRequest::from_json(
+            br#"{"channel":"sms","recipient_address":"+15551234567","body":"Hi","template_id":"t","variables":{"n":1}}"#,
+        )
+        .unwrap_err();
+        let fields: Vec<&str> = err.field_errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["variables", "body"]);
+
+        let err = SendRequest::from_json(
+            br#"{"channel":"sms","recipient_address":"+15551234567","body":"Hi","variables":{"n":"1"}}"#,
+        )
+        .unwrap_err();
+        assert_eq!(err.field_errors[0].field, "variables");
+    }
+
+    #[test]
+    fn test_send_request_recipient_contact() {
+        let req = SendRequest::from_json(br#"{"channel":"sms","recipient_contact":"board-chair","body":"Hi"}"#)
+            .unwrap();
+        assert_eq!(req.recipient_contact.as_deref(), Some("board-chair"));
+        assert!(req.recipient_address.is_empty());
+
+        let err = SendRequest::from_json(
+            br#"{"channel":"sms","recipient_contact":"c","recipient_address":"+15551234567","recipient_name":"Bo","body":"Hi"}"#,
+        )
+        .unwrap_err();
+        let fields: Vec<&str> = err.field_errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["recipient_address", "recipient_name"]);
+
+        let batch = BatchSendRequest::from_json(
+            br#"{"channel":"sms","body":"Hi","recipients":["+15551234567",{"contact":"board-chair"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(batch.requests[1].recipient_contact.as_deref(), Some("board-chair"));
+    }
+
+    #[test]
+    fn test_batch_send_request() {
+        let batch = BatchSendRequest::from_json(
+            br#"{"channel":"sms","body":"Office closed today","recipients":["+15551234567",{"address":"+15557654321","name":"Bo"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(batch.requests.len(), 2);
+        assert_eq!(batch.requests[1].recipient_address, "+15557654321");
+        assert_eq!(batch.requests[1].recipient_name.as_deref(), Some("Bo"));
+        assert_eq!(batch.requests[1].body, "Office closed today");
+
+        // Shared problems are reported once, recipient problems by index
+        let err = BatchSendRequest::from_json(
+            br#"{"channel":"sms","thread_id":"t","recipients":["+15551234567"," ",7,"+1 555-123-4567"]}"#,
+        )
+        .unwrap_err();
+        let fields: Vec<&str> = err.field_errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["thread_id", "recipients[2]", "recipients[3]", "body", "recipients[1]"]
+        );
+
+        let err = BatchSendRequest::from_json(br#"{"channel":"sms","body":"Hi","recipients":[]}"#).unwrap_err();
+        assert_eq!(err.field_errors[0].field, "recipients");
+    }
+
+    #[test]
+    fn test_send_request_validation_field_errors() {
+        let err = SendRequest::from_json(br#"{"channel":"email","body":"  ","priority":"asap","thread_id":7}"#)
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::ValidationFailed);
+        let fields: Vec<&str> = err.field_errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["recipient_address", "body", "subject", "priority", "thread_id"]);
+
+        let err = SendRequest::from_json(br#"{"channel":"fax","recipient_address":"+15551234567","body":"Hi"}"#)
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidChannel);
+
+        let err = SendRequest::from_json(b"[1, 2]").unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidRequest);
+
+        // Channel values are lowercase in the API
+        let err = SendRequest::from_json(br#"{"channel":"SMS","recipient_address":"+15551234567","body":"Hi"}"#)
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidChannel);
+
+        let req = SendRequest::from_json(br#"{"channel":"sms","recipient_address":"+15551234567","body":"Hi"}"#)
+            .unwrap();
+        assert_eq!(req.priority, Priority::Normal);
+    }
 }
@@ -0,0 +1,632 @@
+//! Browser approval dashboard served by the gateway at `/gateway/ui`.
+//!
+//! Localhost only. `contactcmd gateway web` issues a one-time login code
+//! and opens a link that exchanges it for a session cookie. Every form
+//! carries the session's CSRF token, and requests must name a loopback
+//! host so a rebound DNS name can't reach the dashboard.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::filter::{ContentFilterMatcher, FilterResult};
+use super::keys;
+use crate::cli::http_server::{HttpRequest, HttpResponse};
+use crate::db::gateway::QueueEntry;
+use crate::db::Database;
+
+/// Dashboard root.
+pub const UI_PATH: &str = "/gateway/ui";
+
+/// Settings key for the hash of the current login code.
+pub const SETTING_LOGIN_CODE_HASH: &str = "gateway_web_login_hash";
+/// Settings key for when the current login code stops working.
+pub const SETTING_LOGIN_CODE_EXPIRES: &str = "gateway_web_login_expires";
+
+const SESSION_COOKIE: &str = "gw_session";
+const SESSION_HOURS: i64 = 8;
+const LOGIN_CODE_MINUTES: i64 = 10;
+
+/// A logged-in browser.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub csrf_token: String,
+    expires_at: DateTime<Utc>,
+    /// Shown once on the next page, then cleared
+    flash: Option<String>,
+}
+
+/// Sessions of the running gateway. Kept in memory, so a restart logs everyone out.
+#[derive(Default)]
+pub struct WebSessions {
+    /// Keyed by the hash of the cookie value
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl WebSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a session. Returns the cookie value.
+    pub fn create(&self, now: DateTime<Utc>) -> String {
+        let id = random_token();
+        let session = Session {
+            csrf_token: random_token(),
+            expires_at: now + Duration::hours(SESSION_HOURS),
+            flash: None,
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, s| s.expires_at > now);
+        sessions.insert(keys::hash_key(&id), session);
+        id
+    }
+
+    /// The session named by the request's cookie, if it is still valid.
+    pub fn get(&self, request: &HttpRequest, now: DateTime<Utc>) -> Option<Session> {
+        let id = session_cookie(request)?;
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(&keys::hash_key(&id))
+            .filter(|s| s.expires_at > now)
+            .cloned()
+    }
+
+    /// Leave a message for the session's next page.
+    pub fn set_flash(&self, request: &HttpRequest, message: String) {
+        if let Some(id) = session_cookie(request) {
+            if let Some(session) = self.sessions.lock().unwrap().get_mut(&keys::hash_key(&id)) {
+                session.flash = Some(message);
+            }
+        }
+    }
+
+    pub fn take_flash(&self, request: &HttpRequest) -> Option<String> {
+        let id = session_cookie(request)?;
+        self.sessions
+            .lock()
+            .unwrap()
+            .get_mut(&keys::hash_key(&id))
+            .and_then(|s| s.flash.take())
+    }
+
+    pub fn remove(&self, request: &HttpRequest) {
+        if let Some(id) = session_cookie(request) {
+            self.sessions.lock().unwrap().remove(&keys::hash_key(&id));
+        }
+    }
+}
+
+/// `Set-Cookie` value for a new session.
+pub fn session_cookie_header(id: &str, secure: bool) -> String {
+    format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        SESSION_COOKIE,
+        id,
+        UI_PATH,
+        SESSION_HOURS * 3600,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// `Set-Cookie` value that clears the session cookie.
+pub fn clear_cookie_header() -> String {
+    format!("{}=; Path={}; Max-Age=0; HttpOnly; SameSite=Strict", SESSION_COOKIE, UI_PATH)
+}
+
+fn session_cookie(request: &HttpRequest) -> Option<String> {
+    request.headers.get("cookie")?.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        (name == SESSION_COOKIE && !value.is_empty()).then(|| value.to_string())
+    })
+}
+
+fn random_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    hex::encode(bytes)
+}
+
+/// Issue a login code, replacing any earlier one.
+pub fn create_login_code(db: &Database, now: DateTime<Utc>) -> Result<String> {
+    let code = random_token();
+    db.set_setting(SETTING_LOGIN_CODE_HASH, &keys::hash_key(&code))?;
+    db.set_setting(
+        SETTING_LOGIN_CODE_EXPIRES,
+        &(now + Duration::minutes(LOGIN_CODE_MINUTES)).to_rfc3339(),
+    )?;
+    Ok(code)
+}
+
+/// Use up the login code. Returns whether it was valid.
+pub fn redeem_login_code(db: &Database, code: &str, now: DateTime<Utc>) -> Result<bool> {
+    let (Some(hash), Some(expires)) = (
+        db.get_setting(SETTING_LOGIN_CODE_HASH)?,
+        db.get_setting(SETTING_LOGIN_CODE_EXPIRES)?,
+    ) else {
+        return Ok(false);
+    };
+    if hash != keys::hash_key(code.trim()) {
+        return Ok(false);
+    }
+    db.delete_setting(SETTING_LOGIN_CODE_HASH)?;
+    db.delete_setting(SETTING_LOGIN_CODE_EXPIRES)?;
+    let expires = DateTime::parse_from_rfc3339(&expires).map(|t| t.with_timezone(&Utc));
+    Ok(matches!(expires, Ok(t) if t > now))
+}
+
+/// Local dashboard URL for a gateway listening on `listen_url`.
+pub fn dashboard_url(listen_url: &str) -> Result<String> {
+    let mut url = url::Url::parse(listen_url)?;
+    let host = match url.host() {
+        Some(url::Host::Ipv4(ip)) if ip.is_unspecified() || ip.is_loopback() => "127.0.0.1",
+        Some(url::Host::Ipv6(ip)) if ip.is_unspecified() || ip.is_loopback() => "[::1]",
+        Some(url::Host::Domain("localhost")) => "localhost",
+        _ => {
+            return Err(anyhow!(
+                "The gateway listens on {}; restart it with a loopback --bind to use the dashboard",
+                listen_url
+            ))
+        }
+    };
+    url.set_host(Some(host))?;
+    url.set_path(UI_PATH);
+    Ok(url.to_string())
+}
+
+/// Whether the `Host` header names this machine. Guards against DNS rebinding.
+pub fn is_loopback_host(request: &HttpRequest) -> bool {
+    let Some(host) = request.headers.get("host") else {
+        return false;
+    };
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    name.eq_ignore_ascii_case("localhost") || name == "127.0.0.1" || name == "::1"
+}
+
+/// Whether a form POST came from the dashboard itself.
+///
+/// Checks Origin, falling back to Referer when a browser leaves Origin out.
+/// A POST with neither is refused.
+pub fn is_same_origin(request: &HttpRequest) -> bool {
+    let Some(host) = request.headers.get("host") else {
+        return false;
+    };
+    let source = match (request.headers.get("origin"), request.headers.get("referer")) {
+        (Some(origin), _) => origin.as_str(),
+        // Referer carries a path; only its scheme and authority matter
+        (None, Some(referer)) => {
+            let authority_end = referer
+                .find("://")
+                .and_then(|scheme| referer[scheme + 3..].find('/').map(|i| scheme + 3 + i))
+                .unwrap_or(referer.len());
+            &referer[..authority_end]
+        }
+        (None, None) => return false,
+    };
+    source.strip_prefix("http://").or_else(|| source.strip_prefix("https://")) == Some(host.as_str())
+}
+
+/// Whether `submitted` matches the session's CSRF token.
+pub fn csrf_matches(session: &Session, submitted: Option<&String>) -> bool {
+    // Compare hashes so the comparison time says nothing about the token
+    submitted.is_some_and(|t| keys::hash_key(t) == keys::hash_key(&session.csrf_token))
+}
+
+/// Decode an `application/x-www-form-urlencoded` body.
+pub fn parse_form(body: &[u8]) -> HashMap<String, String> {
+    url::form_urlencoded::parse(body).into_owned().collect()
+}
+
+/// Security headers for every dashboard page.
+pub fn page_response(status: u16, page: String) -> HttpResponse {
+    HttpResponse::html(status, page)
+        .with_header(
+            "content-security-policy",
+            "default-src 'none'; style-src 'unsafe-inline'; form-action 'self'; frame-ancestors 'none'"
+                .to_string(),
+        )
+        .with_header("cache-control", "no-store".to_string())
+        .with_header("referrer-policy", "no-referrer".to_string())
+        .with_header("x-frame-options", "DENY".to_string())
+}
+
+// ========== Page content ==========
+
+/// The recipient as they appear in contacts.
+pub struct ContactCard {
+    pub name: String,
+    pub organization: Option<String>,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+}
+
+/// Everything shown for one queued message.
+pub struct EntryView {
+    pub entry: QueueEntry,
+    pub agent_name: String,
+    pub contact: Option<ContactCard>,
+    pub filter_hits: Vec<String>,
+}
+
+/// Gather what the reviewer needs to judge `entry`.
+pub fn entry_view(
+    db: &Database,
+    matcher: &ContentFilterMatcher,
+    entry: QueueEntry,
+    agent_name: String,
+) -> Result<EntryView> {
+    let contact = contact_card(db, &entry.recipient_address)?;
+
+    let mut filter_hits = Vec::new();
+    let texts = entry.subject.iter().chain(std::iter::once(&entry.body));
+    for result in texts.flat_map(|t| matcher.matches(t)) {
+        let hit = match result {
+            FilterResult::Denied { description, .. } => format!("deny: {}", description),
+            FilterResult::Flagged { description, .. } => format!("flag: {}", description),
+            FilterResult::Passed => continue,
+        };
+        if !filter_hits.contains(&hit) {
+            filter_hits.push(hit);
+        }
+    }
+
+    Ok(EntryView {
+        entry,
+        agent_name,
+        contact,
+        filter_hits,
+    })
+}
+
+fn contact_card(db: &Database, address: &str) -> Result<Option<ContactCard>> {
+    let person = if address.contains('@') {
+        db.get_person_by_email(address)?
+    } else {
+        db.get_person_by_phone(address)?
+    };
+    let Some(person) = person else {
+        return Ok(None);
+    };
+
+    let organization = db
+        .get_organizations_for_person(person.id)?
+        .into_iter()
+        .find(|(po, _)| po.is_current)
+        .map(|(po, org)| match po.title {
+            Some(title) => format!("{}, {}", title, org.name),
+            None => org.name,
+        });
+
+    Ok(Some(ContactCard {
+        name: person.display_name.unwrap_or_else(|| "(no name)".to_string()),
+        organization,
+        emails: db
+            .get_emails_for_person(person.id)?
+            .into_iter()
+            .map(|e| e.email_address)
+            .collect(),
+        phones: db
+            .get_phones_for_person(person.id)?
+            .into_iter()
+            .map(|p| p.phone_number)
+            .collect(),
+    }))
+}
+
+// ========== HTML ==========
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:52rem;margin:2rem auto;padding:0 1rem;color:#222}\
+header{display:flex;justify-content:space-between;align-items:center}\
+.entry{border:1px solid #ccc;border-radius:6px;padding:1rem;margin:1rem 0}\
+.flagged{border-color:#d33;background:#fff6f6}\
+.meta{color:#666;font-size:.9rem}\
+.card{background:#f4f4f4;border-radius:4px;padding:.5rem .75rem;margin:.5rem 0;font-size:.9rem}\
+pre{white-space:pre-wrap;background:#fafafa;border:1px solid #eee;padding:.75rem}\
+.hits{color:#a00}\
+.flash{background:#eef6ff;border:1px solid #9bd;padding:.5rem .75rem;border-radius:4px}\
+form.inline{display:inline}\
+button{padding:.4rem 1rem;margin-right:.5rem;cursor:pointer}\
+.approve{background:#2a7;color:#fff;border:0}\
+.deny{background:#c33;color:#fff;border:0}\
+textarea,input[type=text]{width:100%;box-sizing:border-box;font:inherit}";
+
+/// Escape text for HTML element content and quoted attributes.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn page(title: &str, head_extra: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>{}<style>{}</style></head><body>{}</body></html>",
+        escape(title),
+        head_extra,
+        STYLE,
+        body
+    )
+}
+
+fn csrf_field(session: &Session) -> String {
+    format!(
+        "<input type=\"hidden\" name=\"csrf\" value=\"{}\">",
+        escape(&session.csrf_token)
+    )
+}
+
+fn action_form(session: &Session, id: &str, action: &str, class: &str, label: &str) -> String {
+    format!(
+        "<form class=\"inline\" method=\"post\" action=\"{}/queue/{}/{}\">{}<button class=\"{}\">{}</button></form>",
+        UI_PATH,
+        escape(id),
+        action,
+        csrf_field(session),
+        class,
+        label
+    )
+}
+
+/// Login form, for when the link from `gateway web` can't be opened.
+pub fn login_page(error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!("<p class=\"hits\">{}</p>", escape(e)))
+        .unwrap_or_default();
+    page(
+        "Gateway login",
+        "",
+        &format!(
+            "<h1>Gateway approvals</h1>{}<p>Run <code>contactcmd gateway web</code> to open a login link, or paste the code it prints.</p>\
+<form method=\"post\" action=\"{}/login\"><p><input type=\"text\" name=\"code\" autocomplete=\"off\" autofocus></p><button>Log in</button></form>",
+            error, UI_PATH
+        ),
+    )
+}
+
+/// Pending and flagged messages with their review actions.
+pub fn dashboard_page(views: &[EntryView], session: &Session, flash: Option<&str>) -> String {
+    let mut body = format!(
+        "<header><h1>Gateway approvals ({})</h1><form method=\"post\" action=\"{}/logout\">{}<button>Log out</button></form></header>",
+        views.len(),
+        UI_PATH,
+        csrf_field(session)
+    );
+    if let Some(flash) = flash {
+        body.push_str(&format!("<p class=\"flash\">{}</p>", escape(flash)));
+    }
+    if views.is_empty() {
+        body.push_str("<p>No messages awaiting review.</p>");
+    }
+    for view in views {
+        body.push_str(&entry_html(view, session));
+    }
+    // Pick up new messages without a reload
+    page(
+        "Gateway approvals",
+        "<meta http-equiv=\"refresh\" content=\"30\">",
+        &body,
+    )
+}
+
+fn entry_html(view: &EntryView, session: &Session) -> String {
+    let e = &view.entry;
+    let flagged = e.status == "flagged";
+    let mut html = format!(
+        "<section class=\"entry{}\"><div class=\"meta\">{}<strong>{}</strong> · {} · {} priority · queued {}",
+        if flagged { " flagged" } else { "" },
+        if flagged { "FLAGGED · " } else { "" },
+        escape(&view.agent_name),
+        escape(&e.channel.to_uppercase()),
+        escape(&e.priority),
+        e.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+    );
+    if let Some(ref thread_id) = e.thread_id {
+        html.push_str(&format!(" · thread {}", escape(thread_id)));
+    }
+    html.push_str("</div>");
+
+    html.push_str(&format!("<p>To: <strong>{}</strong>", escape(&e.recipient_address)));
+    if let Some(ref name) = e.recipient_name {
+        html.push_str(&format!(" ({})", escape(name)));
+    }
+    html.push_str("</p>");
+    match view.contact {
+        Some(ref card) => {
+            html.push_str(&format!("<div class=\"card\"><strong>{}</strong>", escape(&card.name)));
+            if let Some(ref org) = card.organization {
+                html.push_str(&format!("<br>{}", escape(org)));
+            }
+            for address in card.emails.iter().chain(&card.phones) {
+                html.push_str(&format!("<br>{}", escape(address)));
+            }
+            html.push_str("</div>");
+        }
+        None => html.push_str("<div class=\"card\">Not in contacts</div>"),
+    }
+
+    if let Some(ref template_id) = e.template_id {
+        html.push_str(&format!("<p>From template <code>{}</code></p>", escape(template_id)));
+    }
+    if let Some(ref subject) = e.subject {
+        html.push_str(&format!("<p>Subject: <strong>{}</strong></p>", escape(subject)));
+    }
+    html.push_str(&format!("<pre>{}</pre>", escape(&e.body)));
+
+    if !view.filter_hits.is_empty() {
+        html.push_str("<div class=\"hits\">Content filters:<ul>");
+        for hit in &view.filter_hits {
+            html.push_str(&format!("<li>{}</li>", escape(hit)));
+        }
+        html.push_str("</ul></div>");
+    }
+    if e.risk_score.is_some() || !e.risk_reasons.is_empty() {
+        let score = e
+            .risk_score
+            .map_or("unscored".to_string(), |s| format!("{}/100", s));
+        html.push_str(&format!("<div class=\"hits\">Risk: {}<ul>", score));
+        for reason in &e.risk_reasons {
+            html.push_str(&format!("<li>{}</li>", escape(reason)));
+        }
+        html.push_str("</ul></div>");
+    }
+
+    if let Some(context) = e
+        .agent_context
+        .as_deref()
+        .and_then(|c| serde_json::from_str::<serde_json::Value>(c).ok())
+    {
+        if let Some(obj) = context.as_object() {
+            html.push_str("<div class=\"meta\">Context:<ul>");
+            for (key, value) in obj {
+                let value = value.as_str().map_or(value.to_string(), String::from);
+                html.push_str(&format!("<li>{}: {}</li>", escape(key), escape(&value)));
+            }
+            html.push_str("</ul></div>");
+        }
+    }
+
+    html.push_str(&action_form(session, &e.id, "approve", "approve", "Approve"));
+    html.push_str(&action_form(session, &e.id, "deny", "deny", "Deny"));
+    html.push_str(&format!(
+        "<a href=\"{}/queue/{}/edit\">Edit</a></section>",
+        UI_PATH,
+        escape(&e.id)
+    ));
+    html
+}
+
+/// Form for changing a message before approving it.
+pub fn edit_page(entry: &QueueEntry, session: &Session) -> String {
+    let subject = match entry.subject {
+        Some(ref subject) => format!(
+            "<p>Subject<br><input type=\"text\" name=\"subject\" value=\"{}\"></p>",
+            escape(subject)
+        ),
+        None => String::new(),
+    };
+    page(
+        "Edit message",
+        "",
+        &format!(
+            "<h1>Edit message to {}</h1><form method=\"post\" action=\"{}/queue/{}/edit\">{}{}\
+<p>Message<br><textarea name=\"body\" rows=\"12\">{}</textarea></p>\
+<button class=\"approve\">Save</button><a href=\"{}\">Cancel</a></form>",
+            escape(&entry.recipient_address),
+            UI_PATH,
+            escape(&entry.id),
+            csrf_field(session),
+            subject,
+            escape(&entry.body),
+            UI_PATH
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn request(headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: "POST".to_string(),
+            path: UI_PATH.to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: Vec::new(),
+            peer_addr: SocketAddr::from(([127, 0, 0, 1], 50000)),
+            peer_certificate: None,
+        }
+    }
+
+    #[test]
+    fn test_login_code_is_single_use() {
+        let db = Database::open_memory().unwrap();
+        let now = Utc::now();
+        assert!(!redeem_login_code(&db, "nope", now).unwrap());
+
+        let code = create_login_code(&db, now).unwrap();
+        assert!(!redeem_login_code(&db, "wrong", now).unwrap());
+        assert!(redeem_login_code(&db, &code, now).unwrap());
+        assert!(!redeem_login_code(&db, &code, now).unwrap());
+
+        let stale = create_login_code(&db, now).unwrap();
+        let later = now + Duration::minutes(LOGIN_CODE_MINUTES + 1);
+        assert!(!redeem_login_code(&db, &stale, later).unwrap());
+    }
+
+    #[test]
+    fn test_sessions_and_csrf() {
+        let sessions = WebSessions::new();
+        let now = Utc::now();
+        let id = sessions.create(now);
+        let cookie = format!("theme=dark; {}={}", SESSION_COOKIE, id);
+        let req = request(&[("cookie", cookie.as_str())]);
+
+        let session = sessions.get(&req, now).unwrap();
+        assert!(csrf_matches(&session, Some(&session.csrf_token.clone())));
+        assert!(!csrf_matches(&session, Some(&"forged".to_string())));
+        assert!(!csrf_matches(&session, None));
+
+        sessions.set_flash(&req, "Sent".to_string());
+        assert_eq!(sessions.take_flash(&req).as_deref(), Some("Sent"));
+        assert_eq!(sessions.take_flash(&req), None);
+
+        assert!(sessions.get(&req, now + Duration::hours(SESSION_HOURS + 1)).is_none());
+        assert!(sessions.get(&request(&[]), now).is_none());
+        sessions.remove(&req);
+        assert!(sessions.get(&req, now).is_none());
+    }
+
+    #[test]
+    fn test_host_and_origin_checks() {
+        assert!(is_loopback_host(&request(&[("host", "127.0.0.1:9810")])));
+        assert!(is_loopback_host(&request(&[("host", "localhost:9810")])));
+        assert!(is_loopback_host(&request(&[("host", "[::1]:9810")])));
+        assert!(!is_loopback_host(&request(&[("host", "evil.example:9810")])));
+        assert!(!is_loopback_host(&request(&[])));
+
+        let host = ("host", "127.0.0.1:9810");
+        assert!(is_same_origin(&request(&[host, ("origin", "http://127.0.0.1:9810")])));
+        assert!(!is_same_origin(&request(&[host, ("origin", "http://evil.example")])));
+        assert!(!is_same_origin(&request(&[host, ("origin", "null")])));
+        assert!(is_same_origin(&request(&[host, ("referer", "http://127.0.0.1:9810/dashboard?x=1")])));
+        assert!(!is_same_origin(&request(&[host, ("referer", "http://evil.example/127.0.0.1:9810")])));
+        assert!(!is_same_origin(&request(&[host])));
+    }
+
+    #[test]
+    fn test_dashboard_url() {
+        assert_eq!(
+            dashboard_url("http://0.0.0.0:9810").unwrap(),
+            "http://127.0.0.1:9810/gateway/ui"
+        );
+        assert_eq!(
+            dashboard_url("https://[::]:9810").unwrap(),
+            "https://[::1]:9810/gateway/ui"
+        );
+        assert!(dashboard_url("http://192.168.1.5:9810").is_err());
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<script>alert('x') & \"y\"</script>"),
+            "&lt;script&gt;alert(&#39;x&#39;) &amp; &quot;y&quot;&lt;/script&gt;"
+        );
+    }
+}
@@ -1,12 +1,37 @@
 //! Webhook notifications for gateway status changes.
 //!
-//! Sends HTTP POST requests to configured webhook URLs when message status changes.
+//! Sends HTTP POST requests to configured webhook URLs when message status changes
+//! or an inbound message arrives. Every delivery is signed with the key's webhook
+//! secret using the bridge's HMAC scheme (`X-Gateway-Signature` over `timestamp.body`)
+//! and recorded in `webhook_deliveries`; failed deliveries are retried with
+//! exponential backoff by the gateway server's delivery worker.
 
+use anyhow::Result;
 use serde::Serialize;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::cli::bridge::{compute_signature, current_timestamp, generate_secret};
+use crate::db::gateway::WebhookDelivery;
 use crate::db::Database;
 
+/// Webhook event name for queue status changes.
+pub const STATUS_EVENT: &str = "status_change";
+
+/// Attempts before a delivery is given up on.
+const MAX_DELIVERY_ATTEMPTS: i32 = 10;
+
+/// Delay before the first retry; doubles on each further failure.
+const RETRY_BASE_DELAY_SECS: i64 = 30;
+
+/// Upper bound on the delay between retries (6 hours).
+const RETRY_MAX_DELAY_SECS: i64 = 6 * 60 * 60;
+
+/// How often the delivery worker looks for due retries.
+const WORKER_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Webhook payload sent when message status changes.
 #[derive(Debug, Clone, Serialize)]
 pub struct WebhookPayload {
@@ -18,6 +43,8 @@ pub struct WebhookPayload {
     pub error_message: Option<String>,
     pub recipient: String,
     pub channel: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_id: Option<String>,
 }
 
 /// Result of a webhook delivery attempt.
@@ -27,8 +54,18 @@ pub enum WebhookResult {
     Delivered,
     /// No webhook configured for this API key.
     NoWebhook,
-    /// Delivery failed (logged but not blocking).
+    /// Delivery failed (logged and retried later if attempts remain).
     Failed(String),
+    /// Another worker is already attempting this delivery.
+    InFlight,
+}
+
+/// Why a single HTTP attempt failed.
+#[derive(Debug)]
+pub struct SendFailure {
+    /// HTTP status, if the endpoint responded
+    pub response_code: Option<u16>,
+    pub error: String,
 }
 
 /// Send webhook notification for a status change.
@@ -45,67 +82,214 @@ pub fn notify_status_change(
     sent_at: Option<&str>,
     error_message: Option<&str>,
 ) -> WebhookResult {
-    // Get webhook URL for this API key
-    let webhook_url = match db.get_api_key_webhook(api_key_id) {
-        Ok(Some(url)) => url,
-        Ok(None) => return WebhookResult::NoWebhook,
-        Err(e) => {
-            eprintln!("Warning: Failed to get webhook URL: {}", e);
-            return WebhookResult::Failed(e.to_string());
-        }
-    };
-
-    // Build payload
+    // Build payload; batch messages carry their batch ID so agents can group
+    // them, and messages addressed by contact report the contact reference
+    let entry = db.get_queue_entry(action_id).ok().flatten();
+    let batch_id = entry.as_ref().and_then(|e| e.batch_id.clone());
+    let recipient = entry.and_then(|e| e.recipient_contact).unwrap_or_else(|| recipient.to_string());
     let payload = WebhookPayload {
         action_id: action_id.to_string(),
         status: status.to_string(),
         sent_at: sent_at.map(String::from),
         error_message: error_message.map(String::from),
-        recipient: recipient.to_string(),
+        recipient,
         channel: channel.to_string(),
+        batch_id,
+    };
+
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => return WebhookResult::Failed(e.to_string()),
     };
 
-    // Send webhook (blocking HTTP call with timeout)
-    match send_webhook(&webhook_url, &payload) {
-        Ok(()) => WebhookResult::Delivered,
+    let result = deliver_event(db, api_key_id, STATUS_EVENT, Some(action_id), &body);
+    if let WebhookResult::Failed(ref e) = result {
+        eprintln!(
+            "Warning: Webhook delivery failed for action {}: {}",
+            action_id, e
+        );
+    }
+    result
+}
+
+/// Record a webhook delivery for an API key and make the first attempt.
+///
+/// Returns `NoWebhook` without recording anything if the key has no webhook URL.
+pub fn deliver_event(
+    db: &Database,
+    api_key_id: &str,
+    event: &str,
+    action_id: Option<&str>,
+    payload: &str,
+) -> WebhookResult {
+    match db.get_api_key_webhook(api_key_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return WebhookResult::NoWebhook,
         Err(e) => {
-            eprintln!(
-                "Warning: Webhook delivery failed for action {}: {}",
-                action_id, e
-            );
-            WebhookResult::Failed(e)
+            eprintln!("Warning: Failed to get webhook URL: {}", e);
+            return WebhookResult::Failed(e.to_string());
+        }
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let delivery = db
+        .insert_webhook_delivery(&id, api_key_id, event, action_id, payload)
+        .and_then(|()| db.get_webhook_delivery(&id));
+
+    match delivery {
+        Ok(Some(delivery)) => attempt_delivery(db, &delivery, send_webhook),
+        Ok(None) => WebhookResult::Failed("Delivery record missing".to_string()),
+        Err(e) => WebhookResult::Failed(e.to_string()),
+    }
+}
+
+/// Make one attempt at a recorded delivery and update its retry schedule.
+///
+/// The delivery is claimed first, so a due retry is only ever sent by one worker.
+/// `send` receives the current webhook URL, the key's signing secret and the delivery.
+pub fn attempt_delivery<F>(db: &Database, delivery: &WebhookDelivery, send: F) -> WebhookResult
+where
+    F: FnOnce(&str, &str, &WebhookDelivery) -> Result<u16, SendFailure>,
+{
+    let target = db.get_api_key_webhook(&delivery.api_key_id).and_then(|url| {
+        url.map(|url| ensure_webhook_secret(db, &delivery.api_key_id).map(|secret| (url, secret)))
+            .transpose()
+    });
+
+    let outcome = match target {
+        Ok(Some((url, secret))) => match db.claim_webhook_delivery(&delivery.id) {
+            Ok(true) => send(&url, &secret, delivery),
+            Ok(false) => return WebhookResult::InFlight,
+            Err(e) => return WebhookResult::Failed(e.to_string()),
+        },
+        Ok(None) => {
+            // Webhook removed since the event: nothing left to deliver to
+            let error = "No webhook configured".to_string();
+            let _ = db.mark_webhook_attempt_failed(&delivery.id, None, &error, None);
+            return WebhookResult::Failed(error);
+        }
+        Err(e) => return WebhookResult::Failed(e.to_string()),
+    };
+
+    match outcome {
+        Ok(code) => {
+            if let Err(e) = db.mark_webhook_delivered(&delivery.id, code) {
+                eprintln!("Warning: Failed to record webhook delivery: {}", e);
+            }
+            WebhookResult::Delivered
+        }
+        Err(failure) => {
+            let attempts = delivery.attempts + 1;
+            let next_attempt_at = (attempts < MAX_DELIVERY_ATTEMPTS)
+                .then(|| chrono::Utc::now() + retry_delay(attempts));
+            if let Err(e) = db.mark_webhook_attempt_failed(
+                &delivery.id,
+                failure.response_code,
+                &failure.error,
+                next_attempt_at,
+            ) {
+                eprintln!("Warning: Failed to record webhook delivery: {}", e);
+            }
+            WebhookResult::Failed(failure.error)
+        }
+    }
+}
+
+/// Delay before retrying after `attempts` failed attempts.
+pub fn retry_delay(attempts: i32) -> chrono::Duration {
+    let exponent = (attempts.max(1) - 1).min(20) as u32;
+    let secs = RETRY_BASE_DELAY_SECS
+        .saturating_mul(2i64.saturating_pow(exponent))
+        .min(RETRY_MAX_DELAY_SECS);
+    chrono::Duration::seconds(secs)
+}
+
+/// Retry every delivery whose next attempt is due. Returns the number delivered.
+pub fn process_due_deliveries(db: &Database) -> Result<usize> {
+    let mut delivered = 0;
+    for delivery in db.list_due_webhook_deliveries(50)? {
+        if let WebhookResult::Delivered = attempt_delivery(db, &delivery, send_webhook) {
+            delivered += 1;
         }
     }
+    Ok(delivered)
 }
 
-/// Send HTTP POST to webhook URL.
-fn send_webhook(url: &str, payload: &WebhookPayload) -> Result<(), String> {
+/// Retry failed webhook deliveries until shutdown.
+pub fn run_delivery_worker(db_path: PathBuf, shutdown: Arc<AtomicBool>) {
+    let mut last_run: Option<Instant> = None;
+    while !shutdown.load(Ordering::SeqCst) {
+        if last_run.is_some_and(|t| t.elapsed() < WORKER_INTERVAL) {
+            std::thread::sleep(Duration::from_millis(200));
+            continue;
+        }
+        last_run = Some(Instant::now());
+
+        let result = Database::open_at(db_path.clone()).and_then(|db| process_due_deliveries(&db));
+        if let Err(e) = result {
+            eprintln!("Webhook retry error: {}", e);
+        }
+    }
+}
+
+/// Get the webhook signing secret for an API key, generating one if missing.
+pub fn ensure_webhook_secret(db: &Database, api_key_id: &str) -> Result<String> {
+    if let Some(secret) = db.get_api_key_webhook_secret(api_key_id)? {
+        return Ok(secret);
+    }
+    let secret = generate_secret();
+    db.set_api_key_webhook_secret(api_key_id, &secret)?;
+    Ok(secret)
+}
+
+/// Send a signed HTTP POST to a webhook URL.
+///
+/// Adds `X-Gateway-Event`, `X-Gateway-Delivery`, `X-Gateway-Timestamp` and
+/// `X-Gateway-Signature` (hex HMAC-SHA256 of `timestamp.body`) so agents can
+/// verify the sender and ignore retries they've already processed.
+pub fn send_webhook(url: &str, secret: &str, delivery: &WebhookDelivery) -> Result<u16, SendFailure> {
+    let fail = |response_code, error: String| SendFailure {
+        response_code,
+        error,
+    };
+
     // Validate URL format
     if !url.starts_with("http://") && !url.starts_with("https://") {
-        return Err("Invalid webhook URL: must start with http:// or https://".to_string());
+        return Err(fail(
+            None,
+            "Invalid webhook URL: must start with http:// or https://".to_string(),
+        ));
     }
 
-    let json_body = serde_json::to_string(payload).map_err(|e| e.to_string())?;
+    let timestamp = current_timestamp();
+    let signature = compute_signature(secret, &timestamp, delivery.payload.as_bytes());
 
     // Use blocking reqwest client with timeout
     let client = reqwest::blocking::Client::builder()
         .timeout(Duration::from_secs(10))
         .build()
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| fail(None, e.to_string()))?;
 
     let response = client
         .post(url)
         .header("Content-Type", "application/json")
         .header("User-Agent", "contactcmd-gateway/1.0")
-        .body(json_body)
+        .header("X-Gateway-Event", &delivery.event)
+        .header("X-Gateway-Delivery", &delivery.id)
+        .header("X-Gateway-Timestamp", &timestamp)
+        .header("X-Gateway-Signature", &signature)
+        .body(delivery.payload.clone())
         .send()
-        .map_err(|e| format!("HTTP request failed: {}", e))?;
+        .map_err(|e| fail(None, format!("HTTP request failed: {}", e)))?;
 
     let status = response.status();
     if status.is_success() {
-        Ok(())
+        Ok(status.as_u16())
     } else {
-        Err(format!("Webhook returned HTTP {}", status.as_u16()))
+        Err(fail(
+            Some(status.as_u16()),
+            format!("Webhook returned HTTP {}", status.as_u16()),
+        ))
     }
 }
 
@@ -122,6 +306,7 @@ mod tests {
             error_message: None,
             recipient: "test@example.com".to_string(),
             channel: "email".to_string(),
+            batch_id: None,
         };
 
         let json = serde_json::to_string(&payload).unwrap();
@@ -142,6 +327,7 @@ mod tests {
             error_message: Some("Connection refused".to_string()),
             recipient: "+15551234567".to_string(),
             channel: "sms".to_string(),
+            batch_id: None,
         };
 
         let json = serde_json::to_string(&payload).unwrap();
@@ -181,11 +367,105 @@ mod tests {
             error_message: None,
             recipient: "test@example.com".to_string(),
             channel: "email".to_string(),
+            batch_id: None,
+        };
+
+        let delivery = WebhookDelivery {
+            id: "dlv-1".to_string(),
+            api_key_id: "key-1".to_string(),
+            event: STATUS_EVENT.to_string(),
+            action_id: Some(payload.action_id.clone()),
+            payload: serde_json::to_string(&payload).unwrap(),
+            status: "pending".to_string(),
+            attempts: 0,
+            last_response_code: None,
+            last_error: None,
+            next_attempt_at: None,
+            created_at: chrono::Utc::now(),
+            delivered_at: None,
         };
 
         // Test invalid URL
-        let result = send_webhook("not-a-valid-url", &payload);
+        let result = send_webhook("not-a-valid-url", "secret", &delivery);
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("must start with http"));
+        assert!(result.unwrap_err().error.contains("must start with http"));
+    }
+
+    #[test]
+    fn test_ensure_webhook_secret_is_stable() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "hash123", "gw_abc")
+            .unwrap();
+
+        let first = ensure_webhook_secret(&db, "key-1").unwrap();
+        assert_eq!(first.len(), 64);
+        assert_eq!(ensure_webhook_secret(&db, "key-1").unwrap(), first);
+    }
+
+    #[test]
+    fn test_retry_delay_backoff() {
+        assert_eq!(retry_delay(1).num_seconds(), 30);
+        assert_eq!(retry_delay(2).num_seconds(), 60);
+        assert_eq!(retry_delay(4).num_seconds(), 240);
+        assert_eq!(retry_delay(40).num_seconds(), RETRY_MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn test_attempt_delivery_schedules_retry_then_gives_up() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "hash123", "gw_abc")
+            .unwrap();
+        db.set_api_key_webhook("key-1", Some("https://agent.example.com/hook"))
+            .unwrap();
+        db.insert_webhook_delivery("dlv-1", "key-1", STATUS_EVENT, Some("msg-1"), "{}")
+            .unwrap();
+
+        let unavailable = |_: &str, _: &str, _: &WebhookDelivery| {
+            Err(SendFailure {
+                response_code: Some(503),
+                error: "Webhook returned HTTP 503".to_string(),
+            })
+        };
+
+        let delivery = db.get_webhook_delivery("dlv-1").unwrap().unwrap();
+        let result = attempt_delivery(&db, &delivery, unavailable);
+        assert!(matches!(result, WebhookResult::Failed(_)));
+        let delivery = db.get_webhook_delivery("dlv-1").unwrap().unwrap();
+        assert_eq!(delivery.status, "pending");
+        assert!(delivery.next_attempt_at.unwrap() > chrono::Utc::now());
+        // Not due yet, so it isn't attempted again
+        assert!(matches!(attempt_delivery(&db, &delivery, unavailable), WebhookResult::InFlight));
+
+        // Last allowed attempt fails: no further retries
+        db.reset_webhook_delivery("dlv-1").unwrap();
+        let exhausted = WebhookDelivery {
+            attempts: MAX_DELIVERY_ATTEMPTS - 1,
+            ..db.get_webhook_delivery("dlv-1").unwrap().unwrap()
+        };
+        attempt_delivery(&db, &exhausted, unavailable);
+        let delivery = db.get_webhook_delivery("dlv-1").unwrap().unwrap();
+        assert_eq!(delivery.status, "failed");
+        assert!(delivery.next_attempt_at.is_none());
+    }
+
+    #[test]
+    fn test_attempt_delivery_signs_with_key_secret() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "hash123", "gw_abc")
+            .unwrap();
+        db.set_api_key_webhook("key-1", Some("https://agent.example.com/hook"))
+            .unwrap();
+        let secret = ensure_webhook_secret(&db, "key-1").unwrap();
+        db.insert_webhook_delivery("dlv-1", "key-1", STATUS_EVENT, None, "{}")
+            .unwrap();
+
+        let delivery = db.get_webhook_delivery("dlv-1").unwrap().unwrap();
+        let result = attempt_delivery(&db, &delivery, |url, used_secret, _| {
+            assert_eq!(url, "https://agent.example.com/hook");
+            assert_eq!(used_secret, secret);
+            Ok(200)
+        });
+        assert!(matches!(result, WebhookResult::Delivered));
+        assert_eq!(db.get_webhook_delivery("dlv-1").unwrap().unwrap().status, "delivered");
     }
 }
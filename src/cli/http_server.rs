@@ -0,0 +1,459 @@
+//! Async HTTP/1.1 server shared by the gateway and bridge.
+//!
+//! Connections are served by hyper on a tokio runtime; each request runs on
+//! the blocking pool so handlers can use rusqlite and other synchronous APIs.
+//! The server enforces a connection limit, a request body size limit and
+//! timeouts, and drains in-flight requests on shutdown. Connections can
+//! optionally be wrapped in TLS.
+
+use anyhow::Result;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Semaphore;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+/// A fully read HTTP request.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    pub peer_addr: SocketAddr,
+    /// DER client certificate presented during the TLS handshake
+    pub peer_certificate: Option<Vec<u8>>,
+}
+
+/// Response produced by a `Handler`.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    /// Extra headers, e.g. `Location` or `Set-Cookie`
+    pub headers: Vec<(&'static str, String)>,
+}
+
+impl HttpResponse {
+    pub fn json<T: serde::Serialize>(status: u16, body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json",
+                body,
+                headers: Vec::new(),
+            },
+            Err(e) => Self::text(500, &format!("Failed to serialize response: {}", e)),
+        }
+    }
+
+    pub fn text(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: message.as_bytes().to_vec(),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn html(status: u16, page: String) -> Self {
+        Self {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body: page.into_bytes(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// 303 See Other, so a form POST is followed by a GET.
+    pub fn redirect(location: &str) -> Self {
+        Self::text(303, "").with_header("location", location.to_string())
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn into_hyper(self) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(Bytes::from(self.body)));
+        *response.status_mut() =
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        if let Ok(value) = self.content_type.parse() {
+            response
+                .headers_mut()
+                .insert(hyper::header::CONTENT_TYPE, value);
+        }
+        for (name, value) in self.headers {
+            if let Ok(value) = value.parse() {
+                response.headers_mut().append(name, value);
+            }
+        }
+        response
+    }
+}
+
+/// Synchronous request handler.
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, request: HttpRequest) -> HttpResponse;
+
+    /// Response for errors raised before the handler runs (body too large, timeouts).
+    fn error_response(&self, status: u16, message: &str) -> HttpResponse {
+        HttpResponse::text(status, message)
+    }
+}
+
+/// Resource limits for a server.
+#[derive(Debug, Clone)]
+pub struct ServerLimits {
+    /// Maximum simultaneous connections; further clients wait to be accepted
+    pub max_connections: usize,
+    /// Maximum request body size in bytes
+    pub max_body_bytes: usize,
+    /// Time allowed to receive request headers (also the keep-alive idle timeout)
+    pub header_timeout: Duration,
+    /// Time allowed to receive the body and to run the handler
+    pub request_timeout: Duration,
+    /// Time to wait for in-flight requests on shutdown
+    pub shutdown_grace: Duration,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 64,
+            max_body_bytes: 1024 * 1024,
+            header_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            shutdown_grace: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Who is on the other end of a connection.
+#[derive(Debug, Clone)]
+struct Peer {
+    addr: SocketAddr,
+    certificate: Option<Vec<u8>>,
+}
+
+/// Serve requests on `listener` until `shutdown` is set (blocking).
+///
+/// With `tls`, every connection must complete a TLS handshake first.
+pub fn serve<H: Handler>(
+    listener: std::net::TcpListener,
+    handler: Arc<H>,
+    limits: ServerLimits,
+    tls: Option<Arc<ServerConfig>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<()> {
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(run(listener, handler, limits, tls, shutdown))
+}
+
+async fn run<H: Handler>(
+    listener: std::net::TcpListener,
+    handler: Arc<H>,
+    limits: ServerLimits,
+    tls: Option<Arc<ServerConfig>>,
+    shutdown: Arc<AtomicBool>,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let acceptor = tls.map(TlsAcceptor::from);
+    let connections = Arc::new(Semaphore::new(limits.max_connections.max(1)));
+    let graceful = GracefulShutdown::new();
+
+    let mut builder = http1::Builder::new();
+    builder
+        .timer(TokioTimer::new())
+        .header_read_timeout(limits.header_timeout)
+        .keep_alive(true);
+
+    loop {
+        let permit = tokio::select! {
+            _ = wait_for(&shutdown) => break,
+            permit = connections.clone().acquire_owned() => permit?,
+        };
+        let (stream, peer_addr) = tokio::select! {
+            _ = wait_for(&shutdown) => break,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    eprintln!("Accept error: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        let handler = handler.clone();
+        let limits = limits.clone();
+        let builder = builder.clone();
+        let acceptor = acceptor.clone();
+        let watcher = graceful.watcher();
+
+        tokio::spawn(async move {
+            let result = match acceptor {
+                None => {
+                    let peer = Peer {
+                        addr: peer_addr,
+                        certificate: None,
+                    };
+                    serve_connection(&builder, watcher, stream, peer, handler, limits).await
+                }
+                Some(acceptor) => {
+                    match tokio::time::timeout(limits.header_timeout, acceptor.accept(stream)).await
+                    {
+                        Ok(Ok(stream)) => {
+                            let peer = Peer {
+                                addr: peer_addr,
+                                certificate: stream
+                                    .get_ref()
+                                    .1
+                                    .peer_certificates()
+                                    .and_then(|certs| certs.first())
+                                    .map(|cert| cert.to_vec()),
+                            };
+                            serve_connection(&builder, watcher, stream, peer, handler, limits)
+                                .await
+                        }
+                        Ok(Err(e)) => {
+                            eprintln!("TLS handshake with {} failed: {}", peer_addr, e);
+                            Ok(())
+                        }
+                        Err(_) => Ok(()),
+                    }
+                }
+            };
+            if let Err(e) = result {
+                if !e.is_incomplete_message() && !e.is_timeout() {
+                    eprintln!("Connection error from {}: {}", peer_addr, e);
+                }
+            }
+            drop(permit);
+        });
+    }
+
+    drop(listener);
+    tokio::select! {
+        _ = graceful.shutdown() => {}
+        _ = tokio::time::sleep(limits.shutdown_grace) => {
+            eprintln!("Timed out waiting for open connections to finish");
+        }
+    }
+    Ok(())
+}
+
+async fn serve_connection<H, I>(
+    builder: &http1::Builder,
+    watcher: Watcher,
+    stream: I,
+    peer: Peer,
+    handler: Arc<H>,
+    limits: ServerLimits,
+) -> hyper::Result<()>
+where
+    H: Handler,
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let peer = Arc::new(peer);
+    let service = service_fn(move |req| {
+        handle_request(req, peer.clone(), handler.clone(), limits.clone())
+    });
+    watcher
+        .watch(builder.serve_connection(TokioIo::new(stream), service))
+        .await
+}
+
+async fn wait_for(flag: &AtomicBool) {
+    while !flag.load(Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+async fn handle_request<H: Handler>(
+    req: Request<Incoming>,
+    peer: Arc<Peer>,
+    handler: Arc<H>,
+    limits: ServerLimits,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let request = match read_request(req, &peer, &limits).await {
+        Ok(request) => request,
+        Err((status, message)) => return Ok(handler.error_response(status, message).into_hyper()),
+    };
+
+    let task = {
+        let handler = handler.clone();
+        tokio::task::spawn_blocking(move || handler.handle(request))
+    };
+    let response = match tokio::time::timeout(limits.request_timeout, task).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(_)) => handler.error_response(500, "Internal Server Error"),
+        // The handler keeps running and may still commit, so this isn't a
+        // failure the client can blindly retry
+        Err(_) => handler.error_response(
+            504,
+            "Request timed out and may still complete; check its outcome before retrying",
+        ),
+    };
+    Ok(response.into_hyper())
+}
+
+async fn read_request(
+    req: Request<Incoming>,
+    peer: &Peer,
+    limits: &ServerLimits,
+) -> Result<HttpRequest, (u16, &'static str)> {
+    const TOO_LARGE: (u16, &str) = (413, "Request body too large");
+
+    let (parts, body) = req.into_parts();
+
+    let declared_len = parts
+        .headers
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_len.is_some_and(|len| len > limits.max_body_bytes) {
+        return Err(TOO_LARGE);
+    }
+
+    let collected = tokio::time::timeout(
+        limits.request_timeout,
+        Limited::new(body, limits.max_body_bytes).collect(),
+    )
+    .await
+    .map_err(|_| (408, "Timed out reading request body"))?;
+    let body = match collected {
+        Ok(collected) => collected.to_bytes().to_vec(),
+        Err(e) if e.is::<http_body_util::LengthLimitError>() => return Err(TOO_LARGE),
+        Err(_) => return Err((400, "Failed to read request body")),
+    };
+
+    let headers = parts
+        .headers
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|v| (name.as_str().to_string(), v.to_string()))
+        })
+        .collect();
+
+    Ok(HttpRequest {
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        headers,
+        body,
+        peer_addr: peer.addr,
+        peer_certificate: peer.certificate.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    struct Echo;
+
+    impl Handler for Echo {
+        fn handle(&self, request: HttpRequest) -> HttpResponse {
+            if request.path == "/slow" {
+                std::thread::sleep(Duration::from_millis(500));
+            }
+            HttpResponse::text(
+                200,
+                &format!("{} {} {}", request.method, request.path, request.body.len()),
+            )
+        }
+    }
+
+    fn start(limits: ServerLimits) -> (SocketAddr, Arc<AtomicBool>, std::thread::JoinHandle<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let flag = shutdown.clone();
+        let handle =
+            std::thread::spawn(move || serve(listener, Arc::new(Echo), limits, None, flag).unwrap());
+        (addr, shutdown, handle)
+    }
+
+    fn roundtrip(addr: SocketAddr, raw: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    }
+
+    #[test]
+    fn test_serves_chunked_and_limits_body() {
+        let limits = ServerLimits {
+            max_body_bytes: 16,
+            ..ServerLimits::default()
+        };
+        let (addr, shutdown, handle) = start(limits);
+
+        let response = roundtrip(
+            addr,
+            "POST /echo?x=1 HTTP/1.1\r\nHost: t\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("POST /echo 5"));
+
+        let response = roundtrip(
+            addr,
+            "POST /echo HTTP/1.1\r\nHost: t\r\nContent-Length: 100\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+
+        shutdown.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_slow_request_does_not_block_others() {
+        let (addr, shutdown, handle) = start(ServerLimits::default());
+
+        let slow = std::thread::spawn(move || {
+            roundtrip(
+                addr,
+                "GET /slow HTTP/1.1\r\nHost: t\r\nConnection: close\r\n\r\n",
+            )
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        let fast = roundtrip(
+            addr,
+            "GET /fast HTTP/1.1\r\nHost: t\r\nConnection: close\r\n\r\n",
+        );
+        assert!(fast.ends_with("GET /fast 0"));
+        assert!(started.elapsed() < Duration::from_millis(400));
+
+        assert!(slow.join().unwrap().ends_with("GET /slow 0"));
+        shutdown.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+}
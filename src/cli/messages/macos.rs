@@ -308,6 +308,92 @@ pub fn get_recent_message_handles(days: u32) -> Result<Vec<RecentHandle>> {
     Ok(results)
 }
 
+/// A received message in chat.db, identified by its ROWID
+#[derive(Debug, Clone)]
+pub struct IncomingMessage {
+    pub rowid: i64,
+    /// Message text (empty for attachment-only messages)
+    pub text: String,
+    pub date: DateTime<Local>,
+    pub handle: String,
+    pub service: DetectedService,
+}
+
+/// Get the highest message ROWID in chat.db (0 if the database is unavailable)
+pub fn get_max_message_rowid() -> Result<i64> {
+    let conn = match open_messages_db()? {
+        Some(c) => c,
+        None => return Ok(0),
+    };
+
+    let max_rowid: Option<i64> = conn
+        .query_row("SELECT MAX(ROWID) FROM message", [], |row| row.get(0))
+        .unwrap_or(None);
+
+    Ok(max_rowid.unwrap_or(0))
+}
+
+/// Query received messages (not from me) with a ROWID greater than `after_rowid`, oldest first
+pub fn get_incoming_messages_since(after_rowid: i64, limit: u32) -> Result<Vec<IncomingMessage>> {
+    let conn = match open_messages_db()? {
+        Some(c) => c,
+        None => return Ok(vec![]),
+    };
+
+    let query = r#"SELECT m.ROWID, m.text, m.attributedBody, m.date, h.id, m.service
+           FROM message m
+           INNER JOIN handle h ON m.handle_id = h.ROWID
+           WHERE m.ROWID > ? AND m.is_from_me = 0
+           ORDER BY m.ROWID ASC
+           LIMIT ?"#;
+
+    let mut stmt = match conn.prepare(query) {
+        Ok(s) => s,
+        Err(e) => {
+            let err_str = e.to_string().to_lowercase();
+            if err_str.contains("locked") || err_str.contains("encrypted") {
+                return Ok(vec![]);
+            }
+            return Err(e).context("Failed to query incoming messages");
+        }
+    };
+
+    let rows = stmt.query_map(rusqlite::params![after_rowid, limit], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<Vec<u8>>>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+
+    let mut messages = Vec::new();
+    for row_result in rows {
+        let (rowid, text, attributed_body, date, handle, service_name) = match row_result {
+            Ok(r) => r,
+            Err(_) => continue,
+        };
+
+        let service = match service_name.map(|s| s.to_lowercase()) {
+            Some(s) if s.contains("imessage") => DetectedService::IMessage,
+            Some(s) if s.contains("sms") => DetectedService::Sms,
+            _ => DetectedService::Unknown,
+        };
+
+        messages.push(IncomingMessage {
+            rowid,
+            text: extract_message_text(text, attributed_body).unwrap_or_default(),
+            date: apple_timestamp_to_datetime(date),
+            handle,
+            service,
+        });
+    }
+
+    Ok(messages)
+}
+
 /// Make phones_match public for use by other modules
 pub fn phones_match_public(phone1: &str, phone2: &str) -> bool {
     phones_match(phone1, phone2)
@@ -6,9 +6,10 @@
 
 #[cfg(target_os = "macos")]
 mod macos;
+pub mod source;
 
 #[cfg(target_os = "macos")]
-pub use macos::{get_last_message_for_phones, get_last_message_for_handles, get_messages_for_phones, get_messages_for_handles, run_messages, LastMessage, detect_service_for_phone, DetectedService, get_recent_message_handles, RecentHandle, phones_match_public};
+pub use macos::{get_last_message_for_phones, get_last_message_for_handles, get_messages_for_phones, get_messages_for_handles, run_messages, LastMessage, detect_service_for_phone, DetectedService, get_recent_message_handles, RecentHandle, phones_match_public, get_max_message_rowid, get_incoming_messages_since, IncomingMessage};
 
 #[cfg(not(target_os = "macos"))]
 mod stub {
@@ -79,7 +80,27 @@ mod stub {
     pub fn phones_match_public(_phone1: &str, _phone2: &str) -> bool {
         false
     }
+
+    /// A received message in chat.db, identified by its ROWID
+    #[derive(Debug, Clone)]
+    pub struct IncomingMessage {
+        pub rowid: i64,
+        pub text: String,
+        pub date: chrono::DateTime<chrono::Local>,
+        pub handle: String,
+        pub service: DetectedService,
+    }
+
+    /// Stub implementation for non-macOS platforms - always returns 0
+    pub fn get_max_message_rowid() -> Result<i64> {
+        Ok(0)
+    }
+
+    /// Stub implementation for non-macOS platforms - always returns empty
+    pub fn get_incoming_messages_since(_after_rowid: i64, _limit: u32) -> Result<Vec<IncomingMessage>> {
+        Ok(vec![])
+    }
 }
 
 #[cfg(not(target_os = "macos"))]
-pub use stub::{get_last_message_for_phones, get_last_message_for_handles, get_messages_for_phones, get_messages_for_handles, run_messages, LastMessage, detect_service_for_phone, DetectedService, get_recent_message_handles, RecentHandle, phones_match_public};
+pub use stub::{get_last_message_for_phones, get_last_message_for_handles, get_messages_for_phones, get_messages_for_handles, run_messages, LastMessage, detect_service_for_phone, DetectedService, get_recent_message_handles, RecentHandle, phones_match_public, get_max_message_rowid, get_incoming_messages_since, IncomingMessage};
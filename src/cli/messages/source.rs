@@ -0,0 +1,452 @@
+//! Pluggable sources of newly received messages.
+//!
+//! A `MessageSource` is polled for messages that arrived since the last poll.
+//! The chat.db reader is the macOS backend; the drop directory and the JSONL
+//! spool let other platforms (and tests) inject messages as JSON.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::{get_incoming_messages_since, get_max_message_rowid, DetectedService};
+use crate::db::Database;
+
+/// Maximum number of messages read from chat.db per poll.
+const CHATDB_BATCH_SIZE: u32 = 100;
+
+/// Maximum number of messages read from the spool per poll.
+const SPOOL_BATCH_SIZE: usize = 100;
+
+/// A message received from a contact.
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    /// Source-specific unique ID
+    pub id: String,
+    /// Sender phone number or email address
+    pub sender: String,
+    pub content: String,
+    /// Channel name: "imessage", "sms" or "email"
+    pub channel: String,
+    pub received_at: DateTime<Utc>,
+}
+
+/// A source of newly received messages.
+pub trait MessageSource: Send {
+    /// Short name for logging
+    fn name(&self) -> &str;
+
+    /// Return messages received since the previous poll, oldest first.
+    fn poll(&mut self) -> Result<Vec<ReceivedMessage>>;
+
+    /// Resume position to persist across restarts, if the source tracks one.
+    fn cursor(&self) -> Option<String>;
+}
+
+/// Reads new incoming messages from the macOS Messages database.
+///
+/// On other platforms the underlying reader is a stub and never yields messages.
+pub struct ChatDbSource {
+    last_rowid: i64,
+}
+
+impl ChatDbSource {
+    /// Create a source resuming after `cursor` (a chat.db ROWID).
+    ///
+    /// Without a cursor, starts at the newest message so history isn't replayed.
+    pub fn new(cursor: Option<&str>) -> Result<Self> {
+        let last_rowid = match cursor.and_then(|c| c.parse().ok()) {
+            Some(rowid) => rowid,
+            None => get_max_message_rowid()?,
+        };
+        Ok(Self { last_rowid })
+    }
+}
+
+impl MessageSource for ChatDbSource {
+    fn name(&self) -> &str {
+        "chatdb"
+    }
+
+    fn poll(&mut self) -> Result<Vec<ReceivedMessage>> {
+        let incoming = get_incoming_messages_since(self.last_rowid, CHATDB_BATCH_SIZE)?;
+
+        let mut messages = Vec::with_capacity(incoming.len());
+        for msg in incoming {
+            // Advance past attachment-only messages too, so they aren't rescanned
+            self.last_rowid = self.last_rowid.max(msg.rowid);
+            if msg.text.trim().is_empty() {
+                continue;
+            }
+
+            let channel = match msg.service {
+                DetectedService::Sms => "sms",
+                DetectedService::IMessage | DetectedService::Unknown => "imessage",
+            };
+
+            messages.push(ReceivedMessage {
+                id: format!("chatdb-{}", msg.rowid),
+                sender: msg.handle,
+                content: msg.text,
+                channel: channel.to_string(),
+                received_at: msg.date.with_timezone(&Utc),
+            });
+        }
+
+        Ok(messages)
+    }
+
+    fn cursor(&self) -> Option<String> {
+        Some(self.last_rowid.to_string())
+    }
+}
+
+/// JSON file format accepted by `DropDirSource`.
+#[derive(Debug, Deserialize)]
+struct DropFile {
+    #[serde(default)]
+    id: Option<String>,
+    sender: String,
+    content: String,
+    #[serde(default = "default_drop_channel")]
+    channel: String,
+    #[serde(default)]
+    received_at: Option<DateTime<Utc>>,
+}
+
+fn default_drop_channel() -> String {
+    "imessage".to_string()
+}
+
+impl DropFile {
+    fn into_message(self, default_id: impl FnOnce() -> String) -> ReceivedMessage {
+        ReceivedMessage {
+            id: self.id.unwrap_or_else(default_id),
+            sender: self.sender,
+            content: self.content,
+            channel: self.channel.to_lowercase(),
+            received_at: self.received_at.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+/// Reads messages from `*.json` files dropped into a directory.
+///
+/// Each file holds one message: `{"sender": "...", "content": "...", "channel": "sms"}`.
+/// Processed files move to `processed/`, unparseable ones to `rejected/`.
+pub struct DropDirSource {
+    dir: PathBuf,
+}
+
+impl DropDirSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create drop directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn move_to(&self, file: &Path, subdir: &str) -> Result<()> {
+        let target_dir = self.dir.join(subdir);
+        fs::create_dir_all(&target_dir)?;
+        if let Some(name) = file.file_name() {
+            fs::rename(file, target_dir.join(name))?;
+        }
+        Ok(())
+    }
+}
+
+impl MessageSource for DropDirSource {
+    fn name(&self) -> &str {
+        "dropdir"
+    }
+
+    fn poll(&mut self) -> Result<Vec<ReceivedMessage>> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+
+        let mut messages = Vec::new();
+        for file in files {
+            let parsed = fs::read_to_string(&file)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| serde_json::from_str::<DropFile>(&content).map_err(Into::into));
+
+            match parsed {
+                Ok(drop) => {
+                    messages.push(drop.into_message(|| {
+                        file.file_stem()
+                            .map(|s| s.to_string_lossy().to_string())
+                            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+                    }));
+                    self.move_to(&file, "processed")?;
+                }
+                Err(e) => {
+                    eprintln!("Warning: Rejecting {}: {}", file.display(), e);
+                    self.move_to(&file, "rejected")?;
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn cursor(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Reads messages appended to `*.jsonl` files in a spool directory.
+///
+/// Each line holds one message in the drop file format. Files are read in
+/// name order and left untouched; once a later file appears, earlier ones are
+/// considered finished. A trailing line without a newline is still being
+/// written and is picked up on a later poll.
+///
+/// Writers must name new files so they sort after every existing one (e.g. a
+/// UTC timestamp such as `20261018T093000.jsonl`): only the current file's
+/// name is kept, so a file that sorts before it is never read.
+pub struct SpoolSource {
+    dir: PathBuf,
+    /// File being read and the byte offset reached in it
+    position: Option<(String, u64)>,
+}
+
+impl SpoolSource {
+    /// Create a source resuming after `cursor` (`<file>:<offset>`), or from
+    /// the start of the spool.
+    pub fn new(dir: impl Into<PathBuf>, cursor: Option<&str>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create spool directory {}", dir.display()))?;
+        let position = cursor
+            .and_then(|c| c.rsplit_once(':'))
+            .and_then(|(file, offset)| Some((file.to_string(), offset.parse().ok()?)));
+        Ok(Self { dir, position })
+    }
+
+    /// Spool files not yet finished, in read order.
+    fn files(&self) -> Result<Vec<String>> {
+        let mut files: Vec<String> = fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "jsonl"))
+            .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            // Earlier names are finished, or arrived too late to be read
+            .filter(|name| self.position.as_ref().map_or(true, |(file, _)| name >= file))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl MessageSource for SpoolSource {
+    fn name(&self) -> &str {
+        "spool"
+    }
+
+    fn poll(&mut self) -> Result<Vec<ReceivedMessage>> {
+        let mut messages = Vec::new();
+        for name in self.files()? {
+            let mut offset = match self.position {
+                Some((ref file, offset)) if *file == name => offset,
+                _ => 0,
+            };
+            let path = self.dir.join(&name);
+            let mut reader = BufReader::new(fs::File::open(&path)?);
+            reader.seek(SeekFrom::Start(offset))?;
+
+            let mut line = String::new();
+            while messages.len() < SPOOL_BATCH_SIZE {
+                line.clear();
+                let read = reader.read_line(&mut line)?;
+                if read == 0 || !line.ends_with('\n') {
+                    break;
+                }
+                let line_offset = offset;
+                offset += read as u64;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match serde_json::from_str::<DropFile>(&line) {
+                    Ok(drop) => messages.push(drop.into_message(|| {
+                        let stem = name.trim_end_matches(".jsonl");
+                        format!("{}-{}", stem, line_offset)
+                    })),
+                    Err(e) => eprintln!(
+                        "Warning: Skipping {} at byte {}: {}",
+                        path.display(),
+                        line_offset,
+                        e
+                    ),
+                }
+            }
+
+            self.position = Some((name, offset));
+            if messages.len() >= SPOOL_BATCH_SIZE {
+                break;
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn cursor(&self) -> Option<String> {
+        self.position
+            .as_ref()
+            .map(|(file, offset)| format!("{}:{}", file, offset))
+    }
+}
+
+/// A source whose messages are handed on in order, saving its cursor only once
+/// everything read from it has been handled.
+///
+/// Messages left over from a failed attempt are retried before the source is
+/// polled again, so a restart resumes where handling left off without
+/// skipping or repeating messages.
+pub struct ResumableSource {
+    source: Box<dyn MessageSource>,
+    /// Settings key the cursor is saved under, if the source has one
+    cursor_setting: Option<&'static str>,
+    /// Messages read from the source but not yet handled, oldest first
+    pending: VecDeque<ReceivedMessage>,
+}
+
+impl ResumableSource {
+    pub fn new(source: Box<dyn MessageSource>, cursor_setting: Option<&'static str>) -> Self {
+        Self {
+            source,
+            cursor_setting,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Short name of the source for logging.
+    pub fn name(&self) -> &str {
+        self.source.name()
+    }
+
+    /// Whether messages from an earlier poll are still waiting to be handled.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Hand each pending message to `handle`, polling the source first if
+    /// none are pending. Stops at the first failure, leaving the rest pending.
+    /// Returns the sum of what `handle` returned.
+    pub fn drain(
+        &mut self,
+        db: &Database,
+        handle: &mut dyn FnMut(&ReceivedMessage) -> Result<usize>,
+    ) -> Result<usize> {
+        if self.pending.is_empty() {
+            self.pending.extend(self.source.poll()?);
+        }
+
+        let mut handled = 0;
+        while let Some(msg) = self.pending.front() {
+            handled += handle(msg)?;
+            self.pending.pop_front();
+        }
+
+        // Only now is everything up to the cursor handled
+        if let (Some(setting), Some(cursor)) = (self.cursor_setting, self.source.cursor()) {
+            db.set_setting(setting, &cursor)?;
+        }
+        Ok(handled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_dir_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = DropDirSource::new(dir.path()).unwrap();
+
+        fs::write(
+            dir.path().join("001.json"),
+            r#"{"sender": "+15551234567", "content": "See you at 3", "channel": "SMS"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("002.json"),
+            r#"{"id": "m-2", "sender": "alice@example.com", "content": "Thanks!"}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("003.json"), "not json").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let messages = source.poll().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].id, "001");
+        assert_eq!(messages[0].channel, "sms");
+        assert_eq!(messages[1].id, "m-2");
+        assert_eq!(messages[1].channel, "imessage");
+
+        assert!(dir.path().join("processed/001.json").exists());
+        assert!(dir.path().join("rejected/003.json").exists());
+        assert!(dir.path().join("notes.txt").exists());
+
+        // Files are consumed, so the next poll is empty
+        assert!(source.poll().unwrap().is_empty());
+        assert!(source.cursor().is_none());
+    }
+
+    #[test]
+    fn test_spool_source_resumes_from_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = dir.path().join("001.jsonl");
+        fs::write(
+            &spool,
+            concat!(
+                r#"{"id": "s-1", "sender": "+15551234567", "content": "On my way"}"#,
+                "\n",
+                "not json\n",
+                r#"{"sender": "+15551234567", "content": "Parking now", "channel": "sms"}"#,
+                "\n",
+                r#"{"sender": "+15551234567", "content": "half writ"#,
+            ),
+        )
+        .unwrap();
+
+        let mut source = SpoolSource::new(dir.path(), None).unwrap();
+        let messages = source.poll().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].id, "s-1");
+        assert!(messages[1].id.starts_with("001-"));
+        assert_eq!(messages[1].channel, "sms");
+
+        // The unfinished line is read once complete, by a restarted source too
+        let cursor = source.cursor().unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&spool).unwrap();
+        std::io::Write::write_all(&mut file, b"ten\"}\n").unwrap();
+        fs::write(
+            dir.path().join("002.jsonl"),
+            r#"{"id": "s-4", "sender": "a@example.com", "content": "Hi"}"#.to_string() + "\n",
+        )
+        .unwrap();
+
+        let mut restarted = SpoolSource::new(dir.path(), Some(&cursor)).unwrap();
+        let messages = restarted.poll().unwrap();
+        let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["half written", "Hi"]);
+        assert!(restarted.poll().unwrap().is_empty());
+        assert!(restarted.cursor().unwrap().starts_with("002.jsonl:"));
+    }
+
+    #[test]
+    fn test_chatdb_source_cursor() {
+        let source = ChatDbSource::new(Some("42")).unwrap();
+        assert_eq!(source.cursor(), Some("42".to_string()));
+    }
+}
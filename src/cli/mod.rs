@@ -10,6 +10,7 @@ pub mod display;
 pub mod email;
 pub mod gateway;
 pub mod google_auth;
+pub mod http_server;
 pub mod import;
 pub mod learn;
 pub mod list;
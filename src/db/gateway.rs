@@ -19,6 +19,28 @@ pub struct ApiKey {
     pub rate_limit_per_hour: i32,
     pub rate_limit_per_day: i32,
     pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub client_cert_fingerprint: Option<String>,
+    pub scopes: ApiKeyScopes,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub previous_key_expires_at: Option<DateTime<Utc>>, // Old secret still accepted until then
+    pub risk_classification: bool, // Send message bodies to the AI risk classifier
+}
+
+impl ApiKey {
+    /// Whether the key has passed its expiry time
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// What an API key is permitted to do
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApiKeyScopes {
+    pub channels: Option<Vec<String>>, // None = every channel
+    pub max_priority: Option<String>,  // None = any priority
+    pub read_all: bool,                // May read other keys' actions and queue entries
+    pub read_only: bool,               // May read but not send
 }
 
 /// Recipient allowlist entry for an API key
@@ -40,6 +62,8 @@ pub struct ContentFilter {
     pub description: Option<String>,
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
+    pub validator: Option<String>, // Check each regex match, e.g. 'luhn'
+    pub pack: Option<String>,      // Built-in pack that installed this filter
 }
 
 /// Communication queue entry
@@ -59,6 +83,169 @@ pub struct QueueEntry {
     pub reviewed_at: Option<DateTime<Utc>>,
     pub sent_at: Option<DateTime<Utc>>,
     pub error_message: Option<String>,
+    pub thread_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub risk_score: Option<i32>, // 0-100 from the AI classifier, if it ran
+    pub risk_reasons: Vec<String>,
+    pub send_attempts: i32,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub next_retry_at: Option<DateTime<Utc>>, // Set while a failed send waits to be retried
+    pub template_id: Option<String>,          // Template the body was rendered from
+    pub batch_id: Option<String>,             // Set for messages queued by a batch send
+    pub recipient_contact: Option<String>,    // `contact:<id>` when the agent addressed a contact
+}
+
+impl QueueEntry {
+    /// An approved message that never went through the queue, such as a transport test
+    pub fn unqueued(channel: &str, recipient_address: &str, subject: Option<&str>, body: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            api_key_id: String::new(),
+            channel: channel.to_string(),
+            recipient_address: recipient_address.to_string(),
+            recipient_name: None,
+            subject: subject.map(String::from),
+            body: body.to_string(),
+            priority: "normal".to_string(),
+            status: "approved".to_string(),
+            agent_context: None,
+            created_at: Utc::now(),
+            reviewed_at: None,
+            sent_at: None,
+            error_message: None,
+            thread_id: None,
+            in_reply_to: None,
+            risk_score: None,
+            risk_reasons: Vec::new(),
+            send_attempts: 0,
+            last_attempt_at: None,
+            next_retry_at: None,
+            template_id: None,
+            batch_id: None,
+            recipient_contact: None,
+        }
+    }
+}
+
+/// Webhook delivery attempt log entry
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub api_key_id: String,
+    pub event: String,             // 'status_change' or 'inbound_message'
+    pub action_id: Option<String>, // Queue entry the event is about, if any
+    pub payload: String,           // JSON body, re-sent verbatim on retry
+    pub status: String,            // 'pending', 'delivered' or 'failed'
+    pub attempts: i32,
+    pub last_response_code: Option<u16>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+/// Where to announce messages awaiting approval
+#[derive(Debug, Clone)]
+pub struct NotifierConfig {
+    pub id: String,
+    pub kind: String,           // 'desktop', 'ntfy', 'gotify' or 'email'
+    pub target: Option<String>, // Push URL or email address
+    pub preview: bool,          // Include recipient and message text
+    pub enabled: bool,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Server-side message template agents can reference by ID
+#[derive(Debug, Clone)]
+pub struct MessageTemplate {
+    pub id: String, // Chosen by the owner, e.g. "meeting-confirm"
+    pub subject: Option<String>,
+    pub body: String,
+    pub pre_approved: bool, // Messages rendered from it skip review unless flagged
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Token bucket state for one rate limit
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateBucket {
+    pub key: String, // e.g. "key:<id>:hour", "recipient:<normalized>"
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Hash a chain starts from
+pub const AUDIT_GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One entry in the append-only, hash-chained gateway audit log
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    pub seq: i64,
+    pub created_at: DateTime<Utc>,
+    pub event: String,              // e.g. 'queued', 'approved', 'key_created'
+    pub actor: String,              // 'agent:<key id>', 'cli', 'tui', 'web', 'local-api' or 'gateway'
+    pub subject_id: Option<String>, // Queue entry or key the event is about
+    pub details: String,            // JSON object
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// One row of the message counters: events of one kind for a key and channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageEventCount {
+    pub key_name: String,
+    pub channel: String,
+    pub event: String, // 'queued', 'approved', 'denied', 'sent' or 'failed'
+    pub count: i64,
+}
+
+/// Messages awaiting review or a retry.
+#[derive(Debug, Clone, Default)]
+pub struct QueueBacklog {
+    pub pending: i64,
+    pub flagged: i64,
+    pub retrying: i64,
+    pub oldest_waiting: Option<DateTime<Utc>>, // Oldest pending or flagged message
+}
+
+/// A message Moltbot asked the bridge to send.
+#[derive(Debug, Clone)]
+pub struct BridgeOutbound {
+    pub message_id: String,       // Moltbot's ID
+    pub queue_id: Option<String>, // None when the gateway refused it
+    pub status: String,           // Queue status, or "rejected"
+    pub error: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub received_at: DateTime<Utc>,
+    pub reported_status: Option<String>, // Last status Moltbot accepted
+}
+
+impl AuditEvent {
+    /// SHA-256 over every other field, hex encoded
+    pub fn compute_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        for field in [
+            self.seq.to_string().as_str(),
+            &audit_timestamp(self.created_at),
+            &self.event,
+            &self.actor,
+            self.subject_id.as_deref().unwrap_or(""),
+            &self.details,
+            &self.prev_hash,
+        ] {
+            // Length-prefix each field so no two events serialize the same
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Timestamps are hashed as stored, so always store them in one format
+fn audit_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
 }
 
 impl Database {
@@ -80,27 +267,32 @@ impl Database {
         Ok(())
     }
 
-    /// Find API key by hash (for authentication)
+    /// Find API key by hash (for authentication).
+    /// Also matches the previous secret of a rotated key until its grace period ends.
     pub fn find_api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let now = Utc::now().to_rfc3339();
         let result = self.conn.query_row(
-            "SELECT id, name, key_hash, key_prefix, created_at, last_used_at, revoked_at,
-                    rate_limit_per_hour, rate_limit_per_day, webhook_url
-             FROM api_keys WHERE key_hash = ?",
-            [key_hash],
-            |row| {
-                Ok(ApiKey {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    key_hash: row.get(2)?,
-                    key_prefix: row.get(3)?,
-                    created_at: parse_datetime(row.get::<_, String>(4)?),
-                    last_used_at: row.get::<_, Option<String>>(5)?.map(parse_datetime),
-                    revoked_at: row.get::<_, Option<String>>(6)?.map(parse_datetime),
-                    rate_limit_per_hour: row.get(7)?,
-                    rate_limit_per_day: row.get(8)?,
-                    webhook_url: row.get(9)?,
-                })
-            },
+            &format!(
+                "{} WHERE key_hash = ?1 OR (previous_key_hash = ?1 AND previous_key_expires_at > ?2)",
+                API_KEY_SELECT
+            ),
+            rusqlite::params![key_hash, now],
+            row_to_api_key,
+        );
+
+        match result {
+            Ok(key) => Ok(Some(key)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Find API key by client certificate fingerprint (for mutual TLS)
+    pub fn find_api_key_by_client_cert(&self, fingerprint: &str) -> Result<Option<ApiKey>> {
+        let result = self.conn.query_row(
+            &format!("{} WHERE client_cert_fingerprint = ?", API_KEY_SELECT),
+            [fingerprint],
+            row_to_api_key,
         );
 
         match result {
@@ -112,27 +304,12 @@ impl Database {
 
     /// List all API keys (for management)
     pub fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, key_hash, key_prefix, created_at, last_used_at, revoked_at,
-                    rate_limit_per_hour, rate_limit_per_day, webhook_url
-             FROM api_keys ORDER BY created_at DESC",
-        )?;
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{} ORDER BY created_at DESC", API_KEY_SELECT))?;
 
         let keys = stmt
-            .query_map([], |row| {
-                Ok(ApiKey {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    key_hash: row.get(2)?,
-                    key_prefix: row.get(3)?,
-                    created_at: parse_datetime(row.get::<_, String>(4)?),
-                    last_used_at: row.get::<_, Option<String>>(5)?.map(parse_datetime),
-                    revoked_at: row.get::<_, Option<String>>(6)?.map(parse_datetime),
-                    rate_limit_per_hour: row.get(7)?,
-                    rate_limit_per_day: row.get(8)?,
-                    webhook_url: row.get(9)?,
-                })
-            })?
+            .query_map([], row_to_api_key)?
             .filter_map(|r| r.ok())
             .collect();
 
@@ -183,6 +360,177 @@ impl Database {
         }
     }
 
+    /// Set the webhook signing secret for an API key
+    pub fn set_api_key_webhook_secret(&self, id: &str, secret: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE api_keys SET webhook_secret = ? WHERE id = ?",
+            rusqlite::params![secret, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Get the webhook signing secret for an API key by ID
+    pub fn get_api_key_webhook_secret(&self, id: &str) -> Result<Option<String>> {
+        let result = self.conn.query_row(
+            "SELECT webhook_secret FROM api_keys WHERE id = ?",
+            [id],
+            |row| row.get::<_, Option<String>>(0),
+        );
+
+        match result {
+            Ok(secret) => Ok(secret),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the scopes of an API key
+    pub fn set_api_key_scopes(&self, id: &str, scopes: &ApiKeyScopes) -> Result<bool> {
+        let channels = scopes.channels.as_ref().map(|c| c.join(","));
+        let rows = self.conn.execute(
+            "UPDATE api_keys SET scope_channels = ?, scope_max_priority = ?,
+                    scope_read_all = ?, scope_read_only = ?
+             WHERE id = ?",
+            rusqlite::params![
+                channels,
+                scopes.max_priority,
+                scopes.read_all,
+                scopes.read_only,
+                id
+            ],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Register (or with `None`, remove) the TLS client certificate for an API key
+    pub fn set_api_key_client_cert(&self, id: &str, fingerprint: Option<&str>) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE api_keys SET client_cert_fingerprint = ? WHERE id = ?",
+            rusqlite::params![fingerprint, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Set the hourly and daily send limits of an API key
+    pub fn set_api_key_rate_limits(&self, id: &str, per_hour: i32, per_day: i32) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE api_keys SET rate_limit_per_hour = ?, rate_limit_per_day = ? WHERE id = ?",
+            rusqlite::params![per_hour, per_day, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Opt an API key in or out of AI risk classification
+    pub fn set_api_key_risk_classification(&self, id: &str, enabled: bool) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE api_keys SET risk_classification = ? WHERE id = ?",
+            rusqlite::params![enabled, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Set (or with `None`, clear) the expiry time of an API key
+    pub fn set_api_key_expiry(&self, id: &str, expires_at: Option<DateTime<Utc>>) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE api_keys SET expires_at = ? WHERE id = ?",
+            rusqlite::params![expires_at.map(|t| t.to_rfc3339()), id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Replace an API key's secret, keeping the old one valid until `grace_until`.
+    /// With `None` the old secret stops working immediately.
+    pub fn rotate_api_key(
+        &self,
+        id: &str,
+        key_hash: &str,
+        key_prefix: &str,
+        grace_until: Option<DateTime<Utc>>,
+    ) -> Result<bool> {
+        let grace_until = grace_until.map(|t| t.to_rfc3339());
+        let rows = self.conn.execute(
+            "UPDATE api_keys
+             SET previous_key_hash = CASE WHEN ?3 IS NULL THEN NULL ELSE key_hash END,
+                 previous_key_expires_at = ?3,
+                 key_hash = ?1, key_prefix = ?2
+             WHERE id = ?4 AND revoked_at IS NULL",
+            rusqlite::params![key_hash, key_prefix, grace_until, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    // ========== Rate Limit Buckets ==========
+
+    /// Load the named buckets (`None` if never used), pass them to `update`, and
+    /// save what it returns. Runs in one write transaction so concurrent requests
+    /// can't spend the same token. Nothing is saved when `update` returns `Err`.
+    pub fn update_rate_buckets<E>(
+        &self,
+        keys: &[String],
+        update: impl FnOnce(Vec<Option<RateBucket>>) -> std::result::Result<Vec<RateBucket>, E>,
+    ) -> Result<std::result::Result<(), E>> {
+        self.conn.execute("BEGIN IMMEDIATE", [])?;
+        let result = (|| -> Result<std::result::Result<(), E>> {
+            let mut current = Vec::with_capacity(keys.len());
+            for key in keys {
+                current.push(self.get_rate_bucket(key)?);
+            }
+            let updated = match update(current) {
+                Ok(updated) => updated,
+                Err(e) => return Ok(Err(e)),
+            };
+            for bucket in updated {
+                self.conn.execute(
+                    "INSERT INTO rate_limit_buckets (bucket_key, tokens, updated_at) VALUES (?, ?, ?)
+                     ON CONFLICT(bucket_key) DO UPDATE SET tokens = excluded.tokens, updated_at = excluded.updated_at",
+                    rusqlite::params![bucket.key, bucket.tokens, bucket.updated_at.to_rfc3339()],
+                )?;
+            }
+            Ok(Ok(()))
+        })();
+
+        match result {
+            Ok(Ok(())) => {
+                self.conn.execute("COMMIT", [])?;
+                Ok(Ok(()))
+            }
+            other => {
+                let _ = self.conn.execute("ROLLBACK", []);
+                other
+            }
+        }
+    }
+
+    /// Get a rate limit bucket by key
+    pub fn get_rate_bucket(&self, key: &str) -> Result<Option<RateBucket>> {
+        let result = self.conn.query_row(
+            "SELECT bucket_key, tokens, updated_at FROM rate_limit_buckets WHERE bucket_key = ?",
+            [key],
+            |row| {
+                Ok(RateBucket {
+                    key: row.get(0)?,
+                    tokens: row.get(1)?,
+                    updated_at: parse_datetime(row.get::<_, String>(2)?),
+                })
+            },
+        );
+
+        match result {
+            Ok(bucket) => Ok(Some(bucket)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Delete rate limit buckets whose key starts with `prefix` (they refill to full)
+    pub fn reset_rate_buckets(&self, prefix: &str) -> Result<usize> {
+        let rows = self.conn.execute(
+            "DELETE FROM rate_limit_buckets WHERE substr(bucket_key, 1, length(?1)) = ?1",
+            [prefix],
+        )?;
+        Ok(rows)
+    }
+
     // ========== Allowlist Operations ==========
 
     /// Add a recipient pattern to an API key's allowlist
@@ -277,9 +625,7 @@ impl Database {
     /// Get a queue entry by ID
     pub fn get_queue_entry(&self, id: &str) -> Result<Option<QueueEntry>> {
         let result = self.conn.query_row(
-            "SELECT id, api_key_id, channel, recipient_address, recipient_name, subject, body,
-                    priority, status, agent_context, created_at, reviewed_at, sent_at, error_message
-             FROM communication_queue WHERE id = ?",
+            &format!("{} WHERE id = ?", QUEUE_SELECT),
             [id],
             row_to_queue_entry,
         );
@@ -293,16 +639,32 @@ impl Database {
 
     /// List pending queue entries (includes flagged entries that need review)
     pub fn list_pending_queue(&self) -> Result<Vec<QueueEntry>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, api_key_id, channel, recipient_address, recipient_name, subject, body,
-                    priority, status, agent_context, created_at, reviewed_at, sent_at, error_message
-             FROM communication_queue
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
              WHERE status IN ('pending', 'flagged')
              ORDER BY
                 CASE status WHEN 'flagged' THEN 0 ELSE 1 END,
                 CASE priority WHEN 'urgent' THEN 0 WHEN 'high' THEN 1 WHEN 'normal' THEN 2 ELSE 3 END,
                 created_at ASC",
-        )?;
+            QUEUE_SELECT
+        ))?;
+
+        let entries = stmt
+            .query_map([], row_to_queue_entry)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// List dead-lettered (failed) entries and those waiting to be retried, most recent first
+    pub fn list_failed_queue(&self) -> Result<Vec<QueueEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
+             WHERE status = 'failed' OR (status = 'approved' AND next_retry_at IS NOT NULL)
+             ORDER BY COALESCE(last_attempt_at, created_at) DESC",
+            QUEUE_SELECT
+        ))?;
 
         let entries = stmt
             .query_map([], row_to_queue_entry)?
@@ -339,17 +701,16 @@ impl Database {
         &self,
         status_filter: Option<&str>,
         agent_filter: Option<&str>,
+        thread_filter: Option<&str>,
         limit: usize,
     ) -> Result<Vec<(QueueEntry, String)>> {
         // Build query with optional filters
-        let mut sql = String::from(
-            "SELECT q.id, q.api_key_id, q.channel, q.recipient_address, q.recipient_name,
-                    q.subject, q.body, q.priority, q.status, q.agent_context,
-                    q.created_at, q.reviewed_at, q.sent_at, q.error_message,
-                    k.name as agent_name
-             FROM communication_queue q
+        let mut sql = format!(
+            "SELECT q.*, k.name AS agent_name
+             FROM ({}) q
              LEFT JOIN api_keys k ON q.api_key_id = k.id
              WHERE 1=1",
+            QUEUE_SELECT
         );
 
         let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
@@ -364,6 +725,11 @@ impl Database {
             params.push(Box::new(format!("%{}%", agent)));
         }
 
+        if let Some(thread) = thread_filter {
+            sql.push_str(" AND q.thread_id = ?");
+            params.push(Box::new(thread.to_string()));
+        }
+
         sql.push_str(" ORDER BY q.created_at DESC LIMIT ?");
         params.push(Box::new(limit as i64));
 
@@ -373,23 +739,8 @@ impl Database {
 
         let entries = stmt
             .query_map(param_refs.as_slice(), |row| {
-                let entry = QueueEntry {
-                    id: row.get(0)?,
-                    api_key_id: row.get(1)?,
-                    channel: row.get(2)?,
-                    recipient_address: row.get(3)?,
-                    recipient_name: row.get(4)?,
-                    subject: row.get(5)?,
-                    body: row.get(6)?,
-                    priority: row.get(7)?,
-                    status: row.get(8)?,
-                    agent_context: row.get(9)?,
-                    created_at: parse_datetime(row.get::<_, String>(10)?),
-                    reviewed_at: row.get::<_, Option<String>>(11)?.map(parse_datetime),
-                    sent_at: row.get::<_, Option<String>>(12)?.map(parse_datetime),
-                    error_message: row.get(13)?,
-                };
-                let agent_name: String = row.get::<_, Option<String>>(14)?.unwrap_or_else(|| "unknown".to_string());
+                let entry = row_to_queue_entry(row)?;
+                let agent_name: String = row.get::<_, Option<String>>("agent_name")?.unwrap_or_else(|| "unknown".to_string());
                 Ok((entry, agent_name))
             })?
             .filter_map(|r| r.ok())
@@ -408,156 +759,1000 @@ impl Database {
         Ok(rows > 0)
     }
 
-    /// Mark queue entry as sent
-    pub fn mark_queue_sent(&self, id: &str) -> Result<bool> {
-        let now = Utc::now().to_rfc3339();
-        let rows = self.conn.execute(
-            "UPDATE communication_queue SET status = 'sent', sent_at = ? WHERE id = ?",
-            rusqlite::params![now, id],
+    /// Record a reviewer's decision on an entry still awaiting review.
+    ///
+    /// Returns false if it was already reviewed, so concurrent approvals of the
+    /// same entry can't both go ahead.
+    pub fn review_queue_entry(&self, id: &str, status: &str) -> Result<bool> {
+        let now = Utc::now().to_rfc3339();
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET status = ?, reviewed_at = ?
+             WHERE id = ? AND status IN ('pending', 'flagged')",
+            rusqlite::params![status, now, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// The action a key already queued under an idempotency key, if any
+    pub fn get_idempotent_action(&self, api_key_id: &str, idempotency_key: &str) -> Result<Option<String>> {
+        let result = self.conn.query_row(
+            "SELECT action_id FROM gateway_idempotency_keys WHERE api_key_id = ? AND idempotency_key = ?",
+            rusqlite::params![api_key_id, idempotency_key],
+            |row| row.get(0),
+        );
+        match result {
+            Ok(action_id) => Ok(Some(action_id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remember which action a key queued under an idempotency key
+    pub fn insert_idempotency_key(&self, api_key_id: &str, idempotency_key: &str, action_id: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO gateway_idempotency_keys (api_key_id, idempotency_key, action_id, created_at)
+             VALUES (?, ?, ?, ?)",
+            rusqlite::params![api_key_id, idempotency_key, action_id, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Replace the subject and body of an entry still awaiting review
+    pub fn update_queue_content(&self, id: &str, subject: Option<&str>, body: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET subject = ?, body = ?
+             WHERE id = ? AND status IN ('pending', 'flagged')",
+            rusqlite::params![subject, body, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Mark queue entry as sent
+    pub fn mark_queue_sent(&self, id: &str) -> Result<bool> {
+        let now = Utc::now().to_rfc3339();
+        let rows = self.conn.execute(
+            "UPDATE communication_queue
+             SET status = 'sent', sent_at = ?1, send_attempts = send_attempts + 1,
+                 last_attempt_at = ?1, next_retry_at = NULL
+             WHERE id = ?2",
+            rusqlite::params![now, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Mark queue entry as failed for good
+    pub fn mark_queue_failed(&self, id: &str, error: &str) -> Result<bool> {
+        let now = Utc::now().to_rfc3339();
+        let rows = self.conn.execute(
+            "UPDATE communication_queue
+             SET status = 'failed', sent_at = ?1, error_message = ?2, send_attempts = send_attempts + 1,
+                 last_attempt_at = ?1, next_retry_at = NULL
+             WHERE id = ?3",
+            rusqlite::params![now, error, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Record a failed send that will be retried at `next_retry_at` (stays approved)
+    pub fn mark_queue_retry(&self, id: &str, error: &str, next_retry_at: DateTime<Utc>) -> Result<bool> {
+        let now = Utc::now().to_rfc3339();
+        let rows = self.conn.execute(
+            "UPDATE communication_queue
+             SET error_message = ?1, send_attempts = send_attempts + 1, last_attempt_at = ?2,
+                 next_retry_at = ?3
+             WHERE id = ?4 AND status = 'approved'",
+            rusqlite::params![error, now, next_retry_at.to_rfc3339(), id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Put a failed entry back to approved with a fresh set of attempts
+    pub fn reset_queue_for_retry(&self, id: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue
+             SET status = 'approved', send_attempts = 0, next_retry_at = NULL, sent_at = NULL
+             WHERE id = ? AND status = 'failed'",
+            [id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Attach a queue entry to a conversation thread
+    pub fn set_queue_thread(&self, id: &str, thread_id: &str, in_reply_to: Option<&str>) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET thread_id = ?, in_reply_to = ? WHERE id = ?",
+            rusqlite::params![thread_id, in_reply_to, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Add a queue entry to a batch
+    pub fn set_queue_batch(&self, id: &str, batch_id: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET batch_id = ? WHERE id = ?",
+            rusqlite::params![batch_id, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Record the contact reference a queue entry was addressed to
+    pub fn set_queue_recipient_contact(&self, id: &str, reference: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET recipient_contact = ? WHERE id = ?",
+            rusqlite::params![reference, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// List the entries of a batch in the order they were queued
+    pub fn list_batch_entries(&self, batch_id: &str) -> Result<Vec<QueueEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
+             WHERE batch_id = ?
+             ORDER BY created_at ASC, rowid ASC",
+            QUEUE_SELECT
+        ))?;
+
+        let entries = stmt
+            .query_map([batch_id], row_to_queue_entry)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Record the template a queue entry's body was rendered from
+    pub fn set_queue_template(&self, id: &str, template_id: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET template_id = ? WHERE id = ?",
+            rusqlite::params![template_id, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Record the AI risk classification of a queue entry
+    pub fn set_queue_risk(&self, id: &str, score: Option<i32>, reasons: &[String]) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET risk_score = ?, risk_reasons = ? WHERE id = ?",
+            rusqlite::params![score, serde_json::to_string(reasons)?, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// List approved entries whose retry time has passed
+    pub fn list_due_sends(&self, now: DateTime<Utc>) -> Result<Vec<QueueEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE status = 'approved' AND next_retry_at <= ? ORDER BY next_retry_at ASC",
+            QUEUE_SELECT
+        ))?;
+
+        let entries = stmt
+            .query_map([now.to_rfc3339()], row_to_queue_entry)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Claim a due retry for sending by clearing its retry time; returns false
+    /// if it is no longer due (already claimed, retried by hand or reviewed)
+    pub fn claim_due_send(&self, id: &str, now: DateTime<Utc>) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE communication_queue SET next_retry_at = NULL
+             WHERE id = ? AND status = 'approved' AND next_retry_at <= ?",
+            rusqlite::params![id, now.to_rfc3339()],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// List pending and flagged entries not yet announced by a notifier, oldest first
+    pub fn list_unnotified_queue(&self) -> Result<Vec<QueueEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
+             WHERE status IN ('pending', 'flagged') AND notified_at IS NULL
+             ORDER BY created_at ASC",
+            QUEUE_SELECT
+        ))?;
+
+        let entries = stmt
+            .query_map([], row_to_queue_entry)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Record that queue entries have been announced
+    pub fn mark_queue_notified(&self, ids: &[String]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        for id in ids {
+            self.conn.execute(
+                "UPDATE communication_queue SET notified_at = ? WHERE id = ?",
+                rusqlite::params![now, id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// List pending entries queued by one API key
+    pub fn list_pending_queue_for_key(&self, api_key_id: &str) -> Result<Vec<QueueEntry>> {
+        Ok(self
+            .list_pending_queue()?
+            .into_iter()
+            .filter(|e| e.api_key_id == api_key_id)
+            .collect())
+    }
+
+    /// List (recipient_address, thread_id) pairs for an API key, most recent first
+    pub fn list_threaded_recipients(&self, api_key_id: &str) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT recipient_address, thread_id
+             FROM communication_queue
+             WHERE api_key_id = ? AND thread_id IS NOT NULL
+             ORDER BY created_at DESC",
+        )?;
+
+        let pairs = stmt
+            .query_map([api_key_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(pairs)
+    }
+
+    /// List all queue entries in an agent's thread, oldest first
+    pub fn list_thread_entries(&self, api_key_id: &str, thread_id: &str) -> Result<Vec<QueueEntry>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{}
+             WHERE api_key_id = ? AND thread_id = ?
+             ORDER BY created_at ASC",
+            QUEUE_SELECT
+        ))?;
+
+        let entries = stmt
+            .query_map(rusqlite::params![api_key_id, thread_id], row_to_queue_entry)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(entries)
+    }
+
+    // ========== Content Filter Operations ==========
+
+    /// Insert a new content filter
+    pub fn insert_content_filter(
+        &self,
+        id: &str,
+        pattern: &str,
+        pattern_type: &str,
+        action: &str,
+        description: Option<&str>,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO content_filters (id, pattern, pattern_type, action, description, enabled, created_at)
+             VALUES (?, ?, ?, ?, ?, 1, ?)",
+            rusqlite::params![id, pattern, pattern_type, action, description, now],
+        )?;
+        self.touch_content_filters()?;
+        Ok(())
+    }
+
+    /// Insert a filter from a built-in pack.
+    /// Returns Ok(false) if a filter with this ID is already installed.
+    pub fn insert_pack_filter(&self, filter: &ContentFilter) -> Result<bool> {
+        let rows = self.conn.execute(
+            "INSERT OR IGNORE INTO content_filters
+                (id, pattern, pattern_type, action, description, enabled, created_at, validator, pack)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            rusqlite::params![
+                filter.id,
+                filter.pattern,
+                filter.pattern_type,
+                filter.action,
+                filter.description,
+                filter.enabled,
+                filter.created_at.to_rfc3339(),
+                filter.validator,
+                filter.pack
+            ],
+        )?;
+        if rows > 0 {
+            self.touch_content_filters()?;
+        }
+        Ok(rows > 0)
+    }
+
+    /// List all content filters
+    pub fn list_content_filters(&self) -> Result<Vec<ContentFilter>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{} ORDER BY created_at ASC", CONTENT_FILTER_SELECT))?;
+
+        let filters = stmt
+            .query_map([], row_to_content_filter)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(filters)
+    }
+
+    /// List enabled content filters (for enforcement)
+    pub fn list_enabled_content_filters(&self) -> Result<Vec<ContentFilter>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE enabled = 1 ORDER BY action = 'deny' DESC, created_at ASC",
+            CONTENT_FILTER_SELECT
+        ))?;
+
+        let filters = stmt
+            .query_map([], row_to_content_filter)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(filters)
+    }
+
+    /// Get a content filter by ID
+    pub fn get_content_filter(&self, id: &str) -> Result<Option<ContentFilter>> {
+        let result = self.conn.query_row(
+            &format!("{} WHERE id = ?", CONTENT_FILTER_SELECT),
+            [id],
+            row_to_content_filter,
+        );
+
+        match result {
+            Ok(filter) => Ok(Some(filter)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Enable or disable a content filter
+    pub fn set_content_filter_enabled(&self, id: &str, enabled: bool) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE content_filters SET enabled = ? WHERE id = ?",
+            rusqlite::params![if enabled { 1 } else { 0 }, id],
+        )?;
+        self.touch_content_filters()?;
+        Ok(rows > 0)
+    }
+
+    /// Delete a content filter
+    pub fn delete_content_filter(&self, id: &str) -> Result<bool> {
+        let rows = self.conn.execute(
+            "DELETE FROM content_filters WHERE id = ?",
+            [id],
+        )?;
+        self.touch_content_filters()?;
+        Ok(rows > 0)
+    }
+
+    /// Delete every filter installed by a pack
+    pub fn delete_pack_filters(&self, pack: &str) -> Result<usize> {
+        let rows = self
+            .conn
+            .execute("DELETE FROM content_filters WHERE pack = ?", [pack])?;
+        self.touch_content_filters()?;
+        Ok(rows)
+    }
+
+    /// Current content filter revision; changes whenever a filter is added, changed or removed.
+    /// A running gateway compares it to reload filters without a restart.
+    pub fn content_filters_revision(&self) -> Result<Option<String>> {
+        self.get_setting(SETTING_CONTENT_FILTERS_REVISION)
+    }
+
+    fn touch_content_filters(&self) -> Result<()> {
+        self.set_setting(
+            SETTING_CONTENT_FILTERS_REVISION,
+            &Uuid::new_v4().to_string(),
+        )
+    }
+
+    /// Seed default content filters (called during migration)
+    pub(crate) fn seed_content_filters(&self) -> Result<()> {
+        let default_filters = get_default_content_filters();
+
+        for (pattern, pattern_type, action, description) in default_filters {
+            let id = Uuid::new_v4().to_string();
+            self.insert_content_filter(&id, pattern, pattern_type, action, Some(description))?;
+        }
+
+        Ok(())
+    }
+
+    // ========== Webhook Delivery Operations ==========
+
+    /// Record a webhook delivery to attempt as soon as possible
+    pub fn insert_webhook_delivery(
+        &self,
+        id: &str,
+        api_key_id: &str,
+        event: &str,
+        action_id: Option<&str>,
+        payload: &str,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO webhook_deliveries (id, api_key_id, event, action_id, payload, status,
+                                             attempts, next_attempt_at, created_at)
+             VALUES (?, ?, ?, ?, ?, 'pending', 0, ?, ?)",
+            rusqlite::params![id, api_key_id, event, action_id, payload, now, now],
+        )?;
+        Ok(())
+    }
+
+    /// Get a webhook delivery by ID, or by unique ID prefix
+    pub fn get_webhook_delivery(&self, id_or_prefix: &str) -> Result<Option<WebhookDelivery>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE id = ?1 OR id LIKE ?2 ESCAPE '\\' LIMIT 2",
+            WEBHOOK_DELIVERY_SELECT
+        ))?;
+        let prefix = format!("{}%", Self::escape_like(id_or_prefix));
+        let mut matches: Vec<WebhookDelivery> = stmt
+            .query_map(rusqlite::params![id_or_prefix, prefix], row_to_webhook_delivery)?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        match matches.len() {
+            1 => Ok(matches.pop()),
+            0 => Ok(None),
+            _ => Err(anyhow::anyhow!(
+                "Multiple deliveries match '{}'. Be more specific",
+                id_or_prefix
+            )),
+        }
+    }
+
+    /// List webhook deliveries for an API key, most recent first
+    pub fn list_webhook_deliveries(&self, api_key_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE api_key_id = ? ORDER BY created_at DESC LIMIT ?",
+            WEBHOOK_DELIVERY_SELECT
+        ))?;
+        let deliveries = stmt
+            .query_map(rusqlite::params![api_key_id, limit as i64], row_to_webhook_delivery)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(deliveries)
+    }
+
+    /// List pending deliveries whose next attempt is due, oldest first
+    pub fn list_due_webhook_deliveries(&self, limit: usize) -> Result<Vec<WebhookDelivery>> {
+        let now = Utc::now().to_rfc3339();
+        let mut stmt = self.conn.prepare(&format!(
+            "{} WHERE status = 'pending' AND next_attempt_at <= ? ORDER BY next_attempt_at LIMIT ?",
+            WEBHOOK_DELIVERY_SELECT
+        ))?;
+        let deliveries = stmt
+            .query_map(rusqlite::params![now, limit as i64], row_to_webhook_delivery)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(deliveries)
+    }
+
+    /// Claim a due delivery for one attempt by clearing its next attempt time; returns
+    /// false if another worker already has it or it is no longer pending
+    pub fn claim_webhook_delivery(&self, id: &str) -> Result<bool> {
+        let now = Utc::now().to_rfc3339();
+        let rows = self.conn.execute(
+            "UPDATE webhook_deliveries SET next_attempt_at = NULL
+             WHERE id = ? AND status = 'pending' AND next_attempt_at <= ?",
+            rusqlite::params![id, now],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Mark a delivery as successfully delivered
+    pub fn mark_webhook_delivered(&self, id: &str, response_code: u16) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "UPDATE webhook_deliveries
+             SET status = 'delivered', attempts = attempts + 1, last_response_code = ?,
+                 last_error = NULL, next_attempt_at = NULL, delivered_at = ?
+             WHERE id = ?",
+            rusqlite::params![response_code, now, id],
+        )?;
+        Ok(())
+    }
+
+    /// Record a failed attempt. With no `next_attempt_at` the delivery is given up on.
+    pub fn mark_webhook_attempt_failed(
+        &self,
+        id: &str,
+        response_code: Option<u16>,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let status = if next_attempt_at.is_some() { "pending" } else { "failed" };
+        self.conn.execute(
+            "UPDATE webhook_deliveries
+             SET status = ?, attempts = attempts + 1, last_response_code = ?,
+                 last_error = ?, next_attempt_at = ?
+             WHERE id = ?",
+            rusqlite::params![
+                status,
+                response_code,
+                error,
+                next_attempt_at.map(|dt| dt.to_rfc3339()),
+                id
+            ],
         )?;
-        Ok(rows > 0)
+        Ok(())
     }
 
-    /// Mark queue entry as failed
-    pub fn mark_queue_failed(&self, id: &str, error: &str) -> Result<bool> {
+    /// Queue a delivery to be sent again now, restarting its retry schedule
+    pub fn reset_webhook_delivery(&self, id: &str) -> Result<bool> {
         let now = Utc::now().to_rfc3339();
         let rows = self.conn.execute(
-            "UPDATE communication_queue SET status = 'failed', sent_at = ?, error_message = ? WHERE id = ?",
-            rusqlite::params![now, error, id],
+            "UPDATE webhook_deliveries
+             SET status = 'pending', attempts = 0, next_attempt_at = ?, delivered_at = NULL
+             WHERE id = ?",
+            rusqlite::params![now, id],
         )?;
         Ok(rows > 0)
     }
 
-    // ========== Content Filter Operations ==========
+    // ========== Notifier Operations ==========
 
-    /// Insert a new content filter
-    pub fn insert_content_filter(
+    /// Add a notifier
+    pub fn insert_notifier(
         &self,
         id: &str,
-        pattern: &str,
-        pattern_type: &str,
-        action: &str,
-        description: Option<&str>,
+        kind: &str,
+        target: Option<&str>,
+        preview: bool,
     ) -> Result<()> {
         let now = Utc::now().to_rfc3339();
         self.conn.execute(
-            "INSERT INTO content_filters (id, pattern, pattern_type, action, description, enabled, created_at)
-             VALUES (?, ?, ?, ?, ?, 1, ?)",
-            rusqlite::params![id, pattern, pattern_type, action, description, now],
+            "INSERT INTO gateway_notifiers (id, kind, target, preview, enabled, created_at)
+             VALUES (?, ?, ?, ?, 1, ?)",
+            rusqlite::params![id, kind, target, preview, now],
         )?;
         Ok(())
     }
 
-    /// List all content filters
-    pub fn list_content_filters(&self) -> Result<Vec<ContentFilter>> {
+    /// List all notifiers, oldest first
+    pub fn list_notifiers(&self) -> Result<Vec<NotifierConfig>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, pattern, pattern_type, action, description, enabled, created_at
-             FROM content_filters
-             ORDER BY created_at ASC",
+            "SELECT id, kind, target, preview, enabled, last_error, created_at
+             FROM gateway_notifiers
+             ORDER BY created_at",
         )?;
-
-        let filters = stmt
+        let notifiers = stmt
             .query_map([], |row| {
-                Ok(ContentFilter {
+                Ok(NotifierConfig {
                     id: row.get(0)?,
-                    pattern: row.get(1)?,
-                    pattern_type: row.get(2)?,
-                    action: row.get(3)?,
-                    description: row.get(4)?,
-                    enabled: row.get::<_, i32>(5)? != 0,
+                    kind: row.get(1)?,
+                    target: row.get(2)?,
+                    preview: row.get(3)?,
+                    enabled: row.get(4)?,
+                    last_error: row.get(5)?,
                     created_at: parse_datetime(row.get::<_, String>(6)?),
                 })
             })?
             .filter_map(|r| r.ok())
             .collect();
+        Ok(notifiers)
+    }
 
-        Ok(filters)
+    /// Enable or disable a notifier
+    pub fn set_notifier_enabled(&self, id: &str, enabled: bool) -> Result<bool> {
+        let rows = self.conn.execute(
+            "UPDATE gateway_notifiers SET enabled = ? WHERE id = ?",
+            rusqlite::params![enabled, id],
+        )?;
+        Ok(rows > 0)
     }
 
-    /// List enabled content filters (for enforcement)
-    pub fn list_enabled_content_filters(&self) -> Result<Vec<ContentFilter>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, pattern, pattern_type, action, description, enabled, created_at
-             FROM content_filters
-             WHERE enabled = 1
-             ORDER BY action DESC, created_at ASC",
+    /// Record the outcome of a notifier's last attempt (`None` on success)
+    pub fn set_notifier_error(&self, id: &str, error: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE gateway_notifiers SET last_error = ? WHERE id = ?",
+            rusqlite::params![error, id],
         )?;
+        Ok(())
+    }
 
-        let filters = stmt
-            .query_map([], |row| {
-                Ok(ContentFilter {
-                    id: row.get(0)?,
-                    pattern: row.get(1)?,
-                    pattern_type: row.get(2)?,
-                    action: row.get(3)?,
-                    description: row.get(4)?,
-                    enabled: row.get::<_, i32>(5)? != 0,
-                    created_at: parse_datetime(row.get::<_, String>(6)?),
-                })
-            })?
-            .filter_map(|r| r.ok())
-            .collect();
+    /// Delete a notifier
+    pub fn delete_notifier(&self, id: &str) -> Result<bool> {
+        let rows = self
+            .conn
+            .execute("DELETE FROM gateway_notifiers WHERE id = ?", [id])?;
+        Ok(rows > 0)
+    }
 
-        Ok(filters)
+    // ========== Template Operations ==========
+
+    /// Add a template. Fails if the ID is taken.
+    pub fn insert_template(&self, id: &str, subject: Option<&str>, body: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO gateway_templates (id, subject, body, pre_approved, created_at, updated_at)
+             VALUES (?1, ?2, ?3, 0, ?4, ?4)",
+            rusqlite::params![id, subject, body, now],
+        )?;
+        Ok(())
     }
 
-    /// Get a content filter by ID
-    pub fn get_content_filter(&self, id: &str) -> Result<Option<ContentFilter>> {
+    /// Get a template by ID
+    pub fn get_template(&self, id: &str) -> Result<Option<MessageTemplate>> {
         let result = self.conn.query_row(
-            "SELECT id, pattern, pattern_type, action, description, enabled, created_at
-             FROM content_filters WHERE id = ?",
+            "SELECT id, subject, body, pre_approved, created_at, updated_at
+             FROM gateway_templates WHERE id = ?",
             [id],
-            |row| {
-                Ok(ContentFilter {
-                    id: row.get(0)?,
-                    pattern: row.get(1)?,
-                    pattern_type: row.get(2)?,
-                    action: row.get(3)?,
-                    description: row.get(4)?,
-                    enabled: row.get::<_, i32>(5)? != 0,
-                    created_at: parse_datetime(row.get::<_, String>(6)?),
-                })
-            },
+            row_to_template,
         );
-
         match result {
-            Ok(filter) => Ok(Some(filter)),
+            Ok(template) => Ok(Some(template)),
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
             Err(e) => Err(e.into()),
         }
     }
 
-    /// Enable or disable a content filter
-    pub fn set_content_filter_enabled(&self, id: &str, enabled: bool) -> Result<bool> {
+    /// List all templates by ID
+    pub fn list_templates(&self) -> Result<Vec<MessageTemplate>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, subject, body, pre_approved, created_at, updated_at
+             FROM gateway_templates
+             ORDER BY id",
+        )?;
+        let templates = stmt
+            .query_map([], row_to_template)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(templates)
+    }
+
+    /// Replace a template's text. Clears pre-approval, which covered the old text.
+    pub fn update_template(&self, id: &str, subject: Option<&str>, body: &str) -> Result<bool> {
         let rows = self.conn.execute(
-            "UPDATE content_filters SET enabled = ? WHERE id = ?",
-            rusqlite::params![if enabled { 1 } else { 0 }, id],
+            "UPDATE gateway_templates SET subject = ?, body = ?, pre_approved = 0, updated_at = ?
+             WHERE id = ?",
+            rusqlite::params![subject, body, Utc::now().to_rfc3339(), id],
         )?;
         Ok(rows > 0)
     }
 
-    /// Delete a content filter
-    pub fn delete_content_filter(&self, id: &str) -> Result<bool> {
+    /// Mark a template as pre-approved or not
+    pub fn set_template_pre_approved(&self, id: &str, pre_approved: bool) -> Result<bool> {
         let rows = self.conn.execute(
-            "DELETE FROM content_filters WHERE id = ?",
-            [id],
+            "UPDATE gateway_templates SET pre_approved = ? WHERE id = ?",
+            rusqlite::params![pre_approved, id],
         )?;
         Ok(rows > 0)
     }
 
-    /// Seed default content filters (called during migration)
-    pub(crate) fn seed_content_filters(&self) -> Result<()> {
-        let default_filters = get_default_content_filters();
+    /// Delete a template. Queue entries keep its ID.
+    pub fn delete_template(&self, id: &str) -> Result<bool> {
+        let rows = self
+            .conn
+            .execute("DELETE FROM gateway_templates WHERE id = ?", [id])?;
+        Ok(rows > 0)
+    }
 
-        for (pattern, pattern_type, action, description) in default_filters {
-            let id = Uuid::new_v4().to_string();
-            self.insert_content_filter(&id, pattern, pattern_type, action, Some(description))?;
+    // ========== Audit Log Operations ==========
+
+    /// Append an event to the audit log, chained to the current last event.
+    /// Runs in one write transaction so concurrent writers can't fork the chain.
+    pub fn append_audit_event(
+        &self,
+        event: &str,
+        actor: &str,
+        subject_id: Option<&str>,
+        details: &str,
+    ) -> Result<AuditEvent> {
+        self.conn.execute("BEGIN IMMEDIATE", [])?;
+        let result = (|| -> Result<AuditEvent> {
+            let last = self.conn.query_row(
+                "SELECT seq, hash FROM gateway_audit_log ORDER BY seq DESC LIMIT 1",
+                [],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            );
+            let (seq, prev_hash) = match last {
+                Ok((seq, hash)) => (seq + 1, hash),
+                Err(rusqlite::Error::QueryReturnedNoRows) => (1, AUDIT_GENESIS_HASH.to_string()),
+                Err(e) => return Err(e.into()),
+            };
+
+            // Round-trip the timestamp so the hash matches what is read back
+            let created_at = parse_datetime(audit_timestamp(Utc::now()));
+            let mut audit = AuditEvent {
+                seq,
+                created_at,
+                event: event.to_string(),
+                actor: actor.to_string(),
+                subject_id: subject_id.map(String::from),
+                details: details.to_string(),
+                prev_hash,
+                hash: String::new(),
+            };
+            audit.hash = audit.compute_hash();
+
+            self.conn.execute(
+                "INSERT INTO gateway_audit_log (seq, created_at, event, actor, subject_id, details, prev_hash, hash)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                rusqlite::params![
+                    audit.seq,
+                    audit_timestamp(audit.created_at),
+                    audit.event,
+                    audit.actor,
+                    audit.subject_id,
+                    audit.details,
+                    audit.prev_hash,
+                    audit.hash
+                ],
+            )?;
+            Ok(audit)
+        })();
+
+        match result {
+            Ok(audit) => {
+                self.conn.execute("COMMIT", [])?;
+                Ok(audit)
+            }
+            Err(e) => {
+                let _ = self.conn.execute("ROLLBACK", []);
+                Err(e)
+            }
+        }
+    }
+
+    /// List audit events in chain order, starting after `after_seq`
+    pub fn list_audit_events(&self, after_seq: i64) -> Result<Vec<AuditEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT seq, created_at, event, actor, subject_id, details, prev_hash, hash
+             FROM gateway_audit_log
+             WHERE seq > ?
+             ORDER BY seq",
+        )?;
+        let events = stmt
+            .query_map([after_seq], row_to_audit_event)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(events)
+    }
+
+    // ========== Metrics Operations ==========
+
+    /// Count queued/approved/denied/sent/failed events per key name and channel
+    pub fn count_message_events(&self) -> Result<Vec<MessageEventCount>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(k.name, q.api_key_id), q.channel, a.event, COUNT(*)
+             FROM gateway_audit_log a
+             JOIN communication_queue q ON q.id = a.subject_id
+             LEFT JOIN api_keys k ON k.id = q.api_key_id
+             WHERE a.event IN ('queued', 'approved', 'denied', 'sent', 'failed')
+             GROUP BY 1, 2, 3
+             ORDER BY 1, 2, 3",
+        )?;
+        let counts = stmt
+            .query_map([], |row| {
+                Ok(MessageEventCount {
+                    key_name: row.get(0)?,
+                    channel: row.get(1)?,
+                    event: row.get(2)?,
+                    count: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(counts)
+    }
+
+    /// Count content filter matches as (filter ID, "deny" or "flag", count)
+    pub fn count_filter_hits(&self) -> Result<Vec<(String, String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT json_extract(details, '$.filter'),
+                    CASE event WHEN 'filtered' THEN 'deny' ELSE 'flag' END,
+                    COUNT(*)
+             FROM gateway_audit_log
+             WHERE event IN ('filtered', 'queued') AND json_extract(details, '$.filter') IS NOT NULL
+             GROUP BY 1, 2
+             ORDER BY 1, 2",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(counts)
+    }
+
+    /// Count a rate limit rejection in the window starting at `window_start`.
+    /// Returns Ok(true) if it's the first one for this key and limit in the window.
+    pub fn count_rate_limit_rejection(
+        &self,
+        api_key_id: &str,
+        limit_type: &str,
+        window_start: DateTime<Utc>,
+    ) -> Result<bool> {
+        let params = rusqlite::params![api_key_id, limit_type, window_start.to_rfc3339()];
+        let inserted = self.conn.execute(
+            "INSERT INTO gateway_rate_limit_rejections (api_key_id, limit_type, window_start, count)
+             VALUES (?1, ?2, ?3, 1)
+             ON CONFLICT(api_key_id, limit_type, window_start) DO NOTHING",
+            params,
+        )?;
+        if inserted == 0 {
+            self.conn.execute(
+                "UPDATE gateway_rate_limit_rejections SET count = count + 1
+                 WHERE api_key_id = ?1 AND limit_type = ?2 AND window_start = ?3",
+                params,
+            )?;
         }
+        Ok(inserted > 0)
+    }
+
+    /// Count rate limit rejections as (key name, limit type, count)
+    pub fn count_rate_limited(&self) -> Result<Vec<(String, String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(k.name, r.api_key_id), r.limit_type, SUM(r.count)
+             FROM gateway_rate_limit_rejections r
+             LEFT JOIN api_keys k ON k.id = r.api_key_id
+             GROUP BY 1, 2
+             ORDER BY 1, 2",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(counts)
+    }
+
+    /// Seconds from queueing to a reviewer's decision, as ("approved" or "denied", seconds).
+    /// Template pre-approvals aren't reviews and are left out.
+    pub fn list_review_latencies(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.event, q.created_at, d.created_at
+             FROM gateway_audit_log d
+             JOIN gateway_audit_log q ON q.subject_id = d.subject_id AND q.event = 'queued'
+             WHERE d.event IN ('approved', 'denied') AND d.actor NOT LIKE 'template:%'",
+        )?;
+        let latencies = stmt
+            .query_map([], |row| {
+                let queued = parse_datetime(row.get::<_, String>(1)?);
+                let decided = parse_datetime(row.get::<_, String>(2)?);
+                Ok((row.get(0)?, (decided - queued).num_seconds().max(0)))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(latencies)
+    }
+
+    /// Count webhook deliveries that gave up, per key name
+    pub fn count_webhook_failures(&self) -> Result<Vec<(String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(k.name, w.api_key_id), COUNT(*)
+             FROM webhook_deliveries w
+             LEFT JOIN api_keys k ON k.id = w.api_key_id
+             WHERE w.status = 'failed'
+             GROUP BY 1
+             ORDER BY 1",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(counts)
+    }
+
+    /// Messages waiting on a reviewer or a retry right now
+    pub fn queue_backlog(&self) -> Result<QueueBacklog> {
+        let backlog = self.conn.query_row(
+            "SELECT COALESCE(SUM(status = 'pending'), 0),
+                    COALESCE(SUM(status = 'flagged'), 0),
+                    COALESCE(SUM(status = 'approved' AND next_retry_at IS NOT NULL), 0),
+                    MIN(CASE WHEN status IN ('pending', 'flagged') THEN created_at END)
+             FROM communication_queue",
+            [],
+            |row| {
+                Ok(QueueBacklog {
+                    pending: row.get(0)?,
+                    flagged: row.get(1)?,
+                    retrying: row.get(2)?,
+                    oldest_waiting: row.get::<_, Option<String>>(3)?.map(parse_datetime),
+                })
+            },
+        )?;
+        Ok(backlog)
+    }
+
+    // ========== Bridge Operations ==========
+
+    /// Record a bridge message as received; returns false if its ID was already seen
+    pub fn insert_bridge_outbound(&self, message_id: &str) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO bridge_outbound (message_id, status, received_at)
+             VALUES (?, 'received', ?)",
+            rusqlite::params![message_id, Utc::now().to_rfc3339()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    /// Record the queue entry a bridge message became, or why it was refused
+    pub fn set_bridge_outbound_status(
+        &self,
+        message_id: &str,
+        queue_id: Option<&str>,
+        status: &str,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE bridge_outbound SET queue_id = ?, status = ?, error = ? WHERE message_id = ?",
+            rusqlite::params![queue_id, status, error, message_id],
+        )?;
+        Ok(())
+    }
+
+    /// Record a bridge message as rejected, whether or not it was recorded as
+    /// received; a message that reached the queue is left alone
+    pub fn reject_bridge_outbound(&self, message_id: &str, error: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO bridge_outbound (message_id, status, error, received_at)
+             VALUES (?1, 'rejected', ?2, ?3)
+             ON CONFLICT(message_id) DO UPDATE SET status = 'rejected', error = excluded.error
+             WHERE queue_id IS NULL",
+            rusqlite::params![message_id, error, Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Bridge messages whose final outcome Moltbot hasn't accepted yet, oldest first
+    pub fn list_unreported_bridge_outbound(&self) -> Result<Vec<BridgeOutbound>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT b.message_id, b.queue_id, COALESCE(q.status, b.status),
+                    COALESCE(q.error_message, b.error), q.sent_at, b.received_at, b.reported_status
+             FROM bridge_outbound b
+             LEFT JOIN communication_queue q ON q.id = b.queue_id
+             WHERE b.reported_status IS NULL
+                OR b.reported_status NOT IN ('sent', 'failed', 'denied', 'rejected')
+             ORDER BY b.received_at",
+        )?;
+        let entries = stmt
+            .query_map([], |row| {
+                Ok(BridgeOutbound {
+                    message_id: row.get(0)?,
+                    queue_id: row.get(1)?,
+                    status: row.get(2)?,
+                    error: row.get(3)?,
+                    sent_at: row.get::<_, Option<String>>(4)?.map(parse_datetime),
+                    received_at: parse_datetime(row.get::<_, String>(5)?),
+                    reported_status: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Record a bridge request nonce, forgetting those seen before `expire_before`
+    /// (Unix seconds). Returns false if the nonce was already recorded.
+    pub fn record_bridge_nonce(&self, nonce: &str, seen_at: i64, expire_before: i64) -> Result<bool> {
+        self.conn
+            .execute("DELETE FROM bridge_nonces WHERE seen_at < ?", [expire_before])?;
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO bridge_nonces (nonce, seen_at) VALUES (?, ?)",
+            rusqlite::params![nonce, seen_at],
+        )?;
+        Ok(inserted > 0)
+    }
 
+    /// Record the status Moltbot accepted for a bridge message
+    pub fn mark_bridge_outbound_reported(&self, message_id: &str, status: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE bridge_outbound SET reported_status = ?, reported_at = ? WHERE message_id = ?",
+            rusqlite::params![status, Utc::now().to_rfc3339(), message_id],
+        )?;
         Ok(())
     }
 }
 
+fn row_to_audit_event(row: &rusqlite::Row) -> rusqlite::Result<AuditEvent> {
+    Ok(AuditEvent {
+        seq: row.get(0)?,
+        created_at: parse_datetime(row.get::<_, String>(1)?),
+        event: row.get(2)?,
+        actor: row.get(3)?,
+        subject_id: row.get(4)?,
+        details: row.get(5)?,
+        prev_hash: row.get(6)?,
+        hash: row.get(7)?,
+    })
+}
+
 /// Default content filters for message safety
 /// Returns: (pattern, pattern_type, action, description)
 fn get_default_content_filters() -> Vec<(&'static str, &'static str, &'static str, &'static str)> {
@@ -593,6 +1788,26 @@ fn get_default_content_filters() -> Vec<(&'static str, &'static str, &'static st
     ]
 }
 
+fn row_to_template(row: &rusqlite::Row) -> rusqlite::Result<MessageTemplate> {
+    Ok(MessageTemplate {
+        id: row.get(0)?,
+        subject: row.get(1)?,
+        body: row.get(2)?,
+        pre_approved: row.get(3)?,
+        created_at: parse_datetime(row.get::<_, String>(4)?),
+        updated_at: parse_datetime(row.get::<_, String>(5)?),
+    })
+}
+
+/// Columns read by `row_to_queue_entry`, in order.
+const QUEUE_SELECT: &str =
+    "SELECT id, api_key_id, channel, recipient_address, recipient_name, subject, body,
+            priority, status, agent_context, created_at, reviewed_at, sent_at, error_message,
+            thread_id, in_reply_to, risk_score, risk_reasons,
+            send_attempts, last_attempt_at, next_retry_at, template_id, batch_id,
+            recipient_contact
+     FROM communication_queue";
+
 fn row_to_queue_entry(row: &rusqlite::Row) -> rusqlite::Result<QueueEntry> {
     Ok(QueueEntry {
         id: row.get(0)?,
@@ -609,6 +1824,98 @@ fn row_to_queue_entry(row: &rusqlite::Row) -> rusqlite::Result<QueueEntry> {
         reviewed_at: row.get::<_, Option<String>>(11)?.map(parse_datetime),
         sent_at: row.get::<_, Option<String>>(12)?.map(parse_datetime),
         error_message: row.get(13)?,
+        thread_id: row.get(14)?,
+        in_reply_to: row.get(15)?,
+        risk_score: row.get(16)?,
+        risk_reasons: row
+            .get::<_, Option<String>>(17)?
+            .and_then(|r| serde_json::from_str(&r).ok())
+            .unwrap_or_default(),
+        send_attempts: row.get(18)?,
+        last_attempt_at: row.get::<_, Option<String>>(19)?.map(parse_datetime),
+        next_retry_at: row.get::<_, Option<String>>(20)?.map(parse_datetime),
+        template_id: row.get(21)?,
+        batch_id: row.get(22)?,
+        recipient_contact: row.get(23)?,
+    })
+}
+
+/// Settings key holding the content filter revision
+const SETTING_CONTENT_FILTERS_REVISION: &str = "gateway_content_filters_revision";
+
+const CONTENT_FILTER_SELECT: &str =
+    "SELECT id, pattern, pattern_type, action, description, enabled, created_at, validator, pack
+     FROM content_filters";
+
+fn row_to_content_filter(row: &rusqlite::Row) -> rusqlite::Result<ContentFilter> {
+    Ok(ContentFilter {
+        id: row.get(0)?,
+        pattern: row.get(1)?,
+        pattern_type: row.get(2)?,
+        action: row.get(3)?,
+        description: row.get(4)?,
+        enabled: row.get::<_, i32>(5)? != 0,
+        created_at: parse_datetime(row.get::<_, String>(6)?),
+        validator: row.get(7)?,
+        pack: row.get(8)?,
+    })
+}
+
+const API_KEY_SELECT: &str =
+    "SELECT id, name, key_hash, key_prefix, created_at, last_used_at, revoked_at,
+            rate_limit_per_hour, rate_limit_per_day, webhook_url, webhook_secret,
+            client_cert_fingerprint, scope_channels, scope_max_priority,
+            scope_read_all, scope_read_only, expires_at, previous_key_expires_at,
+            risk_classification
+     FROM api_keys";
+
+fn row_to_api_key(row: &rusqlite::Row) -> rusqlite::Result<ApiKey> {
+    Ok(ApiKey {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        key_hash: row.get(2)?,
+        key_prefix: row.get(3)?,
+        created_at: parse_datetime(row.get::<_, String>(4)?),
+        last_used_at: row.get::<_, Option<String>>(5)?.map(parse_datetime),
+        revoked_at: row.get::<_, Option<String>>(6)?.map(parse_datetime),
+        rate_limit_per_hour: row.get(7)?,
+        rate_limit_per_day: row.get(8)?,
+        webhook_url: row.get(9)?,
+        webhook_secret: row.get(10)?,
+        client_cert_fingerprint: row.get(11)?,
+        scopes: ApiKeyScopes {
+            channels: row
+                .get::<_, Option<String>>(12)?
+                .map(|c| c.split(',').map(String::from).collect()),
+            max_priority: row.get(13)?,
+            read_all: row.get(14)?,
+            read_only: row.get(15)?,
+        },
+        expires_at: row.get::<_, Option<String>>(16)?.map(parse_datetime),
+        previous_key_expires_at: row.get::<_, Option<String>>(17)?.map(parse_datetime),
+        risk_classification: row.get(18)?,
+    })
+}
+
+const WEBHOOK_DELIVERY_SELECT: &str =
+    "SELECT id, api_key_id, event, action_id, payload, status, attempts, last_response_code,
+            last_error, next_attempt_at, created_at, delivered_at
+     FROM webhook_deliveries";
+
+fn row_to_webhook_delivery(row: &rusqlite::Row) -> rusqlite::Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        api_key_id: row.get(1)?,
+        event: row.get(2)?,
+        action_id: row.get(3)?,
+        payload: row.get(4)?,
+        status: row.get(5)?,
+        attempts: row.get(6)?,
+        last_response_code: row.get(7)?,
+        last_error: row.get(8)?,
+        next_attempt_at: row.get::<_, Option<String>>(9)?.map(parse_datetime),
+        created_at: parse_datetime(row.get::<_, String>(10)?),
+        delivered_at: row.get::<_, Option<String>>(11)?.map(parse_datetime),
     })
 }
 
@@ -703,6 +2010,78 @@ mod tests {
         assert!(key.revoked_at.is_some());
     }
 
+    #[test]
+    fn test_api_key_scopes() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "hash123", "gw_abc")
+            .unwrap();
+        let key = db.find_api_key_by_hash("hash123").unwrap().unwrap();
+        assert_eq!(key.scopes, ApiKeyScopes::default());
+
+        let scopes = ApiKeyScopes {
+            channels: Some(vec!["sms".to_string(), "email".to_string()]),
+            max_priority: Some("normal".to_string()),
+            read_all: true,
+            read_only: false,
+        };
+        assert!(db.set_api_key_scopes("key-1", &scopes).unwrap());
+        let key = db.find_api_key_by_hash("hash123").unwrap().unwrap();
+        assert_eq!(key.scopes, scopes);
+    }
+
+    #[test]
+    fn test_api_key_expiry_and_rotation() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "old-hash", "gw_old")
+            .unwrap();
+
+        let expires = Utc::now() + chrono::Duration::days(30);
+        assert!(db.set_api_key_expiry("key-1", Some(expires)).unwrap());
+        let key = db.find_api_key_by_hash("old-hash").unwrap().unwrap();
+        assert!(!key.is_expired(Utc::now()));
+        assert!(key.is_expired(expires + chrono::Duration::seconds(1)));
+
+        // Old secret stays valid during the grace window
+        let grace = Utc::now() + chrono::Duration::hours(1);
+        assert!(db.rotate_api_key("key-1", "new-hash", "gw_new", Some(grace)).unwrap());
+        let key = db.find_api_key_by_hash("new-hash").unwrap().unwrap();
+        assert_eq!(key.key_prefix, "gw_new");
+        assert!(key.previous_key_expires_at.is_some());
+        assert_eq!(key.expires_at.map(|t| t.timestamp()), Some(expires.timestamp()));
+        assert_eq!(db.find_api_key_by_hash("old-hash").unwrap().unwrap().id, "key-1");
+
+        // Rotating without grace drops the previous secret
+        assert!(db.rotate_api_key("key-1", "newer-hash", "gw_nwr", None).unwrap());
+        assert!(db.find_api_key_by_hash("new-hash").unwrap().is_none());
+        assert!(db.find_api_key_by_hash("old-hash").unwrap().is_none());
+
+        // An elapsed grace window no longer matches
+        let past = Utc::now() - chrono::Duration::minutes(1);
+        db.rotate_api_key("key-1", "newest-hash", "gw_nws", Some(past))
+            .unwrap();
+        assert!(db.find_api_key_by_hash("newer-hash").unwrap().is_none());
+
+        assert!(db.set_api_key_expiry("key-1", None).unwrap());
+        let key = db.find_api_key_by_hash("newest-hash").unwrap().unwrap();
+        assert!(key.expires_at.is_none());
+    }
+
+    #[test]
+    fn test_api_key_client_cert() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "hash123", "gw_abc")
+            .unwrap();
+        assert!(db.find_api_key_by_client_cert("AB:CD").unwrap().is_none());
+
+        assert!(db.set_api_key_client_cert("key-1", Some("AB:CD")).unwrap());
+        let key = db.find_api_key_by_client_cert("AB:CD").unwrap().unwrap();
+        assert_eq!(key.id, "key-1");
+        assert_eq!(key.client_cert_fingerprint.as_deref(), Some("AB:CD"));
+
+        db.set_api_key_client_cert("key-1", None).unwrap();
+        assert!(db.find_api_key_by_client_cert("AB:CD").unwrap().is_none());
+    }
+
     #[test]
     fn test_queue_entry_crud() {
         let db = Database::open_memory().unwrap();
@@ -730,6 +2109,12 @@ mod tests {
         assert_eq!(entry.channel, "email");
         assert_eq!(entry.status, "pending");
 
+        // Edit while pending
+        assert!(db.update_queue_content("msg-1", Some("Hi"), "Edited").unwrap());
+        let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
+        assert_eq!(entry.subject.as_deref(), Some("Hi"));
+        assert_eq!(entry.body, "Edited");
+
         // List pending
         let pending = db.list_pending_queue().unwrap();
         assert_eq!(pending.len(), 1);
@@ -741,6 +2126,7 @@ mod tests {
         db.update_queue_status("msg-1", "approved").unwrap();
         let entry = db.get_queue_entry("msg-1").unwrap().unwrap();
         assert_eq!(entry.status, "approved");
+        assert!(!db.update_queue_content("msg-1", None, "Too late").unwrap());
 
         // Count pending (should be 0 now)
         assert_eq!(db.count_pending_queue().unwrap(), 0);
@@ -873,6 +2259,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_audit_log_chain_is_append_only() {
+        let db = Database::open_memory().unwrap();
+        let first = db
+            .append_audit_event("key_created", "cli", Some("key-1"), r#"{"name":"Bot"}"#)
+            .unwrap();
+        let second = db
+            .append_audit_event("queued", "agent:key-1", Some("msg-1"), "{}")
+            .unwrap();
+        assert_eq!((first.seq, second.seq), (1, 2));
+        assert_eq!(first.prev_hash, AUDIT_GENESIS_HASH);
+        assert_eq!(second.prev_hash, first.hash);
+
+        // Read back exactly as written, so hashes still verify
+        let events = db.list_audit_events(0).unwrap();
+        assert_eq!(events, vec![first.clone(), second]);
+        assert!(events.iter().all(|e| e.compute_hash() == e.hash));
+        assert_eq!(db.list_audit_events(1).unwrap().len(), 1);
+
+        assert!(db
+            .conn
+            .execute("UPDATE gateway_audit_log SET actor = 'web' WHERE seq = 1", [])
+            .is_err());
+        assert!(db.conn.execute("DELETE FROM gateway_audit_log", []).is_err());
+        assert_eq!(db.list_audit_events(0).unwrap()[0], first);
+    }
+
     #[test]
     fn test_webhook_url_crud() {
         let db = Database::open_memory().unwrap();
@@ -917,4 +2330,123 @@ mod tests {
         let webhook = db.get_api_key_webhook("key-1").unwrap();
         assert!(webhook.is_none());
     }
+
+    #[test]
+    fn test_webhook_delivery_lifecycle() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Test Agent", "hash123", "gw_abc")
+            .unwrap();
+
+        db.insert_webhook_delivery("dlv-1", "key-1", "status_change", Some("msg-1"), "{}")
+            .unwrap();
+        let due = db.list_due_webhook_deliveries(10).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].status, "pending");
+        assert_eq!(due[0].attempts, 0);
+
+        // Failed attempt with a retry scheduled in the future is no longer due
+        let later = Utc::now() + chrono::Duration::minutes(5);
+        db.mark_webhook_attempt_failed("dlv-1", Some(503), "HTTP 503", Some(later))
+            .unwrap();
+        assert!(db.list_due_webhook_deliveries(10).unwrap().is_empty());
+        assert!(!db.claim_webhook_delivery("dlv-1").unwrap());
+        let delivery = db.get_webhook_delivery("dlv").unwrap().unwrap();
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_response_code, Some(503));
+
+        // Giving up marks it failed; redelivery puts it back in the queue
+        db.mark_webhook_attempt_failed("dlv-1", None, "timeout", None).unwrap();
+        assert_eq!(db.get_webhook_delivery("dlv-1").unwrap().unwrap().status, "failed");
+        assert!(db.reset_webhook_delivery("dlv-1").unwrap());
+        assert_eq!(db.list_due_webhook_deliveries(10).unwrap().len(), 1);
+
+        // Only one worker gets to attempt it; in flight it is no longer due
+        assert!(db.claim_webhook_delivery("dlv-1").unwrap());
+        assert!(!db.claim_webhook_delivery("dlv-1").unwrap());
+        assert!(db.list_due_webhook_deliveries(10).unwrap().is_empty());
+
+        db.mark_webhook_delivered("dlv-1", 200).unwrap();
+        let delivery = db.get_webhook_delivery("dlv-1").unwrap().unwrap();
+        assert_eq!(delivery.status, "delivered");
+        assert!(delivery.delivered_at.is_some());
+        assert!(delivery.last_error.is_none());
+
+        assert_eq!(db.list_webhook_deliveries("key-1", 10).unwrap().len(), 1);
+        assert!(db.get_webhook_delivery("missing").unwrap().is_none());
+        // LIKE wildcards in a prefix are matched literally
+        assert!(db.get_webhook_delivery("dlv_").unwrap().is_none());
+        assert!(db.get_webhook_delivery("%").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_template_edit_clears_pre_approval() {
+        let db = Database::open_memory().unwrap();
+        db.insert_template("meeting-confirm", None, "Hi {first_name}, see you at {time}")
+            .unwrap();
+        assert!(db.insert_template("meeting-confirm", None, "dup").is_err());
+        assert!(db.set_template_pre_approved("meeting-confirm", true).unwrap());
+        assert!(db.get_template("meeting-confirm").unwrap().unwrap().pre_approved);
+
+        db.update_template("meeting-confirm", Some("Meeting"), "Hi {first_name}, moved to {time}")
+            .unwrap();
+        let template = db.get_template("meeting-confirm").unwrap().unwrap();
+        assert!(!template.pre_approved);
+        assert_eq!(template.subject.as_deref(), Some("Meeting"));
+
+        assert!(db.delete_template("meeting-confirm").unwrap());
+        assert!(db.get_template("meeting-confirm").unwrap().is_none());
+        assert!(db.list_templates().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_batch_entries() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Agent", "hash", "prefix").unwrap();
+        for (id, to) in [("msg-1", "+15551234567"), ("msg-2", "+15557654321"), ("msg-3", "+15550000000")] {
+            db.insert_queue_entry(id, "key-1", "sms", to, None, None, "Closed today", "normal", None)
+                .unwrap();
+        }
+        assert!(db.set_queue_batch("msg-1", "batch-1").unwrap());
+        assert!(db.set_queue_batch("msg-2", "batch-1").unwrap());
+
+        let batch = db.list_batch_entries("batch-1").unwrap();
+        let ids: Vec<&str> = batch.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["msg-1", "msg-2"]);
+        assert_eq!(batch[0].batch_id.as_deref(), Some("batch-1"));
+        assert!(db.get_queue_entry("msg-3").unwrap().unwrap().batch_id.is_none());
+        assert!(db.list_batch_entries("msg-1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bridge_outbound() {
+        let db = Database::open_memory().unwrap();
+        db.insert_api_key("key-1", "Bridge", "hash", "prefix").unwrap();
+        db.insert_queue_entry("msg-1", "key-1", "sms", "+15551234567", None, None, "On my way", "normal", None)
+            .unwrap();
+        assert!(db.insert_bridge_outbound("mb-1").unwrap());
+        assert!(!db.insert_bridge_outbound("mb-1").unwrap());
+        db.set_bridge_outbound_status("mb-1", Some("msg-1"), "queued", None).unwrap();
+        assert!(db.insert_bridge_outbound("mb-2").unwrap());
+        db.set_bridge_outbound_status("mb-2", None, "rejected", Some("opted out")).unwrap();
+
+        // Linked messages follow the queue; refused ones keep their own status
+        db.mark_bridge_outbound_reported("mb-1", "queued").unwrap();
+        db.mark_queue_sent("msg-1").unwrap();
+        let entries = db.list_unreported_bridge_outbound().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].status.as_str(), entries[0].reported_status.as_deref()), ("sent", Some("queued")));
+        assert!(entries[0].sent_at.is_some());
+        assert_eq!((entries[1].status.as_str(), entries[1].error.as_deref()), ("rejected", Some("opted out")));
+
+        db.mark_bridge_outbound_reported("mb-1", "sent").unwrap();
+        db.mark_bridge_outbound_reported("mb-2", "rejected").unwrap();
+        assert!(db.list_unreported_bridge_outbound().unwrap().is_empty());
+
+        // A rejection saved later is reported, but doesn't touch a queued message
+        db.reject_bridge_outbound("mb-3", "Could not record message").unwrap();
+        db.reject_bridge_outbound("mb-1", "Could not record message").unwrap();
+        let entries = db.list_unreported_bridge_outbound().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].message_id.as_str(), entries[0].status.as_str()), ("mb-3", "rejected"));
+    }
 }
@@ -59,7 +59,23 @@ impl Database {
         &self.conn
     }
 
-    fn default_path() -> Result<PathBuf> {
+    /// Run `f` in one write transaction, rolling back if it fails
+    pub fn in_transaction<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn.execute("BEGIN IMMEDIATE", [])?;
+        match f() {
+            Ok(value) => {
+                self.conn.execute("COMMIT", [])?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = self.conn.execute("ROLLBACK", []);
+                Err(e)
+            }
+        }
+    }
+
+    /// Location of the contacts database in the user's config directory.
+    pub(crate) fn default_path() -> Result<PathBuf> {
         let config_dir =
             dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
         Ok(config_dir.join("contactcmd").join("contacts.db"))
@@ -169,6 +185,139 @@ impl Database {
             self.set_schema_version(14)?;
         }
 
+        if self.get_schema_version()? == 14 {
+            // V14 → V15: Add thread_id and in_reply_to columns to communication_queue
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V15))?;
+            self.set_schema_version(15)?;
+        }
+
+        if self.get_schema_version()? == 15 {
+            // V15 → V16: Add webhook_secret column to api_keys
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V16))?;
+            self.set_schema_version(16)?;
+        }
+
+        if self.get_schema_version()? == 16 {
+            // V16 → V17: Add webhook_deliveries table
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V17))?;
+            self.set_schema_version(17)?;
+        }
+
+        if self.get_schema_version()? == 17 {
+            // V17 → V18: Add api_keys.client_cert_fingerprint
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V18))?;
+            self.set_schema_version(18)?;
+        }
+
+        if self.get_schema_version()? == 18 {
+            // V18 → V19: Add api_keys scope columns
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V19))?;
+            self.set_schema_version(19)?;
+        }
+
+        if self.get_schema_version()? == 19 {
+            // V19 → V20: Add api_keys expiry and rotation columns
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V20))?;
+            self.set_schema_version(20)?;
+        }
+
+        if self.get_schema_version()? == 20 {
+            // V20 → V21: Add rate_limit_buckets table
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V21))?;
+            self.set_schema_version(21)?;
+        }
+
+        if self.get_schema_version()? == 21 {
+            // V21 → V22: Add content_filters validator and pack columns
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V22))?;
+            self.set_schema_version(22)?;
+        }
+
+        if self.get_schema_version()? == 22 {
+            // V22 → V23: Add risk classification columns
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V23))?;
+            self.set_schema_version(23)?;
+        }
+
+        if self.get_schema_version()? == 23 {
+            // V23 → V24: Add approval notifiers
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V24))?;
+            self.set_schema_version(24)?;
+        }
+
+        if self.get_schema_version()? == 24 {
+            // V24 → V25: Add hash-chained gateway audit log
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V25))?;
+            self.set_schema_version(25)?;
+        }
+
+        if self.get_schema_version()? == 25 {
+            // V25 → V26: Track send attempts for automatic retries
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V26))?;
+            self.set_schema_version(26)?;
+        }
+
+        if self.get_schema_version()? == 26 {
+            // V26 → V27: Message templates
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V27))?;
+            self.set_schema_version(27)?;
+        }
+
+        if self.get_schema_version()? == 27 {
+            // V27 → V28: Batch sends
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V28))?;
+            self.set_schema_version(28)?;
+        }
+
+        if self.get_schema_version()? == 28 {
+            // V28 → V29: Recipients addressed by contact
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V29))?;
+            self.set_schema_version(29)?;
+        }
+
+        if self.get_schema_version()? == 29 {
+            // V29 → V30: Moltbot bridge outbound messages
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V30))?;
+            self.set_schema_version(30)?;
+        }
+
+        if self.get_schema_version()? == 30 {
+            // V30 → V31: Bridge request nonces
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V31))?;
+            self.set_schema_version(31)?;
+        }
+
+        if self.get_schema_version()? == 31 {
+            // V31 → V32: Gateway send idempotency keys
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V32))?;
+            self.set_schema_version(32)?;
+        }
+
+        if self.get_schema_version()? == 32 {
+            // V32 → V33: Rate limit rejection counts
+            self.conn
+                .execute_batch(&format!("BEGIN TRANSACTION; {} COMMIT;", schema::MIGRATION_V33))?;
+            self.set_schema_version(33)?;
+        }
+
         Ok(())
     }
 
@@ -630,7 +630,7 @@ impl Database {
     }
 
     /// Escape LIKE metacharacters (% _ \)
-    fn escape_like(s: &str) -> String {
+    pub(super) fn escape_like(s: &str) -> String {
         let mut result = String::with_capacity(s.len());
         for c in s.chars() {
             match c {
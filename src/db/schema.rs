@@ -1,4 +1,4 @@
-pub const SCHEMA_VERSION: i32 = 14;
+pub const SCHEMA_VERSION: i32 = 33;
 
 pub const MIGRATION_V2: &str = r#"
 ALTER TABLE persons ADD COLUMN photo_path TEXT;
@@ -164,6 +164,210 @@ pub const MIGRATION_V14: &str = r#"
 ALTER TABLE persons ADD COLUMN ai_contact_allowed INTEGER NOT NULL DEFAULT 1;
 "#;
 
+/// V15 migration: Add conversation threading columns to communication_queue
+/// thread_id groups an agent's back-and-forth with a contact; in_reply_to points at the previous action
+pub const MIGRATION_V15: &str = r#"
+ALTER TABLE communication_queue ADD COLUMN thread_id TEXT;
+ALTER TABLE communication_queue ADD COLUMN in_reply_to TEXT;
+CREATE INDEX IF NOT EXISTS idx_queue_thread ON communication_queue(api_key_id, thread_id);
+"#;
+
+/// V16 migration: Add per-key webhook signing secret (HMAC-SHA256, same scheme as the bridge)
+pub const MIGRATION_V16: &str = r#"
+ALTER TABLE api_keys ADD COLUMN webhook_secret TEXT;
+"#;
+
+/// V17 migration: Add webhook_deliveries table so failed webhooks are retried and auditable
+pub const MIGRATION_V17: &str = r#"
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id TEXT PRIMARY KEY,
+    api_key_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    action_id TEXT,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_response_code INTEGER,
+    last_error TEXT,
+    next_attempt_at TEXT,
+    created_at TEXT NOT NULL,
+    delivered_at TEXT,
+    FOREIGN KEY (api_key_id) REFERENCES api_keys(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON webhook_deliveries(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_key ON webhook_deliveries(api_key_id, created_at);
+"#;
+
+/// V18 migration: Add client certificate fingerprint so agents can authenticate with mutual TLS
+pub const MIGRATION_V18: &str = r#"
+ALTER TABLE api_keys ADD COLUMN client_cert_fingerprint TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_client_cert ON api_keys(client_cert_fingerprint);
+"#;
+
+/// V19 migration: Add per-key scopes
+pub const MIGRATION_V19: &str = r#"
+ALTER TABLE api_keys ADD COLUMN scope_channels TEXT;
+ALTER TABLE api_keys ADD COLUMN scope_max_priority TEXT;
+ALTER TABLE api_keys ADD COLUMN scope_read_all INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE api_keys ADD COLUMN scope_read_only INTEGER NOT NULL DEFAULT 0;
+"#;
+
+/// V20 migration: Add API key expiry and a previous secret valid during rotation
+pub const MIGRATION_V20: &str = r#"
+ALTER TABLE api_keys ADD COLUMN expires_at TEXT;
+ALTER TABLE api_keys ADD COLUMN previous_key_hash TEXT;
+ALTER TABLE api_keys ADD COLUMN previous_key_expires_at TEXT;
+CREATE INDEX IF NOT EXISTS idx_api_keys_previous_hash ON api_keys(previous_key_hash);
+"#;
+
+/// V21 migration: Add token buckets for gateway rate limiting
+pub const MIGRATION_V21: &str = r#"
+CREATE TABLE IF NOT EXISTS rate_limit_buckets (
+    bucket_key TEXT PRIMARY KEY,
+    tokens REAL NOT NULL,
+    updated_at TEXT NOT NULL
+);
+"#;
+
+/// V22 migration: Add content filter validators (e.g. Luhn check) and the pack a filter came from
+pub const MIGRATION_V22: &str = r#"
+ALTER TABLE content_filters ADD COLUMN validator TEXT;
+ALTER TABLE content_filters ADD COLUMN pack TEXT;
+CREATE INDEX IF NOT EXISTS idx_content_filter_pack ON content_filters(pack);
+"#;
+
+/// V23 migration: Add opt-in AI risk classification per key and risk results on queue entries
+pub const MIGRATION_V23: &str = r#"
+ALTER TABLE api_keys ADD COLUMN risk_classification INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE communication_queue ADD COLUMN risk_score INTEGER;
+ALTER TABLE communication_queue ADD COLUMN risk_reasons TEXT;
+"#;
+
+/// V24 migration: Add approval notifiers and track which queue entries were announced
+pub const MIGRATION_V24: &str = r#"
+CREATE TABLE IF NOT EXISTS gateway_notifiers (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK(kind IN ('desktop', 'ntfy', 'gotify', 'email')),
+    target TEXT,
+    preview INTEGER NOT NULL DEFAULT 0,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT,
+    created_at TEXT NOT NULL
+);
+
+ALTER TABLE communication_queue ADD COLUMN notified_at TEXT;
+UPDATE communication_queue SET notified_at = created_at;
+"#;
+
+pub const MIGRATION_V25: &str = r#"
+CREATE TABLE IF NOT EXISTS gateway_audit_log (
+    seq INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    event TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    subject_id TEXT,
+    details TEXT NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL UNIQUE
+);
+
+CREATE INDEX IF NOT EXISTS idx_gateway_audit_subject ON gateway_audit_log(subject_id);
+
+CREATE TRIGGER IF NOT EXISTS gateway_audit_log_no_update
+BEFORE UPDATE ON gateway_audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'gateway_audit_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS gateway_audit_log_no_delete
+BEFORE DELETE ON gateway_audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'gateway_audit_log is append-only');
+END;
+"#;
+
+pub const MIGRATION_V26: &str = r#"
+ALTER TABLE communication_queue ADD COLUMN send_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE communication_queue ADD COLUMN last_attempt_at TEXT;
+ALTER TABLE communication_queue ADD COLUMN next_retry_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_queue_next_retry ON communication_queue(next_retry_at);
+"#;
+
+/// V27 migration: Add message templates and record which template a queue entry used
+pub const MIGRATION_V27: &str = r#"
+CREATE TABLE IF NOT EXISTS gateway_templates (
+    id TEXT PRIMARY KEY,
+    subject TEXT,
+    body TEXT NOT NULL,
+    pre_approved INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+ALTER TABLE communication_queue ADD COLUMN template_id TEXT;
+"#;
+
+/// V28 migration: Group messages queued together by a batch send
+pub const MIGRATION_V28: &str = r#"
+ALTER TABLE communication_queue ADD COLUMN batch_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_queue_batch ON communication_queue(batch_id);
+"#;
+
+/// V29 migration: Remember the contact reference for messages addressed by contact
+pub const MIGRATION_V29: &str = r#"
+ALTER TABLE communication_queue ADD COLUMN recipient_contact TEXT;
+"#;
+
+/// V30 migration: Track messages from the Moltbot bridge and what was reported back
+pub const MIGRATION_V30: &str = r#"
+CREATE TABLE IF NOT EXISTS bridge_outbound (
+    message_id TEXT PRIMARY KEY,
+    queue_id TEXT,
+    status TEXT NOT NULL,
+    error TEXT,
+    received_at TEXT NOT NULL,
+    reported_status TEXT,
+    reported_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_bridge_outbound_queue ON bridge_outbound(queue_id);
+"#;
+
+/// V31 migration: Remember bridge request nonces so replays are refused across restarts
+pub const MIGRATION_V31: &str = r#"
+CREATE TABLE IF NOT EXISTS bridge_nonces (
+    nonce TEXT PRIMARY KEY,
+    seen_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_bridge_nonces_seen ON bridge_nonces(seen_at);
+"#;
+
+/// V32 migration: Idempotency keys for gateway sends
+pub const MIGRATION_V32: &str = r#"
+CREATE TABLE IF NOT EXISTS gateway_idempotency_keys (
+    api_key_id TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    action_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (api_key_id, idempotency_key)
+);
+"#;
+
+/// V33 migration: Count rate limit rejections per key, limit and hour
+pub const MIGRATION_V33: &str = r#"
+CREATE TABLE IF NOT EXISTS gateway_rate_limit_rejections (
+    api_key_id TEXT NOT NULL,
+    limit_type TEXT NOT NULL,
+    window_start TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (api_key_id, limit_type, window_start)
+);
+"#;
+
 /// Fallback for older SQLite: rebuild table without photo_path
 pub const MIGRATION_V3_REBUILD: &str = r#"
 CREATE TABLE persons_new (